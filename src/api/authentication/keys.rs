@@ -0,0 +1,68 @@
+use std::{fs, path::Path};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::TOKEN_LIFETIME_HOURS;
+
+/// A key tokens are signed with. Retired keys stop signing but keep validating until every
+/// token they signed has expired.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SigningKey {
+    pub kid: String,
+    pub secret: String,
+    pub created_at: DateTime<Utc>,
+    pub retired_at: Option<DateTime<Utc>>,
+}
+
+/// The jwt keys of `auth.keys_path`, rotated by `payme-admin rotate-jwt-key`. The api reads it on
+/// start, so a rotation takes effect with the next restart and tokens signed before it stay valid.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct KeyRing {
+    pub keys: Vec<SigningKey>,
+}
+
+impl KeyRing {
+    /// A missing file is an empty ring.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        let json = serde_json::to_string_pretty(self).expect("key ring serializes");
+        // the ring holds the only copy of the signing key, it's never left half written
+        let part = path.with_extension("part");
+        fs::write(&part, json)
+            .and_then(|_| fs::rename(&part, path))
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// The key new tokens are signed with.
+    pub fn current(&self) -> Option<&SigningKey> {
+        self.keys.iter().rev().find(|key| key.retired_at.is_none())
+    }
+
+    /// Retires the current key, adds a new one, and drops keys that can't have a valid token left.
+    pub fn rotate(&mut self, now: DateTime<Utc>) -> &SigningKey {
+        for key in self.keys.iter_mut().filter(|key| key.retired_at.is_none()) {
+            key.retired_at = Some(now);
+        }
+        let lifetime = Duration::hours(TOKEN_LIFETIME_HOURS);
+        self.keys.retain(|key| key.retired_at.is_some_and(|retired_at| now - retired_at < lifetime));
+
+        self.keys.push(SigningKey {
+            kid: Uuid::new_v4().to_string(),
+            secret: format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple()),
+            created_at: now,
+            retired_at: None,
+        });
+        self.keys.last().expect("just pushed")
+    }
+}
@@ -0,0 +1,65 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use crate::api::authentication::{keys::KeyRing, AuthenticationService, TOKEN_LIFETIME_HOURS};
+
+    const SECRET: &str = "the-secret-from-before-the-first-rotation";
+
+    #[test]
+    fn test_rotation_keeps_tokens_of_the_retired_key_valid() {
+        let mut ring = KeyRing::default();
+        ring.rotate(Utc::now());
+        let before = AuthenticationService::from_key_ring(&ring, SECRET);
+        let token = before.create_token("support".to_string(), "admin".to_string()).unwrap();
+
+        ring.rotate(Utc::now());
+        let after = AuthenticationService::from_key_ring(&ring, SECRET);
+
+        assert_eq!(after.validate_token(&token).unwrap().sub, "support");
+        let fresh = after.create_token("support".to_string(), "admin".to_string()).unwrap();
+        assert!(before.validate_token(&fresh).is_err(), "the old key ring doesn't know the new key");
+    }
+
+    #[test]
+    fn test_tokens_signed_with_the_secret_survive_the_first_rotation() {
+        let token = AuthenticationService::from_secret(SECRET)
+            .create_token_for("merchant-user".to_string(), "merchant".to_string(), Some("m_1".to_string()))
+            .unwrap();
+
+        let mut ring = KeyRing::default();
+        ring.rotate(Utc::now());
+        let claims = AuthenticationService::from_key_ring(&ring, SECRET).validate_token(&token).unwrap();
+
+        assert_eq!(claims.merchant_id.as_deref(), Some("m_1"));
+        assert!(AuthenticationService::from_key_ring(&ring, "another-secret").validate_token(&token).is_err());
+    }
+
+    #[test]
+    fn test_keys_retired_longer_than_a_token_lives_are_dropped() {
+        let start = Utc::now();
+        let mut ring = KeyRing::default();
+        let first = ring.rotate(start).kid.clone();
+        ring.rotate(start + Duration::hours(1));
+        assert_eq!(ring.keys.len(), 2);
+
+        let current = ring.rotate(start + Duration::hours(1 + TOKEN_LIFETIME_HOURS)).kid.clone();
+
+        assert_eq!(ring.keys.len(), 2);
+        assert!(ring.keys.iter().all(|key| key.kid != first));
+        assert_eq!(ring.current().unwrap().kid, current);
+    }
+
+    #[test]
+    fn test_ring_round_trips_through_its_file() {
+        let path = std::env::temp_dir().join(format!("payme-keys-{}.json", uuid::Uuid::new_v4()));
+        assert_eq!(KeyRing::load(&path).unwrap(), KeyRing::default());
+
+        let mut ring = KeyRing::default();
+        ring.rotate(Utc::now());
+        ring.save(&path).unwrap();
+
+        assert_eq!(KeyRing::load(&path).unwrap(), ring);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -2,24 +2,60 @@ use std::env;
 
 use axum::{response::IntoResponse, Json};
 use hyper::StatusCode;
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use jsonwebtoken::{decode, decode_header, encode, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use utoipa::ToSchema;
+
+use keys::KeyRing;
+
+use crate::core::config::AuthConfig;
+
+pub mod keys;
+pub mod keys_test;
+
+// how long a token is valid, and so how long a retired signing key has to keep validating
+pub const TOKEN_LIFETIME_HOURS: i64 = 24;
+
+// platform staff, admins pass every role check
+pub const ADMIN_ROLE: &str = "admin";
+pub const FINANCE_ROLE: &str = "finance";
+pub const RISK_ROLE: &str = "risk";
+// only tokens with this role can read the audit log
+pub const AUDITOR_ROLE: &str = "auditor";
+pub const STAFF_ROLES: &[&str] = &[ADMIN_ROLE, FINANCE_ROLE, RISK_ROLE, AUDITOR_ROLE];
+// a merchant's own users, and what its api keys act as
+pub const MERCHANT_ROLE: &str = "merchant";
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Claims {
     pub sub: String,
     pub exp: i64,
-    pub role: String
+    pub role: String,
+    // set on tokens of a merchant's own users, they only ever see that merchant's data
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merchant_id: Option<String>
+}
+
+impl Claims {
+    /// Platform tokens act for every merchant, a merchant's users and api keys only for their own.
+    pub fn may_act_for(&self, merchant_id: &str) -> bool {
+        self.merchant_id.as_deref().is_none_or(|own| own == merchant_id)
+    }
+
+    /// Operator endpoints: platform staff with one of `roles`, or an admin.
+    pub fn is_operator(&self, roles: &[&str]) -> bool {
+        self.merchant_id.is_none() && (self.role == ADMIN_ROLE || roles.contains(&self.role.as_str()))
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct LoginRequest {
     pub username: String,
     pub password: String
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct LoginResponse{
     pub token: String
 }
@@ -39,40 +75,90 @@ pub enum AuthenticationError {
 #[derive(Clone)]
 pub struct AuthenticationService {
     encoding_key: EncodingKey,
-    decoding_key : DecodingKey
+    // set when signing with a key ring key, so validation knows which key to check
+    kid: Option<String>,
+    // the signing key first, then retired ones whose tokens may not have expired yet
+    decoding_keys: Vec<(Option<String>, DecodingKey)>
 }
 
 impl AuthenticationService {
     pub fn new() -> Self {
         let secret = env::var("JWT_SECRET").expect("jwt secet must be set");
+        Self::from_secret(&secret)
+    }
+
+    pub fn from_secret(secret: &str) -> Self {
         Self {
             encoding_key: EncodingKey::from_secret(secret.as_bytes()),
-            decoding_key: DecodingKey::from_secret(secret.as_bytes())
+            kid: None,
+            decoding_keys: vec![(None, DecodingKey::from_secret(secret.as_bytes()))]
+        }
+    }
+
+    /// Signs with the ring's current key and accepts tokens of the retired ones. An empty ring
+    /// falls back to `secret`, so a deployment can move to a ring with its first rotation.
+    pub fn from_key_ring(ring: &KeyRing, secret: &str) -> Self {
+        let Some(current) = ring.current() else {
+            return Self::from_secret(secret);
+        };
+
+        let mut decoding_keys: Vec<_> = ring
+            .keys
+            .iter()
+            .rev()
+            .map(|key| (Some(key.kid.clone()), DecodingKey::from_secret(key.secret.as_bytes())))
+            .collect();
+        // tokens signed with the secret before the first rotation are still out there
+        decoding_keys.push((None, DecodingKey::from_secret(secret.as_bytes())));
+
+        Self {
+            encoding_key: EncodingKey::from_secret(current.secret.as_bytes()),
+            kid: Some(current.kid.clone()),
+            decoding_keys
+        }
+    }
+
+    /// What `[auth]` asks for, the key ring once `payme-admin` rotated a key into it.
+    pub fn from_config(auth: &AuthConfig) -> Result<Self, String> {
+        match auth.keys_path.trim() {
+            "" => Ok(Self::from_secret(auth.jwt_secret.expose())),
+            path => KeyRing::load(path).map(|ring| Self::from_key_ring(&ring, auth.jwt_secret.expose())),
         }
     }
 
     pub fn create_token(&self, user_id: String, role: String) -> Result<String, AuthenticationError> {
+        self.create_token_for(user_id, role, None)
+    }
+
+    /// A token for a merchant's own user carries the merchant, platform staff pass None.
+    pub fn create_token_for(&self, user_id: String, role: String, merchant_id: Option<String>) -> Result<String, AuthenticationError> {
 
         let expiry = chrono::Utc::now()
-                    .checked_add_signed(chrono::Duration::hours(24))
+                    .checked_add_signed(chrono::Duration::hours(TOKEN_LIFETIME_HOURS))
                     .expect("valid timestamp")
                     .timestamp();
 
         let claims = Claims {
             sub: user_id,
             exp: expiry,
-            role
+            role,
+            merchant_id
         };
 
-        encode(&Header::default(), &claims, &self.encoding_key)
-            .map_err(|e| AuthenticationError::InvalidToken)
+        let header = Header { kid: self.kid.clone(), ..Header::default() };
+        encode(&header, &claims, &self.encoding_key)
+            .map_err(|_| AuthenticationError::InvalidToken)
     }
 
     pub fn validate_token(&self, token: &str) -> Result<Claims, AuthenticationError> {
+        let kid = decode_header(token).map_err(|_| AuthenticationError::InvalidToken)?.kid;
         let validation = Validation::default();
-        decode(token, &self.decoding_key, &validation)
-        .map(|data| data.claims)
-        .map_err(|_| AuthenticationError::InvalidToken)
+        self.decoding_keys
+            .iter()
+            .filter(|(key_kid, _)| *key_kid == kid)
+            .find_map(|(_, key)| decode::<Claims>(token, key, &validation).ok())
+            .map(|data| data.claims)
+            .ok_or(AuthenticationError::InvalidToken)
     }
 }
 
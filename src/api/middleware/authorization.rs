@@ -1,64 +1,79 @@
-use std::pin::Pin;
-use http::request::Request as MyRequest;
 use axum::{
-    body::Bytes,
-    extract::FromRequestParts,
-    response::Response,
-    RequestPartsExt,
+    extract::{FromRequestParts, Request, State},
+    http::{request::Parts, HeaderMap},
+    middleware::Next,
+    response::{IntoResponse, Response},
 };
-use tower::ServiceExt;
-use tower_http::auth::AuthorizeRequest;
-use http_body::Body as HttpBody;
 
-use crate::api::authentication::{AuthenticationError, AuthenticationService, Claims};
+use crate::{
+    api::authentication::{AuthenticationError, AuthenticationService, Claims, MERCHANT_ROLE},
+    core::infrastructure::accounts::{AccountStore, API_KEY_PREFIX},
+};
 
 #[derive(Clone)]
 pub struct AuthMiddleware {
     auth_service: AuthenticationService,
+    // keys issued with `payme-admin create-api-key`, only checked when set
+    api_keys: Option<AccountStore>,
 }
 
 impl AuthMiddleware {
-    pub fn new() -> Self {
-        Self {
-            auth_service: AuthenticationService::new(),
-        }
+    pub fn with_service(auth_service: AuthenticationService) -> Self {
+        Self { auth_service, api_keys: None }
     }
-}
 
-impl AuthorizeRequest for AuthMiddleware {
-    type Output = ();
-    type ResponseBody = Pin<Box<dyn HttpBody<Data = Bytes, Error = AuthenticationError> + Send + 'static>>;
-
-    fn unauthorized_response<B>(&mut self, _request: &MyRequest<B>) -> Response<Self::ResponseBody> {
-        // let body = Box::pin(http_body_util::Empty::new());
-        // Response::builder()
-        //     .status(StatusCode::UNAUTHORIZED)
-        //     .body(body)
-        //     .unwrap()
-        todo!("this doesn't seem to work ");
+    /// Also accepts `Bearer pm_...` api keys, which act as their merchant.
+    pub fn with_api_keys(mut self, accounts: AccountStore) -> Self {
+        self.api_keys = Some(accounts);
+        self
     }
 
-    fn authorize<B>(&mut self, request: &MyRequest<B>) -> Option<Self::Output> {
-        let (mut parts, body) = request.into_parts();
-        let auth_header = parts
-            .headers
-            .get("Authorization")
-            .and_then(|header| header.to_str().ok())
-            .ok_or_else(|| self.unauthorized_response(request))?;
-
-        if !auth_header.starts_with("Bearer ") {
-            self.unauthorized_response(request);
-            return None;
-        }
+    /// The caller's claims, None when the request carries no credentials at all.
+    pub async fn authorize(&self, headers: &HeaderMap) -> Result<Option<Claims>, AuthenticationError> {
+        let Some(auth_header) = headers.get("Authorization") else {
+            return Ok(None);
+        };
+        let token = auth_header
+            .to_str()
+            .ok()
+            .and_then(|header| header.strip_prefix("Bearer "))
+            .ok_or(AuthenticationError::MissingToken)?;
 
-        let token = auth_header.trim_start_matches("Bearer ");
-        let claims = self.auth_service.validate_token(token)
-            .ok()?;
+        match (&self.api_keys, token.starts_with(API_KEY_PREFIX)) {
+            (Some(accounts), true) => {
+                let key = accounts
+                    .verify_api_key(token)
+                    .await
+                    .map_err(|e| {
+                        eprintln!("Failed to check api key: {}", e);
+                        AuthenticationError::InvalidToken
+                    })?
+                    .ok_or(AuthenticationError::InvalidToken)?;
 
-        parts.extensions.insert(claims);
-        *request = Request::from_parts(parts, body);
+                Ok(Some(Claims {
+                    sub: format!("api_key:{}", key.id),
+                    // keys don't expire, they're replaced
+                    exp: i64::MAX,
+                    role: MERCHANT_ROLE.to_string(),
+                    merchant_id: Some(key.merchant_id),
+                }))
+            }
+            _ => self.auth_service.validate_token(token).map(Some),
+        }
+    }
+}
 
-        Some(())
+/// Puts the caller's claims on the request for `AuthenticatedUser`. Requests without credentials
+/// go through without any, the handlers that need a caller turn them away, while login, the
+/// provider's webhooks and the customer's 3DS return don't need one.
+pub async fn authenticate(State(auth): State<AuthMiddleware>, mut request: Request, next: Next) -> Response {
+    match auth.authorize(request.headers()).await {
+        Ok(Some(claims)) => {
+            request.extensions_mut().insert(claims);
+            next.run(request).await
+        }
+        Ok(None) => next.run(request).await,
+        Err(e) => e.into_response(),
     }
 }
 
@@ -80,4 +95,4 @@ where
 
         Ok(AuthenticatedUser(claims))
     }
-} 
\ No newline at end of file
+}
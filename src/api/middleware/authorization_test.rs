@@ -0,0 +1,61 @@
+#[cfg(test)]
+mod tests {
+    use axum::{
+        http::{header, HeaderValue},
+        middleware,
+        routing::get,
+        Json, Router,
+    };
+    use axum_test::{TestRequest, TestServer};
+    use hyper::StatusCode;
+
+    use crate::{
+        api::{
+            authentication::{AuthenticationService, Claims},
+            middleware::{authenticate, AuthMiddleware, AuthenticatedUser},
+        },
+        core::infrastructure::accounts::AccountStore,
+    };
+
+    const SECRET: &str = "authorization-test-secret-of-32-chars";
+
+    fn bearer(request: TestRequest, token: &str) -> TestRequest {
+        request.add_header(header::AUTHORIZATION, HeaderValue::from_str(&format!("Bearer {}", token)).unwrap())
+    }
+
+    fn server(accounts: AccountStore) -> TestServer {
+        let authentication = AuthMiddleware::with_service(AuthenticationService::from_secret(SECRET)).with_api_keys(accounts);
+        let app = Router::new()
+            .route("/whoami", get(|AuthenticatedUser(claims): AuthenticatedUser| async move { Json(claims) }))
+            .layer(middleware::from_fn_with_state(authentication, authenticate));
+        TestServer::new(app).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_api_keys_act_as_their_merchant() {
+        let accounts = AccountStore::new();
+        accounts.create_merchant("merch_1", "Coffee Shop").await.unwrap();
+        let (_, key) = accounts.create_api_key("merch_1").await.unwrap();
+        let server = server(accounts);
+
+        let response = bearer(server.get("/whoami"), &key).await;
+
+        response.assert_status_ok();
+        let claims = response.json::<Claims>();
+        assert_eq!((claims.role.as_str(), claims.merchant_id.as_deref()), ("merchant", Some("merch_1")));
+        assert!(claims.may_act_for("merch_1") && !claims.may_act_for("merch_2"));
+    }
+
+    #[tokio::test]
+    async fn test_tokens_are_checked_and_missing_ones_left_to_the_handler() {
+        let server = server(AccountStore::new());
+        let token = AuthenticationService::from_secret(SECRET).create_token("ops".to_string(), "admin".to_string()).unwrap();
+
+        let claims = bearer(server.get("/whoami"), &token).await.json::<Claims>();
+        assert_eq!(claims.sub, "ops");
+
+        bearer(server.get("/whoami"), "pm_not_a_key").await.assert_status(StatusCode::UNAUTHORIZED);
+        bearer(server.get("/whoami"), "not.a.token").await.assert_status(StatusCode::UNAUTHORIZED);
+        server.get("/whoami").await.assert_status(StatusCode::UNAUTHORIZED);
+    }
+}
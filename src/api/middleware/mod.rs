@@ -1,3 +1,4 @@
 pub mod authorization;
+pub mod authorization_test;
 
-pub use authorization::{AuthMiddleware, AuthenticatedUser}; 
\ No newline at end of file
+pub use authorization::{authenticate, AuthMiddleware, AuthenticatedUser};
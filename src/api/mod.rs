@@ -1,3 +1,4 @@
 pub mod authentication;
 pub mod middleware;
-pub mod routes; 
\ No newline at end of file
+pub mod routes;
+pub mod routes_test;
@@ -1,41 +1,115 @@
 use axum::{
+    extract::FromRef,
     routing::{get, post},
     Router,
 };
 use serde_json::json;
 
 use crate::api::{
-    authentication::{AuthenticationService, LoginRequest, LoginResponse},
+    authentication::{AuthenticationError, AuthenticationService, LoginRequest, LoginResponse},
     middleware::AuthenticatedUser,
 };
+use crate::core::{
+    api::{audit::AuditContext, openapi::ErrorResponse},
+    infrastructure::{
+        accounts::AccountStore,
+        audit::{AuditLog, AuditRecord},
+        limits::RateLimiter,
+    },
+    models::{Actor, AuditAction},
+};
 
-pub fn create_router() -> Router {
-    let auth_service = AuthenticationService::new();
+#[derive(Clone, FromRef)]
+struct LoginState {
+    auth_service: AuthenticationService,
+    accounts: AccountStore,
+    // logins, failed ones included, go to the same audit log as the payment api
+    audit: AuditLog,
+    // auth.example_login, for local runs only
+    example_login: bool,
+    // not applied to logins, its trusted proxies tell the audit log where a login came from
+    rate_limiter: RateLimiter,
+}
 
+pub fn create_router(
+    auth_service: AuthenticationService,
+    accounts: AccountStore,
+    audit: AuditLog,
+    example_login: bool,
+    rate_limiter: RateLimiter,
+) -> Router {
     Router::new()
         .route("/login", post(login))
         .route("/protected", get(protected_route))
-        .with_state(auth_service)
+        .with_state(LoginState { auth_service, accounts, audit, example_login, rate_limiter })
 }
 
-async fn login(
+#[utoipa::path(
+    post,
+    path = "/login",
+    tag = "auth",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, description = "Bearer token, valid for 24 hours", body = LoginResponse),
+        (status = 401, description = "Invalid credentials", body = ErrorResponse),
+    ),
+    // called to get a token, so without one
+    security(())
+)]
+pub async fn login(
     axum::extract::State(auth_service): axum::extract::State<AuthenticationService>,
+    axum::extract::State(accounts): axum::extract::State<AccountStore>,
+    axum::extract::State(audit): axum::extract::State<AuditLog>,
+    axum::extract::State(example_login): axum::extract::State<bool>,
+    context: AuditContext,
     axum::Json(payload): axum::Json<LoginRequest>,
-) -> axum::Json<LoginResponse> {
-    // In a real application, you would validate credentials against a database
-    // This is just an example
-    if payload.username == "admin" && payload.password == "password" {
+) -> Result<axum::Json<LoginResponse>, AuthenticationError> {
+    // who logged in, their role and the merchant their tokens are pinned to
+    let login = match accounts.has_users().await {
+        Ok(true) => match accounts.authenticate(&payload.username, &payload.password).await {
+            Ok(user) => user.map(|user| (user.username, user.role, user.merchant_id)),
+            Err(e) => {
+                eprintln!("Login of {} failed: {}", payload.username, e);
+                None
+            }
+        },
+        // the example login is off unless asked for, and only works until payme-admin created the first user
+        Ok(false) if example_login && payload.username == "admin" && payload.password == "password" => {
+            Some(("user123".to_string(), "admin".to_string(), None))
+        }
+        Ok(false) => None,
+        Err(e) => {
+            eprintln!("Login of {} failed: {}", payload.username, e);
+            None
+        }
+    };
+
+    if let Some((subject, role, merchant_id)) = login {
         let token = auth_service
-            .create_token("user123".to_string(), "admin".to_string())
+            .create_token_for(subject.clone(), role.clone(), merchant_id)
             .expect("Failed to create token");
-        
-        axum::Json(LoginResponse { token })
+
+        // the request carried no token yet, the entry is attributed to who just logged in
+        let actor = Actor { subject, role };
+        audit.record(AuditRecord { actor, ..context.record(AuditAction::Login) }).await;
+
+        Ok(axum::Json(LoginResponse { token }))
     } else {
-        panic!("Invalid credentials")
+        audit.record(context.record(AuditAction::LoginFailed).target(&payload.username)).await;
+        Err(AuthenticationError::InvalidCredentials)
     }
 }
 
-async fn protected_route(
+#[utoipa::path(
+    get,
+    path = "/protected",
+    tag = "auth",
+    responses(
+        (status = 200, description = "Who the token belongs to", body = Object, example = json!({"message": "This is a protected route", "user_id": "user123", "role": "admin"})),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    )
+)]
+pub async fn protected_route(
     AuthenticatedUser(claims): AuthenticatedUser,
 ) -> axum::Json<serde_json::Value> {
     axum::Json(json!({
@@ -0,0 +1,71 @@
+#[cfg(test)]
+mod tests {
+    use axum_test::TestServer;
+    use hyper::StatusCode;
+    use serde_json::json;
+
+    use crate::{
+        api::{
+            authentication::{AuthenticationService, LoginResponse},
+            routes::create_router,
+        },
+        core::infrastructure::{
+            accounts::AccountStore,
+            audit::AuditLog,
+            limits::{RateLimitConfig, RateLimiter},
+        },
+    };
+
+    const SECRET: &str = "routes-test-secret-of-32-characters";
+
+    fn server(accounts: AccountStore) -> TestServer {
+        server_with_example_login(accounts, false)
+    }
+
+    fn server_with_example_login(accounts: AccountStore, example_login: bool) -> TestServer {
+        TestServer::new(create_router(
+            AuthenticationService::from_secret(SECRET),
+            accounts,
+            AuditLog::new(),
+            example_login,
+            RateLimiter::new(RateLimitConfig::default()),
+        ))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_users_log_in_with_tokens_pinned_to_their_merchant() {
+        let accounts = AccountStore::new();
+        accounts.create_merchant("merch_1", "Coffee Shop").await.unwrap();
+        accounts.create_user("barista", "correct horse battery", "merchant", Some("merch_1")).await.unwrap();
+        let server = server(accounts);
+
+        let response = server.post("/login").json(&json!({ "username": "barista", "password": "correct horse battery" })).await;
+
+        response.assert_status_ok();
+        let claims = AuthenticationService::from_secret(SECRET).validate_token(&response.json::<LoginResponse>().token).unwrap();
+        assert_eq!((claims.sub.as_str(), claims.role.as_str()), ("barista", "merchant"));
+        assert_eq!(claims.merchant_id.as_deref(), Some("merch_1"));
+    }
+
+    #[tokio::test]
+    async fn test_example_login_stops_working_once_there_are_users() {
+        let accounts = AccountStore::new();
+        let example = json!({ "username": "admin", "password": "password" });
+        server_with_example_login(accounts.clone(), true).post("/login").json(&example).await.assert_status_ok();
+
+        accounts.create_user("ops", "correct horse battery", "admin", None).await.unwrap();
+
+        let response = server_with_example_login(accounts, true).post("/login").json(&example).await;
+        assert_eq!(response.status_code(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_example_login_is_off_unless_configured() {
+        let example = json!({ "username": "admin", "password": "password" });
+
+        let response = server(AccountStore::new()).post("/login").json(&example).await;
+
+        assert_eq!(response.status_code(), StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,133 @@
+use std::{env, sync::Arc};
+
+use clap::{Parser, Subcommand};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use payme::core::{
+    config::{Component, Config, ConfigArgs},
+    infrastructure::{
+        audit::AuditLog,
+        kafka::{KafkaEventLog, KafkaPublisher},
+        publisher::EventPublisher,
+    },
+    models::Actor,
+    services::admin::AdminService,
+};
+
+// how long buffered events get to reach kafka before the command exits
+const FLUSH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Support operations on a running payme, through the same services as the api. Every change
+/// is recorded in audit.admin_log_path.
+#[derive(Debug, Parser)]
+#[command(name = "payme-admin")]
+struct AdminArgs {
+    #[command(flatten)]
+    config: ConfigArgs,
+    /// Who is running the command, for the audit log
+    #[arg(long, env = "USER")]
+    operator: String,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// A transaction as its events have it, with the events
+    Show { transaction_id: Uuid },
+    /// Fail a transaction stuck pending, canceling it at the provider first
+    ForceFail {
+        transaction_id: Uuid,
+        #[arg(long)]
+        reason: String,
+    },
+    /// Refund a completed payment, by default whatever hasn't been refunded yet
+    Refund {
+        transaction_id: Uuid,
+        #[arg(long)]
+        amount: Option<u64>,
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// Publish a transaction's events again for consumers that missed them, except its creation
+    Replay { transaction_id: Uuid },
+    /// Messages consumers couldn't read, most recent last
+    DeadLetters {
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+    },
+    CreateMerchant {
+        id: String,
+        #[arg(long)]
+        name: String,
+    },
+    /// The password is read from PAYME_ADMIN_PASSWORD so it stays out of the shell history
+    CreateUser {
+        username: String,
+        /// merchant, or one of the platform roles: admin, finance, risk, auditor
+        #[arg(long)]
+        role: String,
+        /// Pins the user's tokens to this merchant
+        #[arg(long)]
+        merchant: Option<String>,
+        #[arg(long, env = "PAYME_ADMIN_PASSWORD", hide_env_values = true)]
+        password: String,
+    },
+    /// Prints the key, it can't be shown again
+    CreateApiKey { merchant_id: String },
+    /// Sign tokens with a new key from the api's next start, tokens already issued stay valid
+    RotateJwtKey,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv::dotenv().ok();
+    let args = AdminArgs::parse();
+    let config = Config::from_sources(Component::Admin, &args.config, env::vars())?;
+
+    let publisher: Arc<dyn EventPublisher> = Arc::new(KafkaPublisher::new(&config.kafka.brokers));
+    let events = Arc::new(KafkaEventLog::new(&config.kafka.brokers));
+    let audit = AuditLog::open(&config.audit.admin_log_path)?;
+    let actor = Actor { subject: args.operator, role: "admin".to_string() };
+    let admin = AdminService::new(&config, events, publisher.clone(), audit, actor);
+
+    let output = match args.command {
+        Command::Show { transaction_id } => {
+            let history = admin.history(transaction_id).await?;
+            let events: Vec<Value> = history
+                .events
+                .iter()
+                .map(|event| {
+                    let payload = serde_json::from_str(&event.payload).unwrap_or_else(|_| Value::String(event.payload.clone()));
+                    json!({ "topic": event.topic, "partition": event.partition, "offset": event.offset, "timestamp": event.timestamp, "payload": payload })
+                })
+                .collect();
+            json!({ "transaction": history.transaction, "events": events })
+        }
+        Command::ForceFail { transaction_id, reason } => json!(admin.force_fail(transaction_id, &reason).await?),
+        Command::Refund { transaction_id, amount, reason } => json!(admin.refund(transaction_id, amount, reason).await?),
+        Command::Replay { transaction_id } => {
+            let replayed = admin.replay(transaction_id).await?;
+            json!({ "replayed": replayed.len(), "events": replayed })
+        }
+        Command::DeadLetters { limit } => json!(admin.dead_letters(limit).await?),
+        Command::CreateMerchant { id, name } => json!(admin.create_merchant(&id, &name).await?),
+        Command::CreateUser { username, role, merchant, password } => {
+            let user = admin.create_user(&username, &password, &role, merchant.as_deref()).await?;
+            json!({ "username": user.username, "role": user.role, "merchant_id": user.merchant_id, "created_at": user.created_at })
+        }
+        Command::CreateApiKey { merchant_id } => {
+            let (key, secret) = admin.create_api_key(&merchant_id).await?;
+            json!({ "id": key.id, "merchant_id": key.merchant_id, "prefix": key.prefix, "key": secret })
+        }
+        Command::RotateJwtKey => {
+            let kid = admin.rotate_jwt_key().await?;
+            json!({ "kid": kid, "note": "the api signs with the new key once restarted" })
+        }
+    };
+
+    publisher.flush(FLUSH_TIMEOUT).await?;
+    println!("{}", serde_json::to_string_pretty(&output)?);
+    Ok(())
+}
@@ -0,0 +1,18 @@
+use payme::core::{
+    config::{Component, Config},
+    infrastructure::audit::{read_entries, verify_chain},
+};
+
+/* walks the audit log's hash chain, exits non zero when an entry was altered, dropped or reordered */
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let config = Config::load(Component::AuditVerifier)?;
+
+    let entries = read_entries(&config.audit.log_path)?;
+    let verification = verify_chain(&entries);
+    println!("{}", serde_json::to_string_pretty(&verification)?);
+
+    if !verification.valid {
+        std::process::exit(1);
+    }
+    Ok(())
+}
@@ -1,11 +1,34 @@
-use payme::core::services::payment_processor::PaymentProcessor;
+use payme::core::{
+    config::{Component, Config},
+    infrastructure::{
+        dead_letter::install_dead_letter_queue,
+        health::{Health, KafkaCheck, ProviderCheck},
+        kafka::KafkaPublisher,
+        metrics::serve_metrics,
+        shutdown::Shutdown,
+        telemetry::init_telemetry,
+    },
+    services::payment_processor::PaymentProcessor,
+};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    /* for now setting it here */
-    let kafka_broker = "localhost:9092";
-    let stripe_secret_key = "";
-    let processor = PaymentProcessor::new(kafka_broker , stripe_secret_key);
+    let config = Config::load(Component::PaymentProcessor)?;
+    let telemetry = init_telemetry("payme-payment-processor", &config.telemetry);
+    let shutdown = Shutdown::new();
+    shutdown.trigger_on_signal();
 
-    processor.start().await
-}
\ No newline at end of file
+    // what the consumer can't read is parked on the dead letter topic instead of dropped
+    install_dead_letter_queue(std::sync::Arc::new(KafkaPublisher::new(&config.kafka.brokers)), &config.kafka.topics.dead_letter);
+
+    let processor = PaymentProcessor::new(&config);
+    let health = Health::new(shutdown.clone())
+        .with_check(KafkaCheck::new(&config.kafka.brokers))
+        .with_check(ProviderCheck::new(&config.stripe.secret_key));
+    tokio::spawn(serve_metrics(config.server.processor_metrics_address.clone(), health));
+
+    // a payment being charged when the signal comes is finished and its status published before we stop
+    let result = shutdown.drain(processor.start(shutdown.clone()), config.server.shutdown_grace_period()).await.unwrap_or(Ok(()));
+    telemetry.shutdown();
+    result
+}
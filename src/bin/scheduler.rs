@@ -0,0 +1,79 @@
+use std::sync::Arc;
+
+use payme::core::{
+    config::{Component, Config},
+    infrastructure::{
+        dead_letter::install_dead_letter_queue,
+        health::{Health, KafkaCheck, ProviderCheck},
+        kafka::KafkaPublisher,
+        metrics::serve_metrics,
+        projection::TransactionProjection,
+        publisher::EventPublisher,
+        reporting::Reports,
+        settlement::SettlementStore,
+        shutdown::Shutdown,
+        status_hub::StatusHub,
+        stripe::StripeService,
+        telemetry::init_telemetry,
+    },
+    services::{report_scheduler::ReportScheduler, settlement_service::SettlementService, status_consumer::StatusConsumer, transaction_sweeper::TransactionSweeper},
+};
+
+// how long buffered events get to reach the broker once we are asked to stop
+const PUBLISHER_FLUSH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/* the jobs that must run once for the whole deployment, however many api replicas there are.
+   Run a single replica of it. */
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let config = Config::load(Component::Scheduler)?;
+    let kafka = &config.kafka;
+
+    let telemetry = init_telemetry("payme-scheduler", &config.telemetry);
+    let shutdown = Shutdown::new();
+    shutdown.trigger_on_signal();
+
+    let health = Health::new(shutdown.clone())
+        .with_check(KafkaCheck::new(&kafka.brokers))
+        .with_check(ProviderCheck::new(&config.stripe.secret_key));
+    tokio::spawn(serve_metrics(config.server.scheduler_metrics_address.clone(), health));
+
+    let publisher = Arc::new(KafkaPublisher::new(&kafka.brokers));
+    // what the consumers can't read is parked on the dead letter topic instead of dropped
+    install_dead_letter_queue(publisher.clone(), &kafka.topics.dead_letter);
+
+    // Own groups, so these copies see every partition instead of sharing them with the api replicas
+    let projection = TransactionProjection::new();
+    let settlements = SettlementStore::new(config.settlements.schedule());
+    let status_consumer = StatusConsumer::new(kafka, &kafka.groups.scheduler_projection, projection.clone(), StatusHub::new());
+    let settlement_consumer = SettlementService::new(kafka, &kafka.groups.scheduler_settlement, settlements.clone());
+
+    // Settle payments left pending past the SLA against what stripe has for them
+    let provider = Arc::new(StripeService::new(config.stripe.secret_key.expose(), &config.server.public_url));
+    let sweeper = TransactionSweeper::new(&config.sweeper, &kafka.topics, projection.clone(), provider, publisher.clone());
+
+    // Finance's recurring exports
+    let report_scheduler = ReportScheduler::new(Reports::new(projection, settlements, &config.reports.output_dir), &config.reports.schedules);
+
+    let result = shutdown
+        .drain(
+            async {
+                let (projected, settled, _, _) = tokio::join!(
+                    status_consumer.start(shutdown.clone()),
+                    settlement_consumer.start(shutdown.clone()),
+                    sweeper.run(shutdown.clone()),
+                    report_scheduler.run(shutdown.clone()),
+                );
+                projected.and(settled)
+            },
+            config.server.shutdown_grace_period(),
+        )
+        .await
+        .unwrap_or(Ok(()));
+
+    if let Err(e) = publisher.flush(PUBLISHER_FLUSH_TIMEOUT).await {
+        eprintln!("Events may have been lost on shutdown: {}", e);
+    }
+    telemetry.shutdown();
+    result
+}
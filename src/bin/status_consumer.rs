@@ -1,9 +1,37 @@
-use payme::core::services::status_consumer::StatusConsumer;
+use payme::core::{
+    config::{Component, Config},
+    infrastructure::{
+        dead_letter::install_dead_letter_queue,
+        health::{Health, KafkaCheck},
+        kafka::KafkaPublisher,
+        metrics::serve_metrics,
+        projection::TransactionProjection,
+        shutdown::Shutdown,
+        status_hub::StatusHub,
+        telemetry::init_telemetry,
+    },
+    services::status_consumer::StatusConsumer,
+};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let kafka_broker = "localhost:9092";
-    
-    let consumer = StatusConsumer::new(kafka_broker);
-    consumer.start().await
-}
\ No newline at end of file
+    let config = Config::load(Component::StatusConsumer)?;
+
+    let telemetry = init_telemetry("payme-status-consumer", &config.telemetry);
+    let shutdown = Shutdown::new();
+    shutdown.trigger_on_signal();
+
+    let health = Health::new(shutdown.clone()).with_check(KafkaCheck::new(&config.kafka.brokers));
+    tokio::spawn(serve_metrics(config.server.status_consumer_metrics_address.clone(), health));
+
+    // what the consumer can't read is parked on the dead letter topic instead of dropped
+    install_dead_letter_queue(std::sync::Arc::new(KafkaPublisher::new(&config.kafka.brokers)), &config.kafka.topics.dead_letter);
+
+    let consumer = StatusConsumer::new(&config.kafka, &config.kafka.groups.status_consumer, TransactionProjection::new(), StatusHub::new());
+    let result = shutdown
+        .drain(consumer.start(shutdown.clone()), config.server.shutdown_grace_period())
+        .await
+        .unwrap_or(Ok(()));
+    telemetry.shutdown();
+    result
+}
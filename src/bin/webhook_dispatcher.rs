@@ -0,0 +1,65 @@
+use axum::middleware;
+use payme::api::{
+    authentication::AuthenticationService,
+    middleware::{authenticate, AuthMiddleware},
+};
+use payme::core::{
+    api::webhooks::webhook_routes,
+    config::{Component, Config},
+    infrastructure::{
+        accounts::AccountStore,
+        dead_letter::install_dead_letter_queue,
+        health::{health_routes, Health, KafkaCheck},
+        kafka::KafkaPublisher,
+        shutdown::Shutdown,
+        telemetry::init_telemetry,
+        webhook::{WebhookSender, WebhookStore},
+    },
+    services::webhook_dispatcher::WebhookDispatcher,
+};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let config = Config::load(Component::WebhookDispatcher)?;
+    let telemetry = init_telemetry("payme-webhook-dispatcher", &config.telemetry);
+    let shutdown = Shutdown::new();
+    shutdown.trigger_on_signal();
+
+    // what the consumer can't read is parked on the dead letter topic instead of dropped
+    install_dead_letter_queue(std::sync::Arc::new(KafkaPublisher::new(&config.kafka.brokers)), &config.kafka.topics.dead_letter);
+
+    let store = WebhookStore::from_path(&config.webhooks.store_path)?;
+    let sender = WebhookSender::new(store);
+    let dispatcher = WebhookDispatcher::new(&config.kafka, sender.clone());
+
+    let retries = tokio::spawn(WebhookDispatcher::run_retries(sender.clone(), shutdown.clone()));
+
+    // delivery attempts + manual redelivery are served from the dispatcher itself, for the
+    // same tokens and api keys the api accepts. The probes stay outside the auth layer.
+    let auth_service = AuthenticationService::from_config(&config.auth)?;
+    let accounts = AccountStore::from_path(&config.auth.accounts_path);
+    let authentication = AuthMiddleware::with_service(auth_service).with_api_keys(accounts);
+    let health = Health::new(shutdown.clone()).with_check(KafkaCheck::new(&config.kafka.brokers));
+    let app = axum::Router::new()
+        .nest("/api/v1/webhooks", webhook_routes(sender))
+        .layer(middleware::from_fn_with_state(authentication, authenticate))
+        .merge(health_routes(health));
+    let listener = tokio::net::TcpListener::bind(&config.server.webhook_bind_address).await?;
+    println!("webhook api listening on {}", listener.local_addr()?);
+    let stopping = shutdown.clone();
+    let server = tokio::spawn(async move {
+        axum::serve(listener, app)
+            .with_graceful_shutdown(async move { stopping.wait().await })
+            .await
+            .expect("webhook api server failed");
+    });
+
+    // deliveries under way finish, the api answers what it already accepted
+    let work = async {
+        let (result, _, _) = tokio::join!(dispatcher.start(shutdown.clone()), retries, server);
+        result
+    };
+    let result = shutdown.drain(work, config.server.shutdown_grace_period()).await.unwrap_or(Ok(()));
+    telemetry.shutdown();
+    result
+}
@@ -3,4 +3,6 @@ pub mod models;
 pub mod api;
 pub mod events;
 pub mod infrastructure;
-pub mod services;
\ No newline at end of file
+pub mod services;
+pub mod config;
+pub mod config_test;
\ No newline at end of file
@@ -1,31 +1,114 @@
-use axum::{routing::{get, post}, Router};
-use queries::Query;
+use std::sync::Arc;
+
+use axum::{
+    http::Method,
+    middleware,
+    routing::{get, post, MethodRouter},
+    Router,
+};
+use state::AppState;
+
+use crate::core::{config::Config, infrastructure::kafka::KafkaPublisher};
+pub mod audit;
+pub mod audit_test;
 pub mod commands;
+pub mod connected_accounts;
+pub mod customers;
+pub mod ledger;
+pub mod metrics;
+pub mod openapi;
+pub mod openapi_test;
+pub mod pricing;
 pub mod queries;
+pub mod queries_test;
+pub mod rate_limit;
+pub mod rate_limit_test;
+pub mod reconciliation;
+pub mod reports;
+pub mod reports_test;
+pub mod risk;
+pub mod settlements;
+pub mod state;
+pub mod status_stream;
+pub mod status_stream_test;
+pub mod subscriptions;
+pub mod telemetry;
 pub mod commands_test;
+pub mod webhooks;
+pub mod webhooks_test;
+pub mod stripe_webhooks;
+pub mod stripe_webhooks_test;
 
 
+/* default config, for tests and local runs against a broker on localhost */
 pub async fn create_router() -> Router {
-    Router::new().nest("/api/v1", payments_routes())
+    let config = Config::default();
+    create_router_with(AppState::new(&config, Arc::new(KafkaPublisher::new(&config.kafka.brokers)))).await
+}
+
+/* the stores in the state are shared with the consumers and the subscription scheduler running next to the api */
+pub async fn create_router_with(state: AppState) -> Router {
+    Router::new()
+        .nest("/api/v1", payments_routes(&state))
+        // provider callbacks are authenticated by signature, not by our JWT
+        .nest("/webhooks", stripe_webhooks::stripe_webhook_routes())
+        .route_layer(middleware::from_fn(telemetry::trace_requests))
+        .route_layer(middleware::from_fn(metrics::track_requests))
+        .route("/metrics", get(metrics::render_metrics))
+        .route("/openapi.json", get(openapi::openapi_json))
+        .route("/docs", get(openapi::swagger_ui))
+        .with_state(state)
 }
 
-fn payments_routes() -> Router {
+fn payments_routes(state: &AppState) -> Router<AppState> {
     // this basically divides the api req in 2, which are then consumed by either the commnad service or the query
     Router::new()
-        .nest("/transaction", transaction_routes())
-        .nest("/queries", query_routes())
+        .merge(transaction_routes(state))
+        .merge(routes(query_operations()))
+        .nest("/connected-accounts", connected_accounts::connected_account_routes())
+        .nest("/customers", customers::customer_routes())
+        .nest("/billing", subscriptions::billing_routes())
+        .nest("/ledger", ledger::ledger_routes())
+        .nest("/pricing", pricing::pricing_routes())
+        .nest("/settlements", settlements::settlement_routes())
+        .nest("/risk", risk::risk_routes())
+        .nest("/reconciliation", reconciliation::reconciliation_routes())
+        .nest("/reports", reports::report_routes())
+        .nest("/audit", audit::audit_routes())
 }
 
 
-fn transaction_routes() -> Router {
-    Router::new()
-        .route("/", post(commands::create_transaction))
+/* a route as the openapi document lists it: method, path under /api/v1, handler */
+pub type Operation = (Method, &'static str, MethodRouter<AppState>);
+
+/* every one of these is in openapi.rs, openapi_test checks it */
+pub fn transaction_operations() -> Vec<Operation> {
+    vec![
+        (Method::POST, "/transaction", post(commands::create_transaction)),
+        (Method::POST, "/transaction/:id/refunds", post(commands::create_refund)),
+        (Method::GET, "/transaction/:id/return", get(commands::complete_authentication)),
+    ]
+}
+
+pub fn query_operations() -> Vec<Operation> {
+    vec![
+        (Method::GET, "/transactions", get(queries::list_transactions)),
+        (Method::GET, "/transactions/:id/events", get(status_stream::stream_events)),
+        (Method::GET, "/transactions/:id/ws", get(status_stream::stream_websocket)),
+        (Method::GET, "/queries/status/:id", get(queries::get_payment_status)),
+        (Method::GET, "/queries/reports/fees", get(queries::fee_report)),
+    ]
 }
 
+fn routes(operations: Vec<Operation>) -> Router<AppState> {
+    operations
+        .into_iter()
+        .fold(Router::new(), |router, (_, path, handler)| router.route(path, handler))
+}
+
+fn transaction_routes(state: &AppState) -> Router<AppState> {
+    routes(transaction_operations())
+        .route_layer(middleware::from_fn_with_state(state.clone(), rate_limit::enforce_rate_limit))
+}
 
 
-fn query_routes() -> Router {
-    let query = Query::new("localhost:9092");
-    // need to figure out a way to dal with the handler taking a reference
-    Router::new().route("/status/:id", todo!("need to get this done"))
-}
\ No newline at end of file
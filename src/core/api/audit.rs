@@ -0,0 +1,150 @@
+use std::{convert::Infallible, net::SocketAddr};
+
+use axum::{
+    extract::{ConnectInfo, FromRef, FromRequestParts, Query, State},
+    http::request::Parts,
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use hyper::StatusCode;
+use serde::Deserialize;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::{
+    api::{authentication::{Claims, AUDITOR_ROLE}, middleware::AuthenticatedUser},
+    core::{
+        api::{rate_limit::client_ip, state::AppState},
+        infrastructure::{
+            audit::{AuditFilter, AuditLog, AuditRecord, AuditVerification},
+            limits::RateLimiter,
+        },
+        models::{Actor, AuditAction, AuditEntry},
+    },
+};
+
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+/*request payload types*/
+#[derive(Deserialize)]
+pub struct AuditQuery {
+    pub actor: Option<String>,
+    pub action: Option<AuditAction>,
+    pub target: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Error)]
+pub enum AuditApiError {
+    #[error("Only auditors can read the audit log")]
+    Forbidden,
+}
+
+/// Who is behind a request and where it came from, for the audit entries it leads to.
+pub struct AuditContext {
+    pub actor: Actor,
+    pub request_id: String,
+    pub ip_address: Option<String>,
+}
+
+impl AuditContext {
+    pub fn record(&self, action: AuditAction) -> AuditRecord {
+        AuditRecord::new(self.actor.clone(), action, self.request_id.clone(), self.ip_address.clone())
+    }
+}
+
+#[axum::async_trait]
+impl<S> FromRequestParts<S> for AuditContext
+where
+    S: Send + Sync,
+    RateLimiter: FromRef<S>,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let actor = parts
+            .extensions
+            .get::<Claims>()
+            .map(|claims| Actor { subject: claims.sub.clone(), role: claims.role.clone() })
+            .unwrap_or_else(Actor::anonymous);
+
+        let request_id = parts
+            .headers
+            .get("x-request-id")
+            .and_then(|h| h.to_str().ok())
+            .map(str::to_string)
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+        // forwarded hops only count when they were added by one of our proxies, same as for rate limits
+        let peer = parts.extensions.get::<ConnectInfo<SocketAddr>>().map(|info| info.0.ip());
+        let forwarded_for = parts.headers.get("x-forwarded-for").and_then(|h| h.to_str().ok());
+        let ip_address = client_ip(peer, forwarded_for, RateLimiter::from_ref(state).trusted_proxies()).map(|ip| ip.to_string());
+
+        Ok(Self { actor, request_id, ip_address })
+    }
+}
+
+pub fn audit_routes() -> Router<AppState> {
+    Router::new()
+        .route("/", get(search_audit_log))
+        .route("/verify", get(verify_audit_log))
+}
+
+fn require_auditor(claims: &Claims) -> Result<(), AuditApiError> {
+    if claims.role == AUDITOR_ROLE {
+        Ok(())
+    } else {
+        Err(AuditApiError::Forbidden)
+    }
+}
+
+/* who did what, most recent first */
+async fn search_audit_log(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(audit): State<AuditLog>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<Vec<AuditEntry>>, AuditApiError> {
+    require_auditor(&claims)?;
+
+    let filter = AuditFilter {
+        actor: query.actor,
+        action: query.action,
+        target: query.target,
+        from: query.from,
+        to: query.to,
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+
+    Ok(Json(audit.search(&filter, limit).await))
+}
+
+async fn verify_audit_log(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(audit): State<AuditLog>,
+) -> Result<impl IntoResponse, AuditApiError> {
+    require_auditor(&claims)?;
+
+    let verification: AuditVerification = audit.verify().await;
+    let status = if verification.valid { StatusCode::OK } else { StatusCode::CONFLICT };
+
+    Ok((status, Json(verification)))
+}
+
+impl IntoResponse for AuditApiError {
+    fn into_response(self) -> axum::response::Response {
+        let status = match self {
+            AuditApiError::Forbidden => StatusCode::FORBIDDEN,
+        };
+
+        let body = Json(serde_json::json!({
+            "error": self.to_string()
+        }));
+
+        (status, body).into_response()
+    }
+}
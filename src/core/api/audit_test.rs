@@ -0,0 +1,116 @@
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, sync::Arc};
+
+    use axum::{extract::ConnectInfo, http::{HeaderName, HeaderValue}, Extension};
+    use axum_test::TestServer;
+    use hyper::StatusCode;
+    use serde_json::{json, Value};
+
+    use crate::{
+        api::authentication::Claims,
+        core::{
+            api::{create_router_with, state::AppState},
+            config::Config,
+            infrastructure::publisher::InMemoryPublisher,
+        },
+    };
+
+    fn claims(sub: &str, role: &str) -> Claims {
+        Claims { sub: sub.to_string(), exp: i64::MAX, role: role.to_string(), merchant_id: None }
+    }
+
+    /* the auth middleware runs in main, here the claims are handed to every request directly */
+    async fn server_as(state: AppState, claims: Claims) -> TestServer {
+        TestServer::new(create_router_with(state).await.layer(Extension(claims))).unwrap()
+    }
+
+    fn state() -> AppState {
+        AppState::new(&Config::default(), Arc::new(InMemoryPublisher::new()))
+    }
+
+    #[tokio::test]
+    async fn test_actions_are_attributed_to_the_caller() {
+        let mut config = Config::default();
+        config.rate_limits.trusted_proxies = vec!["10.0.0.1".parse().unwrap()];
+        let state = AppState::new(&config, Arc::new(InMemoryPublisher::new()));
+        // connected through the load balancer, which appends the hop it received the request from
+        let balancer = ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 443)));
+        let merchant = TestServer::new(
+            create_router_with(state.clone())
+                .await
+                .layer(Extension(claims("merchant_user_1", "merchant")))
+                .layer(Extension(balancer)),
+        )
+        .unwrap();
+        merchant
+            .post("/api/v1/transaction")
+            .add_header(HeaderName::from_static("x-request-id"), HeaderValue::from_static("req_audit_1"))
+            .add_header(HeaderName::from_static("x-forwarded-for"), HeaderValue::from_static("203.0.113.7, 10.0.0.1"))
+            .json(&json!({
+                "amount": 1000,
+                "currency": "USD",
+                "merchant_id": "merch_audit",
+                "customer_id": "cust_audit",
+                "idempotency_key": "audit_key_1"
+            }))
+            .await
+            .assert_status_ok();
+
+        let auditor = server_as(state, claims("auditor_1", "auditor")).await;
+        let response = auditor.get("/api/v1/audit").add_query_param("action", "transaction_created").await;
+
+        response.assert_status_ok();
+        let entries: Vec<Value> = response.json();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["actor"], json!({ "subject": "merchant_user_1", "role": "merchant" }));
+        assert_eq!(entries[0]["request_id"], "req_audit_1");
+        assert_eq!(entries[0]["ip_address"], "203.0.113.7");
+        assert_eq!(entries[0]["after"]["merchant_id"], "merch_audit");
+
+        let verification = auditor.get("/api/v1/audit/verify").await;
+        verification.assert_status_ok();
+        assert_eq!(verification.json::<Value>()["valid"], true);
+    }
+
+    #[tokio::test]
+    async fn test_only_auditors_can_read_the_log() {
+        let state = state();
+
+        let admin = server_as(state.clone(), claims("admin_1", "admin")).await;
+        admin.get("/api/v1/audit").await.assert_status(StatusCode::FORBIDDEN);
+        admin.get("/api/v1/audit/verify").await.assert_status(StatusCode::FORBIDDEN);
+
+        let anonymous = TestServer::new(create_router_with(state).await).unwrap();
+        anonymous.get("/api/v1/audit").await.assert_status(StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_forwarded_for_from_an_untrusted_peer_is_not_recorded() {
+        let state = state();
+        let client = ConnectInfo(SocketAddr::from(([198, 51, 100, 4], 50000)));
+        let merchant = TestServer::new(
+            create_router_with(state.clone())
+                .await
+                .layer(Extension(claims("merchant_user_1", "merchant")))
+                .layer(Extension(client)),
+        )
+        .unwrap();
+        merchant
+            .post("/api/v1/transaction")
+            .add_header(HeaderName::from_static("x-forwarded-for"), HeaderValue::from_static("203.0.113.7"))
+            .json(&json!({
+                "amount": 1000,
+                "currency": "USD",
+                "merchant_id": "merch_audit",
+                "customer_id": "cust_audit",
+                "idempotency_key": "audit_key_2"
+            }))
+            .await
+            .assert_status_ok();
+
+        let auditor = server_as(state, claims("auditor_1", "auditor")).await;
+        let entries: Vec<Value> = auditor.get("/api/v1/audit").add_query_param("action", "transaction_created").await.json();
+        assert_eq!(entries[0]["ip_address"], "198.51.100.4");
+    }
+}
@@ -1,36 +1,250 @@
+use std::{collections::HashMap, sync::Arc};
+
 use axum::{
-    body::{to_bytes, Body, Bytes}, extract::Request, http::header, Json
+    body::{to_bytes, Body, Bytes}, extract::{FromRef, Path, Query, Request, State}, http::header, response::IntoResponse, Json
 };
+use hyper::StatusCode;
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 use axum_extra::{
     headers::{Header, HeaderName},
     TypedHeader,
 };
 
-use crate::core::{infrastructure::kafka::KafkaProducer, models::IdempotencyKey};
-use crate::core::models::TransactionStatus;
-use crate::core::events::TransactionCreatedEvent;
+use crate::api::{authentication::FINANCE_ROLE, middleware::AuthenticatedUser};
+use crate::core::{api::{audit::AuditContext, openapi::ErrorResponse, state::AppState}, config::Topics, infrastructure::{audit::AuditLog, customers::CustomerStore, limits::{VelocityError, VelocityLimiter}, publisher::EventPublisher}, models::IdempotencyKey};
+use crate::core::infrastructure::{
+    projection::TransactionProjection,
+    splits::{allocate_splits, reverse_proportionally, transfers_to_reverse, ConnectedAccountStore, SplitShare},
+    stripe::{status_from_intent, StripeService},
+};
+use crate::core::models::{AuditAction, RiskContext, SplitLeg, SplitReversal, Transaction, TransactionStatus};
+use crate::core::events::{PaymentStatusUpdatedEvent, RefundCreatedEvent, TransactionCreatedEvent};
 
 /*request payload types - this is from the user*/
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct CreateTransactionRequest {
     amount: u64,
     currency: String,
     merchant_id: String,
     customer_id: String,
     idempotency_key: String,  // Client-provided idempotency key
+    #[serde(default)]
+    metadata: HashMap<String, String>,
+    /// A method saved on the customer, their default one is used when this is left out.
+    #[serde(default)]
+    payment_method_id: Option<String>,
+    /// Marketplace payments, shares of the charge for each seller.
+    #[serde(default)]
+    splits: Vec<SplitRequest>,
+    /// Buyer details the risk engine scores the payment on.
+    #[serde(default)]
+    ip_address: Option<String>,
+    #[serde(default)]
+    billing_country: Option<String>,
+    #[serde(default)]
+    card_country: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct SplitRequest {
+    destination_merchant_id: String,
+    /// Exactly one of `amount` or `percentage_bps`.
+    amount: Option<u64>,
+    percentage_bps: Option<u32>,
+    #[serde(default)]
+    application_fee: u64,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateRefundRequest {
+    /// Defaults to whatever hasn't been refunded yet.
+    amount: Option<u64>,
+    reason: Option<String>,
 }
 
+// stripe allows 50 keys, a few of those are taken by the keys we set ourselves
+pub const MAX_METADATA_KEYS: usize = 20;
+pub const MAX_METADATA_KEY_LEN: usize = 40;
+pub const MAX_METADATA_VALUE_LEN: usize = 500;
+pub const RESERVED_METADATA_KEYS: [&str; 3] = ["transaction_id", "merchant_id", "customer_id"];
+
 /*response payload types*/
 
-#[derive(Serialize,Deserialize)]
+#[derive(Serialize,Deserialize, ToSchema)]
 pub struct CreateTransactionResponse {
     pub id : Uuid,
     pub status: TransactionStatus
 }
 
-pub async fn create_transaction(request: Request<Body>) -> Json<CreateTransactionResponse> {
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RefundResponse {
+    pub refund_id: Uuid,
+    pub transaction_id: Uuid,
+    pub amount: u64,
+    pub reversals: Vec<SplitReversal>,
+}
+
+/* query string stripe appends when redirecting the customer back after authentication */
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuthenticationReturnParams {
+    pub payment_intent: String,
+}
+
+#[derive(Debug, Error)]
+pub enum CommandError {
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("Payment provider error: {0}")]
+    Provider(String),
+    #[error("Failed to publish event: {0}")]
+    Publish(String),
+    #[error("Limit exceeded: {0}")]
+    LimitExceeded(String),
+    #[error("This token can't act for merchant {0}")]
+    Forbidden(String),
+    #[error("Refunds with a platform token are for the finance team")]
+    FinanceOnly,
+}
+
+#[derive(Clone)]
+pub struct TransactionCommandState {
+    pub customers: CustomerStore,
+    pub connected_accounts: ConnectedAccountStore,
+    pub velocity: VelocityLimiter,
+    pub publisher: Arc<dyn EventPublisher>,
+    pub topics: Arc<Topics>,
+}
+
+#[derive(Clone)]
+pub struct RefundState {
+    pub stripe_service: Arc<StripeService>,
+    pub projection: TransactionProjection,
+    pub publisher: Arc<dyn EventPublisher>,
+    pub topics: Arc<Topics>,
+}
+
+#[derive(Clone)]
+pub struct AuthenticationReturnState {
+    pub stripe_service: Arc<StripeService>,
+    pub publisher: Arc<dyn EventPublisher>,
+    pub topics: Arc<Topics>,
+}
+
+impl FromRef<AppState> for TransactionCommandState {
+    fn from_ref(state: &AppState) -> Self {
+        Self {
+            customers: state.customers.clone(),
+            connected_accounts: state.connected_accounts.clone(),
+            velocity: state.velocity.clone(),
+            publisher: state.publisher.clone(),
+            topics: state.topics.clone(),
+        }
+    }
+}
+
+impl RefundState {
+    /// Refunds at the provider and publishes the refund, `amount` defaults to what hasn't been
+    /// refunded yet. Sellers of a split payment give their part back afterwards, a reversal that
+    /// fails doesn't undo the refund. Returns the transaction as it was before, for the audit trail.
+    pub async fn refund(&self, transaction_id: Uuid, amount: Option<u64>, reason: Option<String>) -> Result<(Transaction, RefundCreatedEvent), CommandError> {
+        let transaction = self.projection
+            .get(transaction_id)
+            .await
+            .ok_or_else(|| CommandError::InvalidRequest(format!("unknown transaction {}", transaction_id)))?;
+
+        let (TransactionStatus::Completed, Some(provider_payment_id)) = (&transaction.status, &transaction.provider_payment_id) else {
+            return Err(CommandError::InvalidRequest("only completed payments can be refunded".to_string()));
+        };
+
+        let refundable = (transaction.amount - transaction.amount_refunded).max(0) as u64;
+        let amount = amount.unwrap_or(refundable);
+        if amount == 0 || amount > refundable {
+            return Err(CommandError::InvalidRequest(format!("refund must be between 1 and {}", refundable)));
+        }
+
+        let reversals = reverse_proportionally(&transaction.splits, transaction.amount as u64, amount);
+        // checked before the customer gets their money, not after
+        let transfers = transfers_to_reverse(&transaction.splits, &reversals).map_err(CommandError::InvalidRequest)?;
+
+        self.stripe_service
+            .refund_payment(provider_payment_id, transaction_id, amount)
+            .await
+            .map_err(|e| CommandError::Provider(e.to_string()))?;
+
+        let event = RefundCreatedEvent::new(
+            transaction_id,
+            transaction.merchant_id.clone(),
+            amount,
+            format!("{:?}", transaction.currency),
+            reason,
+        ).with_reversals(reversals);
+
+        self.publisher
+            .publish_event(&self.topics.refunds, &transaction_id.to_string(), &event)
+            .await
+            .map_err(CommandError::Publish)?;
+
+        for (transfer_id, reversal) in transfers {
+            if let Err(e) = self.stripe_service.reverse_transfer(&transfer_id, reversal, event.refund_id).await {
+                // counted with the other failed provider calls, the idempotency key makes it safe to retry
+                eprintln!("Failed to reverse {} of transfer {} for refund {}: {}", reversal, transfer_id, event.refund_id, e);
+            }
+        }
+
+        Ok((transaction, event))
+    }
+}
+
+impl FromRef<AppState> for RefundState {
+    fn from_ref(state: &AppState) -> Self {
+        Self {
+            stripe_service: state.stripe_service.clone(),
+            projection: state.projection.clone(),
+            publisher: state.publisher.clone(),
+            topics: state.topics.clone(),
+        }
+    }
+}
+
+impl FromRef<AppState> for AuthenticationReturnState {
+    fn from_ref(state: &AppState) -> Self {
+        Self {
+            stripe_service: state.stripe_service.clone(),
+            publisher: state.publisher.clone(),
+            topics: state.topics.clone(),
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/transaction",
+    tag = "commands",
+    params(
+        ("x-idempotency-key" = Option<String>, Header, description = "Retries with the same key get the first response back instead of a second payment"),
+    ),
+    request_body = CreateTransactionRequest,
+    responses(
+        (status = 200, description = "Payment accepted, it settles asynchronously", body = CreateTransactionResponse),
+        (status = 400, description = "Malformed body or metadata", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Another merchant's payment", body = ErrorResponse),
+        (status = 422, description = "Velocity limit exceeded", body = ErrorResponse),
+        (status = 429, description = "Rate limited", body = ErrorResponse),
+        (status = 500, description = "The payment couldn't be queued, nothing was charged", body = ErrorResponse),
+    )
+)]
+pub async fn create_transaction(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<TransactionCommandState>,
+    State(audit): State<AuditLog>,
+    context: AuditContext,
+    request: Request<Body>,
+) -> Result<Json<CreateTransactionResponse>, CommandError> {
     // Extract idempotency key from headers FIRST
     let headers = request.headers().clone();
     let idempotency_key = headers
@@ -40,32 +254,69 @@ pub async fn create_transaction(request: Request<Body>) -> Json<CreateTransactio
 
     // Check idempotency
     if let Some(cached_response) = check_idempotency_key(idempotency_key).await {
-        return Json(cached_response);
+        return Ok(Json(cached_response));
     }
 
     // NOW we can consume the body
     let body = request.into_body();
-    let body_bytes = to_bytes(body, usize::MAX).await.expect("Failed to parse the body in bytes");
+    let body_bytes = to_bytes(body, usize::MAX).await
+        .map_err(|e| CommandError::InvalidRequest(e.to_string()))?;
 
     
     let req_payload: CreateTransactionRequest = serde_json::from_slice(&body_bytes)
-        .expect("Failed to parse request");
+        .map_err(|e| CommandError::InvalidRequest(e.to_string()))?;
+
+    if !claims.may_act_for(&req_payload.merchant_id) {
+        return Err(CommandError::Forbidden(req_payload.merchant_id));
+    }
+
+    validate_metadata(&req_payload.metadata)?;
+
+    let payment_method = resolve_payment_method(&state.customers, &req_payload).await?;
+    let splits = resolve_splits(&state.connected_accounts, &req_payload).await?;
+
+    // server side integrations pass the buyer's ip along, otherwise it's whoever called us, as
+    // seen through our trusted proxies
+    let risk_context = RiskContext {
+        ip_address: req_payload.ip_address.clone().or_else(|| context.ip_address.clone()),
+        billing_country: req_payload.billing_country.clone(),
+        card_country: req_payload.card_country.clone(),
+    };
+
+    let now = chrono::Utc::now();
+    state.velocity
+        .reserve(&req_payload.merchant_id, &req_payload.customer_id, &req_payload.currency, req_payload.amount, now)
+        .await
+        .map_err(|e| match e {
+            VelocityError::Exceeded(remaining) => CommandError::LimitExceeded(format!(
+                "customer {} can be charged at most {} more {} today",
+                req_payload.customer_id, remaining, req_payload.currency
+            )),
+            VelocityError::InvalidAmount => CommandError::InvalidRequest(e.to_string()),
+        })?;
 
     let transaction_id = Uuid::new_v4();
-    let event = TransactionCreatedEvent::new(
+    let mut event = TransactionCreatedEvent::new(
         transaction_id,
         req_payload.amount,
         req_payload.currency,
         req_payload.merchant_id,
         req_payload.customer_id,
-    );
+    ).with_metadata(req_payload.metadata)
+    .with_splits(splits)
+    .with_risk_context(risk_context);
 
+    if let Some((provider_customer_id, payment_method_id)) = payment_method {
+        event = event.with_payment_method(provider_customer_id, payment_method_id);
+    }
 
-    let producer = KafkaProducer::new("localhost:9092", "transactions");
 
-    if let Err(e) = producer.publish_event(&event).await {
-        eprintln!("Failed to publish event to topic: {}", e);
+    // nothing is charged without the event, so the caller is told and a retry with the same key starts over
+    if let Err(e) = state.publisher.publish_event(&state.topics.transactions, &transaction_id.to_string(), &event).await {
+        state.velocity.release(&event.merchant_id, &event.customer_id, &event.currency, event.amount, now).await;
+        return Err(CommandError::Publish(e));
     }
+    audit.record(context.record(AuditAction::TransactionCreated).target(transaction_id).after(&event)).await;
 
     let response = CreateTransactionResponse {
         id: transaction_id,
@@ -74,7 +325,185 @@ pub async fn create_transaction(request: Request<Body>) -> Json<CreateTransactio
 
     cache_response(idempotency_key, &response).await;
 
-    Json(response)
+    Ok(Json(response))
+}
+
+/// Works out which saved method (and the provider customer it's attached to) to charge,
+/// `None` when the customer isn't managed by us and the client will confirm the intent itself.
+async fn resolve_payment_method(
+    customers: &CustomerStore,
+    request: &CreateTransactionRequest,
+) -> Result<Option<(String, String)>, CommandError> {
+    let Some(customer) = customers.get(&request.merchant_id, &request.customer_id).await else {
+        return match &request.payment_method_id {
+            Some(_) => Err(CommandError::InvalidRequest(format!("unknown customer {}", request.customer_id))),
+            None => Ok(None),
+        };
+    };
+
+    let payment_method_id = match &request.payment_method_id {
+        Some(id) if customer.has_payment_method(id) => id.clone(),
+        Some(id) => return Err(CommandError::InvalidRequest(format!("payment method {} is not attached to the customer", id))),
+        None => match customer.default_payment_method_id {
+            Some(id) => id,
+            None => return Ok(None),
+        },
+    };
+
+    Ok(Some((customer.provider_customer_id, payment_method_id)))
+}
+
+/// Turns the requested shares into legs paid to the sellers' connected accounts.
+async fn resolve_splits(
+    connected_accounts: &ConnectedAccountStore,
+    request: &CreateTransactionRequest,
+) -> Result<Vec<SplitLeg>, CommandError> {
+    if request.splits.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let shares: Vec<SplitShare> = request.splits.iter().map(|split| SplitShare {
+        destination_merchant_id: split.destination_merchant_id.clone(),
+        amount: split.amount,
+        percentage_bps: split.percentage_bps,
+        application_fee: split.application_fee,
+    }).collect();
+
+    let amounts = allocate_splits(request.amount, &shares).map_err(CommandError::InvalidRequest)?;
+
+    let mut legs = Vec::with_capacity(shares.len());
+    for (share, amount) in shares.into_iter().zip(amounts) {
+        let account = connected_accounts
+            .get(&share.destination_merchant_id)
+            .await
+            .ok_or_else(|| CommandError::InvalidRequest(format!("{} has no connected account", share.destination_merchant_id)))?;
+
+        legs.push(SplitLeg {
+            destination_merchant_id: share.destination_merchant_id,
+            provider_account_id: account.provider_account_id,
+            amount,
+            application_fee: share.application_fee,
+            transfer_id: None,
+        });
+    }
+
+    Ok(legs)
+}
+
+fn validate_metadata(metadata: &HashMap<String, String>) -> Result<(), CommandError> {
+    if metadata.len() > MAX_METADATA_KEYS {
+        return Err(CommandError::InvalidRequest(format!("metadata can have at most {} keys", MAX_METADATA_KEYS)));
+    }
+
+    for (key, value) in metadata {
+        if key.is_empty() || key.len() > MAX_METADATA_KEY_LEN {
+            return Err(CommandError::InvalidRequest(format!("metadata keys must be 1 to {} characters", MAX_METADATA_KEY_LEN)));
+        }
+        if value.len() > MAX_METADATA_VALUE_LEN {
+            return Err(CommandError::InvalidRequest(format!("metadata value for {} exceeds {} characters", key, MAX_METADATA_VALUE_LEN)));
+        }
+        if RESERVED_METADATA_KEYS.contains(&key.as_str()) {
+            return Err(CommandError::InvalidRequest(format!("metadata key {} is reserved", key)));
+        }
+    }
+
+    Ok(())
+}
+
+/// Landing point of the 3DS redirect, re-checks the intent with stripe and
+/// publishes whatever state it ended up in. The customer's browser comes back without
+/// a token, the intent's metadata is what ties the call to the payment.
+#[utoipa::path(
+    get,
+    path = "/api/v1/transaction/{id}/return",
+    tag = "commands",
+    params(("id" = Uuid, Path, description = "Transaction id"), AuthenticationReturnParams),
+    responses(
+        (status = 200, description = "Status after authentication", body = CreateTransactionResponse),
+        (status = 400, description = "The intent belongs to another transaction", body = ErrorResponse),
+        (status = 429, description = "Rate limited", body = ErrorResponse),
+        (status = 500, description = "Status could not be published", body = ErrorResponse),
+        (status = 502, description = "Provider error", body = ErrorResponse),
+    ),
+    // the customer's browser has no token
+    security(())
+)]
+pub async fn complete_authentication(
+    State(state): State<AuthenticationReturnState>,
+    Path(transaction_id): Path<Uuid>,
+    Query(params): Query<AuthenticationReturnParams>,
+) -> Result<Json<CreateTransactionResponse>, CommandError> {
+    let intent = state.stripe_service
+        .reconfirm_intent(&params.payment_intent, transaction_id)
+        .await
+        .map_err(|e| CommandError::Provider(e.to_string()))?;
+
+    // the redirect is customer controlled, make sure the intent is really ours
+    if intent.metadata.get("transaction_id") != Some(&transaction_id.to_string()) {
+        return Err(CommandError::InvalidRequest("payment intent does not belong to this transaction".to_string()));
+    }
+
+    let status = status_from_intent(&intent);
+    let merchant_id = intent.metadata.get("merchant_id").cloned().unwrap_or_default();
+    let event = PaymentStatusUpdatedEvent::new(transaction_id, merchant_id, status.clone(), intent.id.to_string())
+        .with_client_secret(intent.client_secret.clone());
+
+    state.publisher
+        .publish_event(&state.topics.payment_status, &transaction_id.to_string(), &event)
+        .await
+        .map_err(CommandError::Publish)?;
+
+    Ok(Json(CreateTransactionResponse {
+        id: transaction_id,
+        status,
+    }))
+}
+
+/// Refunds a completed payment, split payments are reversed from every seller in proportion.
+#[utoipa::path(
+    post,
+    path = "/api/v1/transaction/{id}/refunds",
+    tag = "commands",
+    params(("id" = Uuid, Path, description = "Transaction id")),
+    request_body = CreateRefundRequest,
+    responses(
+        (status = 200, description = "Refund issued", body = RefundResponse),
+        (status = 400, description = "Unknown or not refundable transaction, or amount out of range", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Another merchant's payment, or a platform token outside the finance team", body = ErrorResponse),
+        (status = 429, description = "Rate limited", body = ErrorResponse),
+        (status = 500, description = "Refund could not be published", body = ErrorResponse),
+        (status = 502, description = "Provider error", body = ErrorResponse),
+    )
+)]
+pub async fn create_refund(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<RefundState>,
+    State(audit): State<AuditLog>,
+    context: AuditContext,
+    Path(transaction_id): Path<Uuid>,
+    Json(payload): Json<CreateRefundRequest>,
+) -> Result<Json<RefundResponse>, CommandError> {
+    // platform tokens refund any merchant's payments, so only finance gets to, like the ledger
+    if claims.merchant_id.is_none() && !claims.is_operator(&[FINANCE_ROLE]) {
+        return Err(CommandError::FinanceOnly);
+    }
+    if let Some(transaction) = state.projection.get(transaction_id).await {
+        if !claims.may_act_for(&transaction.merchant_id) {
+            return Err(CommandError::Forbidden(transaction.merchant_id));
+        }
+    }
+
+    let (transaction, event) = state.refund(transaction_id, payload.amount, payload.reason).await?;
+
+    audit.record(context.record(AuditAction::RefundCreated).target(transaction_id).before(&transaction).after(&event)).await;
+
+    Ok(Json(RefundResponse {
+        refund_id: event.refund_id,
+        transaction_id,
+        amount: event.amount,
+        reversals: event.reversals,
+    }))
 }
 
 async fn check_idempotency_key(key: &str) -> Option<CreateTransactionResponse> {
@@ -85,4 +514,22 @@ async fn check_idempotency_key(key: &str) -> Option<CreateTransactionResponse> {
 
 async fn cache_response(key: &str, response: &CreateTransactionResponse) {
     // In production, store in Redis/database with TTL
-}
\ No newline at end of file
+}
+
+impl IntoResponse for CommandError {
+    fn into_response(self) -> axum::response::Response {
+        let status = match self {
+            CommandError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            CommandError::Provider(_) => StatusCode::BAD_GATEWAY,
+            CommandError::Publish(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            CommandError::LimitExceeded(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            CommandError::Forbidden(_) | CommandError::FinanceOnly => StatusCode::FORBIDDEN,
+        };
+
+        let body = Json(serde_json::json!({
+            "error": self.to_string()
+        }));
+
+        (status, body).into_response()
+    }
+}
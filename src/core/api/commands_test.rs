@@ -1,17 +1,47 @@
 #[cfg(test)]
 mod tests {
-    use axum::http::{HeaderName, HeaderValue};
+    use axum::{http::{HeaderName, HeaderValue}, Extension, Router};
     use axum_test::TestServer;
     use hyper::StatusCode;
     use serde_json::json;
 
     use super::*;
-    use crate::core::{api::{commands::CreateTransactionResponse, create_router}, models::TransactionStatus};
+    use std::sync::Arc;
+
+    use crate::api::authentication::Claims;
+    use crate::core::{
+        api::{commands::{CreateTransactionResponse, MAX_METADATA_KEYS}, create_router_with, state::AppState},
+        config::Config,
+        infrastructure::publisher::{EventPublisher, InMemoryPublisher},
+        models::TransactionStatus,
+    };
+
+    fn merchant_user(merchant_id: &str) -> Claims {
+        Claims { sub: "merchant_user".to_string(), exp: i64::MAX, role: "merchant".to_string(), merchant_id: Some(merchant_id.to_string()) }
+    }
+
+    // the api on a publisher that needs no broker
+    async fn app() -> Router {
+        create_router_with(AppState::new(&Config::default(), Arc::new(InMemoryPublisher::new()))).await
+    }
+
+    struct BrokerDown;
+
+    #[axum::async_trait]
+    impl EventPublisher for BrokerDown {
+        async fn publish(&self, _topic: &str, _key: &str, _payload: String) -> Result<(), String> {
+            Err("broker unreachable".to_string())
+        }
+    }
+
+    fn as_merchant(app: Router) -> TestServer {
+        TestServer::new(app.layer(Extension(merchant_user("merch_123")))).unwrap()
+    }
 
     #[tokio::test]
     async fn test_create_transaction_success() {
-        let app = create_router().await;
-        let server = TestServer::new(app).unwrap();
+        let app = app().await;
+        let server = as_merchant(app);
 
         let request_body = json!({
             "amount": 1000,
@@ -36,8 +66,8 @@ mod tests {
 
     #[tokio::test]
     async fn test_create_transaction_idempotency() {
-        let app = create_router().await;
-        let server = TestServer::new(app).unwrap();
+        let app = app().await;
+        let server = as_merchant(app);
 
         let request_body = json!({
             "amount": 1000,
@@ -70,8 +100,8 @@ mod tests {
 
     #[tokio::test]
     async fn test_create_transaction_missing_idempotency() {
-        let app = create_router().await;
-        let server = TestServer::new(app).unwrap();
+        let app = app().await;
+        let server = as_merchant(app);
 
         let request_body = json!({
             "amount": 1000,
@@ -87,4 +117,251 @@ mod tests {
 
         response.assert_status(StatusCode::BAD_REQUEST);
     }
-} 
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_create_transaction_rejects_reserved_metadata_key() {
+        let app = app().await;
+        let server = as_merchant(app);
+
+        let request_body = json!({
+            "amount": 1000,
+            "currency": "USD",
+            "merchant_id": "merch_123",
+            "customer_id": "cust_123",
+            "idempotency_key": "test_key_3",
+            "metadata": { "transaction_id": "spoofed" }
+        });
+
+        let response = server
+            .post("/api/v1/transaction")
+            .add_header(HeaderName::from_static("x-idempotency-key"), HeaderValue::from_static("test_key_3"))
+            .json(&request_body)
+            .await;
+
+        response.assert_status(StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_create_transaction_rejects_too_many_metadata_keys() {
+        let app = app().await;
+        let server = as_merchant(app);
+
+        let metadata: serde_json::Map<String, serde_json::Value> = (0..=MAX_METADATA_KEYS)
+            .map(|i| (format!("key_{}", i), json!("value")))
+            .collect();
+
+        let request_body = json!({
+            "amount": 1000,
+            "currency": "USD",
+            "merchant_id": "merch_123",
+            "customer_id": "cust_123",
+            "idempotency_key": "test_key_4",
+            "metadata": metadata
+        });
+
+        let response = server
+            .post("/api/v1/transaction")
+            .add_header(HeaderName::from_static("x-idempotency-key"), HeaderValue::from_static("test_key_4"))
+            .json(&request_body)
+            .await;
+
+        response.assert_status(StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_create_transaction_rejects_payment_method_for_unknown_customer() {
+        let app = app().await;
+        let server = as_merchant(app);
+
+        let request_body = json!({
+            "amount": 1000,
+            "currency": "USD",
+            "merchant_id": "merch_123",
+            "customer_id": "cust_unknown",
+            "idempotency_key": "test_key_5",
+            "payment_method_id": "pm_card_visa"
+        });
+
+        let response = server
+            .post("/api/v1/transaction")
+            .add_header(HeaderName::from_static("x-idempotency-key"), HeaderValue::from_static("test_key_5"))
+            .json(&request_body)
+            .await;
+
+        response.assert_status(StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_create_transaction_publishes_through_shared_publisher() {
+        let publisher = Arc::new(InMemoryPublisher::new());
+        let app = create_router_with(AppState::new(&Config::default(), publisher.clone())).await;
+        let server = as_merchant(app);
+
+        let request_body = json!({
+            "amount": 1000,
+            "currency": "USD",
+            "merchant_id": "merch_123",
+            "customer_id": "cust_123",
+            "idempotency_key": "test_key_6"
+        });
+
+        let response = server
+            .post("/api/v1/transaction")
+            .add_header(HeaderName::from_static("x-idempotency-key"), HeaderValue::from_static("test_key_6"))
+            .json(&request_body)
+            .await;
+        response.assert_status_ok();
+        let body = response.json::<CreateTransactionResponse>();
+
+        let published = publisher.published("transactions");
+        assert_eq!(published.len(), 1);
+        assert_eq!(published[0]["transaction_id"], json!(body.id));
+        assert_eq!(publisher.events()[0].key, body.id.to_string());
+    }
+
+    #[tokio::test]
+    async fn test_daily_amount_limit_rejects_with_unprocessable_entity() {
+        let publisher = Arc::new(InMemoryPublisher::new());
+        let app = create_router_with(AppState::new(&Config::default(), publisher.clone())).await;
+        let server = as_merchant(app);
+
+        let request_body = json!({
+            "amount": 10_000_001u64,
+            "currency": "USD",
+            "merchant_id": "merch_123",
+            "customer_id": "cust_123",
+            "idempotency_key": "test_key_7"
+        });
+
+        let response = server
+            .post("/api/v1/transaction")
+            .add_header(HeaderName::from_static("x-idempotency-key"), HeaderValue::from_static("test_key_7"))
+            .json(&request_body)
+            .await;
+
+        response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(response.headers().contains_key("ratelimit-remaining"));
+        assert!(publisher.published("transactions").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_metrics_label_requests_by_route_template() {
+        let app = create_router_with(AppState::new(&Config::default(), Arc::new(InMemoryPublisher::new()))).await;
+        let server = as_merchant(app);
+
+        server
+            .get(&format!("/api/v1/queries/status/{}", uuid::Uuid::new_v4()))
+            .await;
+
+        let response = server.get("/metrics").await;
+        response.assert_status_ok();
+        let body = response.text();
+        assert!(body.contains("route=\"/api/v1/queries/status/:id\""));
+        assert!(!body.contains("route=\"/metrics\""));
+    }
+
+    #[tokio::test]
+    async fn test_published_events_continue_the_callers_trace() {
+        use opentelemetry::{global, trace::TracerProvider as _};
+        use opentelemetry_sdk::{propagation::TraceContextPropagator, trace::SdkTracerProvider};
+        use tracing_subscriber::layer::SubscriberExt;
+
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        let provider = SdkTracerProvider::builder().build();
+        let subscriber = tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let publisher = Arc::new(InMemoryPublisher::new());
+        let app = create_router_with(AppState::new(&Config::default(), publisher.clone())).await;
+        let server = as_merchant(app);
+
+        let request_body = json!({
+            "amount": 1000,
+            "currency": "USD",
+            "merchant_id": "merch_123",
+            "customer_id": "cust_123",
+            "idempotency_key": "test_key_8"
+        });
+
+        server
+            .post("/api/v1/transaction")
+            .add_header(HeaderName::from_static("x-idempotency-key"), HeaderValue::from_static("test_key_8"))
+            .add_header(
+                HeaderName::from_static("traceparent"),
+                HeaderValue::from_static("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"),
+            )
+            .json(&request_body)
+            .await
+            .assert_status_ok();
+
+        let events = publisher.events();
+        assert_eq!(events.len(), 1);
+        assert!(events[0].headers["traceparent"].starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+    }
+
+    #[tokio::test]
+    async fn test_create_transaction_only_for_the_callers_merchant() {
+        let publisher = Arc::new(InMemoryPublisher::new());
+        let app = create_router_with(AppState::new(&Config::default(), publisher.clone())).await;
+        let request_body = json!({
+            "amount": 1000,
+            "currency": "USD",
+            "merchant_id": "merch_other",
+            "customer_id": "cust_123",
+            "idempotency_key": "test_key_other"
+        });
+
+        let response = as_merchant(app.clone())
+            .post("/api/v1/transaction")
+            .add_header(HeaderName::from_static("x-idempotency-key"), HeaderValue::from_static("test_key_other"))
+            .json(&request_body)
+            .await;
+        response.assert_status(StatusCode::FORBIDDEN);
+
+        TestServer::new(app).unwrap()
+            .post("/api/v1/transaction")
+            .add_header(HeaderName::from_static("x-idempotency-key"), HeaderValue::from_static("test_key_other"))
+            .json(&request_body)
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+        assert!(publisher.published("transactions").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_create_transaction_fails_when_the_event_isnt_published() {
+        let state = AppState::new(&Config::default(), Arc::new(BrokerDown));
+        let server = as_merchant(create_router_with(state.clone()).await);
+
+        let response = server
+            .post("/api/v1/transaction")
+            .add_header(HeaderName::from_static("x-idempotency-key"), HeaderValue::from_static("broker_down_1"))
+            .json(&json!({ "amount": 1000, "currency": "USD", "merchant_id": "merch_123", "customer_id": "cust_123", "idempotency_key": "broker_down_1" }))
+            .await;
+
+        response.assert_status(StatusCode::INTERNAL_SERVER_ERROR);
+        // the customer's daily total got the amount back
+        let limit = Config::default().velocity.max_daily_amount_per_customer;
+        assert!(state.velocity.reserve("merch_123", "cust_123", "USD", limit, chrono::Utc::now()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_platform_refunds_are_for_the_finance_team() {
+        let app = app().await;
+        let staff = |role: &str| Claims { sub: "staff".to_string(), exp: i64::MAX, role: role.to_string(), merchant_id: None };
+        let transaction_id = uuid::Uuid::new_v4();
+
+        let risk = TestServer::new(app.clone().layer(Extension(staff("risk")))).unwrap();
+        risk.post(&format!("/api/v1/transaction/{}/refunds", transaction_id))
+            .json(&json!({}))
+            .await
+            .assert_status(StatusCode::FORBIDDEN);
+
+        // finance gets past the check, the payment itself is unknown
+        let finance = TestServer::new(app.layer(Extension(staff("finance")))).unwrap();
+        finance
+            .post(&format!("/api/v1/transaction/{}/refunds", transaction_id))
+            .json(&json!({}))
+            .await
+            .assert_status(StatusCode::BAD_REQUEST);
+    }
+}
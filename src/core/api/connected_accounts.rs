@@ -0,0 +1,87 @@
+use axum::{
+    extract::{Path, State},
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::Utc;
+use hyper::StatusCode;
+use serde::Deserialize;
+
+use crate::api::middleware::AuthenticatedUser;
+use crate::core::{
+    api::{audit::AuditContext, state::AppState},
+    infrastructure::{audit::AuditLog, splits::ConnectedAccountStore},
+    models::{AuditAction, ConnectedAccount},
+};
+
+/*request payload types*/
+#[derive(Deserialize)]
+pub struct RegisterConnectedAccountRequest {
+    pub merchant_id: String,
+    pub provider_account_id: String,
+}
+
+pub fn connected_account_routes() -> Router<AppState> {
+    Router::new()
+        .route("/", post(register_connected_account))
+        .route("/:merchant_id", get(get_connected_account))
+}
+
+type ConnectedAccountError = (StatusCode, Json<serde_json::Value>);
+
+fn forbidden(merchant_id: &str) -> ConnectedAccountError {
+    (
+        StatusCode::FORBIDDEN,
+        Json(serde_json::json!({ "error": format!("This token can't act for merchant {}", merchant_id) })),
+    )
+}
+
+/* links a seller to the stripe connect account their share of split payments goes to,
+   the platform or the seller themselves, since it decides where their money is paid */
+async fn register_connected_account(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(store): State<ConnectedAccountStore>,
+    State(audit): State<AuditLog>,
+    context: AuditContext,
+    Json(payload): Json<RegisterConnectedAccountRequest>,
+) -> Result<Json<ConnectedAccount>, ConnectedAccountError> {
+    if !claims.may_act_for(&payload.merchant_id) {
+        return Err(forbidden(&payload.merchant_id));
+    }
+    if !payload.provider_account_id.starts_with("acct_") {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({ "error": "provider_account_id must be a stripe connect account (acct_...)" })),
+        ));
+    }
+
+    let previous = store.get(&payload.merchant_id).await;
+    let account = ConnectedAccount {
+        merchant_id: payload.merchant_id,
+        provider_account_id: payload.provider_account_id,
+        created_at: Utc::now(),
+    };
+
+    store.save(account.clone()).await;
+    audit
+        .record(context.record(AuditAction::ConnectedAccountRegistered).target(&account.merchant_id).before(&previous).after(&account))
+        .await;
+
+    Ok(Json(account))
+}
+
+async fn get_connected_account(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(store): State<ConnectedAccountStore>,
+    Path(merchant_id): Path<String>,
+) -> Result<Json<ConnectedAccount>, ConnectedAccountError> {
+    if !claims.may_act_for(&merchant_id) {
+        return Err(forbidden(&merchant_id));
+    }
+    store.get(&merchant_id).await.map(Json).ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({ "error": format!("No connected account for {}", merchant_id) })),
+        )
+    })
+}
@@ -0,0 +1,307 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{FromRef, Path, Query, State},
+    response::IntoResponse,
+    routing::{delete, get, post, put},
+    Json, Router,
+};
+use chrono::Utc;
+use hyper::StatusCode;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::api::{authentication::Claims, middleware::AuthenticatedUser};
+use crate::core::{
+    api::state::AppState,
+    infrastructure::{customers::CustomerStore, stripe::StripeService},
+    models::{Customer, SavedPaymentMethod},
+};
+
+/*request payload types*/
+#[derive(Deserialize)]
+pub struct CreateCustomerRequest {
+    pub customer_id: String,
+    pub merchant_id: String,
+    pub email: Option<String>,
+    pub name: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateCustomerRequest {
+    pub email: Option<String>,
+    pub name: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct AttachPaymentMethodRequest {
+    // token produced by stripe.js / the mobile sdk, e.g. `pm_...`
+    pub payment_method_id: String,
+    #[serde(default)]
+    pub set_default: bool,
+}
+
+#[derive(Deserialize)]
+pub struct DefaultPaymentMethodRequest {
+    pub payment_method_id: String,
+}
+
+#[derive(Deserialize)]
+pub struct MerchantScope {
+    // platform tokens say whose customer they mean, a merchant's own tokens are pinned to it
+    pub merchant_id: Option<String>,
+}
+
+#[derive(Debug, Error)]
+pub enum CustomerError {
+    #[error("Customer {0} not found")]
+    NotFound(String),
+    #[error("Customer {0} already exists")]
+    AlreadyExists(String),
+    #[error("Payment method {0} is not attached to this customer")]
+    UnknownPaymentMethod(String),
+    #[error("Payment provider error: {0}")]
+    Provider(String),
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("This token can't act for merchant {0}")]
+    Forbidden(String),
+}
+
+#[derive(Clone)]
+pub struct CustomerState {
+    pub store: CustomerStore,
+    pub stripe_service: Arc<StripeService>,
+}
+
+impl FromRef<AppState> for CustomerState {
+    fn from_ref(state: &AppState) -> Self {
+        Self {
+            store: state.customers.clone(),
+            stripe_service: state.stripe_service.clone(),
+        }
+    }
+}
+
+pub fn customer_routes() -> Router<AppState> {
+    Router::new()
+        .route("/", post(create_customer))
+        .route("/:id", get(get_customer).patch(update_customer).delete(delete_customer))
+        .route("/:id/payment-methods", post(attach_payment_method))
+        .route("/:id/payment-methods/:payment_method_id", delete(detach_payment_method))
+        .route("/:id/default-payment-method", put(set_default_payment_method))
+}
+
+async fn create_customer(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<CustomerState>,
+    Json(payload): Json<CreateCustomerRequest>,
+) -> Result<Json<Customer>, CustomerError> {
+    if !claims.may_act_for(&payload.merchant_id) {
+        return Err(CustomerError::Forbidden(payload.merchant_id));
+    }
+    if state.store.get(&payload.merchant_id, &payload.customer_id).await.is_some() {
+        return Err(CustomerError::AlreadyExists(payload.customer_id));
+    }
+
+    let provider_customer = state
+        .stripe_service
+        .create_customer(
+            &payload.customer_id,
+            &payload.merchant_id,
+            payload.email.as_deref(),
+            payload.name.as_deref(),
+        )
+        .await
+        .map_err(|e| CustomerError::Provider(e.to_string()))?;
+
+    let now = Utc::now();
+    let customer = Customer {
+        id: payload.customer_id,
+        merchant_id: payload.merchant_id,
+        email: payload.email,
+        name: payload.name,
+        provider_customer_id: provider_customer.id.to_string(),
+        default_payment_method_id: None,
+        payment_methods: Vec::new(),
+        created_at: now,
+        updated_at: now,
+    };
+
+    state.store.save(customer.clone()).await;
+
+    Ok(Json(customer))
+}
+
+async fn get_customer(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<CustomerState>,
+    Query(scope): Query<MerchantScope>,
+    Path(customer_id): Path<String>,
+) -> Result<Json<Customer>, CustomerError> {
+    find_customer(&state, &scope.resolve(&claims)?, &customer_id).await.map(Json)
+}
+
+async fn update_customer(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<CustomerState>,
+    Query(scope): Query<MerchantScope>,
+    Path(customer_id): Path<String>,
+    Json(payload): Json<UpdateCustomerRequest>,
+) -> Result<Json<Customer>, CustomerError> {
+    let mut customer = find_customer(&state, &scope.resolve(&claims)?, &customer_id).await?;
+
+    if payload.email.is_some() {
+        customer.email = payload.email;
+    }
+    if payload.name.is_some() {
+        customer.name = payload.name;
+    }
+    customer.updated_at = Utc::now();
+
+    state.store.save(customer.clone()).await;
+
+    Ok(Json(customer))
+}
+
+async fn delete_customer(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<CustomerState>,
+    Query(scope): Query<MerchantScope>,
+    Path(customer_id): Path<String>,
+) -> Result<StatusCode, CustomerError> {
+    let customer = find_customer(&state, &scope.resolve(&claims)?, &customer_id).await?;
+
+    // removing the stripe customer detaches all of its payment methods too
+    state
+        .stripe_service
+        .delete_customer(&customer.provider_customer_id)
+        .await
+        .map_err(|e| CustomerError::Provider(e.to_string()))?;
+
+    state.store.remove(&customer.merchant_id, &customer_id).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn attach_payment_method(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<CustomerState>,
+    Query(scope): Query<MerchantScope>,
+    Path(customer_id): Path<String>,
+    Json(payload): Json<AttachPaymentMethodRequest>,
+) -> Result<Json<Customer>, CustomerError> {
+    let mut customer = find_customer(&state, &scope.resolve(&claims)?, &customer_id).await?;
+
+    if !customer.has_payment_method(&payload.payment_method_id) {
+        state
+            .stripe_service
+            .attach_payment_method(&payload.payment_method_id, &customer.provider_customer_id)
+            .await
+            .map_err(|e| CustomerError::Provider(e.to_string()))?;
+
+        customer.payment_methods.push(SavedPaymentMethod {
+            id: payload.payment_method_id.clone(),
+            attached_at: Utc::now(),
+        });
+    }
+
+    // the first method saved becomes the default
+    if payload.set_default || customer.default_payment_method_id.is_none() {
+        customer.default_payment_method_id = Some(payload.payment_method_id);
+    }
+    customer.updated_at = Utc::now();
+
+    state.store.save(customer.clone()).await;
+
+    Ok(Json(customer))
+}
+
+async fn detach_payment_method(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<CustomerState>,
+    Query(scope): Query<MerchantScope>,
+    Path((customer_id, payment_method_id)): Path<(String, String)>,
+) -> Result<Json<Customer>, CustomerError> {
+    let mut customer = find_customer(&state, &scope.resolve(&claims)?, &customer_id).await?;
+
+    if !customer.has_payment_method(&payment_method_id) {
+        return Err(CustomerError::UnknownPaymentMethod(payment_method_id));
+    }
+
+    state
+        .stripe_service
+        .detach_payment_method(&payment_method_id)
+        .await
+        .map_err(|e| CustomerError::Provider(e.to_string()))?;
+
+    customer.payment_methods.retain(|pm| pm.id != payment_method_id);
+    if customer.default_payment_method_id.as_deref() == Some(payment_method_id.as_str()) {
+        customer.default_payment_method_id = None;
+    }
+    customer.updated_at = Utc::now();
+
+    state.store.save(customer.clone()).await;
+
+    Ok(Json(customer))
+}
+
+async fn set_default_payment_method(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<CustomerState>,
+    Query(scope): Query<MerchantScope>,
+    Path(customer_id): Path<String>,
+    Json(payload): Json<DefaultPaymentMethodRequest>,
+) -> Result<Json<Customer>, CustomerError> {
+    let mut customer = find_customer(&state, &scope.resolve(&claims)?, &customer_id).await?;
+
+    if !customer.has_payment_method(&payload.payment_method_id) {
+        return Err(CustomerError::UnknownPaymentMethod(payload.payment_method_id));
+    }
+
+    customer.default_payment_method_id = Some(payload.payment_method_id);
+    customer.updated_at = Utc::now();
+
+    state.store.save(customer.clone()).await;
+
+    Ok(Json(customer))
+}
+
+async fn find_customer(state: &CustomerState, merchant_id: &str, customer_id: &str) -> Result<Customer, CustomerError> {
+    state
+        .store
+        .get(merchant_id, customer_id)
+        .await
+        .ok_or_else(|| CustomerError::NotFound(customer_id.to_string()))
+}
+
+impl MerchantScope {
+    /// The merchant whose customers the caller is working with.
+    fn resolve(self, claims: &Claims) -> Result<String, CustomerError> {
+        match (&claims.merchant_id, self.merchant_id) {
+            (Some(own), Some(requested)) if *own != requested => Err(CustomerError::Forbidden(requested)),
+            (Some(own), _) => Ok(own.clone()),
+            (None, Some(requested)) => Ok(requested),
+            (None, None) => Err(CustomerError::InvalidRequest("merchant_id is required with a platform token".to_string())),
+        }
+    }
+}
+
+impl IntoResponse for CustomerError {
+    fn into_response(self) -> axum::response::Response {
+        let status = match self {
+            CustomerError::NotFound(_) => StatusCode::NOT_FOUND,
+            CustomerError::AlreadyExists(_) => StatusCode::CONFLICT,
+            CustomerError::UnknownPaymentMethod(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            CustomerError::Provider(_) => StatusCode::BAD_GATEWAY,
+            CustomerError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            CustomerError::Forbidden(_) => StatusCode::FORBIDDEN,
+        };
+
+        let body = Json(serde_json::json!({
+            "error": self.to_string()
+        }));
+
+        (status, body).into_response()
+    }
+}
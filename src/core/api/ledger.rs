@@ -0,0 +1,95 @@
+use std::str::FromStr;
+
+use axum::{
+    extract::{Path, Query, State},
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use hyper::StatusCode;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::api::{
+    authentication::{Claims, FINANCE_ROLE},
+    middleware::AuthenticatedUser,
+};
+use crate::core::{
+    api::state::AppState,
+    infrastructure::ledger::{AccountBalance, Ledger, LedgerVerification},
+    models::{JournalEntry, LedgerAccount},
+};
+
+/*request payload types*/
+#[derive(Deserialize)]
+pub struct BalanceQuery {
+    pub currency: String,
+    // defaults to now
+    pub as_of: Option<DateTime<Utc>>,
+}
+
+pub fn ledger_routes() -> Router<AppState> {
+    Router::new()
+        .route("/accounts/:account/balance", get(account_balance))
+        .route("/transactions/:id/entries", get(transaction_entries))
+        .route("/verify", get(verify_ledger))
+}
+
+type LedgerApiError = (StatusCode, Json<serde_json::Value>);
+
+fn forbidden(message: &str) -> LedgerApiError {
+    (StatusCode::FORBIDDEN, Json(serde_json::json!({ "error": message })))
+}
+
+fn require_finance(claims: &Claims) -> Result<(), LedgerApiError> {
+    if claims.is_operator(&[FINANCE_ROLE]) {
+        Ok(())
+    } else {
+        Err(forbidden("The ledger is for the finance team"))
+    }
+}
+
+/* merchants can read their own accounts, the platform's accounts are finance only */
+async fn account_balance(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(ledger): State<Ledger>,
+    Path(account): Path<String>,
+    Query(query): Query<BalanceQuery>,
+) -> Result<Json<AccountBalance>, LedgerApiError> {
+    let account = LedgerAccount::from_str(&account)
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": e }))))?;
+
+    match account.merchant_id() {
+        Some(merchant_id) if !claims.may_act_for(merchant_id) => {
+            return Err(forbidden(&format!("This token can't act for merchant {}", merchant_id)))
+        }
+        Some(_) => {}
+        None => require_finance(&claims)?,
+    }
+
+    let as_of = query.as_of.unwrap_or_else(Utc::now);
+
+    Ok(Json(ledger.balance(&account, &query.currency, as_of).await))
+}
+
+async fn transaction_entries(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(ledger): State<Ledger>,
+    Path(transaction_id): Path<Uuid>,
+) -> Result<Json<Vec<JournalEntry>>, LedgerApiError> {
+    require_finance(&claims)?;
+    Ok(Json(ledger.entries_for_transaction(transaction_id).await))
+}
+
+async fn verify_ledger(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(ledger): State<Ledger>,
+) -> Result<impl IntoResponse, LedgerApiError> {
+    require_finance(&claims)?;
+
+    let verification: LedgerVerification = ledger.verify().await;
+    let status = if verification.balanced { StatusCode::OK } else { StatusCode::CONFLICT };
+
+    Ok((status, Json(verification)))
+}
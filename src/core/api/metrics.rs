@@ -0,0 +1,31 @@
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, Request},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::core::infrastructure::metrics::metrics;
+
+/// Times every routed request, labelled with the route template rather than the raw path
+/// so ids don't end up in the label set.
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let started = Instant::now();
+    let response = next.run(request).await;
+    metrics().observe_request(&method, &route, response.status().as_u16(), started.elapsed());
+
+    response
+}
+
+pub async fn render_metrics() -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], metrics().render())
+}
@@ -0,0 +1,91 @@
+use axum::{response::Html, Json};
+use serde::{Deserialize, Serialize};
+use utoipa::{
+    openapi::{
+        security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+        OpenApi as OpenApiDocument,
+    },
+    Modify, OpenApi, ToSchema,
+};
+
+use crate::{
+    api::routes,
+    core::api::{commands, queries, status_stream},
+};
+
+/*response payload types*/
+/// The body of every error response, whatever the status.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+/* the document is generated from the handlers' annotations, `openapi.json` at the root of the repo is a copy kept in sync by openapi_test */
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "payme",
+        description = "Payments api. Commands are accepted right away and processed asynchronously, queries read what has been processed so far.",
+        license(name = "MIT", identifier = "MIT")
+    ),
+    paths(
+        routes::login,
+        routes::protected_route,
+        commands::create_transaction,
+        commands::create_refund,
+        commands::complete_authentication,
+        queries::list_transactions,
+        queries::get_payment_status,
+        queries::fee_report,
+        status_stream::stream_events,
+        status_stream::stream_websocket,
+    ),
+    modifiers(&BearerAuth),
+    security(("bearer" = [])),
+    tags(
+        (name = "auth", description = "Tokens for the rest of the api"),
+        (name = "commands", description = "Payments and refunds"),
+        (name = "queries", description = "The read side, eventually consistent with the commands"),
+    )
+)]
+pub struct ApiDoc;
+
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut OpenApiDocument) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).bearer_format("JWT").build()),
+        );
+    }
+}
+
+pub async fn openapi_json() -> Json<OpenApiDocument> {
+    Json(ApiDoc::openapi())
+}
+
+/* the ui is loaded from a cdn, nothing gets bundled into the binary */
+pub async fn swagger_ui() -> Html<&'static str> {
+    Html(SWAGGER_UI)
+}
+
+const SWAGGER_UI: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8" />
+  <title>payme api</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5.17.14/swagger-ui.css" />
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5.17.14/swagger-ui-bundle.js" crossorigin></script>
+  <script>
+    window.onload = () => {
+      window.ui = SwaggerUIBundle({ url: "/openapi.json", dom_id: "#swagger-ui" });
+    };
+  </script>
+</body>
+</html>
+"##;
@@ -0,0 +1,139 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{http::Method, Extension};
+    use axum_test::TestServer;
+    use hyper::StatusCode;
+    use serde_json::Value;
+    use utoipa::OpenApi;
+    use uuid::Uuid;
+
+    use crate::{
+        api::{
+            authentication::{AuthenticationService, Claims},
+            routes::create_router,
+        },
+        core::{
+            api::{create_router_with, openapi::ApiDoc, query_operations, state::AppState, transaction_operations},
+            config::Config,
+            infrastructure::{accounts::AccountStore, publisher::InMemoryPublisher},
+        },
+    };
+
+    // what the frontend builds against, rewrite it with `UPDATE_OPENAPI=1 cargo test openapi`
+    const COMMITTED_SPEC: &str = include_str!("../../../openapi.json");
+
+    async fn server() -> TestServer {
+        let state = AppState::new(&Config::default(), Arc::new(InMemoryPublisher::new()));
+        let claims = Claims { sub: "frontend".to_string(), exp: i64::MAX, role: "admin".to_string(), merchant_id: None };
+        let app = create_router(AuthenticationService::from_secret("openapi"), AccountStore::new(), state.audit.clone(), false, state.rate_limiter.clone())
+            .merge(create_router_with(state).await)
+            .layer(Extension(claims));
+        TestServer::new(app).unwrap()
+    }
+
+    #[test]
+    fn test_committed_spec_matches_the_code() {
+        let generated = ApiDoc::openapi().to_pretty_json().unwrap() + "\n";
+        if std::env::var_os("UPDATE_OPENAPI").is_some() {
+            std::fs::write(concat!(env!("CARGO_MANIFEST_DIR"), "/openapi.json"), &generated).unwrap();
+            return;
+        }
+
+        assert!(
+            generated == COMMITTED_SPEC,
+            "openapi.json no longer matches the handlers, regenerate it with `UPDATE_OPENAPI=1 cargo test openapi` and commit the result"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_every_documented_operation_is_routed() {
+        let server = server().await;
+
+        for (path, item) in ApiDoc::openapi().paths.paths {
+            let uri = path
+                .split('/')
+                .map(|segment| if segment.starts_with('{') { Uuid::new_v4().to_string() } else { segment.to_string() })
+                .collect::<Vec<_>>()
+                .join("/");
+            let operations = [
+                (Method::GET, item.get.is_some()),
+                (Method::POST, item.post.is_some()),
+                (Method::PUT, item.put.is_some()),
+                (Method::PATCH, item.patch.is_some()),
+                (Method::DELETE, item.delete.is_some()),
+            ];
+
+            for (method, _) in operations.into_iter().filter(|(_, documented)| *documented) {
+                let response = server.method(method.clone(), &uri).await;
+                // handlers answer a 404 with an error body, an unrouted path gets an empty one
+                let unrouted = response.status_code() == StatusCode::NOT_FOUND && response.text().is_empty();
+                assert!(
+                    !unrouted && response.status_code() != StatusCode::METHOD_NOT_ALLOWED,
+                    "{} {} is documented but not routed ({})",
+                    method,
+                    path,
+                    response.status_code()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_every_routed_payment_operation_is_documented() {
+        let documented = ApiDoc::openapi().paths.paths;
+
+        for (method, route, _) in transaction_operations().into_iter().chain(query_operations()) {
+            // axum's `:id` is openapi's `{id}`
+            let path = route
+                .split('/')
+                .map(|segment| match segment.strip_prefix(':') {
+                    Some(name) => format!("{{{}}}", name),
+                    None => segment.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join("/");
+            let path = format!("/api/v1{}", path);
+
+            let item = documented.get(&path);
+            let operation = item.and_then(|item| match method {
+                Method::GET => item.get.as_ref(),
+                Method::POST => item.post.as_ref(),
+                Method::PUT => item.put.as_ref(),
+                Method::PATCH => item.patch.as_ref(),
+                Method::DELETE => item.delete.as_ref(),
+                _ => None,
+            });
+            assert!(operation.is_some(), "{} {} is routed but not documented", method, path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_spec_and_ui_are_served() {
+        let server = server().await;
+
+        let spec: Value = server.get("/openapi.json").await.json();
+        assert!(spec["openapi"].as_str().unwrap().starts_with("3.1"));
+        assert_eq!(spec["components"]["securitySchemes"]["bearer"]["scheme"], "bearer");
+        assert_eq!(spec["security"][0]["bearer"], Value::Array(vec![]));
+        let create = &spec["paths"]["/api/v1/transaction"]["post"];
+        assert!(create["parameters"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|p| p["name"] == "x-idempotency-key" && p["in"] == "header"));
+        assert_eq!(
+            create["requestBody"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/CreateTransactionRequest"
+        );
+        assert_eq!(
+            create["responses"]["400"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/ErrorResponse"
+        );
+
+        let ui = server.get("/docs").await;
+        ui.assert_status_ok();
+        assert!(ui.text().contains("url: \"/openapi.json\""));
+    }
+}
@@ -0,0 +1,153 @@
+use std::{collections::HashMap, str::FromStr};
+
+use axum::{
+    extract::{Path, Query, State},
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use chrono::Utc;
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::api::{authentication::FINANCE_ROLE, middleware::AuthenticatedUser};
+use crate::core::{
+    api::{audit::AuditContext, state::AppState},
+    infrastructure::{
+        audit::AuditLog,
+        pricing::{calculate_fee, currency_key, validate_plan, PricingStore},
+    },
+    models::{AuditAction, Currency, FeeBreakdown, FeeRate, PricingPlan, VolumeTier},
+};
+
+/*request payload types*/
+#[derive(Deserialize)]
+pub struct PricingPlanRequest {
+    pub default_rate: FeeRate,
+    #[serde(default)]
+    pub currency_overrides: HashMap<String, FeeRate>,
+    #[serde(default)]
+    pub volume_tiers: Vec<VolumeTier>,
+}
+
+#[derive(Deserialize)]
+pub struct FeeQuoteRequest {
+    pub amount: u64,
+    pub currency: String,
+}
+
+/*response payload types*/
+#[derive(Serialize)]
+pub struct FeeQuoteResponse {
+    pub merchant_id: String,
+    pub amount: u64,
+    pub currency: String,
+    // what the merchant already processed this month, which picks the volume tier
+    pub month_volume: u64,
+    pub fee: FeeBreakdown,
+    pub net: u64,
+}
+
+#[derive(Debug, Error)]
+pub enum PricingError {
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("This token can't act for merchant {0}")]
+    Forbidden(String),
+    #[error("Pricing plans are set by the platform")]
+    NotOperator,
+}
+
+pub fn pricing_routes() -> Router<AppState> {
+    Router::new()
+        .route("/:merchant_id", get(get_pricing_plan).put(set_pricing_plan))
+        .route("/:merchant_id/quote", get(quote_fee))
+}
+
+async fn get_pricing_plan(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(store): State<PricingStore>,
+    Path(merchant_id): Path<String>,
+) -> Result<Json<PricingPlan>, PricingError> {
+    if !claims.may_act_for(&merchant_id) {
+        return Err(PricingError::Forbidden(merchant_id));
+    }
+    Ok(Json(store.plan_for(&merchant_id).await))
+}
+
+// merchants can read their plan but not give themselves a better one
+async fn set_pricing_plan(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(store): State<PricingStore>,
+    State(audit): State<AuditLog>,
+    context: AuditContext,
+    Path(merchant_id): Path<String>,
+    Json(payload): Json<PricingPlanRequest>,
+) -> Result<Json<PricingPlan>, PricingError> {
+    if !claims.is_operator(&[FINANCE_ROLE]) {
+        return Err(PricingError::NotOperator);
+    }
+
+    let mut currency_overrides = HashMap::new();
+    for (currency, rate) in payload.currency_overrides {
+        Currency::from_str(&currency).map_err(PricingError::InvalidRequest)?;
+        currency_overrides.insert(currency_key(&currency), rate);
+    }
+
+    let plan = PricingPlan {
+        merchant_id,
+        default_rate: payload.default_rate,
+        currency_overrides,
+        volume_tiers: payload.volume_tiers,
+        updated_at: Utc::now(),
+    };
+
+    validate_plan(&plan).map_err(PricingError::InvalidRequest)?;
+    let previous = store.plan_for(&plan.merchant_id).await;
+    store.save_plan(plan.clone()).await;
+    audit.record(context.record(AuditAction::PricingPlanChanged).target(&plan.merchant_id).before(&previous).after(&plan)).await;
+
+    Ok(Json(plan))
+}
+
+/* previews the fee without counting towards the month's volume */
+async fn quote_fee(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(store): State<PricingStore>,
+    Path(merchant_id): Path<String>,
+    Query(request): Query<FeeQuoteRequest>,
+) -> Result<Json<FeeQuoteResponse>, PricingError> {
+    if !claims.may_act_for(&merchant_id) {
+        return Err(PricingError::Forbidden(merchant_id));
+    }
+    Currency::from_str(&request.currency).map_err(PricingError::InvalidRequest)?;
+
+    let plan = store.plan_for(&merchant_id).await;
+    let month_volume = store.month_volume(&merchant_id, &request.currency, Utc::now()).await;
+    let fee = calculate_fee(&plan, request.amount, &request.currency, month_volume);
+
+    Ok(Json(FeeQuoteResponse {
+        merchant_id,
+        amount: request.amount,
+        currency: currency_key(&request.currency),
+        month_volume,
+        net: request.amount - fee.total,
+        fee,
+    }))
+}
+
+impl IntoResponse for PricingError {
+    fn into_response(self) -> axum::response::Response {
+        let status = match self {
+            PricingError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            PricingError::Forbidden(_) | PricingError::NotOperator => StatusCode::FORBIDDEN,
+        };
+
+        let body = Json(serde_json::json!({
+            "error": self.to_string()
+        }));
+
+        (status, body).into_response()
+    }
+}
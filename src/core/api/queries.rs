@@ -1,62 +1,358 @@
-use std::time::Duration;
+use std::{
+    collections::{BTreeMap, HashMap},
+    str::FromStr,
+};
 
 use axum::{
-    extract::Path,
+    extract::{self, FromRef, Path, State},
+    response::IntoResponse,
     Json,
 };
-use rdkafka::{producer::{FutureProducer, FutureRecord}, ClientConfig};
+use chrono::{DateTime, Utc};
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
-use crate::core::events::PaymentStatusRequestEvent;
+use crate::{
+    api::middleware::AuthenticatedUser,
+    core::{
+        api::{openapi::ErrorResponse, state::AppState},
+        infrastructure::projection::{PageCursor, TransactionFilter, TransactionProjection, TransactionSort},
+        models::{Currency, FeeBreakdown, Transaction, TransactionStatus},
+    },
+};
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 200;
+
+// what `fields=` can pick from, the client secret is left out of listings on purpose
+pub const TRANSACTION_FIELDS: [&str; 13] = [
+    "id",
+    "amount",
+    "currency",
+    "merchant_id",
+    "customer_id",
+    "status",
+    "provider_payment_id",
+    "metadata",
+    "fee",
+    "splits",
+    "amount_refunded",
+    "created_at",
+    "update_at",
+];
+
+/*request payload types*/
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FeeReportRequest {
+    pub merchant_id: String,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListTransactionsRequest {
+    /// Merchant tokens only ever see their own merchant.
+    pub merchant_id: Option<String>,
+    pub customer_id: Option<String>,
+    /// `pending`, `completed`, `failed`, `requires_action`, `under_review` or `refunded`.
+    pub status: Option<String>,
+    pub currency: Option<String>,
+    pub min_amount: Option<i64>,
+    pub max_amount: Option<i64>,
+    pub created_from: Option<DateTime<Utc>>,
+    pub created_to: Option<DateTime<Utc>>,
+    pub metadata_key: Option<String>,
+    pub metadata_value: Option<String>,
+    /// `created_at`, `amount`, `-` in front for descending, newest first by default.
+    pub sort: Option<String>,
+    /// `next_cursor` of the previous page.
+    pub cursor: Option<String>,
+    /// Page size, 50 by default and at most 200.
+    pub limit: Option<usize>,
+    /// Comma separated, everything when absent.
+    pub fields: Option<String>,
+}
+
+/*response payload types*/
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct PaymentStatusResponse {
+    pub id: Uuid,
+    pub status: TransactionStatus,
+    pub provider_payment_id: Option<String>,
+    /// Only useful while the status is `RequiresAction`, lets stripe.js finish authentication.
+    pub client_secret: Option<String>,
+    pub metadata: HashMap<String, String>,
+    /// Only present once the payment completed.
+    pub fee: Option<FeeBreakdown>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq, ToSchema)]
+pub struct CurrencyFeeSummary {
+    pub transactions: u64,
+    pub gross: u64,
+    pub fees: u64,
+    pub net: u64,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct FeeReportResponse {
+    pub merchant_id: String,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub by_currency: BTreeMap<String, CurrencyFeeSummary>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct TransactionListResponse {
+    /// Transactions, with only the requested `fields` when those were given.
+    #[schema(value_type = Vec<Object>)]
+    pub data: Vec<serde_json::Value>,
+    /// Hand back as `cursor` for the next page, absent on the last one.
+    pub next_cursor: Option<String>,
+}
 
+#[derive(Debug, Error)]
+pub enum QueryError {
+    #[error("Transaction {0} not found")]
+    NotFound(Uuid),
+    #[error("{0}")]
+    InvalidRequest(String),
+    #[error("Transactions of merchant {0} are not visible to this token")]
+    Forbidden(String),
+}
 
+#[derive(Clone)]
 pub struct Query {
-    producer: FutureProducer
+    projection: TransactionProjection
+}
+
+impl FromRef<AppState> for Query {
+    fn from_ref(state: &AppState) -> Self {
+        Self::new(state.projection.clone())
+    }
 }
 
 impl Query {
-    pub fn new(kf_broker: &str) -> Self {
-        let producer = ClientConfig::new()
-                    .set("bootstrap.servers", kf_broker)
-                    .set("message.timeout.ms", "5000")
-                    .create()
-                    .expect("Failed to create a producer");
-        
-        Self { producer }
-    }
-
-    pub async fn get_payment_status(
-        &self,
-        idempotency_key: String,
-        Path(transaction_id): Path<Uuid>
-    ) -> Json<()> {
-        // Check idempotency
-        if let Some(cached_response) = self.check_idempotency(&idempotency_key).await {
-            return cached_response;
+    pub fn new(projection: TransactionProjection) -> Self {
+        Self { projection }
+    }
+
+    /// Another merchant's transactions are reported as unknown rather than forbidden,
+    /// so ids can't be probed.
+    pub async fn payment_status(&self, transaction_id: Uuid, caller_merchant: Option<&str>) -> Result<PaymentStatusResponse, QueryError> {
+        let transaction = self.projection
+            .get(transaction_id)
+            .await
+            .filter(|transaction| caller_merchant.is_none_or(|own| own == transaction.merchant_id))
+            .ok_or(QueryError::NotFound(transaction_id))?;
+
+        Ok(PaymentStatusResponse {
+            id: transaction.id,
+            status: transaction.status,
+            provider_payment_id: transaction.provider_payment_id,
+            client_secret: transaction.client_secret,
+            metadata: transaction.metadata,
+            fee: transaction.fee,
+            updated_at: transaction.update_at,
+        })
+    }
+
+    /// Gross, fees and net per currency for the merchant's assessed payments in `[from, to)`.
+    pub async fn fee_report(&self, merchant_id: &str, from: DateTime<Utc>, to: DateTime<Utc>) -> FeeReportResponse {
+        let mut by_currency: BTreeMap<String, CurrencyFeeSummary> = BTreeMap::new();
+
+        for transaction in self.projection.for_merchant(merchant_id, from, to).await {
+            let Some(fee) = transaction.fee else { continue };
+
+            let summary = by_currency.entry(format!("{:?}", transaction.currency)).or_default();
+            summary.transactions += 1;
+            summary.gross += transaction.amount as u64;
+            summary.fees += fee.total;
+            summary.net += transaction.amount as u64 - fee.total;
         }
 
-        let req_event = PaymentStatusRequestEvent::new(transaction_id);
+        FeeReportResponse {
+            merchant_id: merchant_id.to_string(),
+            from,
+            to,
+            by_currency,
+        }
+    }
+}
 
-        let payload = serde_json::to_string(&req_event).unwrap();
+impl ListTransactionsRequest {
+    /// The filter for a caller: merchant tokens are pinned to their own merchant, platform
+    /// tokens see every merchant unless they ask for one.
+    pub fn filter_for(&self, caller_merchant: Option<&str>) -> Result<TransactionFilter, QueryError> {
+        let merchant_id = match (caller_merchant, &self.merchant_id) {
+            (Some(own), Some(requested)) if own != requested => return Err(QueryError::Forbidden(requested.clone())),
+            (Some(own), _) => Some(own.to_string()),
+            (None, requested) => requested.clone(),
+        };
 
-        match self.producer
-        .send(
-            FutureRecord::to("payment-status-requests").payload(&payload).key(&transaction_id.to_string()), Duration::from_secs(5)).await {
-                
-                Ok(_) => {
-                    todo!("database integration needed here")
-                },
-                Err(e) => {
-                    eprintln!("Failed to publish the status req event to event bus: {}", e.0);
+        let currency = self
+            .currency
+            .as_deref()
+            .map(Currency::from_str)
+            .transpose()
+            .map_err(QueryError::InvalidRequest)?;
 
-                    todo!("need to return a valid json response here");
-                }
+        if let Some(status) = &self.status {
+            if !TransactionStatus::KINDS.contains(&status.as_str()) {
+                return Err(QueryError::InvalidRequest(format!("Unsupported status {}", status)));
+            }
         }
+
+        Ok(TransactionFilter {
+            merchant_id,
+            customer_id: self.customer_id.clone(),
+            status: self.status.clone(),
+            currency,
+            min_amount: self.min_amount,
+            max_amount: self.max_amount,
+            created_from: self.created_from,
+            created_to: self.created_to,
+            metadata_key: self.metadata_key.clone(),
+            metadata_value: self.metadata_value.clone(),
+        })
+    }
+
+    fn selected_fields(&self) -> Result<Vec<&str>, QueryError> {
+        let Some(fields) = &self.fields else {
+            return Ok(TRANSACTION_FIELDS.to_vec());
+        };
+
+        fields
+            .split(',')
+            .map(str::trim)
+            .filter(|field| !field.is_empty())
+            .map(|field| match TRANSACTION_FIELDS.contains(&field) {
+                true => Ok(field),
+                false => Err(QueryError::InvalidRequest(format!("Unknown field {}", field))),
+            })
+            .collect()
+    }
+}
+
+/* only the selected fields of a transaction, in the order they were asked for */
+fn select_fields(transaction: &Transaction, fields: &[&str]) -> serde_json::Value {
+    let serde_json::Value::Object(mut all) = serde_json::to_value(transaction).expect("transactions serialize") else {
+        unreachable!("a transaction serializes to an object")
+    };
+
+    let selected = fields
+        .iter()
+        .filter_map(|field| all.remove(*field).map(|value| (field.to_string(), value)))
+        .collect();
+
+    serde_json::Value::Object(selected)
+}
+
+/// Lists the caller's transactions a page at a time, see `ListTransactionsRequest` for what can be asked.
+#[utoipa::path(
+    get,
+    path = "/api/v1/transactions",
+    tag = "queries",
+    params(ListTransactionsRequest),
+    responses(
+        (status = 200, description = "One page of transactions", body = TransactionListResponse),
+        (status = 400, description = "Invalid filter, sort, cursor or field", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Another merchant's transactions", body = ErrorResponse),
+    )
+)]
+pub async fn list_transactions(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(query): State<Query>,
+    extract::Query(request): extract::Query<ListTransactionsRequest>,
+) -> Result<Json<TransactionListResponse>, QueryError> {
+    let filter = request.filter_for(claims.merchant_id.as_deref())?;
+    let fields = request.selected_fields()?;
+
+    let sort = match &request.sort {
+        Some(sort) => TransactionSort::from_str(sort).map_err(QueryError::InvalidRequest)?,
+        None => TransactionSort::default(),
+    };
+
+    let cursor = request
+        .cursor
+        .as_deref()
+        .map(PageCursor::decode)
+        .transpose()
+        .map_err(QueryError::InvalidRequest)?;
+    // a cursor only means something in the order it was made for
+    if cursor.as_ref().is_some_and(|cursor| cursor.sort != sort) {
+        return Err(QueryError::InvalidRequest("Cursor was issued for a different sort".to_string()));
     }
 
-    async fn check_idempotency(&self, key: &str) -> Option<Json<()>> {
-        // Check cache/database for previous response
-        None
+    let limit = request.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let page = query.projection.search(&filter, sort, cursor.as_ref(), limit).await;
+
+    Ok(Json(TransactionListResponse {
+        data: page.transactions.iter().map(|t| select_fields(t, &fields)).collect(),
+        next_cursor: page.next_cursor.map(|cursor| cursor.encode()),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/queries/reports/fees",
+    tag = "queries",
+    params(FeeReportRequest),
+    responses(
+        (status = 200, description = "Fees of the merchant's payments in the range, per currency", body = FeeReportResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Another merchant's report", body = ErrorResponse),
+    )
+)]
+pub async fn fee_report(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(query): State<Query>,
+    extract::Query(request): extract::Query<FeeReportRequest>
+) -> Result<Json<FeeReportResponse>, QueryError> {
+    if !claims.may_act_for(&request.merchant_id) {
+        return Err(QueryError::Forbidden(request.merchant_id));
     }
+    Ok(Json(query.fee_report(&request.merchant_id, request.from, request.to).await))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/v1/queries/status/{id}",
+    tag = "queries",
+    params(("id" = Uuid, Path, description = "Transaction id")),
+    responses(
+        (status = 200, description = "Current status of the payment", body = PaymentStatusResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 404, description = "Unknown transaction", body = ErrorResponse),
+    )
+)]
+pub async fn get_payment_status(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(query): State<Query>,
+    Path(transaction_id): Path<Uuid>
+) -> Result<Json<PaymentStatusResponse>, QueryError> {
+    query.payment_status(transaction_id, claims.merchant_id.as_deref()).await.map(Json)
+}
+
+impl IntoResponse for QueryError {
+    fn into_response(self) -> axum::response::Response {
+        let status = match self {
+            QueryError::NotFound(_) => StatusCode::NOT_FOUND,
+            QueryError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            QueryError::Forbidden(_) => StatusCode::FORBIDDEN,
+        };
+
+        let body = Json(serde_json::json!({
+            "error": self.to_string()
+        }));
+
+        (status, body).into_response()
+    }
+}
@@ -0,0 +1,221 @@
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use axum::Extension;
+    use axum_test::TestServer;
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+    use hyper::StatusCode;
+    use serde_json::Value;
+    use uuid::Uuid;
+
+    use crate::{
+        api::authentication::Claims,
+        core::{
+            api::{create_router_with, queries::TransactionListResponse, state::AppState},
+            config::Config,
+            events::{PaymentStatusUpdatedEvent, TransactionCreatedEvent},
+            infrastructure::publisher::InMemoryPublisher,
+            models::TransactionStatus,
+        },
+    };
+
+    fn merchant_user(merchant_id: &str) -> Claims {
+        Claims { sub: "merchant_user".to_string(), exp: i64::MAX, role: "merchant".to_string(), merchant_id: Some(merchant_id.to_string()) }
+    }
+
+    fn support_user() -> Claims {
+        Claims { sub: "support_user".to_string(), exp: i64::MAX, role: "support".to_string(), merchant_id: None }
+    }
+
+    async fn server_as(state: AppState, claims: Claims) -> TestServer {
+        TestServer::new(create_router_with(state).await.layer(Extension(claims))).unwrap()
+    }
+
+    fn state() -> AppState {
+        AppState::new(&Config::default(), Arc::new(InMemoryPublisher::new()))
+    }
+
+    fn at(minute: i64) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap() + Duration::minutes(minute)
+    }
+
+    async fn create(state: &AppState, merchant_id: &str, amount: u64, currency: &str, created_at: DateTime<Utc>) -> Uuid {
+        let id = Uuid::new_v4();
+        let mut event = TransactionCreatedEvent::new(id, amount, currency.to_string(), merchant_id.to_string(), "cust_1".to_string());
+        event.timestamp = created_at;
+        state.projection.apply_created(&event).await;
+        id
+    }
+
+    fn ids(response: &TransactionListResponse) -> Vec<String> {
+        response.data.iter().map(|t| t["id"].as_str().unwrap().to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_cursor_pages_are_stable_while_transactions_arrive() {
+        let state = state();
+        let mut expected = Vec::new();
+        for minute in 0..5 {
+            expected.push(create(&state, "merch_pages", 1000, "USD", at(minute)).await.to_string());
+        }
+        // same instant, the id decides their order
+        expected.push(create(&state, "merch_pages", 1000, "USD", at(2)).await.to_string());
+        let server = server_as(state.clone(), merchant_user("merch_pages")).await;
+
+        let mut seen = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let mut request = server.get("/api/v1/transactions").add_query_param("limit", 2);
+            if let Some(cursor) = &cursor {
+                request = request.add_query_param("cursor", cursor);
+            }
+            let page: TransactionListResponse = request.await.json();
+            seen.extend(ids(&page));
+
+            // newer than everything already listed, must not shift the following pages
+            create(&state, "merch_pages", 1000, "USD", Utc::now()).await;
+
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+
+        let mut seen_sorted = seen.clone();
+        seen_sorted.sort();
+        seen_sorted.dedup();
+        assert_eq!(seen.len(), 6, "every transaction listed once");
+        assert_eq!(seen_sorted.len(), 6);
+        expected.sort();
+        assert_eq!(seen_sorted, expected);
+    }
+
+    #[tokio::test]
+    async fn test_filters_and_sorting() {
+        let state = state();
+        let small = create(&state, "merch_filters", 500, "USD", at(0)).await;
+        let large = create(&state, "merch_filters", 9000, "USD", at(1)).await;
+        let euro = create(&state, "merch_filters", 3000, "EUR", at(2)).await;
+        let mut tagged = TransactionCreatedEvent::new(Uuid::new_v4(), 4000, "USD".to_string(), "merch_filters".to_string(), "cust_2".to_string())
+            .with_metadata(HashMap::from([("order_id".to_string(), "ord_7".to_string())]));
+        tagged.timestamp = at(3);
+        state.projection.apply_created(&tagged).await;
+        state
+            .projection
+            .apply_status(&PaymentStatusUpdatedEvent::new(large, "merch_filters".to_string(), TransactionStatus::Completed, "pi_1".to_string()))
+            .await;
+        let server = server_as(state, merchant_user("merch_filters")).await;
+
+        let by_amount: TransactionListResponse = server.get("/api/v1/transactions").add_query_param("sort", "amount").await.json();
+        assert_eq!(ids(&by_amount), vec![small.to_string(), euro.to_string(), tagged.transaction_id.to_string(), large.to_string()]);
+
+        let completed: TransactionListResponse = server.get("/api/v1/transactions").add_query_param("status", "completed").await.json();
+        assert_eq!(ids(&completed), vec![large.to_string()]);
+
+        let in_range: TransactionListResponse = server
+            .get("/api/v1/transactions")
+            .add_query_param("currency", "USD")
+            .add_query_param("min_amount", 1000)
+            .add_query_param("max_amount", 5000)
+            .await
+            .json();
+        assert_eq!(ids(&in_range), vec![tagged.transaction_id.to_string()]);
+
+        let created: TransactionListResponse = server
+            .get("/api/v1/transactions")
+            .add_query_param("created_from", at(1).to_rfc3339())
+            .add_query_param("created_to", at(3).to_rfc3339())
+            .await
+            .json();
+        assert_eq!(ids(&created), vec![euro.to_string(), large.to_string()]);
+
+        let by_metadata: TransactionListResponse = server.get("/api/v1/transactions").add_query_param("metadata_key", "order_id").await.json();
+        assert_eq!(ids(&by_metadata), vec![tagged.transaction_id.to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_merchant_tokens_only_see_their_merchant() {
+        let state = state();
+        let own = create(&state, "merch_own", 1000, "USD", at(0)).await;
+        let other = create(&state, "merch_other", 1000, "USD", at(1)).await;
+
+        let merchant = server_as(state.clone(), merchant_user("merch_own")).await;
+        let listed: TransactionListResponse = merchant.get("/api/v1/transactions").await.json();
+        assert_eq!(ids(&listed), vec![own.to_string()]);
+        merchant
+            .get("/api/v1/transactions")
+            .add_query_param("merchant_id", "merch_other")
+            .await
+            .assert_status(StatusCode::FORBIDDEN);
+
+        let support = server_as(state.clone(), support_user()).await;
+        let listed: TransactionListResponse = support.get("/api/v1/transactions").add_query_param("merchant_id", "merch_other").await.json();
+        assert_eq!(ids(&listed), vec![other.to_string()]);
+
+        let anonymous = TestServer::new(create_router_with(state).await).unwrap();
+        anonymous.get("/api/v1/transactions").await.assert_status(StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_field_selection() {
+        let state = state();
+        let id = create(&state, "merch_fields", 1234, "INR", at(0)).await;
+        let server = server_as(state, merchant_user("merch_fields")).await;
+
+        let listed: TransactionListResponse = server.get("/api/v1/transactions").add_query_param("fields", "id,amount").await.json();
+        let expected: Value = serde_json::json!({ "id": id, "amount": 1234 });
+        assert_eq!(listed.data, vec![expected]);
+
+        let all: TransactionListResponse = server.get("/api/v1/transactions").await.json();
+        assert!(all.data[0].get("client_secret").is_none());
+
+        server
+            .get("/api/v1/transactions")
+            .add_query_param("fields", "id,password")
+            .await
+            .assert_status(StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_cursor_is_tied_to_its_sort() {
+        let state = state();
+        for minute in 0..3 {
+            create(&state, "merch_cursor", 1000, "USD", at(minute)).await;
+        }
+        let server = server_as(state, merchant_user("merch_cursor")).await;
+
+        let page: TransactionListResponse = server.get("/api/v1/transactions").add_query_param("limit", 1).await.json();
+        let cursor = page.next_cursor.expect("more pages");
+
+        server
+            .get("/api/v1/transactions")
+            .add_query_param("sort", "amount")
+            .add_query_param("cursor", &cursor)
+            .await
+            .assert_status(StatusCode::BAD_REQUEST);
+        server
+            .get("/api/v1/transactions")
+            .add_query_param("cursor", "not-a-cursor")
+            .await
+            .assert_status(StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_creation_moves_an_early_status_update_in_the_index() {
+        let state = state();
+        let id = Uuid::new_v4();
+        let mut status = PaymentStatusUpdatedEvent::new(id, "merch_late".to_string(), TransactionStatus::Completed, "pi_2".to_string());
+        status.timestamp = at(30);
+        state.projection.apply_status(&status).await;
+
+        let mut created = TransactionCreatedEvent::new(id, 700, "USD".to_string(), "merch_late".to_string(), "cust_1".to_string());
+        created.timestamp = at(0);
+        state.projection.apply_created(&created).await;
+
+        let listed = state.projection.for_merchant("merch_late", at(0), at(1)).await;
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].status, TransactionStatus::Completed);
+        assert!(state.projection.for_merchant("merch_late", at(30), at(31)).await.is_empty());
+    }
+}
@@ -0,0 +1,84 @@
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::Utc;
+use hyper::StatusCode;
+use sha2::{Digest, Sha256};
+
+use crate::api::authentication::Claims;
+use crate::core::infrastructure::limits::{LimitKey, RateLimitDecision, RateLimiter};
+
+/// Token bucket limiting of the command api, keyed by the merchant the credential belongs to, the
+/// credential and the client ip.
+/// Every response carries the RateLimit-* headers of the tightest bucket.
+pub async fn enforce_rate_limit(State(limiter): State<RateLimiter>, request: Request, next: Next) -> Response {
+    let keys = limit_keys(&request, limiter.trusted_proxies());
+    let decision = limiter.check(&keys, Utc::now()).await;
+
+    let mut response = if decision.allowed {
+        next.run(request).await
+    } else {
+        let body = Json(serde_json::json!({
+            "error": format!("Rate limit exceeded, retry in {} seconds", decision.retry_after)
+        }));
+        let mut response = (StatusCode::TOO_MANY_REQUESTS, body).into_response();
+        response.headers_mut().insert("retry-after", HeaderValue::from(decision.retry_after));
+        response
+    };
+
+    add_rate_limit_headers(response.headers_mut(), &decision);
+    response
+}
+
+fn limit_keys(request: &Request, trusted_proxies: &[IpAddr]) -> Vec<(LimitKey, String)> {
+    let headers = request.headers();
+    let header = |name: &str| headers.get(name).and_then(|h| h.to_str().ok()).map(str::trim).filter(|v| !v.is_empty());
+    let mut keys = Vec::with_capacity(3);
+
+    // set by the auth layer from the token or api key, platform tokens have no merchant bucket
+    if let Some(merchant_id) = request.extensions().get::<Claims>().and_then(|claims| claims.merchant_id.clone()) {
+        keys.push((LimitKey::Merchant, merchant_id));
+    }
+
+    // only a digest of the credential is kept around
+    if let Some(credential) = header("x-api-key").or_else(|| header("authorization")) {
+        keys.push((LimitKey::ApiKey, hex::encode(Sha256::digest(credential.as_bytes()))));
+    }
+
+    let peer = request.extensions().get::<ConnectInfo<SocketAddr>>().map(|info| info.0.ip());
+    let ip = client_ip(peer, header("x-forwarded-for"), trusted_proxies)
+        .map(|ip| ip.to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    keys.push((LimitKey::Ip, ip));
+
+    keys
+}
+
+/// The client at the other end of the connection. X-Forwarded-For is only read when the peer is one
+/// of our proxies, from the right, up to the first hop that isn't ours, anything before it is the
+/// client's say.
+pub fn client_ip(peer: Option<IpAddr>, forwarded_for: Option<&str>, trusted_proxies: &[IpAddr]) -> Option<IpAddr> {
+    let mut client = peer?;
+    for hop in forwarded_for.unwrap_or_default().rsplit(',') {
+        if !trusted_proxies.contains(&client) {
+            break;
+        }
+        match hop.trim().parse() {
+            Ok(ip) => client = ip,
+            Err(_) => break,
+        }
+    }
+    Some(client)
+}
+
+fn add_rate_limit_headers(headers: &mut HeaderMap, decision: &RateLimitDecision) {
+    headers.insert("ratelimit-limit", HeaderValue::from(decision.limit));
+    headers.insert("ratelimit-remaining", HeaderValue::from(decision.remaining));
+    headers.insert("ratelimit-reset", HeaderValue::from(decision.reset_after));
+}
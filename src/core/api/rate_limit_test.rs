@@ -0,0 +1,51 @@
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use axum::{http::{HeaderName, HeaderValue}, middleware, routing::get, Extension, Router};
+    use axum_test::TestServer;
+    use hyper::StatusCode;
+
+    use crate::{
+        api::authentication::Claims,
+        core::{
+            api::rate_limit::{client_ip, enforce_rate_limit},
+            infrastructure::limits::{BucketConfig, RateLimitConfig, RateLimiter},
+        },
+    };
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn test_forwarded_for_is_only_believed_from_trusted_proxies() {
+        let proxies = [ip("10.0.0.1"), ip("10.0.0.2")];
+
+        // straight from the client, whatever it claims
+        assert_eq!(client_ip(Some(ip("203.0.113.9")), Some("1.2.3.4"), &proxies), Some(ip("203.0.113.9")));
+        // through both of our proxies, what the client put in front doesn't count
+        assert_eq!(client_ip(Some(ip("10.0.0.1")), Some("1.2.3.4, 198.51.100.7, 10.0.0.2"), &proxies), Some(ip("198.51.100.7")));
+        assert_eq!(client_ip(Some(ip("10.0.0.1")), None, &proxies), Some(ip("10.0.0.1")));
+        assert_eq!(client_ip(None, Some("1.2.3.4"), &proxies), None);
+    }
+
+    #[tokio::test]
+    async fn test_the_merchant_bucket_follows_the_token_not_the_header() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            per_merchant: BucketConfig::new(1, 0.01),
+            ..Default::default()
+        });
+        let claims = Claims { sub: "merchant_user".to_string(), exp: i64::MAX, role: "merchant".to_string(), merchant_id: Some("merch_123".to_string()) };
+        let app = Router::new()
+            .route("/", get(|| async { "ok" }))
+            .route_layer(middleware::from_fn_with_state(limiter, enforce_rate_limit))
+            .layer(Extension(claims));
+        let server = TestServer::new(app).unwrap();
+
+        server.get("/").add_header(HeaderName::from_static("x-merchant-id"), HeaderValue::from_static("merch_a")).await.assert_status_ok();
+        let second = server.get("/").add_header(HeaderName::from_static("x-merchant-id"), HeaderValue::from_static("merch_b")).await;
+
+        second.assert_status(StatusCode::TOO_MANY_REQUESTS);
+    }
+}
@@ -0,0 +1,150 @@
+use axum::{
+    extract::{FromRef, Path, Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::{DateTime, Days, Utc};
+use hyper::StatusCode;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::api::{
+    authentication::{Claims, FINANCE_ROLE},
+    middleware::AuthenticatedUser,
+};
+use crate::core::{
+    api::state::AppState,
+    infrastructure::{
+        projection::TransactionProjection,
+        reconciliation::{parse_balance_transactions, reconcile, ReconciliationReport, ReconciliationStore},
+    },
+};
+
+/*request payload types*/
+#[derive(Deserialize)]
+pub struct ReconcileParams {
+    // default to the whole days covered by the export
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub format: ReportFormat,
+}
+
+#[derive(Deserialize)]
+pub struct ReportParams {
+    #[serde(default)]
+    pub format: ReportFormat,
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Clone)]
+pub struct ReconciliationState {
+    pub projection: TransactionProjection,
+    pub store: ReconciliationStore,
+}
+
+impl FromRef<AppState> for ReconciliationState {
+    fn from_ref(state: &AppState) -> Self {
+        Self {
+            projection: state.projection.clone(),
+            store: state.reconciliations.clone(),
+        }
+    }
+}
+
+pub fn reconciliation_routes() -> Router<AppState> {
+    Router::new()
+        .route("/stripe", post(reconcile_stripe_export))
+        .route("/reports/:id", get(get_report))
+}
+
+// reports cover every merchant, so they're for the finance team only
+fn is_finance(claims: &Claims) -> bool {
+    claims.is_operator(&[FINANCE_ROLE])
+}
+
+fn forbidden() -> Response {
+    (StatusCode::FORBIDDEN, Json(serde_json::json!({ "error": "Reconciliation is for the finance team" }))).into_response()
+}
+
+/* takes a stripe balance transaction export (csv) as the request body */
+async fn reconcile_stripe_export(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<ReconciliationState>,
+    Query(params): Query<ReconcileParams>,
+    body: String,
+) -> Response {
+    if !is_finance(&claims) {
+        return forbidden();
+    }
+
+    let rows = match parse_balance_transactions(&body) {
+        Ok(rows) => rows,
+        Err(e) => {
+            return (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": e.to_string() }))).into_response();
+        }
+    };
+
+    let first_day = rows.iter().map(|r| r.created).min().unwrap_or_else(Utc::now);
+    let last_day = rows.iter().map(|r| r.created).max().unwrap_or_else(Utc::now);
+
+    let period_start = params
+        .from
+        .unwrap_or_else(|| first_day.date_naive().and_hms_opt(0, 0, 0).expect("midnight is valid").and_utc());
+    let period_end = params.to.unwrap_or_else(|| {
+        (last_day.date_naive() + Days::new(1))
+            .and_hms_opt(0, 0, 0)
+            .expect("midnight is valid")
+            .and_utc()
+    });
+
+    let transactions = state.projection.with_provider_payment().await;
+    let report = reconcile(&rows, &transactions, period_start, period_end);
+
+    println!(
+        "Reconciled {} export rows: {} matched, {} discrepancies",
+        report.rows_read,
+        report.matched,
+        report.discrepancies.len()
+    );
+
+    state.store.save(report.clone()).await;
+
+    render(report, params.format)
+}
+
+async fn get_report(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<ReconciliationState>,
+    Path(id): Path<Uuid>,
+    Query(params): Query<ReportParams>,
+) -> Response {
+    if !is_finance(&claims) {
+        return forbidden();
+    }
+
+    match state.store.get(id).await {
+        Some(report) => render(report, params.format),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({ "error": format!("Reconciliation report {} not found", id) })),
+        )
+            .into_response(),
+    }
+}
+
+fn render(report: ReconciliationReport, format: ReportFormat) -> Response {
+    match format {
+        ReportFormat::Json => Json(report).into_response(),
+        ReportFormat::Csv => ([(header::CONTENT_TYPE, "text/csv")], report.to_csv()).into_response(),
+    }
+}
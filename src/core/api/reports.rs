@@ -0,0 +1,184 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, State},
+    http::header,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::{fs::File, io::AsyncReadExt};
+use uuid::Uuid;
+
+use crate::{
+    api::{
+        authentication::{Claims, FINANCE_ROLE},
+        middleware::AuthenticatedUser,
+    },
+    core::{
+        api::state::AppState,
+        infrastructure::reporting::{ReportFormat, ReportJob, ReportJobStatus, ReportKind, ReportRequest, Reports},
+    },
+};
+
+const DOWNLOAD_CHUNK_SIZE: usize = 64 * 1024;
+
+/*request payload types*/
+#[derive(Deserialize)]
+pub struct CreateReportRequest {
+    pub kind: ReportKind,
+    #[serde(default)]
+    pub format: ReportFormat,
+    pub merchant_id: Option<String>,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+}
+
+/*response payload types*/
+#[derive(Serialize, Deserialize)]
+pub struct ReportJobResponse {
+    #[serde(flatten)]
+    pub job: ReportJob,
+    // set once the report is ready
+    pub download_url: Option<String>,
+}
+
+impl From<ReportJob> for ReportJobResponse {
+    fn from(job: ReportJob) -> Self {
+        let download_url = match job.status {
+            ReportJobStatus::Completed => Some(format!("/api/v1/reports/{}/download", job.id)),
+            _ => None,
+        };
+        Self { job, download_url }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ReportApiError {
+    #[error("Report {0} not found")]
+    NotFound(Uuid),
+    #[error("Reports of merchant {0} are not available to this token")]
+    Forbidden(String),
+    #[error("Reports across merchants are for the finance team")]
+    FinanceOnly,
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("Report {0} is not ready")]
+    NotReady(Uuid),
+    #[error("Failed to read report: {0}")]
+    Io(String),
+}
+
+pub fn report_routes() -> Router<AppState> {
+    Router::new()
+        .route("/", post(create_report))
+        .route("/:id", get(get_report))
+        .route("/:id/download", get(download_report))
+}
+
+/* merchant tokens only get their own merchant's reports, platform tokens any merchant's,
+   and the finance team, like the ledger, all of them at once */
+fn scope(claims: &Claims, requested: Option<String>) -> Result<Option<String>, ReportApiError> {
+    match (&claims.merchant_id, requested) {
+        (Some(own), Some(requested)) if own != &requested => Err(ReportApiError::Forbidden(requested)),
+        (Some(own), _) => Ok(Some(own.clone())),
+        (None, None) if !claims.is_operator(&[FINANCE_ROLE]) => Err(ReportApiError::FinanceOnly),
+        (None, requested) => Ok(requested),
+    }
+}
+
+async fn visible_job(reports: &Reports, claims: &Claims, id: Uuid) -> Result<ReportJob, ReportApiError> {
+    let job = reports.job(id).await.ok_or(ReportApiError::NotFound(id))?;
+    let visible = match &job.request.merchant_id {
+        Some(merchant_id) => claims.may_act_for(merchant_id),
+        None => claims.is_operator(&[FINANCE_ROLE]),
+    };
+    if !visible {
+        return Err(ReportApiError::NotFound(id));
+    }
+    Ok(job)
+}
+
+async fn create_report(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(reports): State<Reports>,
+    Json(request): Json<CreateReportRequest>,
+) -> Result<impl IntoResponse, ReportApiError> {
+    let request = ReportRequest {
+        kind: request.kind,
+        format: request.format,
+        merchant_id: scope(&claims, request.merchant_id)?,
+        from: request.from,
+        to: request.to,
+    };
+    request.validate().map_err(ReportApiError::InvalidRequest)?;
+
+    let job = reports.submit(request, &claims.sub).await;
+    Ok((StatusCode::ACCEPTED, Json(ReportJobResponse::from(job))))
+}
+
+async fn get_report(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(reports): State<Reports>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ReportJobResponse>, ReportApiError> {
+    visible_job(&reports, &claims, id).await.map(|job| Json(job.into()))
+}
+
+/* streamed from disk, large reports are never loaded whole */
+async fn download_report(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(reports): State<Reports>,
+    Path(id): Path<Uuid>,
+) -> Result<Response, ReportApiError> {
+    let job = visible_job(&reports, &claims, id).await?;
+    if job.status != ReportJobStatus::Completed {
+        return Err(ReportApiError::NotReady(id));
+    }
+
+    let file = File::open(reports.job_file(&job)).await.map_err(|e| ReportApiError::Io(e.to_string()))?;
+    let chunks = futures_util::stream::unfold(Some(file), |file| async move {
+        let mut file = file?;
+        let mut buffer = vec![0; DOWNLOAD_CHUNK_SIZE];
+        match file.read(&mut buffer).await {
+            Ok(0) => None,
+            Ok(read) => {
+                buffer.truncate(read);
+                Some((Ok(Bytes::from(buffer)), Some(file)))
+            }
+            // the error ends the body, the client sees a truncated download rather than a short file
+            Err(e) => Some((Err(e), None)),
+        }
+    });
+
+    let disposition = format!("attachment; filename=\"{}\"", job.request.file_name());
+    Ok((
+        [
+            (header::CONTENT_TYPE, job.request.format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        Body::from_stream(chunks),
+    )
+        .into_response())
+}
+
+impl IntoResponse for ReportApiError {
+    fn into_response(self) -> axum::response::Response {
+        let status = match self {
+            ReportApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ReportApiError::Forbidden(_) | ReportApiError::FinanceOnly => StatusCode::FORBIDDEN,
+            ReportApiError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            ReportApiError::NotReady(_) => StatusCode::CONFLICT,
+            ReportApiError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        let body = Json(serde_json::json!({
+            "error": self.to_string()
+        }));
+
+        (status, body).into_response()
+    }
+}
@@ -0,0 +1,150 @@
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use axum::Extension;
+    use axum_test::TestServer;
+    use chrono::{TimeZone, Utc};
+    use hyper::StatusCode;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use crate::{
+        api::authentication::Claims,
+        core::{
+            api::{create_router_with, reports::ReportJobResponse, state::AppState},
+            config::Config,
+            events::TransactionCreatedEvent,
+            infrastructure::{publisher::InMemoryPublisher, reporting::ReportJobStatus},
+        },
+    };
+
+    fn claims(merchant_id: Option<&str>) -> Claims {
+        Claims { sub: "finance_user".to_string(), exp: i64::MAX, role: "finance".to_string(), merchant_id: merchant_id.map(str::to_string) }
+    }
+
+    fn state() -> AppState {
+        let mut config = Config::default();
+        config.reports.output_dir = std::env::temp_dir().join(format!("payme-reports-{}", Uuid::new_v4())).display().to_string();
+        AppState::new(&config, Arc::new(InMemoryPublisher::new()))
+    }
+
+    async fn server_as(state: AppState, claims: Claims) -> TestServer {
+        TestServer::new(create_router_with(state).await.layer(Extension(claims))).unwrap()
+    }
+
+    async fn create(state: &AppState, merchant_id: &str, amount: u64) {
+        let mut event = TransactionCreatedEvent::new(Uuid::new_v4(), amount, "USD".to_string(), merchant_id.to_string(), "cust_1".to_string());
+        event.timestamp = Utc.with_ymd_and_hms(2024, 3, 5, 10, 0, 0).unwrap();
+        state.projection.apply_created(&event).await;
+    }
+
+    async fn wait_for_completion(server: &TestServer, id: Uuid) -> ReportJobResponse {
+        for _ in 0..100 {
+            let job: ReportJobResponse = server.get(&format!("/api/v1/reports/{}", id)).await.json();
+            if job.job.status != ReportJobStatus::Pending {
+                return job;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("report {} never finished", id);
+    }
+
+    #[tokio::test]
+    async fn test_report_job_produces_a_download() {
+        let state = state();
+        create(&state, "merch_report", 1_500).await;
+        create(&state, "merch_other", 9_900).await;
+        let server = server_as(state.clone(), claims(Some("merch_report"))).await;
+
+        let response = server
+            .post("/api/v1/reports")
+            .json(&json!({ "kind": "transactions", "from": "2024-03-01T00:00:00Z", "to": "2024-04-01T00:00:00Z" }))
+            .await;
+        response.assert_status(StatusCode::ACCEPTED);
+        let submitted: ReportJobResponse = response.json();
+        // merchant tokens are pinned to their merchant
+        assert_eq!(submitted.job.request.merchant_id.as_deref(), Some("merch_report"));
+
+        let job = wait_for_completion(&server, submitted.job.id).await;
+        assert_eq!(job.job.status, ReportJobStatus::Completed);
+        assert_eq!(job.job.rows, Some(1));
+        let url = job.download_url.expect("a completed job links its download");
+
+        let download = server.get(&url).await;
+        download.assert_status_ok();
+        assert_eq!(download.header("content-type"), "text/csv; charset=utf-8");
+        assert_eq!(
+            download.header("content-disposition"),
+            "attachment; filename=\"transactions-2024-03-01-2024-04-01.csv\""
+        );
+        let csv = download.text();
+        assert_eq!(csv.lines().count(), 2);
+        assert!(csv.contains(",merch_report,cust_1,pending,1500,USD,"));
+
+        std::fs::remove_dir_all(state.reports.output_dir()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reports_are_scoped_to_the_merchant() {
+        let state = state();
+        let platform = server_as(state.clone(), claims(None)).await;
+        let submitted: ReportJobResponse = platform
+            .post("/api/v1/reports")
+            .json(&json!({ "kind": "refunds", "merchant_id": "merch_a", "from": "2024-03-01T00:00:00Z", "to": "2024-04-01T00:00:00Z" }))
+            .await
+            .json();
+
+        let merchant_b = server_as(state.clone(), claims(Some("merch_b"))).await;
+        merchant_b
+            .get(&format!("/api/v1/reports/{}", submitted.job.id))
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+        merchant_b
+            .post("/api/v1/reports")
+            .json(&json!({ "kind": "refunds", "merchant_id": "merch_a", "from": "2024-03-01T00:00:00Z", "to": "2024-04-01T00:00:00Z" }))
+            .await
+            .assert_status(StatusCode::FORBIDDEN);
+
+        wait_for_completion(&platform, submitted.job.id).await;
+        let _ = std::fs::remove_dir_all(state.reports.output_dir());
+    }
+
+    #[tokio::test]
+    async fn test_reports_across_merchants_are_finance_only() {
+        let state = state();
+        let finance = server_as(state.clone(), claims(None)).await;
+        let submitted: ReportJobResponse = finance
+            .post("/api/v1/reports")
+            .json(&json!({ "kind": "settlements", "from": "2024-03-01T00:00:00Z", "to": "2024-04-01T00:00:00Z" }))
+            .await
+            .json();
+
+        let risk = server_as(state.clone(), Claims { role: "risk".to_string(), ..claims(None) }).await;
+        risk.post("/api/v1/reports")
+            .json(&json!({ "kind": "settlements", "from": "2024-03-01T00:00:00Z", "to": "2024-04-01T00:00:00Z" }))
+            .await
+            .assert_status(StatusCode::FORBIDDEN);
+        risk.get(&format!("/api/v1/reports/{}", submitted.job.id))
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+        risk.post("/api/v1/reports")
+            .json(&json!({ "kind": "settlements", "merchant_id": "merch_a", "from": "2024-03-01T00:00:00Z", "to": "2024-04-01T00:00:00Z" }))
+            .await
+            .assert_status(StatusCode::ACCEPTED);
+
+        wait_for_completion(&finance, submitted.job.id).await;
+        let _ = std::fs::remove_dir_all(state.reports.output_dir());
+    }
+
+    #[tokio::test]
+    async fn test_empty_ranges_are_rejected() {
+        let server = server_as(state(), claims(None)).await;
+
+        server
+            .post("/api/v1/reports")
+            .json(&json!({ "kind": "fees", "from": "2024-04-01T00:00:00Z", "to": "2024-03-01T00:00:00Z" }))
+            .await
+            .assert_status(StatusCode::BAD_REQUEST);
+    }
+}
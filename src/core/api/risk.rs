@@ -0,0 +1,278 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{FromRef, Path, State},
+    response::IntoResponse,
+    routing::{delete, get, post},
+    Json, Router,
+};
+use chrono::Utc;
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::api::{
+    authentication::{Claims, RISK_ROLE},
+    middleware::AuthenticatedUser,
+};
+use crate::core::{
+    api::{audit::AuditContext, state::AppState},
+    config::Topics,
+    events::{PaymentStatusUpdatedEvent, TransactionCreatedEvent},
+    infrastructure::{
+        audit::AuditLog,
+        publisher::EventPublisher,
+        risk::{BlocklistKind, RiskError, RiskStore},
+    },
+    models::{AuditAction, RiskAction, RiskDecision, TransactionStatus},
+};
+
+/*request payload types*/
+#[derive(Deserialize)]
+pub struct UpdateRulesRequest {
+    // one rule per line, see `parse_rules`
+    pub rules: String,
+}
+
+#[derive(Deserialize)]
+pub struct BlocklistEntryRequest {
+    pub value: String,
+}
+
+// the reviewer is whoever the token belongs to
+#[derive(Deserialize)]
+pub struct ReviewRequest {
+    pub note: Option<String>,
+}
+
+/*response payload types*/
+#[derive(Serialize)]
+pub struct RulesResponse {
+    pub rules: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct BlocklistResponse {
+    pub kind: BlocklistKind,
+    pub values: Vec<String>,
+}
+
+#[derive(Debug, Error)]
+pub enum RiskApiError {
+    #[error(transparent)]
+    Risk(#[from] RiskError),
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("Failed to publish event: {0}")]
+    Publish(String),
+    #[error("Risk tooling is for the risk team")]
+    Forbidden,
+}
+
+#[derive(Clone)]
+pub struct RiskState {
+    pub store: RiskStore,
+    // approved payments go to the processor, declines straight to the status topic
+    pub publisher: Arc<dyn EventPublisher>,
+    pub topics: Arc<Topics>,
+}
+
+impl FromRef<AppState> for RiskState {
+    fn from_ref(state: &AppState) -> Self {
+        Self {
+            store: state.risk.clone(),
+            publisher: state.publisher.clone(),
+            topics: state.topics.clone(),
+        }
+    }
+}
+
+pub fn risk_routes() -> Router<AppState> {
+    Router::new()
+        .route("/rules", get(get_rules).put(update_rules))
+        .route("/blocklists/:kind", get(get_blocklist).post(add_to_blocklist))
+        .route("/blocklists/:kind/:value", delete(remove_from_blocklist))
+        .route("/reviews", get(pending_reviews))
+        .route("/reviews/:transaction_id/approve", post(approve))
+        .route("/reviews/:transaction_id/decline", post(decline))
+        .route("/decisions/:transaction_id", get(decisions))
+}
+
+async fn get_rules(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<RiskState>,
+) -> Result<Json<RulesResponse>, RiskApiError> {
+    require_risk_team(&claims)?;
+    Ok(Json(RulesResponse { rules: state.store.rules().await }))
+}
+
+async fn update_rules(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<RiskState>,
+    State(audit): State<AuditLog>,
+    context: AuditContext,
+    Json(payload): Json<UpdateRulesRequest>,
+) -> Result<Json<RulesResponse>, RiskApiError> {
+    require_risk_team(&claims)?;
+
+    let previous = state.store.rules().await;
+    let rules = state.store.set_rules(&payload.rules).await?;
+    audit.record(context.record(AuditAction::RiskRulesChanged).before(&previous).after(&rules)).await;
+    Ok(Json(RulesResponse { rules }))
+}
+
+async fn get_blocklist(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<RiskState>,
+    Path(kind): Path<String>,
+) -> Result<Json<BlocklistResponse>, RiskApiError> {
+    require_risk_team(&claims)?;
+
+    let kind: BlocklistKind = kind.parse()?;
+    Ok(Json(BlocklistResponse { kind, values: state.store.blocklist(kind).await }))
+}
+
+async fn add_to_blocklist(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<RiskState>,
+    State(audit): State<AuditLog>,
+    context: AuditContext,
+    Path(kind): Path<String>,
+    Json(payload): Json<BlocklistEntryRequest>,
+) -> Result<Json<BlocklistResponse>, RiskApiError> {
+    require_risk_team(&claims)?;
+
+    let kind: BlocklistKind = kind.parse()?;
+    if payload.value.trim().is_empty() {
+        return Err(RiskApiError::InvalidRequest("value can't be empty".to_string()));
+    }
+
+    let previous = state.store.blocklist(kind).await;
+    state.store.add_to_blocklist(kind, &payload.value).await;
+    let values = state.store.blocklist(kind).await;
+    audit.record(context.record(AuditAction::BlocklistChanged).target(format!("{:?}", kind)).before(&previous).after(&values)).await;
+    Ok(Json(BlocklistResponse { kind, values }))
+}
+
+async fn remove_from_blocklist(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<RiskState>,
+    State(audit): State<AuditLog>,
+    context: AuditContext,
+    Path((kind, value)): Path<(String, String)>,
+) -> Result<Json<BlocklistResponse>, RiskApiError> {
+    require_risk_team(&claims)?;
+
+    let kind: BlocklistKind = kind.parse()?;
+    let previous = state.store.blocklist(kind).await;
+    let removed = state.store.remove_from_blocklist(kind, &value).await;
+    let values = state.store.blocklist(kind).await;
+    if removed {
+        audit.record(context.record(AuditAction::BlocklistChanged).target(format!("{:?}", kind)).before(&previous).after(&values)).await;
+    }
+    Ok(Json(BlocklistResponse { kind, values }))
+}
+
+async fn pending_reviews(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<RiskState>,
+) -> Result<Json<Vec<TransactionCreatedEvent>>, RiskApiError> {
+    require_risk_team(&claims)?;
+    Ok(Json(state.store.pending_reviews().await))
+}
+
+/* releases a held payment to the processor */
+async fn approve(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<RiskState>,
+    State(audit): State<AuditLog>,
+    context: AuditContext,
+    Path(transaction_id): Path<Uuid>,
+    Json(payload): Json<ReviewRequest>,
+) -> Result<Json<RiskDecision>, RiskApiError> {
+    require_risk_team(&claims)?;
+
+    // the processor's pending SLA runs from the approval, not from the payment's creation
+    let event = state.store.take_review(transaction_id).await?.screened(Utc::now());
+
+    let key = event.transaction_id.to_string();
+    let published = match state.publisher.publish_event(&state.topics.transactions_screened, &key, &event).await {
+        Ok(()) => publish_status(&state, &event, TransactionStatus::Pending).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = published {
+        state.store.return_review(event).await;
+        return Err(RiskApiError::Publish(e));
+    }
+
+    let decision = state.store.record_review(&event, RiskAction::Allow, claims.sub.clone(), payload.note).await;
+    audit.record(context.record(AuditAction::ReviewApproved).target(transaction_id).before(&event).after(&decision)).await;
+    Ok(Json(decision))
+}
+
+async fn decline(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<RiskState>,
+    State(audit): State<AuditLog>,
+    context: AuditContext,
+    Path(transaction_id): Path<Uuid>,
+    Json(payload): Json<ReviewRequest>,
+) -> Result<Json<RiskDecision>, RiskApiError> {
+    require_risk_team(&claims)?;
+
+    let event = state.store.take_review(transaction_id).await?;
+
+    let status = TransactionStatus::Failed {
+        reason: "declined after review".to_string(),
+    };
+    if let Err(e) = publish_status(&state, &event, status).await {
+        state.store.return_review(event).await;
+        return Err(RiskApiError::Publish(e));
+    }
+
+    let decision = state.store.record_review(&event, RiskAction::Block, claims.sub.clone(), payload.note).await;
+    audit.record(context.record(AuditAction::ReviewDeclined).target(transaction_id).before(&event).after(&decision)).await;
+    Ok(Json(decision))
+}
+
+async fn publish_status(state: &RiskState, event: &TransactionCreatedEvent, status: TransactionStatus) -> Result<(), String> {
+    let status_event = PaymentStatusUpdatedEvent::new(event.transaction_id, event.merchant_id.clone(), status, String::new());
+    state.publisher.publish_event(&state.topics.payment_status, &event.transaction_id.to_string(), &status_event).await
+}
+
+fn require_risk_team(claims: &Claims) -> Result<(), RiskApiError> {
+    if claims.is_operator(&[RISK_ROLE]) {
+        Ok(())
+    } else {
+        Err(RiskApiError::Forbidden)
+    }
+}
+
+/* the audit trail, every automatic and manual decision for the payment in order */
+async fn decisions(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<RiskState>,
+    Path(transaction_id): Path<Uuid>,
+) -> Result<Json<Vec<RiskDecision>>, RiskApiError> {
+    require_risk_team(&claims)?;
+    Ok(Json(state.store.decisions(transaction_id).await))
+}
+
+impl IntoResponse for RiskApiError {
+    fn into_response(self) -> axum::response::Response {
+        let status = match self {
+            RiskApiError::Risk(RiskError::NotUnderReview(_)) => StatusCode::NOT_FOUND,
+            RiskApiError::Risk(RiskError::ReviewExpired(_)) => StatusCode::CONFLICT,
+            RiskApiError::Risk(_) | RiskApiError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            RiskApiError::Publish(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            RiskApiError::Forbidden => StatusCode::FORBIDDEN,
+        };
+
+        let body = Json(serde_json::json!({
+            "error": self.to_string()
+        }));
+
+        (status, body).into_response()
+    }
+}
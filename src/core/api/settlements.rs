@@ -0,0 +1,167 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{FromRef, Path, Query, State},
+    response::IntoResponse,
+    routing::{get, post},
+    Json, Router,
+};
+use hyper::StatusCode;
+use serde::Deserialize;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::api::{authentication::FINANCE_ROLE, middleware::AuthenticatedUser};
+use crate::core::{
+    api::{audit::AuditContext, state::AppState},
+    config::Topics,
+    events::PayoutStatusUpdatedEvent,
+    infrastructure::{
+        audit::AuditLog,
+        publisher::EventPublisher,
+        settlement::{SettlementError, SettlementStore},
+    },
+    models::{AuditAction, Payout, PayoutStatus, SettlementBatch},
+};
+
+/*request payload types*/
+#[derive(Deserialize)]
+pub struct BatchesFilter {
+    pub merchant_id: String,
+    pub currency: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdatePayoutRequest {
+    pub status: PayoutStatus,
+}
+
+#[derive(Debug, Error)]
+pub enum SettlementApiError {
+    #[error("Settlement batch {0} not found")]
+    BatchNotFound(Uuid),
+    #[error("Payout {0} not found")]
+    PayoutNotFound(Uuid),
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("Failed to publish event: {0}")]
+    Publish(String),
+    #[error("This token can't act for merchant {0}")]
+    Forbidden(String),
+    #[error("Payouts are confirmed by the finance team")]
+    NotFinance,
+}
+
+#[derive(Clone)]
+pub struct SettlementState {
+    pub store: SettlementStore,
+    pub publisher: Arc<dyn EventPublisher>,
+    pub topics: Arc<Topics>,
+}
+
+impl FromRef<AppState> for SettlementState {
+    fn from_ref(state: &AppState) -> Self {
+        Self {
+            store: state.settlements.clone(),
+            publisher: state.publisher.clone(),
+            topics: state.topics.clone(),
+        }
+    }
+}
+
+pub fn settlement_routes() -> Router<AppState> {
+    Router::new()
+        .route("/batches", get(list_batches))
+        .route("/batches/:id", get(get_batch))
+        .route("/payouts/:id", get(get_payout))
+        .route("/payouts/:id/status", post(update_payout_status))
+}
+
+async fn list_batches(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<SettlementState>,
+    Query(filter): Query<BatchesFilter>,
+) -> Result<Json<Vec<SettlementBatch>>, SettlementApiError> {
+    if !claims.may_act_for(&filter.merchant_id) {
+        return Err(SettlementApiError::Forbidden(filter.merchant_id));
+    }
+    Ok(Json(state.store.batches_for_merchant(&filter.merchant_id, filter.currency.as_deref()).await))
+}
+
+// other merchants' batches and payouts read as not found
+async fn get_batch(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<SettlementState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<SettlementBatch>, SettlementApiError> {
+    state
+        .store
+        .batch(id)
+        .await
+        .filter(|batch| claims.may_act_for(&batch.merchant_id))
+        .map(Json)
+        .ok_or(SettlementApiError::BatchNotFound(id))
+}
+
+async fn get_payout(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<SettlementState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Payout>, SettlementApiError> {
+    state
+        .store
+        .payout(id)
+        .await
+        .filter(|payout| claims.may_act_for(&payout.merchant_id))
+        .map(Json)
+        .ok_or(SettlementApiError::PayoutNotFound(id))
+}
+
+/* called once the bank confirms or rejects the transfer */
+async fn update_payout_status(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<SettlementState>,
+    State(audit): State<AuditLog>,
+    context: AuditContext,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<UpdatePayoutRequest>,
+) -> Result<Json<Payout>, SettlementApiError> {
+    if !claims.is_operator(&[FINANCE_ROLE]) {
+        return Err(SettlementApiError::NotFinance);
+    }
+    if payload.status == PayoutStatus::Pending {
+        return Err(SettlementApiError::InvalidRequest("a payout can only move to Paid or Failed".to_string()));
+    }
+
+    let previous = state.store.payout(id).await;
+    let payout = state.store.update_payout(id, payload.status).await.map_err(|e| match e {
+        SettlementError::UnknownPayout(id) => SettlementApiError::PayoutNotFound(id),
+        e => SettlementApiError::InvalidRequest(e.to_string()),
+    })?;
+
+    state
+        .publisher
+        .publish_event(&state.topics.payout_status, &payout.id.to_string(), &PayoutStatusUpdatedEvent::new(&payout))
+        .await
+        .map_err(SettlementApiError::Publish)?;
+
+    audit.record(context.record(AuditAction::PayoutStatusChanged).target(payout.id).before(&previous).after(&payout)).await;
+    Ok(Json(payout))
+}
+
+impl IntoResponse for SettlementApiError {
+    fn into_response(self) -> axum::response::Response {
+        let status = match self {
+            SettlementApiError::BatchNotFound(_) | SettlementApiError::PayoutNotFound(_) => StatusCode::NOT_FOUND,
+            SettlementApiError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            SettlementApiError::Publish(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            SettlementApiError::Forbidden(_) | SettlementApiError::NotFinance => StatusCode::FORBIDDEN,
+        };
+
+        let body = Json(serde_json::json!({
+            "error": self.to_string()
+        }));
+
+        (status, body).into_response()
+    }
+}
@@ -0,0 +1,82 @@
+use std::sync::Arc;
+
+use axum::extract::FromRef;
+
+use crate::core::{
+    api::stripe_webhooks::StripeWebhookState,
+    config::{Config, Topics},
+    infrastructure::{
+        audit::AuditLog,
+        billing::BillingStore,
+        customers::CustomerStore,
+        ledger::Ledger,
+        limits::{RateLimiter, VelocityLimiter},
+        pricing::PricingStore,
+        projection::TransactionProjection,
+        publisher::EventPublisher,
+        reconciliation::ReconciliationStore,
+        reporting::Reports,
+        risk::RiskStore,
+        settlement::SettlementStore,
+        splits::ConnectedAccountStore,
+        status_hub::StatusHub,
+        stripe::StripeService,
+    },
+};
+
+/// Everything the api handlers share. Handlers take the part they need through `State<_>`,
+/// the stores are also handed to the consumers running in the same process.
+#[derive(Clone, FromRef)]
+pub struct AppState {
+    pub publisher: Arc<dyn EventPublisher>,
+    pub topics: Arc<Topics>,
+    pub stripe_service: Arc<StripeService>,
+    pub projection: TransactionProjection,
+    pub status_hub: StatusHub,
+    pub customers: CustomerStore,
+    pub billing: BillingStore,
+    pub ledger: Ledger,
+    pub pricing: PricingStore,
+    pub settlements: SettlementStore,
+    pub risk: RiskStore,
+    pub connected_accounts: ConnectedAccountStore,
+    pub reconciliations: ReconciliationStore,
+    pub reports: Reports,
+    pub velocity: VelocityLimiter,
+    pub rate_limiter: RateLimiter,
+    pub stripe_webhooks: StripeWebhookState,
+    pub audit: AuditLog,
+}
+
+impl AppState {
+    /// Fresh stores with the limits in `config`, publishing through `publisher` to its topics.
+    pub fn new(config: &Config, publisher: Arc<dyn EventPublisher>) -> Self {
+        let webhook_secret = config.stripe.webhook_secret.expose().to_string();
+        let projection = TransactionProjection::new();
+        let settlements = SettlementStore::new(config.settlements.schedule());
+
+        Self {
+            topics: Arc::new(config.kafka.topics.clone()),
+            stripe_service: Arc::new(StripeService::new(config.stripe.secret_key.expose(), &config.server.public_url)),
+            reports: Reports::new(projection.clone(), settlements.clone(), &config.reports.output_dir),
+            projection,
+            status_hub: StatusHub::new(),
+            customers: CustomerStore::new(),
+            billing: BillingStore::new(),
+            ledger: Ledger::new(),
+            pricing: PricingStore::new(),
+            settlements,
+            risk: RiskStore::new().with_review_sla(config.sweeper.review_sla()),
+            connected_accounts: ConnectedAccountStore::new(),
+            reconciliations: ReconciliationStore::new(),
+            velocity: VelocityLimiter::new(config.velocity.clone()),
+            rate_limiter: RateLimiter::new(config.rate_limits.clone()),
+            stripe_webhooks: StripeWebhookState::new(webhook_secret, publisher.clone(), &config.kafka.topics.payment_status),
+            audit: match config.audit.log_path.as_str() {
+                "" => AuditLog::new(),
+                path => AuditLog::open(path).expect("Failed to open the audit log"),
+            },
+            publisher,
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use std::{convert::Infallible, time::Duration};
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        FromRef, Path, Query, State,
+    },
+    http::HeaderMap,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    Json,
+};
+use futures_util::Stream;
+use hyper::StatusCode;
+use serde::Deserialize;
+use thiserror::Error;
+use utoipa::IntoParams;
+use uuid::Uuid;
+
+use crate::{
+    api::{authentication::Claims, middleware::AuthenticatedUser},
+    core::{
+        api::{openapi::ErrorResponse, state::AppState},
+        infrastructure::{
+            projection::TransactionProjection,
+            status_hub::{StatusChange, StatusHub, StatusSubscription},
+        },
+    },
+};
+
+// proxies drop connections that stay quiet for too long, a heartbeat keeps idle streams open
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+/*request payload types*/
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ResumeQuery {
+    // websockets have no `Last-Event-ID` header, the id goes in the query instead
+    pub last_event_id: Option<u64>,
+}
+
+#[derive(Debug, Error)]
+pub enum StatusStreamError {
+    #[error("Transaction {0} not found")]
+    NotFound(Uuid),
+    #[error("Transaction {0} is not visible to this token")]
+    Forbidden(Uuid),
+}
+
+#[derive(Clone)]
+pub struct StatusStreamState {
+    pub projection: TransactionProjection,
+    pub hub: StatusHub,
+}
+
+impl FromRef<AppState> for StatusStreamState {
+    fn from_ref(state: &AppState) -> Self {
+        Self {
+            projection: state.projection.clone(),
+            hub: state.status_hub.clone(),
+        }
+    }
+}
+
+impl StatusStreamState {
+    /// Subscribes the caller to a transaction it may see. Clients that can't be caught up from
+    /// the replay, new ones included, first get where the transaction stands right now.
+    pub async fn open(&self, claims: &Claims, transaction_id: Uuid, last_event_id: Option<u64>) -> Result<StatusSubscription, StatusStreamError> {
+        let transaction = self
+            .projection
+            .get(transaction_id)
+            .await
+            .ok_or(StatusStreamError::NotFound(transaction_id))?;
+
+        if claims.merchant_id.as_ref().is_some_and(|own| own != &transaction.merchant_id) {
+            return Err(StatusStreamError::Forbidden(transaction_id));
+        }
+
+        let mut subscription = self.hub.subscribe(transaction_id, last_event_id);
+        if !subscription.resumed {
+            // read after subscribing, so a change racing the snapshot is delivered again rather than lost
+            let current = self.projection.get(transaction_id).await.unwrap_or(transaction);
+            subscription.start_from(StatusChange::snapshot(&current, subscription.position));
+        }
+
+        Ok(subscription)
+    }
+}
+
+fn last_event_id(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get("last-event-id")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|id| id.trim().parse().ok())
+}
+
+fn sse_event(change: &StatusChange) -> Event {
+    Event::default()
+        .id(change.sequence.to_string())
+        .event("status")
+        .data(serde_json::to_string(change).expect("status changes serialize"))
+}
+
+/// `text/event-stream` of the transaction's status changes, resumable through `Last-Event-ID`.
+#[utoipa::path(
+    get,
+    path = "/api/v1/transactions/{id}/events",
+    tag = "queries",
+    params(
+        ("id" = Uuid, Path, description = "Transaction id"),
+        ("last-event-id" = Option<u64>, Header, description = "Sequence of the last change received, to resume after it"),
+    ),
+    responses(
+        (status = 200, description = "Server-sent `status` events, the current status first unless resuming", content_type = "text/event-stream", body = StatusChange),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Another merchant's payment", body = ErrorResponse),
+        (status = 404, description = "Unknown transaction", body = ErrorResponse),
+    )
+)]
+pub async fn stream_events(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(streams): State<StatusStreamState>,
+    Path(transaction_id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, StatusStreamError> {
+    let subscription = streams.open(&claims, transaction_id, last_event_id(&headers)).await?;
+
+    let events = futures_util::stream::unfold(subscription, |mut subscription| async move {
+        let change = subscription.next().await?;
+        Some((Ok(sse_event(&change)), subscription))
+    });
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::new().interval(HEARTBEAT_INTERVAL)))
+}
+
+/// The same changes over a websocket, one json text message each, resumable through `?last_event_id=`.
+#[utoipa::path(
+    get,
+    path = "/api/v1/transactions/{id}/ws",
+    tag = "queries",
+    params(("id" = Uuid, Path, description = "Transaction id"), ResumeQuery),
+    responses(
+        (status = 101, description = "Upgraded, every text message is a `StatusChange`", body = StatusChange),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Another merchant's payment", body = ErrorResponse),
+        (status = 404, description = "Unknown transaction", body = ErrorResponse),
+    )
+)]
+pub async fn stream_websocket(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(streams): State<StatusStreamState>,
+    Path(transaction_id): Path<Uuid>,
+    Query(resume): Query<ResumeQuery>,
+    upgrade: WebSocketUpgrade,
+) -> Result<Response, StatusStreamError> {
+    let subscription = streams.open(&claims, transaction_id, resume.last_event_id).await?;
+
+    Ok(upgrade.on_upgrade(move |socket| forward_to_socket(socket, subscription)))
+}
+
+async fn forward_to_socket(mut socket: WebSocket, mut subscription: StatusSubscription) {
+    let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+    heartbeat.tick().await;
+
+    loop {
+        tokio::select! {
+            change = subscription.next() => {
+                let Some(change) = change else { break };
+                let message = Message::Text(serde_json::to_string(&change).expect("status changes serialize"));
+                if socket.send(message).await.is_err() {
+                    return;
+                }
+            }
+            _ = heartbeat.tick() => {
+                if socket.send(Message::Ping(Vec::new())).await.is_err() {
+                    return;
+                }
+            }
+            incoming = socket.recv() => match incoming {
+                // clients only ever answer pings, anything else is ignored
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+
+    // the hub closed or we fell behind, the client reconnects with its last event id
+    let _ = socket.send(Message::Close(None)).await;
+}
+
+impl IntoResponse for StatusStreamError {
+    fn into_response(self) -> axum::response::Response {
+        let status = match self {
+            StatusStreamError::NotFound(_) => StatusCode::NOT_FOUND,
+            StatusStreamError::Forbidden(_) => StatusCode::FORBIDDEN,
+        };
+
+        let body = Json(serde_json::json!({
+            "error": self.to_string()
+        }));
+
+        (status, body).into_response()
+    }
+}
@@ -0,0 +1,154 @@
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use axum::{body::Body, http::Request, Extension, Router};
+    use futures_util::StreamExt;
+    use http_body_util::BodyExt;
+    use hyper::StatusCode;
+    use tokio_tungstenite::tungstenite::Message;
+    use tower::ServiceExt;
+    use uuid::Uuid;
+
+    use crate::{
+        api::authentication::Claims,
+        core::{
+            api::{create_router_with, state::AppState},
+            config::Config,
+            events::{PaymentStatusUpdatedEvent, TransactionCreatedEvent},
+            infrastructure::{publisher::InMemoryPublisher, status_hub::StatusChange},
+            models::TransactionStatus,
+        },
+    };
+
+    fn merchant_user(merchant_id: &str) -> Claims {
+        Claims { sub: "merchant_user".to_string(), exp: i64::MAX, role: "merchant".to_string(), merchant_id: Some(merchant_id.to_string()) }
+    }
+
+    async fn app_as(state: AppState, claims: Claims) -> Router {
+        create_router_with(state).await.layer(Extension(claims))
+    }
+
+    /* a pending transaction of `merch_stream`, as the status consumer would have projected it */
+    async fn state_with_transaction() -> (AppState, Uuid) {
+        let state = AppState::new(&Config::default(), Arc::new(InMemoryPublisher::new()));
+        let id = Uuid::new_v4();
+        let created = TransactionCreatedEvent::new(id, 2500, "USD".to_string(), "merch_stream".to_string(), "cust_1".to_string());
+        state.projection.apply_created(&created).await;
+        (state, id)
+    }
+
+    /* what the status consumer does with an update */
+    async fn move_to(state: &AppState, id: Uuid, status: TransactionStatus) -> StatusChange {
+        let event = PaymentStatusUpdatedEvent::new(id, "merch_stream".to_string(), status, "pi_stream".to_string());
+        state.projection.apply_status(&event).await;
+        state.status_hub.publish(&event)
+    }
+
+    /* the id and data of the next event on the stream, heartbeats are skipped */
+    async fn next_event(body: &mut Body) -> (u64, StatusChange) {
+        let mut buffered = String::new();
+        loop {
+            let frame = tokio::time::timeout(Duration::from_secs(2), body.frame())
+                .await
+                .expect("an event in time")
+                .expect("stream still open")
+                .unwrap();
+            let Ok(chunk) = frame.into_data() else { continue };
+            buffered.push_str(std::str::from_utf8(&chunk).unwrap());
+
+            if let Some(end) = buffered.find("\n\n") {
+                let event: String = buffered.drain(..end + 2).collect();
+                let field = |name: &str| {
+                    event
+                        .lines()
+                        .find_map(|line| line.strip_prefix(name))
+                        .map(|value| value.trim().to_string())
+                };
+                let (Some(id), Some(data)) = (field("id:"), field("data:")) else { continue };
+                return (id.parse().unwrap(), serde_json::from_str(&data).unwrap());
+            }
+        }
+    }
+
+    fn events_request(id: Uuid, last_event_id: Option<u64>) -> Request<Body> {
+        let mut request = Request::get(format!("/api/v1/transactions/{}/events", id));
+        if let Some(last) = last_event_id {
+            request = request.header("last-event-id", last.to_string());
+        }
+        request.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_sse_starts_with_the_current_status_then_follows_changes() {
+        let (state, id) = state_with_transaction().await;
+        let app = app_as(state.clone(), merchant_user("merch_stream")).await;
+
+        let response = app.oneshot(events_request(id, None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "text/event-stream");
+        let mut body = response.into_body();
+
+        let (_, snapshot) = next_event(&mut body).await;
+        assert_eq!(snapshot.status, TransactionStatus::Pending);
+
+        let completed = move_to(&state, id, TransactionStatus::Completed).await;
+        let (event_id, change) = next_event(&mut body).await;
+        assert_eq!(event_id, completed.sequence);
+        assert_eq!(change, completed);
+    }
+
+    #[tokio::test]
+    async fn test_sse_resumes_from_last_event_id() {
+        let (state, id) = state_with_transaction().await;
+        let action = move_to(&state, id, TransactionStatus::RequiresAction { next_action_url: "https://3ds".to_string() }).await;
+        let completed = move_to(&state, id, TransactionStatus::Completed).await;
+        let app = app_as(state, merchant_user("merch_stream")).await;
+
+        let response = app.oneshot(events_request(id, Some(action.sequence))).await.unwrap();
+        let mut body = response.into_body();
+
+        let (event_id, change) = next_event(&mut body).await;
+        assert_eq!(event_id, completed.sequence);
+        assert_eq!(change.status, TransactionStatus::Completed);
+    }
+
+    #[tokio::test]
+    async fn test_streams_are_scoped_to_the_merchant() {
+        let (state, id) = state_with_transaction().await;
+
+        let other = app_as(state.clone(), merchant_user("merch_other")).await;
+        let response = other.oneshot(events_request(id, None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let own = app_as(state.clone(), merchant_user("merch_stream")).await;
+        let response = own.oneshot(events_request(Uuid::new_v4(), None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let anonymous = create_router_with(state).await;
+        let response = anonymous.oneshot(events_request(id, None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_websocket_streams_status_changes() {
+        let (state, id) = state_with_transaction().await;
+        let app = app_as(state.clone(), merchant_user("merch_stream")).await;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let url = format!("ws://{}/api/v1/transactions/{}/ws", address, id);
+        let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+
+        let next_change = |message: Message| serde_json::from_str::<StatusChange>(message.to_text().unwrap()).unwrap();
+        let snapshot = next_change(socket.next().await.unwrap().unwrap());
+        assert_eq!(snapshot.status, TransactionStatus::Pending);
+
+        let failed = move_to(&state, id, TransactionStatus::Failed { reason: "card_declined".to_string() }).await;
+        assert_eq!(next_change(socket.next().await.unwrap().unwrap()), failed);
+
+        state.status_hub.close();
+        assert!(matches!(socket.next().await, Some(Ok(Message::Close(_)))));
+    }
+}
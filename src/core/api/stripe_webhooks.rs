@@ -0,0 +1,149 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::HeaderMap,
+    response::IntoResponse,
+    routing::post,
+    Json, Router,
+};
+use chrono::{DateTime, Duration, Utc};
+use hyper::StatusCode;
+
+use crate::core::{api::state::AppState, infrastructure::{
+    publisher::EventPublisher,
+    stripe_webhook::{
+        parse_event, to_status_event, verify_signature, StripeWebhookError,
+        DEFAULT_TOLERANCE_SECS, STRIPE_SIGNATURE_HEADER,
+    },
+}};
+
+// Stripe retries an undelivered event for up to three days, an id older than that won't come back
+const RETRY_WINDOW_DAYS: i64 = 3;
+
+/// The ids of the events already handled, forgotten once Stripe would no longer redeliver them.
+#[derive(Default)]
+pub struct ProcessedEvents {
+    claimed_at: HashMap<String, DateTime<Utc>>,
+    // oldest first, entries whose id was released or claimed again are skipped when expiring
+    claims: VecDeque<(DateTime<Utc>, String)>,
+}
+
+impl ProcessedEvents {
+    /// False when the id was claimed within the retry window, the event is a redelivery.
+    pub fn claim(&mut self, event_id: &str, now: DateTime<Utc>) -> bool {
+        self.expire(now - Duration::days(RETRY_WINDOW_DAYS));
+        if self.claimed_at.contains_key(event_id) {
+            return false;
+        }
+
+        self.claimed_at.insert(event_id.to_string(), now);
+        self.claims.push_back((now, event_id.to_string()));
+        true
+    }
+
+    /* the event wasn't handled after all, the next delivery gets another go */
+    pub fn release(&mut self, event_id: &str) {
+        self.claimed_at.remove(event_id);
+    }
+
+    fn expire(&mut self, before: DateTime<Utc>) {
+        while let Some((at, _)) = self.claims.front() {
+            if *at >= before {
+                return;
+            }
+            let (at, event_id) = self.claims.pop_front().unwrap();
+            if self.claimed_at.get(&event_id) == Some(&at) {
+                self.claimed_at.remove(&event_id);
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct StripeWebhookState {
+    secret: String,
+    publisher: Arc<dyn EventPublisher>,
+    status_topic: String,
+    // Stripe delivers at least once, in production this would be a table keyed by event id
+    processed_events: Arc<Mutex<ProcessedEvents>>,
+}
+
+impl StripeWebhookState {
+    pub fn new(secret: String, publisher: Arc<dyn EventPublisher>, status_topic: &str) -> Self {
+        Self {
+            secret,
+            publisher,
+            status_topic: status_topic.to_string(),
+            processed_events: Arc::new(Mutex::new(ProcessedEvents::default())),
+        }
+    }
+}
+
+pub fn stripe_webhook_routes() -> Router<AppState> {
+    Router::new()
+        .route("/stripe", post(receive_stripe_event))
+}
+
+async fn receive_stripe_event(
+    State(state): State<StripeWebhookState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<StatusCode, StripeWebhookError> {
+    let signature = headers
+        .get(STRIPE_SIGNATURE_HEADER)
+        .and_then(|h| h.to_str().ok())
+        .ok_or(StripeWebhookError::MissingSignature)?;
+
+    let payload = std::str::from_utf8(&body)
+        .map_err(|e| StripeWebhookError::MalformedPayload(e.to_string()))?;
+
+    let now = Utc::now();
+    verify_signature(payload, signature, &state.secret, now, DEFAULT_TOLERANCE_SECS)?;
+
+    let event = parse_event(&body)?;
+
+    // claim the event id up front so concurrent redeliveries don't both publish
+    if !state.processed_events.lock().unwrap().claim(&event.id, now) {
+        println!("Stripe event {} already processed, skipping", event.id);
+        return Ok(StatusCode::OK);
+    }
+
+    let Some(status_event) = to_status_event(&event) else {
+        println!("Ignoring Stripe event {} of type {}", event.id, event.event_type);
+        return Ok(StatusCode::OK);
+    };
+
+    let key = status_event.transaction_id.to_string();
+    if let Err(e) = state.publisher.publish_event(&state.status_topic, &key, &status_event).await {
+        // release the id so Stripe's retry gets another go
+        state.processed_events.lock().unwrap().release(&event.id);
+        return Err(StripeWebhookError::Publish(e));
+    }
+
+    Ok(StatusCode::OK)
+}
+
+impl IntoResponse for StripeWebhookError {
+    fn into_response(self) -> axum::response::Response {
+        let status = match self {
+            StripeWebhookError::MissingSignature
+            | StripeWebhookError::InvalidSignature
+            | StripeWebhookError::TimestampOutsideTolerance
+            | StripeWebhookError::MalformedPayload(_) => StatusCode::BAD_REQUEST,
+            StripeWebhookError::MissingSecret | StripeWebhookError::Publish(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+
+        let body = Json(serde_json::json!({
+            "error": self.to_string()
+        }));
+
+        (status, body).into_response()
+    }
+}
@@ -0,0 +1,157 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::http::{HeaderName, HeaderValue};
+    use axum_test::TestServer;
+    use chrono::{DateTime, Duration, Utc};
+
+    use crate::core::{
+        api::{create_router_with, state::AppState, stripe_webhooks::ProcessedEvents},
+        config::{Config, Secret},
+        infrastructure::{
+            publisher::InMemoryPublisher,
+            stripe_webhook::{parse_event, to_status_event, verify_signature, StripeWebhookError, DEFAULT_TOLERANCE_SECS, STRIPE_SIGNATURE_HEADER},
+            webhook::signature_header,
+        },
+        models::TransactionStatus,
+    };
+
+    const SECRET: &str = "whsec_test_secret";
+
+    const SUCCEEDED: &str = include_str!("../../../fixtures/stripe/payment_intent_succeeded.json");
+    const FAILED: &str = include_str!("../../../fixtures/stripe/payment_intent_payment_failed.json");
+    const CANCELED: &str = include_str!("../../../fixtures/stripe/payment_intent_canceled.json");
+    const REFUNDED: &str = include_str!("../../../fixtures/stripe/charge_refunded.json");
+    const CUSTOMER_CREATED: &str = include_str!("../../../fixtures/stripe/customer_created.json");
+
+    fn now() -> DateTime<Utc> {
+        DateTime::from_timestamp(1709640000, 0).unwrap()
+    }
+
+    #[test]
+    fn test_verify_signature_accepts_valid_signature() {
+        let header = signature_header(SECRET, now().timestamp(), SUCCEEDED);
+
+        assert!(verify_signature(SUCCEEDED, &header, SECRET, now(), DEFAULT_TOLERANCE_SECS).is_ok());
+    }
+
+    #[test]
+    fn test_verify_signature_accepts_any_rolled_secret() {
+        let valid = signature_header(SECRET, now().timestamp(), SUCCEEDED);
+        let header = format!("t={},v1=deadbeef,{}", now().timestamp(), valid.split_once(',').unwrap().1);
+
+        assert!(verify_signature(SUCCEEDED, &header, SECRET, now(), DEFAULT_TOLERANCE_SECS).is_ok());
+    }
+
+    #[test]
+    fn test_verify_signature_rejects_tampered_body() {
+        let header = signature_header(SECRET, now().timestamp(), SUCCEEDED);
+        let tampered = SUCCEEDED.replace("1000", "1");
+
+        assert!(matches!(
+            verify_signature(&tampered, &header, SECRET, now(), DEFAULT_TOLERANCE_SECS),
+            Err(StripeWebhookError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn test_verify_signature_rejects_stale_timestamp() {
+        let signed_at = now().timestamp() - DEFAULT_TOLERANCE_SECS - 1;
+        let header = signature_header(SECRET, signed_at, SUCCEEDED);
+
+        assert!(matches!(
+            verify_signature(SUCCEEDED, &header, SECRET, now(), DEFAULT_TOLERANCE_SECS),
+            Err(StripeWebhookError::TimestampOutsideTolerance)
+        ));
+    }
+
+    #[test]
+    fn test_verify_signature_requires_secret() {
+        let header = signature_header(SECRET, now().timestamp(), SUCCEEDED);
+
+        assert!(matches!(
+            verify_signature(SUCCEEDED, &header, "", now(), DEFAULT_TOLERANCE_SECS),
+            Err(StripeWebhookError::MissingSecret)
+        ));
+    }
+
+    #[test]
+    fn test_payment_intent_succeeded_maps_to_completed() {
+        let event = parse_event(SUCCEEDED.as_bytes()).unwrap();
+        let status_event = to_status_event(&event).unwrap();
+
+        assert_eq!(status_event.status, TransactionStatus::Completed);
+        assert_eq!(status_event.transaction_id.to_string(), "5f0c6a4e-3b7e-4d2a-9a51-0c2f3d8e7b11");
+        assert_eq!(status_event.merchant_id, "merch_123");
+        assert_eq!(status_event.stripe_payment_id, "pi_3OqYbZ2eZvKYlo2C0xYz1234");
+    }
+
+    #[test]
+    fn test_payment_failed_carries_decline_message() {
+        let event = parse_event(FAILED.as_bytes()).unwrap();
+        let status_event = to_status_event(&event).unwrap();
+
+        assert_eq!(
+            status_event.status,
+            TransactionStatus::Failed { reason: "Your card has insufficient funds.".to_string() }
+        );
+    }
+
+    #[test]
+    fn test_canceled_maps_to_failed() {
+        let event = parse_event(CANCELED.as_bytes()).unwrap();
+        let status_event = to_status_event(&event).unwrap();
+
+        assert_eq!(
+            status_event.status,
+            TransactionStatus::Failed { reason: "canceled: abandoned".to_string() }
+        );
+    }
+
+    #[test]
+    fn test_charge_refunded_maps_to_refunded() {
+        let event = parse_event(REFUNDED.as_bytes()).unwrap();
+        let status_event = to_status_event(&event).unwrap();
+
+        assert_eq!(status_event.status, TransactionStatus::Refunded);
+        assert_eq!(status_event.stripe_payment_id, "pi_3OqYbZ2eZvKYlo2C0xYz1234");
+    }
+
+    #[test]
+    fn test_unhandled_event_is_ignored() {
+        let event = parse_event(CUSTOMER_CREATED.as_bytes()).unwrap();
+
+        assert!(to_status_event(&event).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_redelivered_event_is_acknowledged_without_publishing_again() {
+        let mut config = Config::default();
+        config.stripe.webhook_secret = Secret::new(SECRET);
+        let publisher = Arc::new(InMemoryPublisher::new());
+        let server = TestServer::new(create_router_with(AppState::new(&config, publisher.clone())).await).unwrap();
+
+        for _ in 0..2 {
+            let header = signature_header(SECRET, Utc::now().timestamp(), SUCCEEDED);
+            server
+                .post("/webhooks/stripe")
+                .add_header(HeaderName::from_static(STRIPE_SIGNATURE_HEADER), HeaderValue::from_str(&header).unwrap())
+                .text(SUCCEEDED)
+                .await
+                .assert_status_ok();
+        }
+
+        assert_eq!(publisher.published(&config.kafka.topics.payment_status).len(), 1);
+    }
+
+    #[test]
+    fn test_processed_events_are_forgotten_after_the_retry_window() {
+        let mut processed = ProcessedEvents::default();
+        assert!(processed.claim("evt_1", now()));
+        assert!(!processed.claim("evt_1", now() + Duration::days(2)));
+
+        assert!(processed.claim("evt_1", now() + Duration::days(4)));
+        assert!(!processed.claim("evt_1", now() + Duration::days(5)));
+    }
+}
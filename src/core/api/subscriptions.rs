@@ -0,0 +1,329 @@
+use std::str::FromStr;
+
+use axum::{
+    extract::{FromRef, Path, Query, State},
+    response::IntoResponse,
+    routing::{get, post, put},
+    Json, Router,
+};
+use chrono::{DateTime, Duration, Utc};
+use hyper::StatusCode;
+use serde::Deserialize;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::api::{authentication::Claims, middleware::AuthenticatedUser};
+use crate::core::{
+    api::state::AppState,
+    infrastructure::{
+        billing::{plan_change_proration, BillingStore},
+        customers::CustomerStore,
+    },
+    models::{BillingInterval, Currency, Invoice, Plan, Subscription, SubscriptionStatus},
+};
+
+/*request payload types*/
+#[derive(Deserialize)]
+pub struct CreatePlanRequest {
+    pub merchant_id: String,
+    pub name: String,
+    pub amount: u64,
+    pub currency: String,
+    pub interval: BillingInterval,
+    #[serde(default = "default_interval_count")]
+    pub interval_count: u32,
+    #[serde(default)]
+    pub trial_days: u32,
+}
+
+fn default_interval_count() -> u32 {
+    1
+}
+
+#[derive(Deserialize)]
+pub struct PlansFilter {
+    pub merchant_id: String,
+}
+
+#[derive(Deserialize)]
+pub struct CreateSubscriptionRequest {
+    pub merchant_id: String,
+    pub customer_id: String,
+    pub plan_id: Uuid,
+    // defaults to the end of the trial, or now without one
+    pub billing_anchor: Option<DateTime<Utc>>,
+    // overrides the plan's trial length
+    pub trial_days: Option<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct ChangePlanRequest {
+    pub plan_id: Uuid,
+    #[serde(default = "default_prorate")]
+    pub prorate: bool,
+}
+
+fn default_prorate() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+pub struct CancelSubscriptionRequest {
+    #[serde(default)]
+    pub at_period_end: bool,
+}
+
+#[derive(Debug, Error)]
+pub enum BillingError {
+    #[error("Plan {0} not found")]
+    PlanNotFound(Uuid),
+    #[error("Subscription {0} not found")]
+    SubscriptionNotFound(Uuid),
+    #[error("Customer {0} not found")]
+    CustomerNotFound(String),
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("This token can't act for merchant {0}")]
+    Forbidden(String),
+}
+
+#[derive(Clone)]
+pub struct BillingState {
+    pub store: BillingStore,
+    pub customers: CustomerStore,
+}
+
+impl FromRef<AppState> for BillingState {
+    fn from_ref(state: &AppState) -> Self {
+        Self {
+            store: state.billing.clone(),
+            customers: state.customers.clone(),
+        }
+    }
+}
+
+pub fn billing_routes() -> Router<AppState> {
+    Router::new()
+        .route("/plans", post(create_plan).get(list_plans))
+        .route("/plans/:id", get(get_plan))
+        .route("/subscriptions", post(create_subscription))
+        .route("/subscriptions/:id", get(get_subscription))
+        .route("/subscriptions/:id/plan", put(change_plan))
+        .route("/subscriptions/:id/cancel", post(cancel_subscription))
+        .route("/subscriptions/:id/invoices", get(list_invoices))
+}
+
+async fn create_plan(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<BillingState>,
+    Json(payload): Json<CreatePlanRequest>,
+) -> Result<Json<Plan>, BillingError> {
+    require_merchant(&claims, &payload.merchant_id)?;
+    Currency::from_str(&payload.currency).map_err(BillingError::InvalidRequest)?;
+
+    if payload.amount == 0 || payload.interval_count == 0 {
+        return Err(BillingError::InvalidRequest("amount and interval_count must be positive".to_string()));
+    }
+
+    let plan = Plan {
+        id: Uuid::new_v4(),
+        merchant_id: payload.merchant_id,
+        name: payload.name,
+        amount: payload.amount,
+        currency: payload.currency,
+        interval: payload.interval,
+        interval_count: payload.interval_count,
+        trial_days: payload.trial_days,
+        created_at: Utc::now(),
+    };
+
+    state.store.save_plan(plan.clone()).await;
+
+    Ok(Json(plan))
+}
+
+async fn list_plans(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<BillingState>,
+    Query(filter): Query<PlansFilter>,
+) -> Result<Json<Vec<Plan>>, BillingError> {
+    require_merchant(&claims, &filter.merchant_id)?;
+    Ok(Json(state.store.plans_for_merchant(&filter.merchant_id).await))
+}
+
+async fn get_plan(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<BillingState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Plan>, BillingError> {
+    state
+        .store
+        .plan(id)
+        .await
+        .filter(|plan| claims.may_act_for(&plan.merchant_id))
+        .map(Json)
+        .ok_or(BillingError::PlanNotFound(id))
+}
+
+async fn create_subscription(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<BillingState>,
+    Json(payload): Json<CreateSubscriptionRequest>,
+) -> Result<Json<Subscription>, BillingError> {
+    require_merchant(&claims, &payload.merchant_id)?;
+    let plan = find_plan(&state, payload.plan_id, &payload.merchant_id).await?;
+
+    let customer = state
+        .customers
+        .get(&payload.merchant_id, &payload.customer_id)
+        .await
+        .ok_or_else(|| BillingError::CustomerNotFound(payload.customer_id.clone()))?;
+
+    let now = Utc::now();
+    let trial_days = payload.trial_days.unwrap_or(plan.trial_days);
+    let trial_end = (trial_days > 0).then(|| now + Duration::days(trial_days as i64));
+
+    let billing_anchor = payload.billing_anchor.or(trial_end).unwrap_or(now);
+    if billing_anchor < now {
+        return Err(BillingError::InvalidRequest("billing_anchor can't be in the past".to_string()));
+    }
+
+    // the scheduler invoices a period once the previous one has ended, so without a
+    // trial the "previous" period ends right now and the first invoice goes out on its next run
+    let subscription = Subscription {
+        id: Uuid::new_v4(),
+        merchant_id: payload.merchant_id,
+        customer_id: customer.id,
+        plan_id: plan.id,
+        status: if trial_end.is_some() { SubscriptionStatus::Trialing } else { SubscriptionStatus::Active },
+        billing_anchor,
+        current_period_start: now,
+        current_period_end: trial_end.unwrap_or(now),
+        trial_end,
+        cancel_at_period_end: false,
+        pending_proration: 0,
+        canceled_at: None,
+        created_at: now,
+    };
+
+    state.store.save_subscription(subscription.clone()).await;
+
+    Ok(Json(subscription))
+}
+
+async fn get_subscription(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<BillingState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Subscription>, BillingError> {
+    find_subscription(&state, id, &claims).await.map(Json)
+}
+
+async fn change_plan(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<BillingState>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<ChangePlanRequest>,
+) -> Result<Json<Subscription>, BillingError> {
+    let mut subscription = find_subscription(&state, id, &claims).await?;
+
+    if subscription.status == SubscriptionStatus::Canceled {
+        return Err(BillingError::InvalidRequest("subscription is canceled".to_string()));
+    }
+
+    let old_plan = find_plan(&state, subscription.plan_id, &subscription.merchant_id).await?;
+    let new_plan = find_plan(&state, payload.plan_id, &subscription.merchant_id).await?;
+
+    if old_plan.currency != new_plan.currency {
+        return Err(BillingError::InvalidRequest("plans must share a currency".to_string()));
+    }
+
+    // nothing has been charged during a trial so there is nothing to prorate
+    if payload.prorate && subscription.status != SubscriptionStatus::Trialing {
+        subscription.pending_proration += plan_change_proration(&old_plan, &new_plan, &subscription, Utc::now());
+    }
+    subscription.plan_id = new_plan.id;
+
+    state.store.save_subscription(subscription.clone()).await;
+
+    Ok(Json(subscription))
+}
+
+async fn cancel_subscription(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<BillingState>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<CancelSubscriptionRequest>,
+) -> Result<Json<Subscription>, BillingError> {
+    let mut subscription = find_subscription(&state, id, &claims).await?;
+
+    if subscription.status == SubscriptionStatus::Canceled {
+        return Err(BillingError::InvalidRequest("subscription is already canceled".to_string()));
+    }
+
+    if payload.at_period_end {
+        subscription.cancel_at_period_end = true;
+    } else {
+        subscription.status = SubscriptionStatus::Canceled;
+        subscription.canceled_at = Some(Utc::now());
+    }
+
+    state.store.save_subscription(subscription.clone()).await;
+
+    Ok(Json(subscription))
+}
+
+async fn list_invoices(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<BillingState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<Invoice>>, BillingError> {
+    find_subscription(&state, id, &claims).await?;
+
+    Ok(Json(state.store.invoices_for_subscription(id).await))
+}
+
+async fn find_plan(state: &BillingState, plan_id: Uuid, merchant_id: &str) -> Result<Plan, BillingError> {
+    state
+        .store
+        .plan(plan_id)
+        .await
+        .filter(|p| p.merchant_id == merchant_id)
+        .ok_or(BillingError::PlanNotFound(plan_id))
+}
+
+// another merchant's subscription reads as not found
+async fn find_subscription(state: &BillingState, id: Uuid, claims: &Claims) -> Result<Subscription, BillingError> {
+    state
+        .store
+        .subscription(id)
+        .await
+        .filter(|subscription| claims.may_act_for(&subscription.merchant_id))
+        .ok_or(BillingError::SubscriptionNotFound(id))
+}
+
+fn require_merchant(claims: &Claims, merchant_id: &str) -> Result<(), BillingError> {
+    if claims.may_act_for(merchant_id) {
+        Ok(())
+    } else {
+        Err(BillingError::Forbidden(merchant_id.to_string()))
+    }
+}
+
+impl IntoResponse for BillingError {
+    fn into_response(self) -> axum::response::Response {
+        let status = match self {
+            BillingError::PlanNotFound(_)
+            | BillingError::SubscriptionNotFound(_)
+            | BillingError::CustomerNotFound(_) => StatusCode::NOT_FOUND,
+            BillingError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            BillingError::Forbidden(_) => StatusCode::FORBIDDEN,
+        };
+
+        let body = Json(serde_json::json!({
+            "error": self.to_string()
+        }));
+
+        (status, body).into_response()
+    }
+}
@@ -0,0 +1,33 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{MatchedPath, Request},
+    middleware::Next,
+    response::Response,
+};
+use tracing::{field, info_span, Instrument};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use crate::core::infrastructure::telemetry::remote_context;
+
+/// Opens the span every event published while handling the request is traced under,
+/// continuing the caller's trace when it sent a `traceparent`.
+pub async fn trace_requests(request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_default();
+    let span = info_span!("http_request", method = %request.method(), route, status = field::Empty);
+
+    let carrier: HashMap<String, String> = request
+        .headers()
+        .iter()
+        .filter_map(|(name, value)| value.to_str().ok().map(|value| (name.as_str().to_string(), value.to_string())))
+        .collect();
+    let _ = span.set_parent(remote_context(&carrier));
+
+    let response = next.run(request).instrument(span.clone()).await;
+    span.record("status", response.status().as_u16());
+    response
+}
@@ -0,0 +1,187 @@
+use std::net::IpAddr;
+
+use axum::{
+    extract::{Path, Query, State},
+    response::IntoResponse,
+    routing::{get, post, put},
+    Json, Router,
+};
+use chrono::Utc;
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::api::middleware::AuthenticatedUser;
+use crate::core::{
+    infrastructure::webhook::{WebhookError, WebhookSender},
+    models::{DeliveryAttempt, WebhookDelivery, WebhookEndpoint},
+};
+
+/*request payload types*/
+#[derive(Deserialize)]
+pub struct RegisterEndpointRequest {
+    pub url: String,
+}
+
+#[derive(Deserialize)]
+pub struct DeliveriesFilter {
+    pub merchant_id: String,
+}
+
+/*response payload types*/
+#[derive(Serialize, Deserialize)]
+pub struct RegisterEndpointResponse {
+    pub merchant_id: String,
+    pub url: String,
+    // only ever returned here, merchants use it to verify `payme-signature`
+    pub secret: String,
+}
+
+#[derive(Debug, Error)]
+pub enum WebhookApiError {
+    #[error(transparent)]
+    Webhook(#[from] WebhookError),
+    #[error("This token can't act for merchant {0}")]
+    Forbidden(String),
+    #[error("Invalid endpoint url: {0}")]
+    InvalidUrl(String),
+}
+
+/// Served by the dispatcher behind the same token check as the api, a merchant's users only
+/// see and change their own merchant's webhooks.
+pub fn webhook_routes(sender: WebhookSender) -> Router {
+    Router::new()
+        .route("/endpoints/:merchant_id", put(register_endpoint))
+        .route("/deliveries", get(list_deliveries))
+        .route("/deliveries/:id", get(get_delivery))
+        .route("/deliveries/:id/redeliver", post(redeliver))
+        .with_state(sender)
+}
+
+async fn register_endpoint(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(sender): State<WebhookSender>,
+    Path(merchant_id): Path<String>,
+    Json(payload): Json<RegisterEndpointRequest>,
+) -> Result<Json<RegisterEndpointResponse>, WebhookApiError> {
+    if !claims.may_act_for(&merchant_id) {
+        return Err(WebhookApiError::Forbidden(merchant_id));
+    }
+    check_endpoint_url(&payload.url).map_err(WebhookApiError::InvalidUrl)?;
+
+    let endpoint = WebhookEndpoint {
+        merchant_id: merchant_id.clone(),
+        url: payload.url.clone(),
+        secret: format!("whsec_{}", Uuid::new_v4().simple()),
+        created_at: Utc::now(),
+    };
+    let secret = endpoint.secret.clone();
+
+    sender.store().upsert_endpoint(endpoint).await?;
+
+    Ok(Json(RegisterEndpointResponse {
+        merchant_id,
+        url: payload.url,
+        secret,
+    }))
+}
+
+/// The dispatcher posts from inside our network, so endpoints have to be public https urls.
+/// Hosts that are, or spell out, a loopback, private or link-local address are refused.
+pub fn check_endpoint_url(url: &str) -> Result<(), String> {
+    let url = reqwest::Url::parse(url).map_err(|e| e.to_string())?;
+    if url.scheme() != "https" {
+        return Err("webhooks are only delivered over https".to_string());
+    }
+
+    let host = url.host_str().ok_or("the url has no host")?;
+    // ipv6 hosts keep their brackets
+    let internal = match host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        Ok(ip) => is_internal(ip),
+        // single label names only resolve inside a network
+        Err(_) => host == "localhost" || host.ends_with(".localhost") || !host.contains('.'),
+    };
+    if internal {
+        return Err(format!("{} is not a public host", host));
+    }
+    Ok(())
+}
+
+fn is_internal(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                // shared address space, carrier grade nat
+                || (first == 100 && (64..128).contains(&second))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(mapped) => is_internal(IpAddr::V4(mapped)),
+            None => ip.is_loopback() || ip.is_unspecified() || ip.is_unique_local() || ip.is_unicast_link_local(),
+        },
+    }
+}
+
+async fn list_deliveries(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(sender): State<WebhookSender>,
+    Query(filter): Query<DeliveriesFilter>,
+) -> Result<Json<Vec<WebhookDelivery>>, WebhookApiError> {
+    if !claims.may_act_for(&filter.merchant_id) {
+        return Err(WebhookApiError::Forbidden(filter.merchant_id));
+    }
+    Ok(Json(sender.store().deliveries_for_merchant(&filter.merchant_id).await))
+}
+
+// another merchant's delivery reads as unknown
+async fn get_delivery(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(sender): State<WebhookSender>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<WebhookDelivery>, WebhookApiError> {
+    let delivery = sender
+        .store()
+        .delivery(id)
+        .await
+        .filter(|delivery| claims.may_act_for(&delivery.merchant_id))
+        .ok_or(WebhookError::UnknownDelivery(id))?;
+    Ok(Json(delivery))
+}
+
+async fn redeliver(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(sender): State<WebhookSender>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<DeliveryAttempt>, WebhookApiError> {
+    sender
+        .store()
+        .delivery(id)
+        .await
+        .filter(|delivery| claims.may_act_for(&delivery.merchant_id))
+        .ok_or(WebhookError::UnknownDelivery(id))?;
+    Ok(Json(sender.attempt(id, true).await?))
+}
+
+impl IntoResponse for WebhookApiError {
+    fn into_response(self) -> axum::response::Response {
+        let status = match self {
+            WebhookApiError::Webhook(WebhookError::UnknownDelivery(_)) => StatusCode::NOT_FOUND,
+            WebhookApiError::Webhook(WebhookError::MissingEndpoint(_)) => StatusCode::UNPROCESSABLE_ENTITY,
+            WebhookApiError::Webhook(WebhookError::NotPending(_)) => StatusCode::CONFLICT,
+            WebhookApiError::Webhook(WebhookError::Serialization(_) | WebhookError::Io(_)) => StatusCode::INTERNAL_SERVER_ERROR,
+            WebhookApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            WebhookApiError::InvalidUrl(_) => StatusCode::BAD_REQUEST,
+        };
+
+        let body = Json(serde_json::json!({
+            "error": self.to_string()
+        }));
+
+        (status, body).into_response()
+    }
+}
@@ -0,0 +1,28 @@
+#[cfg(test)]
+mod tests {
+    use crate::core::api::webhooks::check_endpoint_url;
+
+    #[test]
+    fn test_endpoints_must_be_public_https_urls() {
+        assert!(check_endpoint_url("https://merchant.example/hooks").is_ok());
+        assert!(check_endpoint_url("https://203.0.113.7:8443/hooks").is_ok());
+
+        for url in [
+            "not a url",
+            "http://merchant.example/hooks",
+            "https://localhost/hooks",
+            "https://payments-internal/hooks",
+            "https://127.0.0.1/hooks",
+            "https://2130706433/hooks",
+            "https://10.1.2.3/hooks",
+            "https://192.168.0.10/hooks",
+            "https://169.254.169.254/latest/meta-data",
+            "https://[::1]/hooks",
+            "https://[fe80::1]/hooks",
+            "https://[fd00::1]/hooks",
+            "https://[::ffff:10.0.0.1]/hooks",
+        ] {
+            assert!(check_endpoint_url(url).is_err(), "{} should be refused", url);
+        }
+    }
+}
@@ -0,0 +1,721 @@
+use std::{collections::HashSet, env, fmt, fs, net::SocketAddr, path::PathBuf, time::Duration};
+
+use clap::Parser;
+use serde::{de, Deserialize, Deserializer};
+use thiserror::Error;
+use toml::{Table, Value};
+
+use crate::core::{
+    infrastructure::{
+        billing::DunningPolicy,
+        limits::{RateLimitConfig, VelocityConfig},
+        reporting::{ReportFormat, ReportKind, ReportPeriod},
+        schedule::CronSchedule,
+        settlement::SettlementConfig,
+    },
+    models::SubscriptionStatus,
+};
+
+/// Read when neither `--config` nor `PAYME_CONFIG` point somewhere else, skipped if it doesn't exist.
+pub const DEFAULT_CONFIG_FILE: &str = "payme.toml";
+
+// prefix of the generic env overrides, `PAYME__KAFKA__TOPICS__FEES` sets `kafka.topics.fees`
+const ENV_PREFIX: &str = "PAYME__";
+
+// env vars the services read before there was a config file, still honoured
+const ENV_ALIASES: [(&str, &str); 7] = [
+    ("KAFKA_BROKERS", "kafka.brokers"),
+    ("STRIPE_SECRET_KEY", "stripe.secret_key"),
+    ("STRIPE_WEBHOOK_SECRET", "stripe.webhook_secret"),
+    ("JWT_SECRET", "auth.jwt_secret"),
+    ("PAYME_PUBLIC_URL", "server.public_url"),
+    ("PAYME_BIND_ADDRESS", "server.bind_address"),
+    ("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT", "telemetry.otlp_endpoint"),
+];
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Failed to read config file {0}: {1}")]
+    File(String, String),
+    #[error("Invalid configuration: {0}")]
+    Parse(String),
+    #[error("Invalid configuration:\n  - {}", .0.join("\n  - "))]
+    Invalid(Vec<String>),
+}
+
+/// Flags every binary accepts, they win over the file and the environment.
+#[derive(Debug, Default, Parser)]
+#[command(about = "payme payment services")]
+pub struct ConfigArgs {
+    /// TOML file to load, defaults to payme.toml
+    #[arg(long, env = "PAYME_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// Address the http server listens on
+    #[arg(long)]
+    pub bind: Option<String>,
+    /// Comma separated kafka bootstrap servers
+    #[arg(long)]
+    pub brokers: Option<String>,
+    /// Any other setting, e.g. --set kafka.topics.fees=fees-v2
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    pub overrides: Vec<String>,
+}
+
+/// Which binary is starting, each one needs different secrets.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Component {
+    ApiServer,
+    PaymentProcessor,
+    StatusConsumer,
+    WebhookDispatcher,
+    // the sweeper and the scheduled reports, one replica for the whole deployment
+    Scheduler,
+    // checks the audit log's hash chain and exits
+    AuditVerifier,
+    // payme-admin, one operation per run
+    Admin,
+}
+
+/* a value that never shows up in logs, `file:/path` reads it from a file instead */
+#[derive(Clone, Default, PartialEq, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Self(value.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn resolve(&mut self, key: &str) -> Result<(), String> {
+        if let Some(path) = self.0.strip_prefix("file:") {
+            let value = fs::read_to_string(path).map_err(|e| format!("{}: failed to read {}: {}", key, path, e))?;
+            self.0 = value.trim().to_string();
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            write!(f, "Secret(<empty>)")
+        } else {
+            write!(f, "Secret(***)")
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub kafka: KafkaConfig,
+    pub stripe: StripeConfig,
+    pub auth: AuthConfig,
+    pub telemetry: TelemetryConfig,
+    pub audit: AuditConfig,
+    pub reports: ReportsConfig,
+    pub sweeper: SweeperConfig,
+    pub webhooks: WebhooksConfig,
+    // the command api's token buckets
+    pub rate_limits: RateLimitConfig,
+    pub velocity: VelocityConfig,
+    pub billing: BillingConfig,
+    pub settlements: SettlementsConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind_address: String,
+    // the webhook dispatcher's delivery api
+    pub webhook_bind_address: String,
+    // where customers are sent back to after 3DS
+    pub public_url: String,
+    // /metrics and the probes of the binaries without an api, the api server serves them next to its routes
+    pub processor_metrics_address: String,
+    pub status_consumer_metrics_address: String,
+    pub scheduler_metrics_address: String,
+    // how long in-flight requests, provider calls and producers get to finish after SIGTERM
+    #[serde(deserialize_with = "seconds")]
+    pub shutdown_grace_period_secs: u64,
+}
+
+impl ServerConfig {
+    pub fn shutdown_grace_period(&self) -> Duration {
+        Duration::from_secs(self.shutdown_grace_period_secs)
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: "127.0.0.1:3000".to_string(),
+            webhook_bind_address: "127.0.0.1:3001".to_string(),
+            public_url: "http://127.0.0.1:3000".to_string(),
+            processor_metrics_address: "127.0.0.1:9101".to_string(),
+            status_consumer_metrics_address: "127.0.0.1:9102".to_string(),
+            scheduler_metrics_address: "127.0.0.1:9103".to_string(),
+            shutdown_grace_period_secs: 25,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KafkaConfig {
+    pub brokers: String,
+    pub topics: Topics,
+    pub groups: ConsumerGroups,
+}
+
+impl Default for KafkaConfig {
+    fn default() -> Self {
+        Self {
+            brokers: "localhost:9092".to_string(),
+            topics: Topics::default(),
+            groups: ConsumerGroups::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Topics {
+    pub transactions: String,
+    // what the risk engine let through to the processor
+    pub transactions_screened: String,
+    pub payment_status: String,
+    pub refunds: String,
+    pub fees: String,
+    pub payouts: String,
+    pub payout_status: String,
+    pub transfers: String,
+    // messages a consumer couldn't read, with the error, instead of them being dropped
+    pub dead_letter: String,
+}
+
+impl Default for Topics {
+    fn default() -> Self {
+        Self {
+            transactions: "transactions".to_string(),
+            transactions_screened: "transactions-screened".to_string(),
+            payment_status: "payment-status".to_string(),
+            refunds: "refunds".to_string(),
+            fees: "fees".to_string(),
+            payouts: "payouts".to_string(),
+            payout_status: "payout-status".to_string(),
+            transfers: "transfers".to_string(),
+            dead_letter: "dead-letter".to_string(),
+        }
+    }
+}
+
+impl Topics {
+    fn named(&self) -> [(&'static str, &str); 9] {
+        [
+            ("transactions", &self.transactions),
+            ("transactions_screened", &self.transactions_screened),
+            ("payment_status", &self.payment_status),
+            ("refunds", &self.refunds),
+            ("fees", &self.fees),
+            ("payouts", &self.payouts),
+            ("payout_status", &self.payout_status),
+            ("transfers", &self.transfers),
+            ("dead_letter", &self.dead_letter),
+        ]
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConsumerGroups {
+    pub payment_processor: String,
+    pub status_consumer: String,
+    // the projection the api server keeps for its queries
+    pub query_projection: String,
+    pub webhook_dispatcher: String,
+    pub subscription_dunning: String,
+    pub ledger: String,
+    pub fee_calculator: String,
+    pub settlement: String,
+    pub risk_engine: String,
+    // payme-scheduler's own copies of the projection and the settlement batches
+    pub scheduler_projection: String,
+    pub scheduler_settlement: String,
+}
+
+impl Default for ConsumerGroups {
+    fn default() -> Self {
+        Self {
+            payment_processor: "stripe-payment-processor".to_string(),
+            status_consumer: "payment-status-consumer".to_string(),
+            query_projection: "payment-query-projection".to_string(),
+            webhook_dispatcher: "merchant-webhook-dispatcher".to_string(),
+            subscription_dunning: "subscription-dunning".to_string(),
+            ledger: "payment-ledger".to_string(),
+            fee_calculator: "payment-fee-calculator".to_string(),
+            settlement: "merchant-settlement".to_string(),
+            risk_engine: "payment-risk-engine".to_string(),
+            scheduler_projection: "payment-scheduler-projection".to_string(),
+            scheduler_settlement: "merchant-settlement-scheduler".to_string(),
+        }
+    }
+}
+
+impl ConsumerGroups {
+    fn named(&self) -> [(&'static str, &str); 11] {
+        [
+            ("payment_processor", &self.payment_processor),
+            ("status_consumer", &self.status_consumer),
+            ("query_projection", &self.query_projection),
+            ("webhook_dispatcher", &self.webhook_dispatcher),
+            ("subscription_dunning", &self.subscription_dunning),
+            ("ledger", &self.ledger),
+            ("fee_calculator", &self.fee_calculator),
+            ("settlement", &self.settlement),
+            ("risk_engine", &self.risk_engine),
+            ("scheduler_projection", &self.scheduler_projection),
+            ("scheduler_settlement", &self.scheduler_settlement),
+        ]
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StripeConfig {
+    pub secret_key: Secret,
+    pub webhook_secret: Secret,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub jwt_secret: Secret,
+    // key ring maintained by `payme-admin rotate-jwt-key`, tokens are signed with its keys instead of jwt_secret once set
+    pub keys_path: String,
+    // users, merchants and api keys created with payme-admin, empty keeps them in memory
+    pub accounts_path: String,
+    // lets admin/password log in while there are no users, never turn it on outside local runs
+    pub example_login: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetryConfig {
+    // where spans go: "none", "stdout" or "otlp"
+    pub exporter: String,
+    // an OTLP/HTTP collector, only used with the otlp exporter
+    pub otlp_endpoint: String,
+    // a tracing-subscriber filter, RUST_LOG wins when set
+    pub log_filter: String,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            exporter: "none".to_string(),
+            otlp_endpoint: "http://localhost:4318/v1/traces".to_string(),
+            log_filter: "info".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuditConfig {
+    // json lines file the api appends the hash chained audit log to, empty keeps it in memory
+    pub log_path: String,
+    // payme-admin's own chain, one file can't take appends from two processes without forking the chain
+    pub admin_log_path: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReportsConfig {
+    // report jobs go to `jobs/` under it, scheduled reports to `scheduled/`
+    pub output_dir: String,
+    pub schedules: Vec<ScheduledReport>,
+}
+
+impl Default for ReportsConfig {
+    fn default() -> Self {
+        Self {
+            output_dir: "reports".to_string(),
+            schedules: Vec::new(),
+        }
+    }
+}
+
+/// A report payme-scheduler writes on a schedule, `[[reports.schedules]]` in the file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduledReport {
+    // also the start of the file names, e.g. monthly-fees-2024-03-01.csv
+    pub name: String,
+    // five field cron expression in UTC, e.g. "0 6 1 * *" for 06:00 on the 1st
+    pub cron: String,
+    pub kind: ReportKind,
+    #[serde(default)]
+    pub format: ReportFormat,
+    #[serde(default)]
+    pub merchant_id: Option<String>,
+    pub period: ReportPeriod,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhooksConfig {
+    // the dispatcher's endpoints, deliveries and their retry state, empty keeps them in memory
+    pub store_path: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BillingConfig {
+    // how long after each failed renewal it's tried again, one entry per retry
+    pub dunning_retry_after_secs: Vec<u64>,
+    // Unpaid or Canceled, where a subscription ends up once every retry failed
+    pub dunning_on_exhausted: SubscriptionStatus,
+}
+
+impl BillingConfig {
+    pub fn dunning_policy(&self) -> DunningPolicy {
+        let retry_after = self.dunning_retry_after_secs.iter().map(|secs| chrono::Duration::seconds(*secs as i64)).collect();
+        DunningPolicy::new(retry_after, self.dunning_on_exhausted.clone())
+    }
+}
+
+impl Default for BillingConfig {
+    fn default() -> Self {
+        let policy = DunningPolicy::default();
+        Self {
+            dunning_retry_after_secs: policy.retry_after.iter().map(|delay| delay.num_seconds() as u64).collect(),
+            dunning_on_exhausted: policy.on_exhausted,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SettlementsConfig {
+    // days between a batch's settlement date and its payout, 2 for T+2
+    #[serde(deserialize_with = "number")]
+    pub delay_days: u64,
+    // utc hour a day's batch closes at, 0 closes it at midnight
+    #[serde(deserialize_with = "number")]
+    pub cutoff_hour: u64,
+}
+
+impl SettlementsConfig {
+    pub fn schedule(&self) -> SettlementConfig {
+        SettlementConfig::new(self.delay_days).with_cutoff_hour(self.cutoff_hour as u32)
+    }
+}
+
+impl Default for SettlementsConfig {
+    fn default() -> Self {
+        let schedule = SettlementConfig::default();
+        Self {
+            delay_days: schedule.delay_days,
+            cutoff_hour: schedule.cutoff_hour as u64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SweeperConfig {
+    // a payment still pending this long is checked against the provider, and the processor no longer charges it
+    #[serde(deserialize_with = "seconds")]
+    pub pending_sla_secs: u64,
+    // a payment held for review this long is failed, the risk team can no longer approve it
+    #[serde(deserialize_with = "seconds")]
+    pub review_sla_secs: u64,
+    // how long a customer gets to finish 3DS before the payment is failed
+    #[serde(deserialize_with = "seconds")]
+    pub requires_action_timeout_secs: u64,
+    #[serde(deserialize_with = "seconds")]
+    pub interval_secs: u64,
+}
+
+impl SweeperConfig {
+    pub fn pending_sla(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.pending_sla_secs as i64)
+    }
+
+    pub fn review_sla(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.review_sla_secs as i64)
+    }
+
+    pub fn requires_action_timeout(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.requires_action_timeout_secs as i64)
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs)
+    }
+}
+
+impl Default for SweeperConfig {
+    fn default() -> Self {
+        Self {
+            pending_sla_secs: 1800,
+            review_sla_secs: 86400,
+            requires_action_timeout_secs: 1800,
+            interval_secs: 60,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config for `component` from the command line, `.env`, the environment and the config file.
+    pub fn load(component: Component) -> Result<Self, ConfigError> {
+        dotenv::dotenv().ok();
+        let args = ConfigArgs::parse();
+        Self::from_sources(component, &args, env::vars())
+    }
+
+    /// Defaults, then the file, then the environment, then the flags. Every layer only
+    /// has to mention what it changes.
+    pub fn from_sources(
+        component: Component,
+        args: &ConfigArgs,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self, ConfigError> {
+        let mut table = match &args.config {
+            Some(path) => read_file(path)?,
+            None if PathBuf::from(DEFAULT_CONFIG_FILE).exists() => read_file(&PathBuf::from(DEFAULT_CONFIG_FILE))?,
+            None => Table::new(),
+        };
+
+        for (name, value) in vars {
+            if let Some((_, key)) = ENV_ALIASES.iter().find(|(alias, _)| *alias == name) {
+                set_path(&mut table, key, value)?;
+            } else if let Some(path) = name.strip_prefix(ENV_PREFIX) {
+                let key = path.to_lowercase().replace("__", ".");
+                set_path(&mut table, &key, value)?;
+            }
+        }
+
+        if let Some(bind) = &args.bind {
+            set_path(&mut table, "server.bind_address", bind.clone())?;
+        }
+        if let Some(brokers) = &args.brokers {
+            set_path(&mut table, "kafka.brokers", brokers.clone())?;
+        }
+        for setting in &args.overrides {
+            let (key, value) = setting
+                .split_once('=')
+                .ok_or_else(|| ConfigError::Parse(format!("--set {} should look like KEY=VALUE", setting)))?;
+            set_path(&mut table, key.trim(), value.trim().to_string())?;
+        }
+
+        let mut config: Config = Value::Table(table)
+            .try_into()
+            .map_err(|e: toml::de::Error| ConfigError::Parse(e.message().to_string()))?;
+
+        config.resolve_secrets()?;
+        config.validate(component)?;
+        Ok(config)
+    }
+
+    fn resolve_secrets(&mut self) -> Result<(), ConfigError> {
+        let problems: Vec<String> = [
+            self.stripe.secret_key.resolve("stripe.secret_key"),
+            self.stripe.webhook_secret.resolve("stripe.webhook_secret"),
+            self.auth.jwt_secret.resolve("auth.jwt_secret"),
+        ]
+        .into_iter()
+        .filter_map(Result::err)
+        .collect();
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+
+    /// Reports every problem at once rather than the first one.
+    pub fn validate(&self, component: Component) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+
+        if self.kafka.brokers.split(',').any(|broker| broker.trim().is_empty()) {
+            problems.push("kafka.brokers: expected a comma separated list of host:port".to_string());
+        }
+
+        let mut seen = HashSet::new();
+        for (name, topic) in self.kafka.topics.named() {
+            if topic.trim().is_empty() {
+                problems.push(format!("kafka.topics.{}: can't be empty", name));
+            } else if !seen.insert(topic) {
+                problems.push(format!("kafka.topics.{}: {} is already used by another topic", name, topic));
+            }
+        }
+        for (name, group) in self.kafka.groups.named() {
+            if group.trim().is_empty() {
+                problems.push(format!("kafka.groups.{}: can't be empty", name));
+            }
+        }
+
+        if matches!(component, Component::ApiServer) {
+            if self.server.bind_address.parse::<SocketAddr>().is_err() {
+                problems.push(format!("server.bind_address: {} is not an ip:port address", self.server.bind_address));
+            }
+            if !self.server.public_url.starts_with("http://") && !self.server.public_url.starts_with("https://") {
+                problems.push("server.public_url: must start with http:// or https://".to_string());
+            }
+        }
+
+        // both check tokens, the dispatcher for its webhook api
+        if matches!(component, Component::ApiServer | Component::WebhookDispatcher) && self.auth.jwt_secret.expose().len() < 32 {
+            problems.push("auth.jwt_secret: must be at least 32 characters (set JWT_SECRET)".to_string());
+        }
+
+        if matches!(component, Component::WebhookDispatcher) && self.server.webhook_bind_address.parse::<SocketAddr>().is_err() {
+            problems.push(format!("server.webhook_bind_address: {} is not an ip:port address", self.server.webhook_bind_address));
+        }
+
+        match self.telemetry.exporter.as_str() {
+            "none" | "stdout" => {}
+            "otlp" if self.telemetry.otlp_endpoint.starts_with("http://") || self.telemetry.otlp_endpoint.starts_with("https://") => {}
+            "otlp" => problems.push("telemetry.otlp_endpoint: must start with http:// or https://".to_string()),
+            other => problems.push(format!("telemetry.exporter: {} is not one of none, stdout or otlp", other)),
+        }
+
+        let metrics_address = match component {
+            Component::PaymentProcessor => Some(("processor_metrics_address", &self.server.processor_metrics_address)),
+            Component::StatusConsumer => Some(("status_consumer_metrics_address", &self.server.status_consumer_metrics_address)),
+            Component::Scheduler => Some(("scheduler_metrics_address", &self.server.scheduler_metrics_address)),
+            _ => None,
+        };
+        if let Some((name, address)) = metrics_address.filter(|(_, address)| address.parse::<SocketAddr>().is_err()) {
+            problems.push(format!("server.{}: {} is not an ip:port address", name, address));
+        }
+
+        if matches!(component, Component::ApiServer | Component::PaymentProcessor | Component::Scheduler | Component::Admin) {
+            let key = self.stripe.secret_key.expose();
+            if !key.starts_with("sk_") && !key.starts_with("rk_") {
+                problems.push("stripe.secret_key: expected a stripe secret or restricted key, sk_... or rk_... (set STRIPE_SECRET_KEY)".to_string());
+            }
+        }
+
+        if matches!(component, Component::AuditVerifier) && self.audit.log_path.trim().is_empty() {
+            problems.push("audit.log_path: point it at the audit log to verify".to_string());
+        }
+        if matches!(component, Component::Admin) && self.audit.admin_log_path.trim().is_empty() {
+            problems.push("audit.admin_log_path: payme-admin records what it does there".to_string());
+        }
+
+        let sweeper = &self.sweeper;
+        if [sweeper.pending_sla_secs, sweeper.review_sla_secs, sweeper.requires_action_timeout_secs, sweeper.interval_secs].contains(&0) {
+            problems.push("sweeper: pending_sla_secs, review_sla_secs, requires_action_timeout_secs and interval_secs must be above 0".to_string());
+        }
+
+        // chrono can't hold more than i64::MAX milliseconds
+        let longest = i64::MAX as u64 / 1000;
+        if self.billing.dunning_retry_after_secs.iter().any(|secs| *secs == 0 || *secs > longest) {
+            problems.push(format!("billing.dunning_retry_after_secs: every delay must be between 1 and {} seconds", longest));
+        }
+        if !matches!(self.billing.dunning_on_exhausted, SubscriptionStatus::Unpaid | SubscriptionStatus::Canceled) {
+            problems.push(format!("billing.dunning_on_exhausted: expected Unpaid or Canceled, got {:?}", self.billing.dunning_on_exhausted));
+        }
+
+        if self.settlements.delay_days > 365 {
+            problems.push("settlements.delay_days: must be at most 365".to_string());
+        }
+        if self.settlements.cutoff_hour > 23 {
+            problems.push("settlements.cutoff_hour: must be an hour between 0 and 23".to_string());
+        }
+
+        let buckets = [&self.rate_limits.per_merchant, &self.rate_limits.per_api_key, &self.rate_limits.per_ip];
+        if buckets.into_iter().chain(self.rate_limits.merchant_overrides.values()).any(|bucket| bucket.capacity == 0 || bucket.refill_per_second <= 0.0) {
+            problems.push("rate_limits: every bucket needs a capacity and refill_per_second above 0".to_string());
+        }
+        if self.velocity.max_daily_amount_per_customer == 0 || self.velocity.merchant_overrides.values().any(|limit| *limit == 0) {
+            problems.push("velocity: daily limits must be above 0".to_string());
+        }
+
+        if self.reports.output_dir.trim().is_empty() {
+            problems.push("reports.output_dir: can't be empty".to_string());
+        }
+        let mut names = HashSet::new();
+        for scheduled in &self.reports.schedules {
+            let name = &scheduled.name;
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+                problems.push(format!("reports.schedules: {:?} should only use letters, digits, - and _", name));
+            } else if !names.insert(name) {
+                problems.push(format!("reports.schedules: {} is scheduled twice", name));
+            }
+            if let Err(e) = scheduled.cron.parse::<CronSchedule>() {
+                problems.push(format!("reports.schedules.{}.cron: {}", name, e));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+}
+
+fn read_file(path: &PathBuf) -> Result<Table, ConfigError> {
+    let display = path.display().to_string();
+    let text = fs::read_to_string(path).map_err(|e| ConfigError::File(display.clone(), e.to_string()))?;
+    text.parse::<Table>().map_err(|e| ConfigError::File(display, e.message().to_string()))
+}
+
+fn set_path(table: &mut Table, key: &str, value: String) -> Result<(), ConfigError> {
+    let mut parts: Vec<&str> = key.split('.').collect();
+    let last = parts.pop().filter(|part| !part.is_empty()).ok_or_else(|| ConfigError::Parse(format!("invalid key {}", key)))?;
+
+    let mut current = table;
+    for part in parts {
+        current = match current.entry(part.to_string()).or_insert_with(|| Value::Table(Table::new())) {
+            Value::Table(inner) => inner,
+            _ => return Err(ConfigError::Parse(format!("{} is not a section", key))),
+        };
+    }
+
+    current.insert(last.to_string(), Value::String(value));
+    Ok(())
+}
+
+// env vars and --set only ever produce strings, so numbers are taken either way
+fn seconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw {
+        Number(u64),
+        Text(String),
+    }
+
+    match Raw::deserialize(deserializer)? {
+        Raw::Number(seconds) => Ok(seconds),
+        Raw::Text(text) => text.trim().parse().map_err(|_| de::Error::custom(format!("{} is not a number of seconds", text))),
+    }
+}
+
+fn number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw {
+        Number(u64),
+        Text(String),
+    }
+
+    match Raw::deserialize(deserializer)? {
+        Raw::Number(number) => Ok(number),
+        Raw::Text(text) => text.trim().parse().map_err(|_| de::Error::custom(format!("{} is not a whole number", text))),
+    }
+}
@@ -0,0 +1,262 @@
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use uuid::Uuid;
+
+    use crate::core::{
+        config::{Component, Config, ConfigArgs, ConfigError},
+        models::SubscriptionStatus,
+    };
+
+    const JWT_SECRET: &str = "0123456789abcdef0123456789abcdef";
+
+    fn write_file(contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("payme-config-{}", Uuid::new_v4()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    fn problems(result: Result<Config, ConfigError>) -> Vec<String> {
+        match result {
+            Err(ConfigError::Invalid(problems)) => problems,
+            other => panic!("expected validation errors, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_status_consumer_starts_on_defaults() {
+        let config = Config::from_sources(Component::StatusConsumer, &ConfigArgs::default(), vars(&[])).unwrap();
+
+        assert_eq!(config.kafka.brokers, "localhost:9092");
+        assert_eq!(config.kafka.topics.transactions_screened, "transactions-screened");
+        assert_eq!(config.kafka.groups.status_consumer, "payment-status-consumer");
+    }
+
+    #[test]
+    fn test_env_overrides_file_and_flags_override_env() {
+        let file = write_file(
+            r#"
+            [server]
+            bind_address = "0.0.0.0:8080"
+
+            [kafka]
+            brokers = "file-broker:9092"
+
+            [kafka.topics]
+            fees = "fees-v2"
+            "#,
+        );
+        let args = ConfigArgs {
+            config: Some(file.clone()),
+            brokers: Some("flag-broker:9092".to_string()),
+            overrides: vec!["kafka.groups.ledger=ledger-v2".to_string()],
+            ..ConfigArgs::default()
+        };
+        let env = vars(&[
+            ("KAFKA_BROKERS", "env-broker:9092"),
+            ("PAYME__KAFKA__TOPICS__REFUNDS", "refunds-v2"),
+            ("STRIPE_SECRET_KEY", "sk_test_123"),
+            ("JWT_SECRET", JWT_SECRET),
+            ("UNRELATED", "ignored"),
+        ]);
+
+        let config = Config::from_sources(Component::ApiServer, &args, env).unwrap();
+        fs::remove_file(file).unwrap();
+
+        assert_eq!(config.server.bind_address, "0.0.0.0:8080");
+        assert_eq!(config.kafka.brokers, "flag-broker:9092");
+        assert_eq!(config.kafka.topics.fees, "fees-v2");
+        assert_eq!(config.kafka.topics.refunds, "refunds-v2");
+        assert_eq!(config.kafka.topics.transactions, "transactions");
+        assert_eq!(config.kafka.groups.ledger, "ledger-v2");
+        assert_eq!(config.stripe.secret_key.expose(), "sk_test_123");
+    }
+
+    #[test]
+    fn test_secrets_are_read_from_files_and_never_printed() {
+        let key_file = write_file("sk_live_from_file\n");
+        let value = format!("file:{}", key_file.display());
+        let env = vars(&[("STRIPE_SECRET_KEY", value.as_str())]);
+
+        let config = Config::from_sources(Component::PaymentProcessor, &ConfigArgs::default(), env).unwrap();
+        fs::remove_file(key_file).unwrap();
+
+        assert_eq!(config.stripe.secret_key.expose(), "sk_live_from_file");
+        assert!(!format!("{:?}", config).contains("sk_live_from_file"));
+    }
+
+    #[test]
+    fn test_missing_secret_file_is_reported() {
+        let env = vars(&[("STRIPE_SECRET_KEY", "file:/nonexistent/payme/stripe_key")]);
+
+        let problems = problems(Config::from_sources(Component::PaymentProcessor, &ConfigArgs::default(), env));
+
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("stripe.secret_key: failed to read /nonexistent/payme/stripe_key"));
+    }
+
+    #[test]
+    fn test_api_server_reports_every_problem_at_once() {
+        let args = ConfigArgs {
+            bind: Some("localhost".to_string()),
+            overrides: vec!["kafka.topics.fees=transactions".to_string()],
+            ..ConfigArgs::default()
+        };
+
+        let problems = problems(Config::from_sources(Component::ApiServer, &args, vars(&[("JWT_SECRET", "short")])));
+
+        assert_eq!(problems.len(), 4);
+        assert!(problems.iter().any(|p| p.starts_with("kafka.topics.fees: transactions is already used")));
+        assert!(problems.iter().any(|p| p.starts_with("server.bind_address")));
+        assert!(problems.iter().any(|p| p.starts_with("auth.jwt_secret")));
+        assert!(problems.iter().any(|p| p.starts_with("stripe.secret_key")));
+    }
+
+    #[test]
+    fn test_webhook_dispatcher_needs_a_jwt_secret() {
+        let problems = problems(Config::from_sources(Component::WebhookDispatcher, &ConfigArgs::default(), vars(&[])));
+
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("auth.jwt_secret"));
+
+        let secret = vars(&[("JWT_SECRET", "a-dispatcher-secret-of-32-characters")]);
+        assert!(Config::from_sources(Component::WebhookDispatcher, &ConfigArgs::default(), secret).is_ok());
+    }
+
+    #[test]
+    fn test_unknown_keys_are_rejected() {
+        let args = ConfigArgs {
+            overrides: vec!["kafka.topics.fes=fees-v2".to_string()],
+            ..ConfigArgs::default()
+        };
+
+        let result = Config::from_sources(Component::StatusConsumer, &args, vars(&[]));
+
+        assert!(matches!(result, Err(ConfigError::Parse(message)) if message.contains("fes")));
+    }
+
+    #[test]
+    fn test_grace_period_can_be_set_from_env() {
+        let env = vars(&[("PAYME__SERVER__SHUTDOWN_GRACE_PERIOD_SECS", "40")]);
+
+        let config = Config::from_sources(Component::StatusConsumer, &ConfigArgs::default(), env).unwrap();
+
+        assert_eq!(config.server.shutdown_grace_period(), std::time::Duration::from_secs(40));
+    }
+
+    #[test]
+    fn test_3ds_timeout_is_read_and_checked() {
+        let env = vars(&[("PAYME__SWEEPER__REQUIRES_ACTION_TIMEOUT_SECS", "600")]);
+        let config = Config::from_sources(Component::StatusConsumer, &ConfigArgs::default(), env).unwrap();
+        assert_eq!(config.sweeper.requires_action_timeout(), chrono::Duration::minutes(10));
+
+        let env = vars(&[("PAYME__SWEEPER__REQUIRES_ACTION_TIMEOUT_SECS", "0")]);
+        let problems = problems(Config::from_sources(Component::StatusConsumer, &ConfigArgs::default(), env));
+        assert!(problems[0].contains("requires_action_timeout_secs"), "{:?}", problems);
+    }
+
+    #[test]
+    fn test_report_schedules_are_read_from_the_file_and_checked() {
+        let file = write_file(
+            r#"
+            [[reports.schedules]]
+            name = "monthly-fees"
+            cron = "0 6 1 * *"
+            kind = "fees"
+            format = "excel_csv"
+            period = "previous_month"
+
+            [[reports.schedules]]
+            name = "monthly-fees"
+            cron = "0 25 * * *"
+            kind = "refunds"
+            period = "previous_day"
+            "#,
+        );
+        let args = ConfigArgs { config: Some(file.clone()), ..Default::default() };
+
+        let problems = problems(Config::from_sources(Component::StatusConsumer, &args, vars(&[])));
+        fs::remove_file(file).unwrap();
+
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(problems[0].contains("monthly-fees is scheduled twice"));
+        assert!(problems[1].starts_with("reports.schedules.monthly-fees.cron: 25 is not a number between 0 and 23"));
+    }
+
+    #[test]
+    fn test_limits_are_read_from_the_file_and_checked() {
+        let file = write_file(
+            r#"
+            [rate_limits]
+            per_ip = { capacity = 0, refill_per_second = 1.0 }
+            trusted_proxies = ["10.0.0.10"]
+
+            [rate_limits.merchant_overrides]
+            merch_big = { capacity = 500, refill_per_second = 100.0 }
+
+            [velocity]
+            max_daily_amount_per_customer = 5000
+            "#,
+        );
+        let args = ConfigArgs { config: Some(file.clone()), ..Default::default() };
+
+        let problems = problems(Config::from_sources(Component::StatusConsumer, &args, vars(&[])));
+        let fixed = fs::read_to_string(&file).unwrap().replace("capacity = 0", "capacity = 20");
+        fs::write(&file, fixed).unwrap();
+        let config = Config::from_sources(Component::StatusConsumer, &args, vars(&[])).unwrap();
+        fs::remove_file(file).unwrap();
+
+        assert_eq!(problems, vec!["rate_limits: every bucket needs a capacity and refill_per_second above 0".to_string()]);
+        assert_eq!(config.rate_limits.merchant_overrides["merch_big"].capacity, 500);
+        assert_eq!(config.rate_limits.trusted_proxies, vec!["10.0.0.10".parse::<std::net::IpAddr>().unwrap()]);
+        assert_eq!(config.velocity.max_daily_amount_per_customer, 5000);
+    }
+
+    #[test]
+    fn test_dunning_policy_is_read_from_the_file_and_checked() {
+        let file = write_file(
+            r#"
+            [billing]
+            dunning_retry_after_secs = [3600, 0]
+            dunning_on_exhausted = "Active"
+            "#,
+        );
+        let args = ConfigArgs { config: Some(file.clone()), ..Default::default() };
+
+        let problems = problems(Config::from_sources(Component::StatusConsumer, &args, vars(&[])));
+        fs::write(&file, "[billing]\ndunning_retry_after_secs = [3600, 7200]\ndunning_on_exhausted = \"Canceled\"\n").unwrap();
+        let policy = Config::from_sources(Component::StatusConsumer, &args, vars(&[])).unwrap().billing.dunning_policy();
+        fs::remove_file(file).unwrap();
+
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(problems[0].starts_with("billing.dunning_retry_after_secs"));
+        assert!(problems[1].starts_with("billing.dunning_on_exhausted"));
+        assert_eq!(policy.retry_after, vec![chrono::Duration::hours(1), chrono::Duration::hours(2)]);
+        assert_eq!(policy.on_exhausted, SubscriptionStatus::Canceled);
+    }
+
+    #[test]
+    fn test_settlement_schedule_is_read_from_the_file_and_checked() {
+        let file = write_file("[settlements]\ndelay_days = 400\ncutoff_hour = 24\n");
+        let args = ConfigArgs { config: Some(file.clone()), ..Default::default() };
+
+        let problems = problems(Config::from_sources(Component::StatusConsumer, &args, vars(&[])));
+        fs::write(&file, "[settlements]\ndelay_days = 1\n").unwrap();
+        let schedule = Config::from_sources(Component::StatusConsumer, &args, vars(&[("PAYME__SETTLEMENTS__CUTOFF_HOUR", "18")]))
+            .unwrap()
+            .settlements
+            .schedule();
+        fs::remove_file(file).unwrap();
+
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(problems[0].starts_with("settlements.delay_days"));
+        assert!(problems[1].starts_with("settlements.cutoff_hour"));
+        assert_eq!(schedule.delay_days, 1);
+        assert_eq!(schedule.cutoff_hour, 18);
+    }
+}
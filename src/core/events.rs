@@ -1,8 +1,10 @@
-use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use super::models::TransactionStatus;
+use super::models::{FeeBreakdown, Payout, PayoutStatus, RiskContext, SettlementBatch, SplitLeg, SplitReversal, TransactionStatus};
 
 #[derive(Serialize, Deserialize,Clone)]
 pub struct TransactionCreatedEvent {
@@ -14,6 +16,23 @@ pub struct TransactionCreatedEvent {
     pub currency: String,
     pub merchant_id: String,
     pub customer_id: String,
+    // merchant supplied, forwarded to the provider alongside our own keys
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    // resolved on the command side so the processor can confirm the intent straight away
+    #[serde(default)]
+    pub provider_customer_id: Option<String>,
+    #[serde(default)]
+    pub payment_method_id: Option<String>,
+    // marketplace payments, paid out to the sellers' connected accounts once the charge succeeds
+    #[serde(default)]
+    pub splits: Vec<SplitLeg>,
+    // screened by the risk engine before the payment reaches the provider
+    #[serde(default)]
+    pub risk_context: RiskContext,
+    // when the risk engine or a reviewer let it through to the processor
+    #[serde(default)]
+    pub screened_at: Option<DateTime<Utc>>,
 }
 
 impl TransactionCreatedEvent {
@@ -33,23 +52,96 @@ impl TransactionCreatedEvent {
             currency,
             merchant_id,
             customer_id,
+            metadata: HashMap::new(),
+            provider_customer_id: None,
+            payment_method_id: None,
+            splits: Vec::new(),
+            risk_context: RiskContext::default(),
+            screened_at: None,
         }
     }
+
+    pub fn with_payment_method(mut self, provider_customer_id: String, payment_method_id: String) -> Self {
+        self.provider_customer_id = Some(provider_customer_id);
+        self.payment_method_id = Some(payment_method_id);
+        self
+    }
+
+    pub fn with_metadata(mut self, metadata: HashMap<String, String>) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    pub fn with_splits(mut self, splits: Vec<SplitLeg>) -> Self {
+        self.splits = splits;
+        self
+    }
+
+    pub fn with_risk_context(mut self, risk_context: RiskContext) -> Self {
+        self.risk_context = risk_context;
+        self
+    }
+
+    pub fn screened(mut self, at: DateTime<Utc>) -> Self {
+        self.screened_at = Some(at);
+        self
+    }
+
+    /// Since when the payment has been waiting on the processor, a payment held for review only
+    /// starts waiting once it is approved.
+    pub fn pending_since(&self) -> DateTime<Utc> {
+        self.screened_at.unwrap_or(self.timestamp)
+    }
 } 
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct PaymentStatusUpdatedEvent {
     pub event_id: Uuid,
     pub event_type: String,
     pub timestamp : DateTime<Utc>,
     pub transaction_id: Uuid,
+    #[serde(default)]
+    pub merchant_id: String,
     pub status: TransactionStatus,
-    pub stripe_payment_id: String
+    pub stripe_payment_id: String,
+    #[serde(default)]
+    pub client_secret: Option<String>
 }
 
 impl PaymentStatusUpdatedEvent {
-    pub fn new(transaction_id: Uuid, status: TransactionStatus , stripe_payment_id: String) -> Self {
-        Self { event_id: Uuid::new_v4(), event_type: "STATUS_UPDATED".to_string(), timestamp: Utc::now(), transaction_id, status, stripe_payment_id }
+    pub fn new(transaction_id: Uuid, merchant_id: String, status: TransactionStatus , stripe_payment_id: String) -> Self {
+        Self { event_id: Uuid::new_v4(), event_type: "STATUS_UPDATED".to_string(), timestamp: Utc::now(), transaction_id, merchant_id, status, stripe_payment_id, client_secret: None }
+    }
+
+    pub fn with_client_secret(mut self, client_secret: Option<String>) -> Self {
+        self.client_secret = client_secret;
+        self
+    }
+}
+
+/* payload we POST to the merchant's webhook endpoint, derived from the status update */
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct MerchantWebhookEvent {
+    pub id: Uuid,
+    pub event_type: String,
+    pub created_at: DateTime<Utc>,
+    pub transaction_id: Uuid,
+    pub merchant_id: String,
+    pub status: TransactionStatus,
+    pub provider_payment_id: String
+}
+
+impl From<&PaymentStatusUpdatedEvent> for MerchantWebhookEvent {
+    fn from(event: &PaymentStatusUpdatedEvent) -> Self {
+        Self {
+            id: event.event_id,
+            event_type: "transaction.status_updated".to_string(),
+            created_at: event.timestamp,
+            transaction_id: event.transaction_id,
+            merchant_id: event.merchant_id.clone(),
+            status: event.status.clone(),
+            provider_payment_id: event.stripe_payment_id.clone()
+        }
     }
 }
 
@@ -66,4 +158,149 @@ impl PaymentStatusRequestEvent{
     pub fn new(transaction_id: Uuid) -> Self {
         Self { event_id: Uuid::new_v4(), evnet_type: "STATUS_REQUEST".to_string(), timestamp: Utc::now(), transaction_id , reply_topic: "payment-status-response".to_string() }
     }
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RefundCreatedEvent {
+    pub event_id: Uuid,
+    pub event_type: String,
+    pub timestamp: DateTime<Utc>,
+    pub refund_id: Uuid,
+    pub transaction_id: Uuid,
+    pub merchant_id: String,
+    pub amount: u64,
+    pub currency: String,
+    pub reason: Option<String>,
+    // how a split payment's refund is shared between the sellers and the platform
+    #[serde(default)]
+    pub reversals: Vec<SplitReversal>
+}
+
+impl RefundCreatedEvent {
+    pub fn new(transaction_id: Uuid, merchant_id: String, amount: u64, currency: String, reason: Option<String>) -> Self {
+        Self {
+            event_id: Uuid::new_v4(),
+            event_type: "REFUND.CREATED".to_string(),
+            timestamp: Utc::now(),
+            refund_id: Uuid::new_v4(),
+            transaction_id,
+            merchant_id,
+            amount,
+            currency,
+            reason,
+            reversals: Vec::new()
+        }
+    }
+
+    pub fn with_reversals(mut self, reversals: Vec<SplitReversal>) -> Self {
+        self.reversals = reversals;
+        self
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct FeeAssessedEvent {
+    pub event_id: Uuid,
+    pub event_type: String,
+    pub timestamp: DateTime<Utc>,
+    pub transaction_id: Uuid,
+    pub merchant_id: String,
+    pub amount: u64,
+    pub currency: String,
+    pub fee: FeeBreakdown
+}
+
+impl FeeAssessedEvent {
+    pub fn new(transaction_id: Uuid, merchant_id: String, amount: u64, currency: String, fee: FeeBreakdown) -> Self {
+        Self {
+            event_id: Uuid::new_v4(),
+            event_type: "FEE.ASSESSED".to_string(),
+            timestamp: Utc::now(),
+            transaction_id,
+            merchant_id,
+            amount,
+            currency,
+            fee
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct PayoutCreatedEvent {
+    pub event_id: Uuid,
+    pub event_type: String,
+    pub timestamp: DateTime<Utc>,
+    pub payout_id: Uuid,
+    pub batch_id: Uuid,
+    pub merchant_id: String,
+    pub currency: String,
+    pub amount: u64,
+    pub settlement_date: NaiveDate
+}
+
+impl PayoutCreatedEvent {
+    pub fn new(payout: &Payout, batch: &SettlementBatch) -> Self {
+        Self {
+            event_id: Uuid::new_v4(),
+            event_type: "PAYOUT.CREATED".to_string(),
+            timestamp: Utc::now(),
+            payout_id: payout.id,
+            batch_id: batch.id,
+            merchant_id: payout.merchant_id.clone(),
+            currency: payout.currency.clone(),
+            amount: payout.amount,
+            settlement_date: batch.settlement_date
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct PayoutStatusUpdatedEvent {
+    pub event_id: Uuid,
+    pub event_type: String,
+    pub timestamp: DateTime<Utc>,
+    pub payout_id: Uuid,
+    pub merchant_id: String,
+    pub currency: String,
+    pub amount: u64,
+    pub status: PayoutStatus
+}
+
+impl PayoutStatusUpdatedEvent {
+    pub fn new(payout: &Payout) -> Self {
+        Self {
+            event_id: Uuid::new_v4(),
+            event_type: "PAYOUT.STATUS_UPDATED".to_string(),
+            timestamp: Utc::now(),
+            payout_id: payout.id,
+            merchant_id: payout.merchant_id.clone(),
+            currency: payout.currency.clone(),
+            amount: payout.amount,
+            status: payout.status.clone()
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SplitTransfersCreatedEvent {
+    pub event_id: Uuid,
+    pub event_type: String,
+    pub timestamp: DateTime<Utc>,
+    pub transaction_id: Uuid,
+    pub merchant_id: String,
+    // the legs with their transfer ids filled in
+    pub legs: Vec<SplitLeg>
+}
+
+impl SplitTransfersCreatedEvent {
+    pub fn new(transaction_id: Uuid, merchant_id: String, legs: Vec<SplitLeg>) -> Self {
+        Self {
+            event_id: Uuid::new_v4(),
+            event_type: "SPLIT.TRANSFERS_CREATED".to_string(),
+            timestamp: Utc::now(),
+            transaction_id,
+            merchant_id,
+            legs
+        }
+    }
+}
@@ -1,2 +1,45 @@
+pub mod accounts;
+pub mod accounts_test;
+pub mod audit;
+pub mod audit_test;
+pub mod billing;
+pub mod billing_test;
+pub mod customers;
+pub mod customers_test;
+pub mod dead_letter;
+pub mod dead_letter_test;
+pub mod health;
+pub mod health_test;
 pub mod kafka;
-pub mod stripe;
\ No newline at end of file
+pub mod ledger;
+pub mod ledger_test;
+pub mod limits;
+pub mod limits_test;
+pub mod metrics;
+pub mod metrics_test;
+pub mod pricing;
+pub mod pricing_test;
+pub mod projection;
+pub mod publisher;
+pub mod reconciliation;
+pub mod reconciliation_test;
+pub mod reporting;
+pub mod reporting_test;
+pub mod risk;
+pub mod risk_test;
+pub mod schedule;
+pub mod schedule_test;
+pub mod settlement;
+pub mod settlement_test;
+pub mod shutdown;
+pub mod shutdown_test;
+pub mod splits;
+pub mod splits_test;
+pub mod status_hub;
+pub mod status_hub_test;
+pub mod stripe;
+pub mod stripe_webhook;
+pub mod telemetry;
+pub mod telemetry_test;
+pub mod webhook;
+pub mod webhook_test;
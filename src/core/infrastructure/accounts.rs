@@ -0,0 +1,293 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tokio::{fs, sync::RwLock};
+use uuid::Uuid;
+
+use crate::api::authentication::{MERCHANT_ROLE, STAFF_ROLES};
+
+// what every api key starts with, so a leaked one is easy to grep for
+pub const API_KEY_PREFIX: &str = "pm_";
+const MIN_PASSWORD_LEN: usize = 12;
+
+#[derive(Debug, Error)]
+pub enum AccountError {
+    #[error("Failed to access the account store: {0}")]
+    Io(String),
+    #[error("{0} already exists")]
+    Exists(String),
+    #[error("Unknown merchant {0}")]
+    UnknownMerchant(String),
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Merchant {
+    pub id: String,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct User {
+    pub username: String,
+    // bcrypt, the password itself is never stored
+    pub password_hash: String,
+    pub role: String,
+    // users of a merchant get tokens pinned to it, platform staff have none
+    pub merchant_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub merchant_id: String,
+    // the first characters of the key, enough to tell keys apart in a listing
+    pub prefix: String,
+    // sha256 of the whole key, it is shown once when created and never again
+    pub key_hash: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Accounts {
+    merchants: BTreeMap<String, Merchant>,
+    users: BTreeMap<String, User>,
+    api_keys: Vec<ApiKey>,
+}
+
+// when the file was last written and how long it is, a change means someone else wrote it
+type FileStamp = (SystemTime, u64);
+
+#[derive(Default)]
+struct Cached {
+    accounts: Accounts,
+    // the file the accounts were read from, none until it's read
+    stamp: Option<FileStamp>,
+}
+
+/// Users, merchants and api keys. Kept in a json file when opened with a path, which is read again
+/// whenever it changed, so what `payme-admin` creates is seen by a running api without a restart.
+#[derive(Clone, Default)]
+pub struct AccountStore {
+    path: Option<PathBuf>,
+    // the accounts themselves when there's no file, otherwise what was last read from it
+    accounts: Arc<RwLock<Cached>>,
+}
+
+impl AccountStore {
+    /// Kept in memory only, for tests and local runs.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// What `auth.accounts_path` asks for, an empty path keeps the accounts in memory.
+    pub fn from_path(path: &str) -> Self {
+        match path.trim() {
+            "" => Self::new(),
+            path => Self::open(path),
+        }
+    }
+
+    /// The file is created with the first account, a missing one is an empty store.
+    pub fn open(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: Some(path.as_ref().to_path_buf()),
+            accounts: Arc::default(),
+        }
+    }
+
+    pub async fn create_merchant(&self, id: &str, name: &str) -> Result<Merchant, AccountError> {
+        if id.trim().is_empty() {
+            return Err(AccountError::InvalidRequest("the merchant id can't be empty".to_string()));
+        }
+
+        self.update(|accounts| {
+            if accounts.merchants.contains_key(id) {
+                return Err(AccountError::Exists(format!("merchant {}", id)));
+            }
+            let merchant = Merchant { id: id.to_string(), name: name.to_string(), created_at: Utc::now() };
+            accounts.merchants.insert(id.to_string(), merchant.clone());
+            Ok(merchant)
+        })
+        .await
+    }
+
+    pub async fn create_user(&self, username: &str, password: &str, role: &str, merchant_id: Option<&str>) -> Result<User, AccountError> {
+        if username.trim().is_empty() {
+            return Err(AccountError::InvalidRequest("the username can't be empty".to_string()));
+        }
+        // a merchant user without a merchant would get a platform token, able to act for every merchant
+        match (role, merchant_id) {
+            (MERCHANT_ROLE, None) => return Err(AccountError::InvalidRequest("merchant users need a --merchant".to_string())),
+            (MERCHANT_ROLE, Some(_)) => {}
+            (role, None) if STAFF_ROLES.contains(&role) => {}
+            (role, Some(_)) if STAFF_ROLES.contains(&role) => {
+                return Err(AccountError::InvalidRequest(format!("{} users are platform staff, they can't have a --merchant", role)))
+            }
+            (role, _) => {
+                return Err(AccountError::InvalidRequest(format!(
+                    "unknown role {}, expected {} or {}",
+                    role,
+                    MERCHANT_ROLE,
+                    STAFF_ROLES.join(", ")
+                )))
+            }
+        }
+        if password.len() < MIN_PASSWORD_LEN {
+            return Err(AccountError::InvalidRequest(format!("passwords need at least {} characters", MIN_PASSWORD_LEN)));
+        }
+        // hashed before taking the lock and off the runtime, bcrypt is slow on purpose
+        let password = password.to_string();
+        let password_hash = tokio::task::spawn_blocking(move || bcrypt::hash(password, bcrypt::DEFAULT_COST))
+            .await
+            .map_err(|e| AccountError::InvalidRequest(e.to_string()))?
+            .map_err(|e| AccountError::InvalidRequest(e.to_string()))?;
+
+        self.update(|accounts| {
+            if accounts.users.contains_key(username) {
+                return Err(AccountError::Exists(format!("user {}", username)));
+            }
+            if let Some(merchant_id) = merchant_id.filter(|id| !accounts.merchants.contains_key(*id)) {
+                return Err(AccountError::UnknownMerchant(merchant_id.to_string()));
+            }
+            let user = User {
+                username: username.to_string(),
+                password_hash,
+                role: role.to_string(),
+                merchant_id: merchant_id.map(str::to_string),
+                created_at: Utc::now(),
+            };
+            accounts.users.insert(username.to_string(), user.clone());
+            Ok(user)
+        })
+        .await
+    }
+
+    /// Returns the key itself next to what's stored about it, this is the only time it can be seen.
+    pub async fn create_api_key(&self, merchant_id: &str) -> Result<(ApiKey, String), AccountError> {
+        let secret = format!("{}{}{}", API_KEY_PREFIX, Uuid::new_v4().simple(), Uuid::new_v4().simple());
+
+        let key = self
+            .update(|accounts| {
+                if !accounts.merchants.contains_key(merchant_id) {
+                    return Err(AccountError::UnknownMerchant(merchant_id.to_string()));
+                }
+                let key = ApiKey {
+                    id: Uuid::new_v4(),
+                    merchant_id: merchant_id.to_string(),
+                    prefix: secret[..API_KEY_PREFIX.len() + 8].to_string(),
+                    key_hash: digest(&secret),
+                    created_at: Utc::now(),
+                };
+                accounts.api_keys.push(key.clone());
+                Ok(key)
+            })
+            .await?;
+
+        Ok((key, secret))
+    }
+
+    pub async fn has_users(&self) -> Result<bool, AccountError> {
+        self.read(|accounts| !accounts.users.is_empty()).await
+    }
+
+    /// The user, when the password is theirs.
+    pub async fn authenticate(&self, username: &str, password: &str) -> Result<Option<User>, AccountError> {
+        let Some(user) = self.read(|accounts| accounts.users.get(username).cloned()).await? else {
+            return Ok(None);
+        };
+
+        let password = password.to_string();
+        let password_hash = user.password_hash.clone();
+        let verified = tokio::task::spawn_blocking(move || bcrypt::verify(password, &password_hash).unwrap_or(false))
+            .await
+            .unwrap_or(false);
+        Ok(verified.then_some(user))
+    }
+
+    pub async fn verify_api_key(&self, key: &str) -> Result<Option<ApiKey>, AccountError> {
+        let key_hash = digest(key);
+        self.read(|accounts| accounts.api_keys.iter().find(|api_key| api_key.key_hash == key_hash).cloned()).await
+    }
+
+    // the file is only read again when it changed since the last time
+    async fn read<T>(&self, look: impl FnOnce(&Accounts) -> T) -> Result<T, AccountError> {
+        let Some(path) = &self.path else {
+            return Ok(look(&self.accounts.read().await.accounts));
+        };
+
+        let Some(stamp) = file_stamp(path).await? else {
+            return Ok(look(&Accounts::default()));
+        };
+        {
+            let cached = self.accounts.read().await;
+            if cached.stamp == Some(stamp) {
+                return Ok(look(&cached.accounts));
+            }
+        }
+
+        let mut cached = self.accounts.write().await;
+        cached.accounts = load_file(path).await?;
+        cached.stamp = Some(stamp);
+        Ok(look(&cached.accounts))
+    }
+
+    async fn update<T>(&self, change: impl FnOnce(&mut Accounts) -> Result<T, AccountError>) -> Result<T, AccountError> {
+        let mut cached = self.accounts.write().await;
+        let Some(path) = &self.path else {
+            return change(&mut cached.accounts);
+        };
+
+        // read again rather than trusting the cache, payme-admin may have written since
+        let mut accounts = load_file(path).await?;
+        let result = change(&mut accounts)?;
+
+        // written next to the store and moved over it, a crash never leaves half a file behind
+        let json = serde_json::to_string_pretty(&accounts).expect("accounts serialize");
+        let part = path.with_extension("part");
+        let written = match fs::write(&part, json).await {
+            Ok(()) => fs::rename(&part, path).await,
+            Err(e) => Err(e),
+        };
+        written.map_err(|e| AccountError::Io(format!("{}: {}", path.display(), e)))?;
+
+        cached.stamp = file_stamp(path).await?;
+        cached.accounts = accounts;
+        Ok(result)
+    }
+}
+
+async fn file_stamp(path: &Path) -> Result<Option<FileStamp>, AccountError> {
+    match fs::metadata(path).await {
+        Ok(metadata) => {
+            let modified = metadata.modified().map_err(|e| AccountError::Io(format!("{}: {}", path.display(), e)))?;
+            Ok(Some((modified, metadata.len())))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(AccountError::Io(format!("{}: {}", path.display(), e))),
+    }
+}
+
+async fn load_file(path: &Path) -> Result<Accounts, AccountError> {
+    let text = match fs::read_to_string(path).await {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Accounts::default()),
+        Err(e) => return Err(AccountError::Io(format!("{}: {}", path.display(), e))),
+    };
+    serde_json::from_str(&text).map_err(|e| AccountError::Io(format!("{}: {}", path.display(), e)))
+}
+
+fn digest(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
@@ -0,0 +1,89 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use uuid::Uuid;
+
+    use crate::core::infrastructure::accounts::{AccountError, AccountStore, API_KEY_PREFIX};
+
+    const PASSWORD: &str = "correct horse battery";
+
+    #[tokio::test]
+    async fn test_users_authenticate_with_their_password_only() {
+        let store = AccountStore::new();
+        store.create_merchant("m_1", "Coffee Shop").await.unwrap();
+        let user = store.create_user("barista", PASSWORD, "merchant", Some("m_1")).await.unwrap();
+        assert_ne!(user.password_hash, PASSWORD);
+
+        let found = store.authenticate("barista", PASSWORD).await.unwrap().unwrap();
+        assert_eq!(found.merchant_id.as_deref(), Some("m_1"));
+        assert!(store.authenticate("barista", "wrong horse battery").await.unwrap().is_none());
+        assert!(store.authenticate("nobody", PASSWORD).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_accounts_are_checked_before_they_are_created() {
+        let store = AccountStore::new();
+        store.create_merchant("m_1", "Coffee Shop").await.unwrap();
+
+        assert!(matches!(store.create_merchant("m_1", "Again").await, Err(AccountError::Exists(_))));
+        assert!(matches!(store.create_user("short", "hunter2", "admin", None).await, Err(AccountError::InvalidRequest(_))));
+        assert!(matches!(
+            store.create_user("barista", PASSWORD, "merchant", Some("m_2")).await,
+            Err(AccountError::UnknownMerchant(_))
+        ));
+        assert!(matches!(store.create_api_key("m_2").await, Err(AccountError::UnknownMerchant(_))));
+        assert!(!store.has_users().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_roles_are_known_and_only_merchant_users_have_a_merchant() {
+        let store = AccountStore::new();
+        store.create_merchant("m_1", "Coffee Shop").await.unwrap();
+
+        for (role, merchant_id) in [("merchant", None), ("admin", Some("m_1")), ("finance", Some("m_1")), ("superuser", None), ("", None)] {
+            assert!(
+                matches!(store.create_user("someone", PASSWORD, role, merchant_id).await, Err(AccountError::InvalidRequest(_))),
+                "{} with {:?} was accepted",
+                role,
+                merchant_id
+            );
+        }
+        assert!(!store.has_users().await.unwrap());
+
+        store.create_user("auditor", PASSWORD, "auditor", None).await.unwrap();
+        store.create_user("barista", PASSWORD, "merchant", Some("m_1")).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_api_keys_are_only_stored_hashed() {
+        let store = AccountStore::new();
+        store.create_merchant("m_1", "Coffee Shop").await.unwrap();
+
+        let (key, secret) = store.create_api_key("m_1").await.unwrap();
+
+        assert!(secret.starts_with(API_KEY_PREFIX) && secret.starts_with(&key.prefix));
+        assert_ne!(key.key_hash, secret);
+        assert_eq!(store.verify_api_key(&secret).await.unwrap(), Some(key));
+        assert_eq!(store.verify_api_key(&format!("{}x", secret)).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_stores_on_the_same_file_see_each_others_accounts() {
+        let path = std::env::temp_dir().join(format!("payme-accounts-{}.json", Uuid::new_v4()));
+        let admin = AccountStore::open(&path);
+        let api = AccountStore::open(&path);
+        assert!(!api.has_users().await.unwrap());
+
+        admin.create_user("ops", PASSWORD, "admin", None).await.unwrap();
+
+        assert!(api.authenticate("ops", PASSWORD).await.unwrap().is_some());
+        assert!(!fs::read_to_string(&path).unwrap().contains(PASSWORD));
+
+        // what the api read is kept until the file changes
+        admin.create_merchant("m_1", "Coffee Shop").await.unwrap();
+        let (key, secret) = admin.create_api_key("m_1").await.unwrap();
+        assert_eq!(api.verify_api_key(&secret).await.unwrap(), Some(key));
+        fs::remove_file(path).unwrap();
+    }
+}
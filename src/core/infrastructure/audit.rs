@@ -0,0 +1,286 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+    sync::{mpsc, Arc},
+    thread,
+};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tokio::sync::{oneshot, RwLock};
+use uuid::Uuid;
+
+use crate::core::models::{Actor, AuditAction, AuditEntry};
+
+// what the first entry chains from
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+#[derive(Debug, Error)]
+pub enum AuditError {
+    #[error("Failed to access the audit log: {0}")]
+    Io(String),
+    #[error("Audit log line {0} is not an entry: {1}")]
+    Corrupt(usize, String),
+}
+
+/* what a handler knows about an action, the log adds the sequence and the hashes */
+#[derive(Debug, Clone)]
+pub struct AuditRecord {
+    pub actor: Actor,
+    pub action: AuditAction,
+    pub target: Option<String>,
+    pub request_id: String,
+    pub ip_address: Option<String>,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+}
+
+impl AuditRecord {
+    pub fn new(actor: Actor, action: AuditAction, request_id: String, ip_address: Option<String>) -> Self {
+        Self {
+            actor,
+            action,
+            target: None,
+            request_id,
+            ip_address,
+            before: None,
+            after: None,
+        }
+    }
+
+    pub fn target(mut self, target: impl ToString) -> Self {
+        self.target = Some(target.to_string());
+        self
+    }
+
+    pub fn before<T: Serialize>(mut self, state: &T) -> Self {
+        self.before = serde_json::to_value(state).ok();
+        self
+    }
+
+    pub fn after<T: Serialize>(mut self, state: &T) -> Self {
+        self.after = serde_json::to_value(state).ok();
+        self
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct AuditFilter {
+    pub actor: Option<String>,
+    pub action: Option<AuditAction>,
+    pub target: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl AuditFilter {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        self.actor.as_ref().is_none_or(|actor| &entry.actor.subject == actor)
+            && self.action.is_none_or(|action| entry.action == action)
+            && self.target.as_ref().is_none_or(|target| entry.target.as_ref() == Some(target))
+            && self.from.is_none_or(|from| entry.recorded_at >= from)
+            && self.to.is_none_or(|to| entry.recorded_at < to)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditVerification {
+    pub entries_checked: usize,
+    pub valid: bool,
+    // the first entry that doesn't fit the chain, nothing after it can be trusted either
+    pub first_invalid_sequence: Option<u64>,
+    pub problem: Option<String>,
+}
+
+// a json line for the writer and where to report whether it reached the disk
+type PendingLine = (String, oneshot::Sender<io::Result<()>>);
+
+#[derive(Default)]
+struct AuditState {
+    // append only, nothing is ever updated or removed
+    entries: Vec<AuditEntry>,
+    // every entry is also written to the file by the writer thread when the log is kept on disk
+    writer: Option<mpsc::Sender<PendingLine>>,
+}
+
+/// Append only log of privileged and financial actions. Each entry's hash covers the
+/// previous one, so editing or dropping an entry breaks every hash after it.
+#[derive(Clone, Default)]
+pub struct AuditLog {
+    state: Arc<RwLock<AuditState>>,
+}
+
+impl AuditLog {
+    /// Kept in memory only, for tests and local runs.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Continues the chain stored at `path`, creating the file if needed.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, AuditError> {
+        let path = path.as_ref();
+        let entries = if path.exists() { read_entries(path)? } else { Vec::new() };
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| AuditError::Io(format!("{}: {}", path.display(), e)))?;
+
+        // writing and syncing blocks, that stays off the runtime
+        let (writer, lines) = mpsc::channel();
+        thread::Builder::new()
+            .name("audit-writer".to_string())
+            .spawn(move || write_lines(file, lines))
+            .map_err(|e| AuditError::Io(e.to_string()))?;
+
+        Ok(Self {
+            state: Arc::new(RwLock::new(AuditState { entries, writer: Some(writer) })),
+        })
+    }
+
+    /// Chains the entry and returns once it's on disk, when the log is kept there.
+    pub async fn record(&self, record: AuditRecord) -> AuditEntry {
+        let (entry, written) = self.append(record).await;
+
+        if let Some(written) = written {
+            // the action already happened, losing its trail is logged loudly rather than undone
+            let result = written.await.unwrap_or_else(|_| Err(io::Error::other("the audit writer stopped")));
+            if let Err(e) = result {
+                eprintln!("Failed to persist audit entry {}: {}", entry.sequence, e);
+            }
+        }
+
+        entry
+    }
+
+    // lines are queued under the lock, so they reach the file in the order they were chained
+    async fn append(&self, record: AuditRecord) -> (AuditEntry, Option<oneshot::Receiver<io::Result<()>>>) {
+        let mut state = self.state.write().await;
+        let previous_hash = state.entries.last().map_or_else(|| GENESIS_HASH.to_string(), |last| last.hash.clone());
+
+        let mut entry = AuditEntry {
+            sequence: state.entries.len() as u64,
+            id: Uuid::new_v4(),
+            recorded_at: Utc::now(),
+            actor: record.actor,
+            action: record.action,
+            target: record.target,
+            request_id: record.request_id,
+            ip_address: record.ip_address,
+            before: record.before,
+            after: record.after,
+            previous_hash,
+            hash: String::new(),
+        };
+        entry.hash = entry_hash(&entry);
+
+        let written = state.writer.as_ref().map(|writer| {
+            let line = serde_json::to_string(&entry).expect("audit entries serialize");
+            let (done, written) = oneshot::channel();
+            // a writer that stopped drops `done`, which reads as a failed write
+            let _ = writer.send((line, done));
+            written
+        });
+
+        state.entries.push(entry.clone());
+        (entry, written)
+    }
+
+    /// Matching entries, most recent first.
+    pub async fn search(&self, filter: &AuditFilter, limit: usize) -> Vec<AuditEntry> {
+        self.state
+            .read()
+            .await
+            .entries
+            .iter()
+            .rev()
+            .filter(|entry| filter.matches(entry))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    pub async fn verify(&self) -> AuditVerification {
+        verify_chain(&self.state.read().await.entries)
+    }
+}
+
+fn write_lines(mut file: File, lines: mpsc::Receiver<PendingLine>) {
+    for (line, done) in lines {
+        let _ = done.send(writeln!(file, "{}", line).and_then(|_| file.sync_data()));
+    }
+}
+
+/// Reads a log written by `AuditLog::open`, one json entry per line.
+pub fn read_entries(path: impl AsRef<Path>) -> Result<Vec<AuditEntry>, AuditError> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|e| AuditError::Io(format!("{}: {}", path.display(), e)))?;
+
+    let mut entries = Vec::new();
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| AuditError::Io(e.to_string()))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        entries.push(serde_json::from_str(&line).map_err(|e| AuditError::Corrupt(index + 1, e.to_string()))?);
+    }
+    Ok(entries)
+}
+
+/// Walks the chain from the start, stops at the first entry that doesn't fit.
+pub fn verify_chain(entries: &[AuditEntry]) -> AuditVerification {
+    let mut previous_hash = GENESIS_HASH;
+
+    for (index, entry) in entries.iter().enumerate() {
+        let problem = if entry.sequence != index as u64 {
+            Some(format!("expected sequence {}, found {}", index, entry.sequence))
+        } else if entry.previous_hash != previous_hash {
+            Some("does not link to the entry before it".to_string())
+        } else if entry.hash != entry_hash(entry) {
+            Some("contents do not match its hash".to_string())
+        } else {
+            None
+        };
+
+        if let Some(problem) = problem {
+            return AuditVerification {
+                entries_checked: index + 1,
+                valid: false,
+                first_invalid_sequence: Some(index as u64),
+                problem: Some(problem),
+            };
+        }
+        previous_hash = &entry.hash;
+    }
+
+    AuditVerification {
+        entries_checked: entries.len(),
+        valid: true,
+        first_invalid_sequence: None,
+        problem: None,
+    }
+}
+
+// every field but the hash itself, serde_json sorts object keys so the bytes are stable
+fn entry_hash(entry: &AuditEntry) -> String {
+    let content = serde_json::json!({
+        "sequence": entry.sequence,
+        "id": entry.id,
+        "recorded_at": entry.recorded_at,
+        "actor": entry.actor,
+        "action": entry.action,
+        "target": entry.target,
+        "request_id": entry.request_id,
+        "ip_address": entry.ip_address,
+        "before": entry.before,
+        "after": entry.after,
+        "previous_hash": entry.previous_hash,
+    });
+
+    let mut hasher = Sha256::new();
+    hasher.update(content.to_string().as_bytes());
+    hex::encode(hasher.finalize())
+}
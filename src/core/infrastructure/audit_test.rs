@@ -0,0 +1,110 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use crate::core::{
+        infrastructure::audit::{read_entries, verify_chain, AuditFilter, AuditLog, AuditRecord, GENESIS_HASH},
+        models::{Actor, AuditAction},
+    };
+
+    fn ops() -> Actor {
+        Actor { subject: "ops_1".to_string(), role: "admin".to_string() }
+    }
+
+    fn refund(transaction: &str) -> AuditRecord {
+        AuditRecord::new(ops(), AuditAction::RefundCreated, "req_1".to_string(), Some("10.0.0.1".to_string()))
+            .target(transaction)
+            .before(&json!({ "amount_refunded": 0 }))
+            .after(&json!({ "amount": 500 }))
+    }
+
+    #[tokio::test]
+    async fn test_entries_are_chained_in_order() {
+        let log = AuditLog::new();
+
+        let first = log.record(refund("txn_1")).await;
+        let second = log.record(refund("txn_2")).await;
+
+        assert_eq!(first.sequence, 0);
+        assert_eq!(first.previous_hash, GENESIS_HASH);
+        assert_eq!(second.previous_hash, first.hash);
+        assert!(log.verify().await.valid);
+    }
+
+    #[tokio::test]
+    async fn test_search_answers_who_refunded_a_transaction() {
+        let log = AuditLog::new();
+        log.record(refund("txn_1")).await;
+        log.record(AuditRecord::new(ops(), AuditAction::RiskRulesChanged, "req_2".to_string(), None)).await;
+        log.record(refund("txn_2")).await;
+
+        let filter = AuditFilter {
+            action: Some(AuditAction::RefundCreated),
+            target: Some("txn_1".to_string()),
+            ..AuditFilter::default()
+        };
+        let found = log.search(&filter, 10).await;
+
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].actor, ops());
+        assert_eq!(found[0].ip_address.as_deref(), Some("10.0.0.1"));
+        assert_eq!(log.search(&AuditFilter::default(), 2).await[0].target.as_deref(), Some("txn_2"));
+    }
+
+    #[tokio::test]
+    async fn test_edited_entry_breaks_the_chain() {
+        let path = std::env::temp_dir().join(format!("payme-audit-{}.jsonl", Uuid::new_v4()));
+        let log = AuditLog::open(&path).unwrap();
+        for transaction in ["txn_1", "txn_2", "txn_3"] {
+            log.record(refund(transaction)).await;
+        }
+
+        // someone shrinks the second refund in place
+        let mut lines: Vec<String> = fs::read_to_string(&path).unwrap().lines().map(str::to_string).collect();
+        let mut second: serde_json::Value = serde_json::from_str(&lines[1]).unwrap();
+        second["after"]["amount"] = json!(5);
+        lines[1] = second.to_string();
+        fs::write(&path, lines.join("\n")).unwrap();
+
+        let verification = verify_chain(&read_entries(&path).unwrap());
+        fs::remove_file(path).unwrap();
+
+        assert!(!verification.valid);
+        assert_eq!(verification.first_invalid_sequence, Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_dropped_entry_breaks_the_chain() {
+        let log = AuditLog::new();
+        for transaction in ["txn_1", "txn_2", "txn_3"] {
+            log.record(refund(transaction)).await;
+        }
+
+        let mut entries = log.search(&AuditFilter::default(), 10).await;
+        entries.reverse();
+        entries.remove(1);
+
+        let verification = verify_chain(&entries);
+        assert!(!verification.valid);
+        assert_eq!(verification.first_invalid_sequence, Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_reopened_log_continues_the_chain() {
+        let path = std::env::temp_dir().join(format!("payme-audit-{}.jsonl", Uuid::new_v4()));
+        AuditLog::open(&path).unwrap().record(refund("txn_1")).await;
+
+        let reopened = AuditLog::open(&path).unwrap();
+        let entry = reopened.record(refund("txn_2")).await;
+        let entries = read_entries(&path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(entry.sequence, 1);
+        assert_eq!(entries.len(), 2);
+        assert!(verify_chain(&entries).valid);
+        assert!(reopened.verify().await.valid);
+    }
+}
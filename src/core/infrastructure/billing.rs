@@ -0,0 +1,210 @@
+use std::{collections::HashMap, sync::Arc};
+
+use chrono::{DateTime, Duration, Months, Utc};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::core::models::{
+    BillingInterval, Invoice, InvoiceStatus, Plan, Subscription, SubscriptionStatus,
+};
+
+pub fn add_interval(from: DateTime<Utc>, interval: BillingInterval, count: u32) -> DateTime<Utc> {
+    match interval {
+        BillingInterval::Day => from + Duration::days(count as i64),
+        BillingInterval::Week => from + Duration::weeks(count as i64),
+        BillingInterval::Month => from
+            .checked_add_months(Months::new(count))
+            .expect("billing date out of range"),
+        BillingInterval::Year => from
+            .checked_add_months(Months::new(count * 12))
+            .expect("billing date out of range"),
+    }
+}
+
+pub fn sub_interval(from: DateTime<Utc>, interval: BillingInterval, count: u32) -> DateTime<Utc> {
+    match interval {
+        BillingInterval::Day => from - Duration::days(count as i64),
+        BillingInterval::Week => from - Duration::weeks(count as i64),
+        BillingInterval::Month => from
+            .checked_sub_months(Months::new(count))
+            .expect("billing date out of range"),
+        BillingInterval::Year => from
+            .checked_sub_months(Months::new(count * 12))
+            .expect("billing date out of range"),
+    }
+}
+
+/// First period boundary strictly after `after`. Boundaries are always computed
+/// from the anchor (anchor + k intervals) so a 31st anchor comes back to the 31st.
+pub fn next_boundary(
+    anchor: DateTime<Utc>,
+    interval: BillingInterval,
+    interval_count: u32,
+    after: DateTime<Utc>,
+) -> DateTime<Utc> {
+    let mut periods = 0;
+    loop {
+        let boundary = add_interval(anchor, interval, interval_count * periods);
+        if boundary > after {
+            return boundary;
+        }
+        periods += 1;
+    }
+}
+
+/// Share of `amount` covering `[start, end)` out of a period of `full` length.
+pub fn prorate(amount: u64, start: DateTime<Utc>, end: DateTime<Utc>, full: Duration) -> i64 {
+    let used = (end - start).num_seconds().max(0) as i128;
+    let full = full.num_seconds().max(1) as i128;
+
+    (amount as i128 * used.min(full) / full) as i64
+}
+
+/* credit for the unused part of the old plan against the cost of the new one for the same time */
+pub fn plan_change_proration(
+    old_plan: &Plan,
+    new_plan: &Plan,
+    subscription: &Subscription,
+    now: DateTime<Utc>,
+) -> i64 {
+    let full = subscription.current_period_end - subscription.current_period_start;
+    let unused_start = now.max(subscription.current_period_start);
+
+    let credit = prorate(old_plan.amount, unused_start, subscription.current_period_end, full);
+    let charge = prorate(new_plan.amount, unused_start, subscription.current_period_end, full);
+
+    charge - credit
+}
+
+#[derive(Debug, Clone)]
+pub struct DunningPolicy {
+    // delay before each retry, counted from the failed attempt
+    pub retry_after: Vec<Duration>,
+    // where the subscription ends up once every retry failed
+    pub on_exhausted: SubscriptionStatus,
+}
+
+impl Default for DunningPolicy {
+    fn default() -> Self {
+        Self {
+            retry_after: vec![Duration::days(1), Duration::days(3), Duration::days(5)],
+            on_exhausted: SubscriptionStatus::Unpaid,
+        }
+    }
+}
+
+impl DunningPolicy {
+    pub fn new(retry_after: Vec<Duration>, on_exhausted: SubscriptionStatus) -> Self {
+        Self { retry_after, on_exhausted }
+    }
+
+    /// When to retry after `attempts_made` failed attempts, `None` once retries are exhausted.
+    pub fn next_retry(&self, attempts_made: u32, failed_at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let index = attempts_made.checked_sub(1)? as usize;
+        self.retry_after.get(index).map(|delay| failed_at + *delay)
+    }
+}
+
+// In production this would live in the database, for now it's kept in memory
+#[derive(Clone, Default)]
+pub struct BillingStore {
+    plans: Arc<RwLock<HashMap<Uuid, Plan>>>,
+    subscriptions: Arc<RwLock<HashMap<Uuid, Subscription>>>,
+    invoices: Arc<RwLock<HashMap<Uuid, Invoice>>>,
+    invoice_by_transaction: Arc<RwLock<HashMap<Uuid, Uuid>>>,
+}
+
+impl BillingStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn save_plan(&self, plan: Plan) {
+        self.plans.write().await.insert(plan.id, plan);
+    }
+
+    pub async fn plan(&self, id: Uuid) -> Option<Plan> {
+        self.plans.read().await.get(&id).cloned()
+    }
+
+    pub async fn plans_for_merchant(&self, merchant_id: &str) -> Vec<Plan> {
+        self.plans
+            .read()
+            .await
+            .values()
+            .filter(|p| p.merchant_id == merchant_id)
+            .cloned()
+            .collect()
+    }
+
+    pub async fn save_subscription(&self, subscription: Subscription) {
+        self.subscriptions
+            .write()
+            .await
+            .insert(subscription.id, subscription);
+    }
+
+    pub async fn subscription(&self, id: Uuid) -> Option<Subscription> {
+        self.subscriptions.read().await.get(&id).cloned()
+    }
+
+    /// Subscriptions whose current period has ended and need renewing (or canceling).
+    pub async fn subscriptions_due(&self, now: DateTime<Utc>) -> Vec<Subscription> {
+        self.subscriptions
+            .read()
+            .await
+            .values()
+            .filter(|s| {
+                matches!(
+                    s.status,
+                    SubscriptionStatus::Trialing | SubscriptionStatus::Active | SubscriptionStatus::PastDue
+                )
+            })
+            .filter(|s| s.current_period_end <= now)
+            .cloned()
+            .collect()
+    }
+
+    pub async fn save_invoice(&self, invoice: Invoice) {
+        let mut index = self.invoice_by_transaction.write().await;
+        for transaction_id in &invoice.transaction_ids {
+            index.insert(*transaction_id, invoice.id);
+        }
+
+        self.invoices.write().await.insert(invoice.id, invoice);
+    }
+
+    pub async fn invoice(&self, id: Uuid) -> Option<Invoice> {
+        self.invoices.read().await.get(&id).cloned()
+    }
+
+    pub async fn invoice_for_transaction(&self, transaction_id: Uuid) -> Option<Invoice> {
+        let invoice_id = *self.invoice_by_transaction.read().await.get(&transaction_id)?;
+        self.invoice(invoice_id).await
+    }
+
+    pub async fn invoices_for_subscription(&self, subscription_id: Uuid) -> Vec<Invoice> {
+        let mut invoices: Vec<Invoice> = self
+            .invoices
+            .read()
+            .await
+            .values()
+            .filter(|i| i.subscription_id == subscription_id)
+            .cloned()
+            .collect();
+
+        invoices.sort_by_key(|i| i.period_start);
+        invoices
+    }
+
+    pub async fn invoices_due_for_retry(&self, now: DateTime<Utc>) -> Vec<Invoice> {
+        self.invoices
+            .read()
+            .await
+            .values()
+            .filter(|i| i.status == InvoiceStatus::Open)
+            .filter(|i| i.next_retry_at.map(|at| at <= now).unwrap_or(false))
+            .cloned()
+            .collect()
+    }
+}
@@ -0,0 +1,87 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+    use uuid::Uuid;
+
+    use crate::core::{
+        infrastructure::billing::{next_boundary, plan_change_proration, prorate, DunningPolicy},
+        models::{BillingInterval, Plan, Subscription, SubscriptionStatus},
+    };
+
+    fn date(y: i32, m: u32, d: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, m, d, 0, 0, 0).unwrap()
+    }
+
+    fn plan(amount: u64) -> Plan {
+        Plan {
+            id: Uuid::new_v4(),
+            merchant_id: "merch_123".to_string(),
+            name: "plan".to_string(),
+            amount,
+            currency: "USD".to_string(),
+            interval: BillingInterval::Month,
+            interval_count: 1,
+            trial_days: 0,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_month_end_anchor_does_not_drift() {
+        let anchor = date(2025, 1, 31);
+
+        let feb = next_boundary(anchor, BillingInterval::Month, 1, anchor);
+        let mar = next_boundary(anchor, BillingInterval::Month, 1, feb);
+
+        assert_eq!(feb, date(2025, 2, 28));
+        assert_eq!(mar, date(2025, 3, 31));
+    }
+
+    #[test]
+    fn test_future_anchor_is_the_first_boundary() {
+        let anchor = date(2025, 6, 15);
+
+        assert_eq!(next_boundary(anchor, BillingInterval::Month, 1, date(2025, 6, 1)), anchor);
+    }
+
+    #[test]
+    fn test_prorate_half_period() {
+        let start = date(2025, 4, 1);
+        let end = date(2025, 4, 16);
+
+        assert_eq!(prorate(3000, start, end, Duration::days(30)), 1500);
+    }
+
+    #[test]
+    fn test_upgrade_halfway_charges_the_difference() {
+        let subscription = Subscription {
+            id: Uuid::new_v4(),
+            merchant_id: "merch_123".to_string(),
+            customer_id: "cust_123".to_string(),
+            plan_id: Uuid::new_v4(),
+            status: SubscriptionStatus::Active,
+            billing_anchor: date(2025, 4, 1),
+            current_period_start: date(2025, 4, 1),
+            current_period_end: date(2025, 5, 1),
+            trial_end: None,
+            cancel_at_period_end: false,
+            pending_proration: 0,
+            canceled_at: None,
+            created_at: date(2025, 4, 1),
+        };
+
+        let proration = plan_change_proration(&plan(1000), &plan(3000), &subscription, date(2025, 4, 16));
+
+        assert_eq!(proration, 1000);
+    }
+
+    #[test]
+    fn test_dunning_schedule_is_exhausted() {
+        let policy = DunningPolicy::new(vec![Duration::days(1), Duration::days(3)], SubscriptionStatus::Canceled);
+        let failed_at = date(2025, 4, 1);
+
+        assert_eq!(policy.next_retry(1, failed_at), Some(date(2025, 4, 2)));
+        assert_eq!(policy.next_retry(2, failed_at), Some(date(2025, 4, 4)));
+        assert_eq!(policy.next_retry(3, failed_at), None);
+    }
+}
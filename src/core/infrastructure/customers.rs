@@ -0,0 +1,40 @@
+use std::{collections::HashMap, sync::Arc};
+
+use tokio::sync::RwLock;
+
+use crate::core::models::Customer;
+
+// In production this would live in the database, for now it's kept in memory.
+// Customer ids are the merchant's own, so two merchants can each have a `cust_1`.
+#[derive(Clone, Default)]
+pub struct CustomerStore {
+    customers: Arc<RwLock<HashMap<(String, String), Customer>>>,
+}
+
+impl CustomerStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn get(&self, merchant_id: &str, customer_id: &str) -> Option<Customer> {
+        self.customers
+            .read()
+            .await
+            .get(&(merchant_id.to_string(), customer_id.to_string()))
+            .cloned()
+    }
+
+    pub async fn save(&self, customer: Customer) {
+        self.customers
+            .write()
+            .await
+            .insert((customer.merchant_id.clone(), customer.id.clone()), customer);
+    }
+
+    pub async fn remove(&self, merchant_id: &str, customer_id: &str) -> Option<Customer> {
+        self.customers
+            .write()
+            .await
+            .remove(&(merchant_id.to_string(), customer_id.to_string()))
+    }
+}
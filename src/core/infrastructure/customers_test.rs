@@ -0,0 +1,35 @@
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use crate::core::{infrastructure::customers::CustomerStore, models::Customer};
+
+    fn customer(merchant_id: &str, id: &str, provider_customer_id: &str) -> Customer {
+        Customer {
+            id: id.to_string(),
+            merchant_id: merchant_id.to_string(),
+            email: None,
+            name: None,
+            provider_customer_id: provider_customer_id.to_string(),
+            default_payment_method_id: None,
+            payment_methods: Vec::new(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_merchants_own_their_customer_ids() {
+        let store = CustomerStore::new();
+        store.save(customer("merch_1", "cust_1", "cus_a")).await;
+        store.save(customer("merch_2", "cust_1", "cus_b")).await;
+
+        assert_eq!(store.get("merch_1", "cust_1").await.unwrap().provider_customer_id, "cus_a");
+        assert_eq!(store.get("merch_2", "cust_1").await.unwrap().provider_customer_id, "cus_b");
+        assert!(store.get("merch_3", "cust_1").await.is_none());
+
+        store.remove("merch_1", "cust_1").await;
+        assert!(store.get("merch_1", "cust_1").await.is_none());
+        assert!(store.get("merch_2", "cust_1").await.is_some());
+    }
+}
@@ -0,0 +1,63 @@
+use std::{fmt, sync::{Arc, OnceLock}};
+
+use chrono::{DateTime, Utc};
+use rdkafka::Message;
+use serde::{Deserialize, Serialize};
+
+use crate::core::infrastructure::{
+    kafka::recorded_event,
+    metrics::metrics,
+    publisher::{EventPublisher, RecordedEvent},
+};
+
+/// A message a consumer couldn't read, published to the dead letter topic as it was received.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub consumer: String,
+    // what the consumer expected the message to be
+    pub kind: String,
+    pub error: String,
+    pub rejected_at: DateTime<Utc>,
+    pub message: RecordedEvent,
+}
+
+impl DeadLetter {
+    pub fn new<M: Message>(consumer: &str, msg: &M, kind: &str, error: impl fmt::Display) -> Self {
+        Self {
+            consumer: consumer.to_string(),
+            kind: kind.to_string(),
+            error: error.to_string(),
+            rejected_at: Utc::now(),
+            message: recorded_event(msg),
+        }
+    }
+}
+
+struct DeadLetterQueue {
+    publisher: Arc<dyn EventPublisher>,
+    topic: String,
+}
+
+static QUEUE: OnceLock<DeadLetterQueue> = OnceLock::new();
+
+/// Sends what the consumers of this process reject to `topic`. Until it's called, and in tests,
+/// rejected messages are only counted and logged.
+pub fn install_dead_letter_queue(publisher: Arc<dyn EventPublisher>, topic: &str) {
+    let queue = DeadLetterQueue { publisher, topic: topic.to_string() };
+    if QUEUE.set(queue).is_err() {
+        eprintln!("Dead letter queue already installed, keeping the first one");
+    }
+}
+
+/// Counts and logs a message the consumer has to skip, then parks it on the dead letter topic.
+pub async fn dead_letter<M: Message>(consumer: &str, msg: &M, kind: &str, error: impl fmt::Display) {
+    metrics().reject_message(consumer, msg.topic(), kind, &error);
+
+    let Some(queue) = QUEUE.get() else { return };
+    let letter = DeadLetter::new(consumer, msg, kind, error);
+    let key = letter.message.key.clone().unwrap_or_default();
+    // the consumer moves on either way, a message lost here is still in the rejected count
+    if let Err(e) = queue.publisher.publish_event(&queue.topic, &key, &letter).await {
+        eprintln!("Failed to dead letter {}/{}@{}: {}", letter.message.topic, letter.message.partition, letter.message.offset, e);
+    }
+}
@@ -0,0 +1,55 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rdkafka::message::{OwnedMessage, Timestamp};
+
+    use crate::core::infrastructure::{
+        dead_letter::{dead_letter, install_dead_letter_queue, DeadLetter},
+        publisher::{EventLog, InMemoryPublisher},
+    };
+
+    fn garbled(key: &str) -> OwnedMessage {
+        OwnedMessage::new(
+            Some(b"{not json".to_vec()),
+            Some(key.as_bytes().to_vec()),
+            "transaction-created".to_string(),
+            Timestamp::CreateTime(1_700_000_000_000),
+            3,
+            42,
+            None,
+        )
+    }
+
+    #[test]
+    fn test_letter_keeps_the_message_as_received() {
+        let letter = DeadLetter::new("fee-calculator", &garbled("txn_1"), "TransactionCreatedEvent", "expected value");
+
+        assert_eq!(letter.message.topic, "transaction-created");
+        assert_eq!((letter.message.partition, letter.message.offset), (3, 42));
+        assert_eq!(letter.message.key.as_deref(), Some("txn_1"));
+        assert_eq!(letter.message.payload, "{not json");
+        assert_eq!(letter.message.timestamp.unwrap().timestamp_millis(), 1_700_000_000_000);
+        assert_eq!(letter.error, "expected value");
+    }
+
+    #[tokio::test]
+    async fn test_rejected_messages_are_parked_on_the_dead_letter_topic() {
+        let publisher = Arc::new(InMemoryPublisher::new());
+        // the queue is per process, other tests' consumers may park messages here too
+        install_dead_letter_queue(publisher.clone(), "dead-letter-test");
+
+        dead_letter("dead-letter-test", &garbled("txn_2"), "TransactionCreatedEvent", "expected value").await;
+
+        let parked: Vec<DeadLetter> = publisher
+            .read(&["dead-letter-test"])
+            .await
+            .unwrap()
+            .iter()
+            .map(|event| serde_json::from_str(&event.payload).unwrap())
+            .filter(|letter: &DeadLetter| letter.consumer == "dead-letter-test")
+            .collect();
+        assert_eq!(parked.len(), 1);
+        assert_eq!(parked[0].message.key.as_deref(), Some("txn_2"));
+    }
+}
@@ -0,0 +1,147 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
+use rdkafka::{producer::FutureProducer, ClientConfig};
+use serde::Serialize;
+
+use crate::core::{config::Secret, infrastructure::shutdown::Shutdown};
+
+// a broker that takes longer than this to answer is as good as down for a probe
+const KAFKA_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/* something a process needs before it should be sent traffic */
+#[axum::async_trait]
+pub trait ReadinessCheck: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn check(&self) -> Result<(), String>;
+}
+
+/* the brokers answer a metadata request */
+pub struct KafkaCheck {
+    producer: FutureProducer,
+}
+
+impl KafkaCheck {
+    pub fn new(brokers: &str) -> Self {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .create()
+            .expect("Failed to create the kafka health check client");
+
+        Self { producer }
+    }
+}
+
+#[axum::async_trait]
+impl ReadinessCheck for KafkaCheck {
+    fn name(&self) -> &'static str {
+        "kafka"
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        let producer = self.producer.clone();
+        // librdkafka blocks until the brokers answer
+        tokio::task::spawn_blocking(move || producer.client().fetch_metadata(None, KAFKA_CHECK_TIMEOUT))
+            .await
+            .map_err(|e| e.to_string())?
+            .map(|_| ())
+            .map_err(|e| format!("brokers unreachable: {}", e))
+    }
+}
+
+/* payments can't be taken without provider credentials */
+pub struct ProviderCheck {
+    configured: bool,
+}
+
+impl ProviderCheck {
+    pub fn new(secret_key: &Secret) -> Self {
+        Self { configured: !secret_key.is_empty() }
+    }
+}
+
+#[axum::async_trait]
+impl ReadinessCheck for ProviderCheck {
+    fn name(&self) -> &'static str {
+        "stripe"
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        if self.configured {
+            Ok(())
+        } else {
+            Err("no secret key configured".to_string())
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    pub status: &'static str,
+    pub checks: BTreeMap<&'static str, String>,
+}
+
+impl Readiness {
+    pub fn is_ready(&self) -> bool {
+        self.status == "ready"
+    }
+}
+
+/// What `/healthz` and `/readyz` report for a process. The stores are still in memory,
+/// so there is no database to check until they move out of the process.
+#[derive(Clone)]
+pub struct Health {
+    checks: Vec<Arc<dyn ReadinessCheck>>,
+    shutdown: Shutdown,
+}
+
+impl Health {
+    pub fn new(shutdown: Shutdown) -> Self {
+        Self { checks: Vec::new(), shutdown }
+    }
+
+    pub fn with_check(mut self, check: impl ReadinessCheck + 'static) -> Self {
+        self.checks.push(Arc::new(check));
+        self
+    }
+
+    /// Not ready once draining starts, so load balancers stop sending requests before the server stops taking them.
+    pub async fn readiness(&self) -> Readiness {
+        let mut checks = BTreeMap::new();
+        let mut failed = false;
+        for check in &self.checks {
+            let outcome = match check.check().await {
+                Ok(()) => "ok".to_string(),
+                Err(e) => {
+                    failed = true;
+                    e
+                }
+            };
+            checks.insert(check.name(), outcome);
+        }
+
+        let status = if self.shutdown.is_triggered() {
+            "shutting_down"
+        } else if failed {
+            "not_ready"
+        } else {
+            "ready"
+        };
+        Readiness { status, checks }
+    }
+}
+
+/// `/healthz` answers as long as the process does, `/readyz` runs the checks.
+pub fn health_routes<S: Clone + Send + Sync + 'static>(health: Health) -> Router<S> {
+    Router::new()
+        .route("/healthz", get(|| async { Json(serde_json::json!({ "status": "ok" })) }))
+        .route("/readyz", get(readyz))
+        .with_state(health)
+}
+
+async fn readyz(State(health): State<Health>) -> (StatusCode, Json<Readiness>) {
+    let readiness = health.readiness().await;
+    let status = if readiness.is_ready() { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(readiness))
+}
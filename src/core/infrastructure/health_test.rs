@@ -0,0 +1,71 @@
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use axum_test::TestServer;
+    use serde_json::Value;
+
+    use crate::core::{
+        config::Secret,
+        infrastructure::{
+            health::{health_routes, Health, ProviderCheck, ReadinessCheck},
+            shutdown::Shutdown,
+        },
+    };
+
+    struct BrokerDown;
+
+    #[axum::async_trait]
+    impl ReadinessCheck for BrokerDown {
+        fn name(&self) -> &'static str {
+            "kafka"
+        }
+
+        async fn check(&self) -> Result<(), String> {
+            Err("brokers unreachable".to_string())
+        }
+    }
+
+    fn server(health: Health) -> TestServer {
+        TestServer::new(health_routes::<()>(health)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_ready_when_every_check_passes() {
+        let health = Health::new(Shutdown::new()).with_check(ProviderCheck::new(&Secret::new("sk_test_123")));
+
+        let response = server(health).get("/readyz").await;
+
+        response.assert_status_ok();
+        let body: Value = response.json();
+        assert_eq!(body["status"], "ready");
+        assert_eq!(body["checks"]["stripe"], "ok");
+    }
+
+    #[tokio::test]
+    async fn test_failed_check_is_reported_by_name() {
+        let health = Health::new(Shutdown::new())
+            .with_check(BrokerDown)
+            .with_check(ProviderCheck::new(&Secret::default()));
+
+        let response = server(health).get("/readyz").await;
+
+        response.assert_status(StatusCode::SERVICE_UNAVAILABLE);
+        let body: Value = response.json();
+        assert_eq!(body["status"], "not_ready");
+        assert_eq!(body["checks"]["kafka"], "brokers unreachable");
+        assert_eq!(body["checks"]["stripe"], "no secret key configured");
+    }
+
+    #[tokio::test]
+    async fn test_not_ready_once_draining_but_still_alive() {
+        let shutdown = Shutdown::new();
+        let server = server(Health::new(shutdown.clone()).with_check(ProviderCheck::new(&Secret::new("sk_test_123"))));
+
+        shutdown.trigger();
+
+        let response = server.get("/readyz").await;
+        response.assert_status(StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.json::<Value>()["status"], "shutting_down");
+        server.get("/healthz").await.assert_status_ok();
+    }
+}
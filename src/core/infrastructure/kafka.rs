@@ -1,7 +1,38 @@
-use rdkafka::producer::{FutureProducer, FutureRecord};
-use rdkafka::ClientConfig;
+use rdkafka::consumer::{BaseConsumer, Consumer};
+use rdkafka::producer::{FutureProducer, FutureRecord, Producer};
+use rdkafka::{ClientConfig, Message, Offset, TopicPartitionList};
 use serde::Serialize;
-use std::time::Duration;
+use std::{collections::HashMap, time::Duration};
+use uuid::Uuid;
+
+use crate::core::infrastructure::{
+    publisher::{EventLog, EventPublisher, RecordedEvent},
+    telemetry::trace_headers,
+};
+
+// how long reading a topic back waits on the broker before giving up
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn create_producer(brokers: &str) -> FutureProducer {
+    ClientConfig::new()
+        .set("bootstrap.servers", brokers)
+        .set("message.timeout.ms", "5000")
+        .create()
+        .expect("Producer creation failed")
+}
+
+// a record is delivered or given up on within message.timeout.ms, so waiting longer gains nothing
+pub const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Waits for everything the producer still buffers to reach the broker.
+pub async fn flush_producer(producer: &FutureProducer, timeout: Duration) -> Result<(), String> {
+    let producer = producer.clone();
+    // librdkafka blocks while flushing
+    tokio::task::spawn_blocking(move || producer.flush(timeout))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| format!("Failed to flush producer: {}", e))
+}
 
 pub struct KafkaProducer {
     producer: FutureProducer,
@@ -10,14 +41,8 @@ pub struct KafkaProducer {
 
 impl KafkaProducer {
     pub fn new(brokers: &str, topic: &str) -> Self {
-        let producer: FutureProducer = ClientConfig::new()
-            .set("bootstrap.servers", brokers)
-            .set("message.timeout.ms", "5000")
-            .create()
-            .expect("Producer creation failed");
-
         Self {
-            producer,
+            producer: create_producer(brokers),
             topic: topic.to_string(),
         }
     }
@@ -30,7 +55,8 @@ impl KafkaProducer {
             .send(
                 FutureRecord::to(&self.topic)
                     .payload(&payload)
-                    .key(""), // You might want to set a key based on transaction_id
+                    .key("") // You might want to set a key based on transaction_id
+                    .headers(trace_headers()),
                 Duration::from_secs(5),
             )
             .await
@@ -38,4 +64,129 @@ impl KafkaProducer {
 
         Ok(())
     }
-} 
\ No newline at end of file
+
+    pub async fn flush(&self) -> Result<(), String> {
+        flush_producer(&self.producer, FLUSH_TIMEOUT).await
+    }
+}
+
+/* one long lived producer the whole api process publishes through, whatever the topic */
+#[derive(Clone)]
+pub struct KafkaPublisher {
+    producer: FutureProducer,
+}
+
+impl KafkaPublisher {
+    pub fn new(brokers: &str) -> Self {
+        Self {
+            producer: create_producer(brokers),
+        }
+    }
+}
+
+#[axum::async_trait]
+impl EventPublisher for KafkaPublisher {
+    async fn publish(&self, topic: &str, key: &str, payload: String) -> Result<(), String> {
+        self.producer
+            .send(
+                FutureRecord::to(topic).payload(&payload).key(key).headers(trace_headers()),
+                Duration::from_secs(5),
+            )
+            .await
+            .map_err(|(e, _)| format!("Failed to send message: {}", e))?;
+
+        Ok(())
+    }
+
+    async fn flush(&self, timeout: Duration) -> Result<(), String> {
+        flush_producer(&self.producer, timeout).await
+    }
+}
+
+/// What a consumed message held, for dead letters and tooling.
+pub fn recorded_event<M: Message>(msg: &M) -> RecordedEvent {
+    RecordedEvent {
+        topic: msg.topic().to_string(),
+        partition: msg.partition(),
+        offset: msg.offset(),
+        key: msg.key().map(|key| String::from_utf8_lossy(key).into_owned()),
+        timestamp: msg.timestamp().to_millis().and_then(chrono::DateTime::from_timestamp_millis),
+        payload: msg.payload().map(|payload| String::from_utf8_lossy(payload).into_owned()).unwrap_or_default(),
+    }
+}
+
+/* reads topics from the beginning up to where they ended when the read started, without joining a consumer group's offsets */
+pub struct KafkaEventLog {
+    brokers: String,
+}
+
+impl KafkaEventLog {
+    pub fn new(brokers: &str) -> Self {
+        Self { brokers: brokers.to_string() }
+    }
+}
+
+#[axum::async_trait]
+impl EventLog for KafkaEventLog {
+    async fn read(&self, topics: &[&str]) -> Result<Vec<RecordedEvent>, String> {
+        let brokers = self.brokers.clone();
+        let topics: Vec<String> = topics.iter().map(|topic| topic.to_string()).collect();
+        // librdkafka blocks while polling
+        tokio::task::spawn_blocking(move || read_topics(&brokers, &topics))
+            .await
+            .map_err(|e| e.to_string())?
+    }
+}
+
+fn read_topics(brokers: &str, topics: &[String]) -> Result<Vec<RecordedEvent>, String> {
+    let consumer: BaseConsumer = ClientConfig::new()
+        .set("bootstrap.servers", brokers)
+        // offsets are never committed, the group only exists because librdkafka wants one
+        .set("group.id", format!("payme-event-log-{}", Uuid::new_v4()))
+        .set("enable.auto.commit", "false")
+        .create()
+        .map_err(|e| format!("Failed to create consumer: {}", e))?;
+
+    // where each partition ended when we started, anything published since is left out
+    let mut ends: HashMap<(String, i32), i64> = HashMap::new();
+    let mut assignment = TopicPartitionList::new();
+    for topic in topics {
+        let metadata = consumer
+            .fetch_metadata(Some(topic), READ_TIMEOUT)
+            .map_err(|e| format!("Failed to fetch metadata of {}: {}", topic, e))?;
+        for partition in metadata.topics().iter().flat_map(|t| t.partitions()) {
+            let (low, high) = consumer
+                .fetch_watermarks(topic, partition.id(), READ_TIMEOUT)
+                .map_err(|e| format!("Failed to fetch watermarks of {}/{}: {}", topic, partition.id(), e))?;
+            if high > low {
+                assignment
+                    .add_partition_offset(topic, partition.id(), Offset::Beginning)
+                    .map_err(|e| e.to_string())?;
+                ends.insert((topic.clone(), partition.id()), high);
+            }
+        }
+    }
+    if ends.is_empty() {
+        return Ok(Vec::new());
+    }
+    consumer.assign(&assignment).map_err(|e| format!("Failed to assign partitions: {}", e))?;
+
+    let mut events = Vec::new();
+    while !ends.is_empty() {
+        let msg = match consumer.poll(READ_TIMEOUT) {
+            Some(Ok(msg)) => msg,
+            Some(Err(e)) => return Err(format!("Failed to read: {}", e)),
+            None => return Err(format!("Timed out reading {}", topics.join(", "))),
+        };
+        let partition = (msg.topic().to_string(), msg.partition());
+        let Some(end) = ends.get(&partition).copied() else { continue };
+        if msg.offset() >= end - 1 {
+            ends.remove(&partition);
+        }
+        if msg.offset() < end {
+            events.push(recorded_event(&msg));
+        }
+    }
+
+    Ok(events)
+}
@@ -0,0 +1,401 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Arc,
+};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use thiserror::Error;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::core::{
+    events::{FeeAssessedEvent, PaymentStatusUpdatedEvent, PayoutStatusUpdatedEvent, RefundCreatedEvent, TransactionCreatedEvent},
+    infrastructure::splits::reverse_proportionally,
+    models::{JournalEntry, LedgerAccount, PayoutStatus, Posting, SplitLeg, SplitReversal, TransactionStatus},
+};
+
+#[derive(Debug, Error)]
+pub enum LedgerError {
+    #[error("Journal entry for {0} does not balance (off by {1})")]
+    Unbalanced(Uuid, i64),
+    #[error("Journal entry for {0} has no postings")]
+    Empty(Uuid),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SettlementState {
+    Pending,
+    Settled,
+    Failed,
+}
+
+/* what the ledger remembers about a transaction to post its later events */
+#[derive(Debug, Clone)]
+struct LedgerTransaction {
+    merchant_id: String,
+    amount: u64,
+    currency: String,
+    state: SettlementState,
+    // refunds recorded but not yet paid out by the provider
+    refunds_outstanding: u64,
+    refunded: u64,
+    // split payments credit each seller instead of the merchant
+    splits: Vec<SplitLeg>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AccountBalance {
+    pub account: String,
+    pub currency: String,
+    pub as_of: DateTime<Utc>,
+    // in the account's normal direction, so a merchant balance we owe is positive
+    pub balance: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LedgerVerification {
+    pub entries_checked: usize,
+    pub unbalanced_entries: Vec<Uuid>,
+    // sum of every posting per currency, zero when the books balance
+    pub trial_balance: BTreeMap<String, i64>,
+    pub balanced: bool,
+}
+
+#[derive(Default)]
+struct LedgerState {
+    // append only, corrections are posted as new entries
+    entries: Vec<JournalEntry>,
+    sources: HashSet<String>,
+    transactions: HashMap<Uuid, LedgerTransaction>,
+    // status updates that overtook their creation event
+    parked: HashMap<Uuid, Vec<PaymentStatusUpdatedEvent>>,
+    // fees are only taken out of settled funds
+    parked_fees: HashMap<Uuid, FeeAssessedEvent>,
+}
+
+// In production this would be its own database, for now it's kept in memory
+#[derive(Clone, Default)]
+pub struct Ledger {
+    state: Arc<RwLock<LedgerState>>,
+}
+
+fn debit(account: LedgerAccount, amount: u64) -> Posting {
+    Posting { account, amount: amount as i64 }
+}
+
+fn credit(account: LedgerAccount, amount: u64) -> Posting {
+    Posting { account, amount: -(amount as i64) }
+}
+
+impl Ledger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn record_created(&self, event: &TransactionCreatedEvent) -> Result<(), LedgerError> {
+        let parked = {
+            let mut state = self.state.write().await;
+            if state.transactions.contains_key(&event.transaction_id) {
+                return Ok(());
+            }
+
+            state.transactions.insert(event.transaction_id, LedgerTransaction {
+                merchant_id: event.merchant_id.clone(),
+                amount: event.amount,
+                currency: event.currency.clone(),
+                state: SettlementState::Pending,
+                refunds_outstanding: 0,
+                refunded: 0,
+                splits: event.splits.clone(),
+            });
+
+            let postings = vec![
+                debit(LedgerAccount::ProviderReceivable, event.amount),
+                credit(LedgerAccount::MerchantPending { merchant_id: event.merchant_id.clone() }, event.amount),
+            ];
+
+            post(&mut state, event.transaction_id, event.event_id.to_string(), "payment authorised", &event.currency, postings, event.timestamp)?;
+
+            state.parked.remove(&event.transaction_id).unwrap_or_default()
+        };
+
+        for status_event in parked {
+            self.record_status(&status_event).await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn record_status(&self, event: &PaymentStatusUpdatedEvent) -> Result<(), LedgerError> {
+        let mut state = self.state.write().await;
+
+        let Some(mut transaction) = state.transactions.get(&event.transaction_id).cloned() else {
+            state.parked.entry(event.transaction_id).or_default().push(event.clone());
+            return Ok(());
+        };
+
+        let merchant_id = transaction.merchant_id.clone();
+        let source = event.event_id.to_string();
+
+        match (&event.status, transaction.state) {
+            (TransactionStatus::Completed, SettlementState::Pending) => {
+                let mut postings = vec![
+                    debit(LedgerAccount::MerchantPending { merchant_id: merchant_id.clone() }, transaction.amount),
+                    credit(LedgerAccount::ProviderReceivable, transaction.amount),
+                    debit(LedgerAccount::ProviderClearing, transaction.amount),
+                ];
+                postings.extend(settlement_credits(&merchant_id, &transaction));
+
+                post(&mut state, event.transaction_id, source, "payment settled", &transaction.currency, postings, event.timestamp)?;
+                transaction.state = SettlementState::Settled;
+
+                if let Some(fee_event) = state.parked_fees.remove(&event.transaction_id) {
+                    post_fee(&mut state, &transaction, &fee_event)?;
+                }
+            }
+            (TransactionStatus::Failed { .. }, SettlementState::Pending) => {
+                let postings = vec![
+                    debit(LedgerAccount::MerchantPending { merchant_id }, transaction.amount),
+                    credit(LedgerAccount::ProviderReceivable, transaction.amount),
+                ];
+
+                post(&mut state, event.transaction_id, source, "payment failed", &transaction.currency, postings, event.timestamp)?;
+                transaction.state = SettlementState::Failed;
+            }
+            (TransactionStatus::Refunded, SettlementState::Settled) => {
+                // a refund issued outside of payme (e.g. the stripe dashboard) was never recorded
+                let unrecorded = transaction.amount - transaction.refunded - transaction.refunds_outstanding;
+                if unrecorded > 0 {
+                    let reversals = reverse_proportionally(&transaction.splits, transaction.amount, unrecorded);
+                    let postings = refund_postings(&merchant_id, unrecorded, &reversals);
+
+                    post(&mut state, event.transaction_id, format!("{}:recorded", source), "refund recorded", &transaction.currency, postings, event.timestamp)?;
+                    transaction.refunds_outstanding += unrecorded;
+                }
+
+                if transaction.refunds_outstanding > 0 {
+                    let postings = vec![
+                        debit(LedgerAccount::RefundsPayable { merchant_id }, transaction.refunds_outstanding),
+                        credit(LedgerAccount::ProviderClearing, transaction.refunds_outstanding),
+                    ];
+
+                    post(&mut state, event.transaction_id, source, "refund paid out", &transaction.currency, postings, event.timestamp)?;
+                    transaction.refunded += transaction.refunds_outstanding;
+                    transaction.refunds_outstanding = 0;
+                }
+            }
+            // requires action, duplicates and late updates don't move money
+            _ => {}
+        }
+
+        state.transactions.insert(event.transaction_id, transaction);
+
+        Ok(())
+    }
+
+    pub async fn record_refund(&self, event: &RefundCreatedEvent) -> Result<(), LedgerError> {
+        let mut state = self.state.write().await;
+
+        let Some(mut transaction) = state.transactions.get(&event.transaction_id).cloned() else {
+            eprintln!("Refund {} for unknown transaction {}", event.refund_id, event.transaction_id);
+            return Ok(());
+        };
+
+        let refundable = transaction.amount - transaction.refunded - transaction.refunds_outstanding;
+        if transaction.state != SettlementState::Settled || event.amount > refundable {
+            eprintln!("Refund {} of {} exceeds what is refundable on {}", event.refund_id, event.amount, event.transaction_id);
+            return Ok(());
+        }
+
+        // the command side already worked out each seller's share, recomputing it could round differently
+        let reversals = if event.reversals.is_empty() {
+            reverse_proportionally(&transaction.splits, transaction.amount, event.amount)
+        } else {
+            event.reversals.clone()
+        };
+        let postings = refund_postings(&transaction.merchant_id, event.amount, &reversals);
+
+        post(&mut state, event.transaction_id, event.event_id.to_string(), "refund recorded", &transaction.currency, postings, event.timestamp)?;
+        transaction.refunds_outstanding += event.amount;
+        state.transactions.insert(event.transaction_id, transaction);
+
+        Ok(())
+    }
+
+    pub async fn record_fee(&self, event: &FeeAssessedEvent) -> Result<(), LedgerError> {
+        let mut state = self.state.write().await;
+
+        match state.transactions.get(&event.transaction_id).cloned() {
+            Some(transaction) if transaction.state == SettlementState::Settled => post_fee(&mut state, &transaction, event),
+            _ => {
+                state.parked_fees.insert(event.transaction_id, event.clone());
+                Ok(())
+            }
+        }
+    }
+
+    /// Funds leave the provider balance once the merchant's bank confirmed the payout.
+    pub async fn record_payout(&self, event: &PayoutStatusUpdatedEvent) -> Result<(), LedgerError> {
+        if event.status != PayoutStatus::Paid || event.amount == 0 {
+            return Ok(());
+        }
+
+        let postings = vec![
+            debit(LedgerAccount::MerchantBalance { merchant_id: event.merchant_id.clone() }, event.amount),
+            credit(LedgerAccount::ProviderClearing, event.amount),
+        ];
+
+        let mut state = self.state.write().await;
+        post(&mut state, event.payout_id, event.event_id.to_string(), "payout", &event.currency, postings, event.timestamp)
+    }
+
+    pub async fn entries_for_transaction(&self, transaction_id: Uuid) -> Vec<JournalEntry> {
+        self.state
+            .read()
+            .await
+            .entries
+            .iter()
+            .filter(|e| e.transaction_id == transaction_id)
+            .cloned()
+            .collect()
+    }
+
+    pub async fn balance(&self, account: &LedgerAccount, currency: &str, as_of: DateTime<Utc>) -> AccountBalance {
+        let raw: i64 = self
+            .state
+            .read()
+            .await
+            .entries
+            .iter()
+            .filter(|e| e.currency == currency && e.effective_at <= as_of)
+            .flat_map(|e| e.postings.iter())
+            .filter(|p| &p.account == account)
+            .map(|p| p.amount)
+            .sum();
+
+        AccountBalance {
+            account: account.code(),
+            currency: currency.to_string(),
+            as_of,
+            balance: if account.is_debit_normal() { raw } else { -raw },
+        }
+    }
+
+    /// Checks every journal entry sums to zero, and so the whole book per currency.
+    pub async fn verify(&self) -> LedgerVerification {
+        let state = self.state.read().await;
+
+        let mut trial_balance = BTreeMap::new();
+        let mut unbalanced_entries = Vec::new();
+
+        for entry in &state.entries {
+            let total = entry.total();
+            if total != 0 || entry.postings.is_empty() {
+                unbalanced_entries.push(entry.id);
+            }
+            *trial_balance.entry(entry.currency.clone()).or_insert(0) += total;
+        }
+
+        let balanced = unbalanced_entries.is_empty() && trial_balance.values().all(|total| *total == 0);
+
+        LedgerVerification {
+            entries_checked: state.entries.len(),
+            unbalanced_entries,
+            trial_balance,
+            balanced,
+        }
+    }
+}
+
+/* who is owed a settled payment: the merchant, or each seller and our application fees */
+fn settlement_credits(merchant_id: &str, transaction: &LedgerTransaction) -> Vec<Posting> {
+    if transaction.splits.is_empty() {
+        return vec![credit(LedgerAccount::MerchantBalance { merchant_id: merchant_id.to_string() }, transaction.amount)];
+    }
+
+    let mut postings: Vec<Posting> = transaction
+        .splits
+        .iter()
+        .filter(|leg| leg.seller_amount() > 0)
+        .map(|leg| credit(LedgerAccount::MerchantBalance { merchant_id: leg.destination_merchant_id.clone() }, leg.seller_amount()))
+        .collect();
+
+    let application_fees: u64 = transaction.splits.iter().map(|leg| leg.application_fee).sum();
+    if application_fees > 0 {
+        postings.push(credit(LedgerAccount::PlatformFees, application_fees));
+    }
+
+    postings
+}
+
+fn refund_postings(merchant_id: &str, amount: u64, reversals: &[SplitReversal]) -> Vec<Posting> {
+    let mut postings = if reversals.is_empty() {
+        vec![debit(LedgerAccount::MerchantBalance { merchant_id: merchant_id.to_string() }, amount)]
+    } else {
+        let mut postings = Vec::new();
+        for reversal in reversals {
+            if reversal.transfer_reversal > 0 {
+                postings.push(debit(LedgerAccount::MerchantBalance { merchant_id: reversal.destination_merchant_id.clone() }, reversal.transfer_reversal));
+            }
+            if reversal.application_fee_refund > 0 {
+                postings.push(debit(LedgerAccount::PlatformFees, reversal.application_fee_refund));
+            }
+        }
+        postings
+    };
+
+    postings.push(credit(LedgerAccount::RefundsPayable { merchant_id: merchant_id.to_string() }, amount));
+    postings
+}
+
+fn post_fee(state: &mut LedgerState, transaction: &LedgerTransaction, event: &FeeAssessedEvent) -> Result<(), LedgerError> {
+    if event.fee.total == 0 {
+        return Ok(());
+    }
+
+    let postings = vec![
+        debit(LedgerAccount::MerchantBalance { merchant_id: transaction.merchant_id.clone() }, event.fee.total),
+        credit(LedgerAccount::PlatformFees, event.fee.total),
+    ];
+
+    post(state, event.transaction_id, event.event_id.to_string(), "processing fee", &transaction.currency, postings, event.timestamp)
+}
+
+fn post(
+    state: &mut LedgerState,
+    transaction_id: Uuid,
+    source: String,
+    description: &str,
+    currency: &str,
+    postings: Vec<Posting>,
+    effective_at: DateTime<Utc>,
+) -> Result<(), LedgerError> {
+    // redelivered events must not post twice
+    if state.sources.contains(&source) {
+        return Ok(());
+    }
+
+    let entry = JournalEntry {
+        id: Uuid::new_v4(),
+        transaction_id,
+        source: source.clone(),
+        description: description.to_string(),
+        currency: currency.to_string(),
+        postings,
+        effective_at,
+        recorded_at: Utc::now(),
+    };
+
+    if entry.postings.is_empty() {
+        return Err(LedgerError::Empty(transaction_id));
+    }
+    if entry.total() != 0 {
+        return Err(LedgerError::Unbalanced(transaction_id, entry.total()));
+    }
+
+    state.sources.insert(source);
+    state.entries.push(entry);
+
+    Ok(())
+}
@@ -0,0 +1,152 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use uuid::Uuid;
+
+    use crate::core::{
+        events::{FeeAssessedEvent, PaymentStatusUpdatedEvent, RefundCreatedEvent, TransactionCreatedEvent},
+        infrastructure::ledger::Ledger,
+        models::{FeeBreakdown, LedgerAccount, SplitLeg, TransactionStatus},
+    };
+
+    fn created(amount: u64) -> TransactionCreatedEvent {
+        TransactionCreatedEvent::new(Uuid::new_v4(), amount, "USD".to_string(), "merch_123".to_string(), "cust_123".to_string())
+    }
+
+    fn status(event: &TransactionCreatedEvent, status: TransactionStatus) -> PaymentStatusUpdatedEvent {
+        PaymentStatusUpdatedEvent::new(event.transaction_id, event.merchant_id.clone(), status, "pi_123".to_string())
+    }
+
+    fn merchant_balance() -> LedgerAccount {
+        LedgerAccount::MerchantBalance { merchant_id: "merch_123".to_string() }
+    }
+
+    #[tokio::test]
+    async fn test_settled_payment_credits_merchant() {
+        let ledger = Ledger::new();
+        let event = created(1000);
+
+        ledger.record_created(&event).await.unwrap();
+        ledger.record_status(&status(&event, TransactionStatus::Completed)).await.unwrap();
+
+        let balance = ledger.balance(&merchant_balance(), "USD", Utc::now()).await;
+        let clearing = ledger.balance(&LedgerAccount::ProviderClearing, "USD", Utc::now()).await;
+        let receivable = ledger.balance(&LedgerAccount::ProviderReceivable, "USD", Utc::now()).await;
+
+        assert_eq!(balance.balance, 1000);
+        assert_eq!(clearing.balance, 1000);
+        assert_eq!(receivable.balance, 0);
+        assert!(ledger.verify().await.balanced);
+    }
+
+    #[tokio::test]
+    async fn test_refund_moves_funds_back_out() {
+        let ledger = Ledger::new();
+        let event = created(1000);
+
+        ledger.record_created(&event).await.unwrap();
+        ledger.record_status(&status(&event, TransactionStatus::Completed)).await.unwrap();
+        ledger.record_refund(&RefundCreatedEvent::new(event.transaction_id, "merch_123".to_string(), 400, "USD".to_string(), None)).await.unwrap();
+        ledger.record_status(&status(&event, TransactionStatus::Refunded)).await.unwrap();
+
+        assert_eq!(ledger.balance(&merchant_balance(), "USD", Utc::now()).await.balance, 0);
+        assert_eq!(ledger.balance(&LedgerAccount::ProviderClearing, "USD", Utc::now()).await.balance, 0);
+        assert!(ledger.verify().await.balanced);
+    }
+
+    #[tokio::test]
+    async fn test_redelivered_events_post_once() {
+        let ledger = Ledger::new();
+        let event = created(1000);
+        let completed = status(&event, TransactionStatus::Completed);
+
+        ledger.record_created(&event).await.unwrap();
+        ledger.record_created(&event).await.unwrap();
+        ledger.record_status(&completed).await.unwrap();
+        ledger.record_status(&completed).await.unwrap();
+
+        assert_eq!(ledger.entries_for_transaction(event.transaction_id).await.len(), 2);
+        assert_eq!(ledger.balance(&merchant_balance(), "USD", Utc::now()).await.balance, 1000);
+    }
+
+    #[tokio::test]
+    async fn test_status_before_creation_is_parked() {
+        let ledger = Ledger::new();
+        let event = created(1000);
+
+        ledger.record_status(&status(&event, TransactionStatus::Completed)).await.unwrap();
+        assert!(ledger.entries_for_transaction(event.transaction_id).await.is_empty());
+
+        ledger.record_created(&event).await.unwrap();
+        assert_eq!(ledger.balance(&merchant_balance(), "USD", Utc::now()).await.balance, 1000);
+    }
+
+    #[tokio::test]
+    async fn test_balance_as_of_ignores_later_entries() {
+        let ledger = Ledger::new();
+        let event = created(1000);
+        let mut completed = status(&event, TransactionStatus::Completed);
+        completed.timestamp = event.timestamp + Duration::hours(1);
+
+        ledger.record_created(&event).await.unwrap();
+        ledger.record_status(&completed).await.unwrap();
+
+        let before = ledger.balance(&merchant_balance(), "USD", event.timestamp + Duration::minutes(30)).await;
+        let pending = ledger.balance(&LedgerAccount::MerchantPending { merchant_id: "merch_123".to_string() }, "USD", event.timestamp).await;
+
+        assert_eq!(before.balance, 0);
+        assert_eq!(pending.balance, 1000);
+    }
+
+    #[tokio::test]
+    async fn test_fee_moves_to_platform_once_settled() {
+        let ledger = Ledger::new();
+        let event = created(1000);
+        let fee = FeeBreakdown { percentage_bps: 290, percentage_fee: 29, fixed_fee: 30, total: 59, volume_tier: None };
+
+        ledger.record_created(&event).await.unwrap();
+        // the fee can arrive before the ledger saw the completion
+        ledger.record_fee(&FeeAssessedEvent::new(event.transaction_id, "merch_123".to_string(), 1000, "USD".to_string(), fee)).await.unwrap();
+        assert_eq!(ledger.balance(&LedgerAccount::PlatformFees, "USD", Utc::now()).await.balance, 0);
+
+        ledger.record_status(&status(&event, TransactionStatus::Completed)).await.unwrap();
+
+        assert_eq!(ledger.balance(&merchant_balance(), "USD", Utc::now()).await.balance, 941);
+        assert_eq!(ledger.balance(&LedgerAccount::PlatformFees, "USD", Utc::now()).await.balance, 59);
+        assert!(ledger.verify().await.balanced);
+    }
+
+    fn leg(merchant_id: &str, amount: u64, application_fee: u64) -> SplitLeg {
+        SplitLeg {
+            destination_merchant_id: merchant_id.to_string(),
+            provider_account_id: format!("acct_{}", merchant_id),
+            amount,
+            application_fee,
+            transfer_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_split_payment_credits_sellers_and_refund_reverses_them() {
+        let ledger = Ledger::new();
+        let event = created(1000).with_splits(vec![leg("seller_a", 600, 60), leg("seller_b", 400, 40)]);
+        let seller_a = LedgerAccount::MerchantBalance { merchant_id: "seller_a".to_string() };
+        let seller_b = LedgerAccount::MerchantBalance { merchant_id: "seller_b".to_string() };
+
+        ledger.record_created(&event).await.unwrap();
+        ledger.record_status(&status(&event, TransactionStatus::Completed)).await.unwrap();
+
+        assert_eq!(ledger.balance(&seller_a, "USD", Utc::now()).await.balance, 540);
+        assert_eq!(ledger.balance(&seller_b, "USD", Utc::now()).await.balance, 360);
+        assert_eq!(ledger.balance(&LedgerAccount::PlatformFees, "USD", Utc::now()).await.balance, 100);
+        assert_eq!(ledger.balance(&merchant_balance(), "USD", Utc::now()).await.balance, 0);
+
+        // half the payment back, every leg gives up half of what it got
+        ledger.record_refund(&RefundCreatedEvent::new(event.transaction_id, "merch_123".to_string(), 500, "USD".to_string(), None)).await.unwrap();
+
+        assert_eq!(ledger.balance(&seller_a, "USD", Utc::now()).await.balance, 270);
+        assert_eq!(ledger.balance(&seller_b, "USD", Utc::now()).await.balance, 180);
+        assert_eq!(ledger.balance(&LedgerAccount::PlatformFees, "USD", Utc::now()).await.balance, 50);
+        assert!(ledger.verify().await.balanced);
+    }
+}
@@ -0,0 +1,259 @@
+use std::{collections::HashMap, net::IpAddr, sync::Arc};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Deserialize;
+use thiserror::Error;
+use tokio::sync::Mutex;
+
+use crate::core::infrastructure::pricing::currency_key;
+
+// full buckets are dropped once this many are tracked, they behave the same as a fresh one
+const MAX_TRACKED_BUCKETS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BucketConfig {
+    // burst size
+    pub capacity: u32,
+    pub refill_per_second: f64,
+}
+
+impl BucketConfig {
+    pub fn new(capacity: u32, refill_per_second: f64) -> Self {
+        Self { capacity, refill_per_second }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub per_merchant: BucketConfig,
+    pub per_api_key: BucketConfig,
+    pub per_ip: BucketConfig,
+    // merchants on a different contract than the default
+    pub merchant_overrides: HashMap<String, BucketConfig>,
+    // load balancers whose x-forwarded-for is believed, anyone else's is ignored
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            per_merchant: BucketConfig::new(100, 50.0),
+            per_api_key: BucketConfig::new(100, 50.0),
+            per_ip: BucketConfig::new(20, 10.0),
+            merchant_overrides: HashMap::new(),
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LimitKey {
+    Merchant,
+    ApiKey,
+    Ip,
+}
+
+/* what the caller is told in the RateLimit-* headers */
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimitDecision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    // seconds until the bucket is full again
+    pub reset_after: u64,
+    // seconds until the next request would be let through, 0 when allowed
+    pub retry_after: u64,
+}
+
+#[derive(Debug, Clone)]
+struct Bucket {
+    tokens: f64,
+    updated_at: DateTime<Utc>,
+}
+
+// In production this would live in redis so every api instance shares it, for now it's kept in memory
+#[derive(Clone, Default)]
+pub struct RateLimiter {
+    config: Arc<RateLimitConfig>,
+    buckets: Arc<Mutex<HashMap<(LimitKey, String), Bucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            buckets: Arc::default(),
+        }
+    }
+
+    pub fn trusted_proxies(&self) -> &[IpAddr] {
+        &self.config.trusted_proxies
+    }
+
+    fn config_for(&self, kind: LimitKey, key: &str) -> BucketConfig {
+        match kind {
+            LimitKey::Merchant => self.config.merchant_overrides.get(key).copied().unwrap_or(self.config.per_merchant),
+            LimitKey::ApiKey => self.config.per_api_key,
+            LimitKey::Ip => self.config.per_ip,
+        }
+    }
+
+    /// Takes a token from every bucket the request counts against. Nothing is taken
+    /// when any of them is empty, the returned decision describes the tightest bucket.
+    pub async fn check(&self, keys: &[(LimitKey, String)], now: DateTime<Utc>) -> RateLimitDecision {
+        let mut buckets = self.buckets.lock().await;
+        if buckets.len() > MAX_TRACKED_BUCKETS {
+            buckets.retain(|(kind, key), bucket| {
+                let config = self.config_for(*kind, key);
+                refilled(bucket, config, now) < config.capacity as f64
+            });
+        }
+
+        let mut levels = Vec::with_capacity(keys.len());
+        for (kind, key) in keys {
+            let config = self.config_for(*kind, key);
+            let bucket = buckets.entry((*kind, key.clone())).or_insert(Bucket {
+                tokens: config.capacity as f64,
+                updated_at: now,
+            });
+            bucket.tokens = refilled(bucket, config, now);
+            bucket.updated_at = now;
+            levels.push((bucket.tokens, config));
+        }
+
+        let allowed = levels.iter().all(|(tokens, _)| *tokens >= 1.0);
+        if allowed {
+            for key in keys {
+                if let Some(bucket) = buckets.get_mut(key) {
+                    bucket.tokens -= 1.0;
+                }
+            }
+        }
+
+        levels
+            .into_iter()
+            .map(|(tokens, config)| {
+                let tokens = if allowed { tokens - 1.0 } else { tokens };
+                decision(allowed, tokens, config)
+            })
+            .min_by_key(|decision| (decision.remaining, std::cmp::Reverse(decision.retry_after)))
+            .unwrap_or(RateLimitDecision {
+                allowed,
+                limit: 0,
+                remaining: 0,
+                reset_after: 0,
+                retry_after: 0,
+            })
+    }
+}
+
+fn refilled(bucket: &Bucket, config: BucketConfig, now: DateTime<Utc>) -> f64 {
+    let elapsed = (now - bucket.updated_at).num_milliseconds().max(0) as f64 / 1000.0;
+    (bucket.tokens + elapsed * config.refill_per_second).min(config.capacity as f64)
+}
+
+fn decision(allowed: bool, tokens: f64, config: BucketConfig) -> RateLimitDecision {
+    let seconds_for = |missing: f64| {
+        if config.refill_per_second <= 0.0 {
+            u64::MAX
+        } else {
+            (missing.max(0.0) / config.refill_per_second).ceil() as u64
+        }
+    };
+
+    RateLimitDecision {
+        allowed,
+        limit: config.capacity,
+        remaining: tokens.max(0.0).floor() as u32,
+        reset_after: seconds_for(config.capacity as f64 - tokens),
+        retry_after: if allowed { 0 } else { seconds_for(1.0 - tokens).max(1) },
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VelocityConfig {
+    // in minor units, per customer, currency and UTC day
+    pub max_daily_amount_per_customer: u64,
+    pub merchant_overrides: HashMap<String, u64>,
+}
+
+impl Default for VelocityConfig {
+    fn default() -> Self {
+        Self {
+            max_daily_amount_per_customer: 10_000_000,
+            merchant_overrides: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum VelocityError {
+    #[error("amount must be between 1 and {}", i64::MAX)]
+    InvalidAmount,
+    // what the customer has left today
+    #[error("at most {0} more can be charged today")]
+    Exceeded(u64),
+}
+
+// merchant, customer, currency, day
+type DailyKey = (String, String, String, NaiveDate);
+
+/* business limits on how much a customer can be charged, enforced when the payment is created */
+#[derive(Clone, Default)]
+pub struct VelocityLimiter {
+    config: Arc<VelocityConfig>,
+    totals: Arc<Mutex<HashMap<DailyKey, u64>>>,
+}
+
+impl VelocityLimiter {
+    pub fn new(config: VelocityConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            totals: Arc::default(),
+        }
+    }
+
+    fn limit_for(&self, merchant_id: &str) -> u64 {
+        self.config
+            .merchant_overrides
+            .get(merchant_id)
+            .copied()
+            .unwrap_or(self.config.max_daily_amount_per_customer)
+    }
+
+    /// Counts the amount against the customer's daily total, or says what they
+    /// have left today when it would go over the limit.
+    pub async fn reserve(&self, merchant_id: &str, customer_id: &str, currency: &str, amount: u64, now: DateTime<Utc>) -> Result<(), VelocityError> {
+        if amount == 0 || amount > i64::MAX as u64 {
+            return Err(VelocityError::InvalidAmount);
+        }
+        let limit = self.limit_for(merchant_id);
+        let mut totals = self.totals.lock().await;
+        let today = now.date_naive();
+        // yesterday's totals can't be hit again
+        totals.retain(|(_, _, _, day), _| *day >= today);
+
+        let total = totals
+            .entry((merchant_id.to_string(), customer_id.to_string(), currency_key(currency), today))
+            .or_insert(0);
+
+        match total.checked_add(amount) {
+            Some(reserved) if reserved <= limit => {
+                *total = reserved;
+                Ok(())
+            }
+            _ => Err(VelocityError::Exceeded(limit.saturating_sub(*total))),
+        }
+    }
+
+    /// Gives a reservation back when the payment never made it out.
+    pub async fn release(&self, merchant_id: &str, customer_id: &str, currency: &str, amount: u64, now: DateTime<Utc>) {
+        let key = (merchant_id.to_string(), customer_id.to_string(), currency_key(currency), now.date_naive());
+        if let Some(total) = self.totals.lock().await.get_mut(&key) {
+            *total = total.saturating_sub(amount);
+        }
+    }
+}
@@ -0,0 +1,102 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::{Duration, Utc};
+
+    use crate::core::infrastructure::limits::{BucketConfig, LimitKey, RateLimitConfig, RateLimiter, VelocityConfig, VelocityError, VelocityLimiter};
+
+    fn limiter() -> RateLimiter {
+        RateLimiter::new(RateLimitConfig {
+            per_merchant: BucketConfig::new(3, 1.0),
+            per_api_key: BucketConfig::new(10, 5.0),
+            per_ip: BucketConfig::new(10, 5.0),
+            merchant_overrides: HashMap::from([("merch_big".to_string(), BucketConfig::new(10, 10.0))]),
+            trusted_proxies: Vec::new(),
+        })
+    }
+
+    fn merchant(id: &str) -> Vec<(LimitKey, String)> {
+        vec![(LimitKey::Merchant, id.to_string()), (LimitKey::Ip, "10.0.0.1".to_string())]
+    }
+
+    #[tokio::test]
+    async fn test_bucket_empties_and_refills() {
+        let limiter = limiter();
+        let now = Utc::now();
+
+        for remaining in [2, 1, 0] {
+            let decision = limiter.check(&merchant("merch_123"), now).await;
+            assert!(decision.allowed);
+            assert_eq!(decision.limit, 3);
+            assert_eq!(decision.remaining, remaining);
+        }
+
+        let denied = limiter.check(&merchant("merch_123"), now).await;
+        assert!(!denied.allowed);
+        assert_eq!(denied.retry_after, 1);
+
+        let later = limiter.check(&merchant("merch_123"), now + Duration::seconds(1)).await;
+        assert!(later.allowed);
+    }
+
+    #[tokio::test]
+    async fn test_denied_request_takes_nothing_from_other_buckets() {
+        let limiter = limiter();
+        let now = Utc::now();
+
+        for _ in 0..4 {
+            limiter.check(&merchant("merch_123"), now).await;
+        }
+
+        // the shared ip bucket only paid for the three requests that went through
+        let other = limiter.check(&[(LimitKey::Ip, "10.0.0.1".to_string())], now).await;
+        assert_eq!(other.remaining, 6);
+    }
+
+    #[tokio::test]
+    async fn test_merchant_override_applies() {
+        let limiter = limiter();
+        let decision = limiter.check(&[(LimitKey::Merchant, "merch_big".to_string())], Utc::now()).await;
+
+        assert_eq!(decision.limit, 10);
+        assert_eq!(decision.remaining, 9);
+    }
+
+    #[tokio::test]
+    async fn test_daily_amount_per_customer() {
+        let velocity = VelocityLimiter::new(VelocityConfig {
+            max_daily_amount_per_customer: 1000,
+            merchant_overrides: HashMap::new(),
+        });
+        let now = Utc::now();
+
+        assert!(velocity.reserve("merch_123", "cust_1", "USD", 700, now).await.is_ok());
+        assert_eq!(velocity.reserve("merch_123", "cust_1", "usd", 400, now).await, Err(VelocityError::Exceeded(300)));
+        // other customers and currencies have their own totals
+        assert!(velocity.reserve("merch_123", "cust_2", "USD", 1000, now).await.is_ok());
+        assert!(velocity.reserve("merch_123", "cust_1", "EUR", 1000, now).await.is_ok());
+
+        velocity.release("merch_123", "cust_1", "USD", 700, now).await;
+        assert!(velocity.reserve("merch_123", "cust_1", "USD", 1000, now).await.is_ok());
+        assert!(velocity.reserve("merch_123", "cust_1", "USD", 1000, now + Duration::days(1)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_amounts_that_cant_be_counted_are_rejected() {
+        let velocity = VelocityLimiter::new(VelocityConfig {
+            max_daily_amount_per_customer: u64::MAX,
+            merchant_overrides: HashMap::new(),
+        });
+        let now = Utc::now();
+
+        assert_eq!(velocity.reserve("merch_123", "cust_1", "USD", 0, now).await, Err(VelocityError::InvalidAmount));
+        assert_eq!(velocity.reserve("merch_123", "cust_1", "USD", u64::MAX, now).await, Err(VelocityError::InvalidAmount));
+
+        // the total can't wrap around to let more through
+        let most = i64::MAX as u64;
+        assert!(velocity.reserve("merch_123", "cust_1", "USD", most, now).await.is_ok());
+        assert!(velocity.reserve("merch_123", "cust_1", "USD", most, now).await.is_ok());
+        assert!(matches!(velocity.reserve("merch_123", "cust_1", "USD", most, now).await, Err(VelocityError::Exceeded(_))));
+    }
+}
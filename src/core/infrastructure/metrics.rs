@@ -0,0 +1,183 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use axum::{routing::get, Router};
+use prometheus::{Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder};
+use rdkafka::{consumer::Consumer, Message};
+
+use crate::core::{
+    infrastructure::health::{health_routes, Health},
+    models::TransactionStatus,
+};
+
+// how often a partition's high watermark is looked up, it is a round trip to the broker
+const LAG_SAMPLE_INTERVAL: Duration = Duration::from_secs(15);
+const WATERMARK_TIMEOUT: Duration = Duration::from_secs(1);
+
+/* everything a process reports, one registry per process served on /metrics */
+pub struct Metrics {
+    registry: Registry,
+    pub http_requests: HistogramVec,
+    pub transactions: IntCounterVec,
+    pub provider_calls: HistogramVec,
+    pub consumer_lag: IntGaugeVec,
+    pub rejected_messages: IntCounterVec,
+    pub sweeper_actions: IntCounterVec,
+    // last time each (consumer, topic, partition) had its lag sampled
+    lag_sampled: Mutex<HashMap<(String, String, i32), Instant>>,
+}
+
+/// The process wide metrics, created on first use.
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::new)
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("payme".to_string()), None).expect("valid metrics prefix");
+
+        let http_requests = HistogramVec::new(
+            HistogramOpts::new("http_request_duration_seconds", "Time taken to answer api requests"),
+            &["method", "route", "status"],
+        )
+        .expect("valid http metric");
+        let transactions = IntCounterVec::new(
+            Opts::new("transactions_total", "Transactions seen moving into each status"),
+            &["status", "currency", "merchant"],
+        )
+        .expect("valid transaction metric");
+        let provider_calls = HistogramVec::new(
+            HistogramOpts::new("provider_request_duration_seconds", "Time taken by payment provider calls"),
+            &["provider", "operation", "outcome"],
+        )
+        .expect("valid provider metric");
+        let consumer_lag = IntGaugeVec::new(
+            Opts::new("consumer_lag", "Messages left to consume on each partition"),
+            &["consumer", "topic", "partition"],
+        )
+        .expect("valid lag metric");
+        let rejected_messages = IntCounterVec::new(
+            Opts::new("consumer_rejected_messages_total", "Messages a consumer skipped because it could not handle them"),
+            &["consumer", "topic"],
+        )
+        .expect("valid rejected message metric");
+        let sweeper_actions = IntCounterVec::new(
+            Opts::new("sweeper_actions_total", "Payments pending past their SLA, by what the sweeper did about them"),
+            &["action"],
+        )
+        .expect("valid sweeper metric");
+
+        registry.register(Box::new(http_requests.clone())).expect("metric registered once");
+        registry.register(Box::new(transactions.clone())).expect("metric registered once");
+        registry.register(Box::new(provider_calls.clone())).expect("metric registered once");
+        registry.register(Box::new(consumer_lag.clone())).expect("metric registered once");
+        registry.register(Box::new(rejected_messages.clone())).expect("metric registered once");
+        registry.register(Box::new(sweeper_actions.clone())).expect("metric registered once");
+
+        Self {
+            registry,
+            http_requests,
+            transactions,
+            provider_calls,
+            consumer_lag,
+            rejected_messages,
+            sweeper_actions,
+            lag_sampled: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The prometheus text exposition of every metric.
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("metrics encode to text");
+        String::from_utf8(buffer).expect("metrics are utf-8")
+    }
+
+    pub fn observe_request(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        self.http_requests
+            .with_label_values(&[method, route, &status.to_string()])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn count_transaction(&self, status: &TransactionStatus, currency: &str, merchant_id: &str) {
+        // the variant only, failure reasons and action urls would blow up the label set
+        self.transactions
+            .with_label_values(&[status.kind(), currency, merchant_id])
+            .inc();
+    }
+
+    /// Error rate is the share of `outcome="error"` in the histogram's count.
+    pub fn observe_provider_call(&self, provider: &str, operation: &str, elapsed: Duration, succeeded: bool) {
+        let outcome = if succeeded { "ok" } else { "error" };
+        self.provider_calls
+            .with_label_values(&[provider, operation, outcome])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// `corrected`, `expired`, `still_pending` or `error`, see `TransactionSweeper`.
+    pub fn count_sweep(&self, action: &str) {
+        self.sweeper_actions.with_label_values(&[action]).inc();
+    }
+
+    /// Counts a message the consumer had to skip and logs why, `dead_letter` calls it
+    /// before parking the message on the dead letter topic.
+    pub fn reject_message(&self, consumer: &str, topic: &str, kind: &str, error: impl fmt::Display) {
+        self.rejected_messages.with_label_values(&[consumer, topic]).inc();
+        eprintln!("Failed to deserialize {} event: {}", kind, error);
+    }
+
+    /// Updates the lag of the message's partition, at most every `LAG_SAMPLE_INTERVAL`. The
+    /// lookup runs in the background, the consumer goes on with the message meanwhile.
+    pub fn record_consumed<C: Consumer + Send + Sync + 'static, M: Message>(&'static self, consumer_name: &str, consumer: &Arc<C>, msg: &M) {
+        let (topic, partition, offset) = (msg.topic().to_string(), msg.partition(), msg.offset());
+        {
+            let mut sampled = self.lag_sampled.lock().unwrap();
+            let key = (consumer_name.to_string(), topic.clone(), partition);
+            if sampled.get(&key).is_some_and(|at| at.elapsed() < LAG_SAMPLE_INTERVAL) {
+                return;
+            }
+            sampled.insert(key, Instant::now());
+        }
+
+        let consumer = consumer.clone();
+        let consumer_name = consumer_name.to_string();
+        // librdkafka blocks until the broker answers
+        tokio::task::spawn_blocking(move || match consumer.fetch_watermarks(&topic, partition, WATERMARK_TIMEOUT) {
+            Ok((_, high)) => self.set_lag(&consumer_name, &topic, partition, high - offset - 1),
+            Err(e) => eprintln!("Failed to fetch watermarks for {}/{}: {}", topic, partition, e),
+        });
+    }
+
+    pub fn set_lag(&self, consumer_name: &str, topic: &str, partition: i32, lag: i64) {
+        self.consumer_lag
+            .with_label_values(&[consumer_name, topic, &partition.to_string()])
+            .set(lag.max(0));
+    }
+}
+
+/// Serves `/metrics` and the health probes for the processes that have no api of their own.
+/// It keeps answering while the process drains, `/readyz` says it is shutting down.
+pub async fn serve_metrics(bind_address: String, health: Health) {
+    let app = Router::new()
+        .route("/metrics", get(|| async { metrics().render() }))
+        .merge(health_routes(health));
+
+    let listener = match tokio::net::TcpListener::bind(&bind_address).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Failed to bind metrics on {}: {}", bind_address, e);
+            return;
+        }
+    };
+    println!("metrics listening on {}", bind_address);
+    if let Err(e) = axum::serve(listener, app).await {
+        eprintln!("Metrics server stopped: {}", e);
+    }
+}
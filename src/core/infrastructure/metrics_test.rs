@@ -0,0 +1,51 @@
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::core::{infrastructure::metrics::metrics, models::TransactionStatus};
+
+    #[test]
+    fn test_transactions_are_counted_by_status_variant() {
+        let before = metrics().transactions.with_label_values(&["failed", "USD", "merch_metrics_1"]).get();
+
+        metrics().count_transaction(&TransactionStatus::Failed { reason: "card declined".to_string() }, "USD", "merch_metrics_1");
+        metrics().count_transaction(&TransactionStatus::Failed { reason: "insufficient funds".to_string() }, "USD", "merch_metrics_1");
+
+        let after = metrics().transactions.with_label_values(&["failed", "USD", "merch_metrics_1"]).get();
+        assert_eq!(after - before, 2);
+    }
+
+    #[test]
+    fn test_provider_errors_are_split_from_successes() {
+        let errors = || metrics().provider_calls.with_label_values(&["stripe", "test_operation", "error"]).get_sample_count();
+        let successes = || metrics().provider_calls.with_label_values(&["stripe", "test_operation", "ok"]).get_sample_count();
+        let (errors_before, successes_before) = (errors(), successes());
+
+        metrics().observe_provider_call("stripe", "test_operation", Duration::from_millis(120), true);
+        metrics().observe_provider_call("stripe", "test_operation", Duration::from_millis(900), false);
+        metrics().observe_provider_call("stripe", "test_operation", Duration::from_millis(80), true);
+
+        assert_eq!(errors() - errors_before, 1);
+        assert_eq!(successes() - successes_before, 2);
+    }
+
+    #[test]
+    fn test_lag_never_goes_negative() {
+        metrics().set_lag("metrics_test", "transactions", 3, 42);
+        assert_eq!(metrics().consumer_lag.with_label_values(&["metrics_test", "transactions", "3"]).get(), 42);
+
+        // a watermark fetched before the message it is compared with arrived
+        metrics().set_lag("metrics_test", "transactions", 3, -1);
+        assert_eq!(metrics().consumer_lag.with_label_values(&["metrics_test", "transactions", "3"]).get(), 0);
+    }
+
+    #[test]
+    fn test_render_uses_prometheus_text_format() {
+        metrics().reject_message("metrics_test", "fees", "fee", "expected value at line 1");
+
+        let rendered = metrics().render();
+
+        assert!(rendered.contains("# TYPE payme_consumer_rejected_messages_total counter"));
+        assert!(rendered.contains("payme_consumer_rejected_messages_total{consumer=\"metrics_test\",topic=\"fees\"}"));
+    }
+}
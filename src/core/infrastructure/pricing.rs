@@ -0,0 +1,133 @@
+use std::{collections::HashMap, str::FromStr, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use tokio::sync::RwLock;
+
+use crate::core::models::{Currency, FeeBreakdown, FeeRate, PricingPlan};
+
+const BPS_DENOMINATOR: u128 = 10_000;
+
+/// Currencies are spelled a few ways ("EUR", "euro"), plans and volumes use one key per currency.
+pub fn currency_key(currency: &str) -> String {
+    Currency::from_str(currency)
+        .map(|c| format!("{:?}", c))
+        .unwrap_or_else(|_| currency.to_uppercase())
+}
+
+/// Rate a payment is charged at: the currency override if there is one, otherwise
+/// the plan's default. A matching volume tier replaces the percentage, never the fixed fee.
+pub fn effective_rate(plan: &PricingPlan, currency: &str, month_volume: u64) -> (FeeRate, Option<usize>) {
+    let mut rate = plan
+        .currency_overrides
+        .get(&currency_key(currency))
+        .copied()
+        .unwrap_or(plan.default_rate);
+
+    let tier = plan
+        .volume_tiers
+        .iter()
+        .position(|t| t.up_to.map(|limit| month_volume < limit).unwrap_or(true));
+
+    if let Some(index) = tier {
+        rate.percentage_bps = plan.volume_tiers[index].percentage_bps;
+    }
+
+    (rate, tier)
+}
+
+/// Fee for a single payment of `amount`, `month_volume` being what the merchant
+/// already processed in that currency this month. Never more than the payment itself.
+pub fn calculate_fee(plan: &PricingPlan, amount: u64, currency: &str, month_volume: u64) -> FeeBreakdown {
+    let (rate, volume_tier) = effective_rate(plan, currency, month_volume);
+
+    // rounded half up to the minor unit
+    let percentage_fee = ((amount as u128 * rate.percentage_bps as u128 + BPS_DENOMINATOR / 2) / BPS_DENOMINATOR) as u64;
+    let total = (percentage_fee + rate.fixed_fee).min(amount);
+
+    FeeBreakdown {
+        percentage_bps: rate.percentage_bps,
+        percentage_fee,
+        fixed_fee: rate.fixed_fee,
+        total,
+        volume_tier,
+    }
+}
+
+pub fn validate_plan(plan: &PricingPlan) -> Result<(), String> {
+    let mut rates = std::iter::once(&plan.default_rate).chain(plan.currency_overrides.values());
+    if rates.any(|r| r.percentage_bps as u128 > BPS_DENOMINATOR) {
+        return Err("percentage_bps can't exceed 10000".to_string());
+    }
+
+    // tiers have to be ascending and only the last one may be open ended
+    let mut previous = 0;
+    for (index, tier) in plan.volume_tiers.iter().enumerate() {
+        if tier.percentage_bps as u128 > BPS_DENOMINATOR {
+            return Err("percentage_bps can't exceed 10000".to_string());
+        }
+        match tier.up_to {
+            Some(limit) if limit <= previous => return Err("volume tiers must be in ascending order".to_string()),
+            Some(limit) => previous = limit,
+            None if index != plan.volume_tiers.len() - 1 => {
+                return Err("only the last volume tier can be open ended".to_string())
+            }
+            None => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn volume_period(at: DateTime<Utc>) -> String {
+    at.format("%Y-%m").to_string()
+}
+
+// merchant, currency, month
+type VolumeKey = (String, String, String);
+
+// In production this would live in the database, for now it's kept in memory
+#[derive(Clone, Default)]
+pub struct PricingStore {
+    plans: Arc<RwLock<HashMap<String, PricingPlan>>>,
+    // volume completed so far
+    volumes: Arc<RwLock<HashMap<VolumeKey, u64>>>,
+}
+
+impl PricingStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn save_plan(&self, plan: PricingPlan) {
+        self.plans.write().await.insert(plan.merchant_id.clone(), plan);
+    }
+
+    /// The merchant's own plan, or standard pricing when they don't have one.
+    pub async fn plan_for(&self, merchant_id: &str) -> PricingPlan {
+        self.plans
+            .read()
+            .await
+            .get(merchant_id)
+            .cloned()
+            .unwrap_or_else(|| PricingPlan::standard(merchant_id.to_string()))
+    }
+
+    pub async fn month_volume(&self, merchant_id: &str, currency: &str, at: DateTime<Utc>) -> u64 {
+        let key = (merchant_id.to_string(), currency_key(currency), volume_period(at));
+        self.volumes.read().await.get(&key).copied().unwrap_or(0)
+    }
+
+    /// Prices a completed payment and counts it towards the merchant's monthly volume.
+    pub async fn assess(&self, merchant_id: &str, amount: u64, currency: &str, completed_at: DateTime<Utc>) -> FeeBreakdown {
+        let plan = self.plan_for(merchant_id).await;
+
+        let key = (merchant_id.to_string(), currency_key(currency), volume_period(completed_at));
+        let mut volumes = self.volumes.write().await;
+        let volume = volumes.entry(key).or_insert(0);
+
+        let fee = calculate_fee(&plan, amount, currency, *volume);
+        *volume += amount;
+
+        fee
+    }
+}
@@ -0,0 +1,99 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::Utc;
+
+    use crate::core::{
+        infrastructure::pricing::{calculate_fee, validate_plan, PricingStore},
+        models::{FeeRate, PricingPlan, VolumeTier},
+    };
+
+    fn plan() -> PricingPlan {
+        PricingPlan {
+            merchant_id: "merch_123".to_string(),
+            default_rate: FeeRate { percentage_bps: 290, fixed_fee: 30 },
+            currency_overrides: HashMap::from([("INR".to_string(), FeeRate { percentage_bps: 200, fixed_fee: 0 })]),
+            volume_tiers: Vec::new(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn tiered() -> PricingPlan {
+        PricingPlan {
+            volume_tiers: vec![
+                VolumeTier { up_to: Some(100_000), percentage_bps: 290 },
+                VolumeTier { up_to: None, percentage_bps: 150 },
+            ],
+            ..plan()
+        }
+    }
+
+    #[test]
+    fn test_percentage_and_fixed_fee() {
+        let fee = calculate_fee(&plan(), 10_000, "USD", 0);
+
+        assert_eq!(fee.percentage_fee, 290);
+        assert_eq!(fee.fixed_fee, 30);
+        assert_eq!(fee.total, 320);
+        assert_eq!(fee.volume_tier, None);
+    }
+
+    #[test]
+    fn test_currency_override_replaces_default_rate() {
+        let fee = calculate_fee(&plan(), 10_000, "inr", 0);
+
+        assert_eq!(fee.percentage_bps, 200);
+        assert_eq!(fee.total, 200);
+    }
+
+    #[test]
+    fn test_fee_is_rounded_and_capped_at_amount() {
+        // 2.9% of 55 is 1.595
+        assert_eq!(calculate_fee(&plan(), 55, "USD", 0).percentage_fee, 2);
+        assert_eq!(calculate_fee(&plan(), 20, "USD", 0).total, 20);
+    }
+
+    #[test]
+    fn test_volume_tier_follows_month_volume() {
+        assert_eq!(calculate_fee(&tiered(), 10_000, "USD", 50_000).volume_tier, Some(0));
+
+        let fee = calculate_fee(&tiered(), 10_000, "USD", 100_000);
+        assert_eq!(fee.volume_tier, Some(1));
+        assert_eq!(fee.total, 180);
+    }
+
+    #[test]
+    fn test_invalid_tiers_are_rejected() {
+        let mut plan = tiered();
+        plan.volume_tiers.reverse();
+
+        assert!(validate_plan(&tiered()).is_ok());
+        assert!(validate_plan(&plan).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_assess_counts_towards_monthly_volume() {
+        let store = PricingStore::new();
+        store.save_plan(tiered()).await;
+        let now = Utc::now();
+
+        let first = store.assess("merch_123", 100_000, "USD", now).await;
+        let second = store.assess("merch_123", 10_000, "USD", now).await;
+
+        assert_eq!(first.volume_tier, Some(0));
+        assert_eq!(second.volume_tier, Some(1));
+        assert_eq!(store.month_volume("merch_123", "USD", now).await, 110_000);
+        // volume is tracked per currency
+        assert_eq!(store.month_volume("merch_123", "INR", now).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_merchant_without_plan_gets_standard_pricing() {
+        let store = PricingStore::new();
+
+        let fee = store.assess("merch_new", 10_000, "USD", Utc::now()).await;
+
+        assert_eq!(fee.total, 320);
+    }
+}
@@ -0,0 +1,440 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, HashMap},
+    ops::Bound,
+    str::FromStr,
+    sync::Arc,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::core::{
+    config::Topics,
+    events::{FeeAssessedEvent, PaymentStatusUpdatedEvent, RefundCreatedEvent, SplitTransfersCreatedEvent, TransactionCreatedEvent},
+    infrastructure::splits::sellers_paid,
+    models::{Currency, Refund, Transaction, TransactionStatus},
+};
+
+/// An event the projection is built from, as read from one of its topics.
+#[derive(Clone)]
+pub enum ProjectionEvent {
+    Created(TransactionCreatedEvent),
+    Status(PaymentStatusUpdatedEvent),
+    Fee(FeeAssessedEvent),
+    Transfers(SplitTransfersCreatedEvent),
+    Refund(RefundCreatedEvent),
+}
+
+impl ProjectionEvent {
+    /// Every topic a transaction's state comes from.
+    pub fn topics(topics: &Topics) -> [&str; 5] {
+        [&topics.transactions, &topics.payment_status, &topics.fees, &topics.transfers, &topics.refunds]
+    }
+
+    /// The error names the kind of event that didn't parse, anything not from one of the other topics is taken for a status update.
+    pub fn parse(topics: &Topics, topic: &str, payload: &[u8]) -> Result<Self, (&'static str, serde_json::Error)> {
+        match topic {
+            t if t == topics.transactions => serde_json::from_slice(payload).map(Self::Created).map_err(|e| ("transaction", e)),
+            t if t == topics.fees => serde_json::from_slice(payload).map(Self::Fee).map_err(|e| ("fee", e)),
+            t if t == topics.transfers => serde_json::from_slice(payload).map(Self::Transfers).map_err(|e| ("transfer", e)),
+            t if t == topics.refunds => serde_json::from_slice(payload).map(Self::Refund).map_err(|e| ("refund", e)),
+            _ => serde_json::from_slice(payload).map(Self::Status).map_err(|e| ("status", e)),
+        }
+    }
+
+    pub fn transaction_id(&self) -> Uuid {
+        match self {
+            Self::Created(event) => event.transaction_id,
+            Self::Status(event) => event.transaction_id,
+            Self::Fee(event) => event.transaction_id,
+            Self::Transfers(event) => event.transaction_id,
+            Self::Refund(event) => event.transaction_id,
+        }
+    }
+}
+
+/* query side read model, built from the transaction and status topics */
+#[derive(Clone, Default)]
+pub struct TransactionProjection {
+    // In production this would be the query database
+    state: Arc<RwLock<ProjectionState>>,
+}
+
+#[derive(Default)]
+struct ProjectionState {
+    transactions: HashMap<Uuid, Transaction>,
+    // transactions ordered by creation, overall and per merchant, listings and reports scan these instead of everything
+    by_created: BTreeSet<(DateTime<Utc>, Uuid)>,
+    by_merchant: HashMap<String, BTreeSet<(DateTime<Utc>, Uuid)>>,
+    refunds: HashMap<Uuid, Refund>,
+}
+
+impl ProjectionState {
+    /* events can overtake the creation, they live on different topics, so any of them may insert the transaction */
+    fn get_or_insert(&mut self, id: Uuid, merchant_id: &str, timestamp: DateTime<Utc>) -> &mut Transaction {
+        if !self.transactions.contains_key(&id) {
+            self.by_created.insert((timestamp, id));
+            self.by_merchant.entry(merchant_id.to_string()).or_default().insert((timestamp, id));
+            self.transactions.insert(id, Transaction {
+                id,
+                merchant_id: merchant_id.to_string(),
+                created_at: timestamp,
+                update_at: timestamp,
+                ..Default::default()
+            });
+        }
+
+        self.transactions.get_mut(&id).expect("inserted above")
+    }
+
+    /* the creation event has the final say on who a transaction belongs to and when it was made */
+    fn reindex(&mut self, id: Uuid, merchant_id: &str, created_at: DateTime<Utc>) {
+        let Some(transaction) = self.transactions.get_mut(&id) else { return };
+        if transaction.merchant_id == merchant_id && transaction.created_at == created_at {
+            return;
+        }
+
+        self.by_created.remove(&(transaction.created_at, id));
+        if let Some(index) = self.by_merchant.get_mut(&transaction.merchant_id) {
+            index.remove(&(transaction.created_at, id));
+        }
+        transaction.merchant_id = merchant_id.to_string();
+        transaction.created_at = created_at;
+        self.by_created.insert((created_at, id));
+        self.by_merchant.entry(merchant_id.to_string()).or_default().insert((created_at, id));
+    }
+
+    /// Transactions created within `[from, to)`, of one merchant or all of them, oldest first.
+    fn created_between(
+        &self,
+        merchant_id: Option<&str>,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Box<dyn DoubleEndedIterator<Item = &Transaction> + '_> {
+        let index = match merchant_id {
+            Some(merchant_id) => self.by_merchant.get(merchant_id),
+            None => Some(&self.by_created),
+        };
+
+        // the nil uuid sorts first, so these bounds are `[from, to)` whatever the ids
+        let lower = Bound::Included((from.unwrap_or(DateTime::<Utc>::MIN_UTC), Uuid::nil()));
+        let upper = match to {
+            Some(to) => Bound::Excluded((to, Uuid::nil())),
+            None => Bound::Unbounded,
+        };
+
+        Box::new(
+            index
+                .into_iter()
+                .flat_map(move |index| index.range((lower, upper)))
+                .filter_map(|(_, id)| self.transactions.get(id)),
+        )
+    }
+}
+
+/// What a transaction listing can be narrowed down to, unset fields match everything.
+#[derive(Debug, Default, Clone)]
+pub struct TransactionFilter {
+    pub merchant_id: Option<String>,
+    pub customer_id: Option<String>,
+    // the variant only, see `TransactionStatus::kind`
+    pub status: Option<String>,
+    pub currency: Option<Currency>,
+    pub min_amount: Option<i64>,
+    pub max_amount: Option<i64>,
+    // `[created_from, created_to)`
+    pub created_from: Option<DateTime<Utc>>,
+    pub created_to: Option<DateTime<Utc>>,
+    pub metadata_key: Option<String>,
+    pub metadata_value: Option<String>,
+}
+
+impl TransactionFilter {
+    pub fn matches(&self, transaction: &Transaction) -> bool {
+        self.merchant_id.as_ref().is_none_or(|m| &transaction.merchant_id == m)
+            && self.customer_id.as_ref().is_none_or(|c| &transaction.customer_id == c)
+            && self.status.as_deref().is_none_or(|s| transaction.status.kind() == s)
+            && self.currency.is_none_or(|c| transaction.currency == c)
+            && self.min_amount.is_none_or(|min| transaction.amount >= min)
+            && self.max_amount.is_none_or(|max| transaction.amount <= max)
+            && self.created_from.is_none_or(|from| transaction.created_at >= from)
+            && self.created_to.is_none_or(|to| transaction.created_at < to)
+            && self.metadata_key.as_ref().is_none_or(|key| match (transaction.metadata.get(key), &self.metadata_value) {
+                (Some(found), Some(expected)) => found == expected,
+                (Some(_), None) => true,
+                (None, _) => false,
+            })
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransactionSort {
+    CreatedAtAsc,
+    #[default]
+    CreatedAtDesc,
+    AmountAsc,
+    AmountDesc,
+}
+
+impl FromStr for TransactionSort {
+    type Err = String;
+
+    /* `created_at` or `amount`, a leading `-` for descending */
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "created_at" => Ok(TransactionSort::CreatedAtAsc),
+            "-created_at" => Ok(TransactionSort::CreatedAtDesc),
+            "amount" => Ok(TransactionSort::AmountAsc),
+            "-amount" => Ok(TransactionSort::AmountDesc),
+            other => Err(format!("Unsupported sort {}", other)),
+        }
+    }
+}
+
+impl TransactionSort {
+    // ties are broken by id so every transaction has exactly one place in the order
+    fn key(&self, transaction: &Transaction) -> (i64, Uuid) {
+        match self {
+            TransactionSort::CreatedAtAsc | TransactionSort::CreatedAtDesc => {
+                (transaction.created_at.timestamp_nanos_opt().unwrap_or(i64::MAX), transaction.id)
+            }
+            TransactionSort::AmountAsc | TransactionSort::AmountDesc => (transaction.amount, transaction.id),
+        }
+    }
+
+    fn compare(&self, a: &(i64, Uuid), b: &(i64, Uuid)) -> Ordering {
+        match self {
+            TransactionSort::CreatedAtAsc | TransactionSort::AmountAsc => a.cmp(b),
+            TransactionSort::CreatedAtDesc | TransactionSort::AmountDesc => b.cmp(a),
+        }
+    }
+}
+
+/// Where the previous page stopped. It holds the last sort key rather than an offset, so
+/// transactions arriving or changing between pages neither repeat nor get skipped.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PageCursor {
+    pub sort: TransactionSort,
+    pub key: i64,
+    pub id: Uuid,
+}
+
+impl PageCursor {
+    /* opaque to clients, they only hand it back */
+    pub fn encode(&self) -> String {
+        hex::encode(serde_json::to_vec(self).expect("cursor serializes"))
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self, String> {
+        let bytes = hex::decode(cursor).map_err(|_| "Invalid cursor".to_string())?;
+        serde_json::from_slice(&bytes).map_err(|_| "Invalid cursor".to_string())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TransactionPage {
+    pub transactions: Vec<Transaction>,
+    // only set when there is more after this page
+    pub next_cursor: Option<PageCursor>,
+}
+
+impl TransactionProjection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn get(&self, transaction_id: Uuid) -> Option<Transaction> {
+        self.state.read().await.transactions.get(&transaction_id).cloned()
+    }
+
+    /// One page of the transactions matching `filter`, in `sort` order, starting after `after`.
+    /// Only the creation index within the created range is scanned, of the merchant if one is set.
+    /// Sorted by creation the scan stops once the page is full, sorted by amount it has to see the whole range.
+    pub async fn search(
+        &self,
+        filter: &TransactionFilter,
+        sort: TransactionSort,
+        after: Option<&PageCursor>,
+        limit: usize,
+    ) -> TransactionPage {
+        let state = self.state.read().await;
+
+        let candidates = state
+            .created_between(filter.merchant_id.as_deref(), filter.created_from, filter.created_to)
+            .map(|t| (sort.key(t), t));
+        let past_cursor =
+            |(key, _): &((i64, Uuid), &Transaction)| after.is_none_or(|cursor| sort.compare(key, &(cursor.key, cursor.id)) == Ordering::Greater);
+        let matching = |(_, t): &((i64, Uuid), &Transaction)| filter.matches(t);
+
+        let mut matches: Vec<((i64, Uuid), &Transaction)> = match sort {
+            TransactionSort::CreatedAtAsc => candidates.filter(past_cursor).filter(matching).take(limit + 1).collect(),
+            TransactionSort::CreatedAtDesc => candidates.rev().filter(past_cursor).filter(matching).take(limit + 1).collect(),
+            TransactionSort::AmountAsc | TransactionSort::AmountDesc => {
+                let mut all: Vec<_> = candidates.filter(past_cursor).filter(matching).collect();
+                all.sort_by(|a, b| sort.compare(&a.0, &b.0));
+                all
+            }
+        };
+
+        let has_more = matches.len() > limit;
+        matches.truncate(limit);
+
+        let next_cursor = match matches.last() {
+            Some(((key, id), _)) if has_more => Some(PageCursor { sort, key: *key, id: *id }),
+            _ => None,
+        };
+
+        TransactionPage {
+            transactions: matches.into_iter().map(|(_, t)| t.clone()).collect(),
+            next_cursor,
+        }
+    }
+
+    pub async fn apply(&self, event: &ProjectionEvent) {
+        match event {
+            ProjectionEvent::Created(event) => self.apply_created(event).await,
+            ProjectionEvent::Status(event) => self.apply_status(event).await,
+            ProjectionEvent::Fee(event) => self.apply_fee(event).await,
+            ProjectionEvent::Transfers(event) => self.apply_transfers(event).await,
+            ProjectionEvent::Refund(event) => self.apply_refund(event).await,
+        }
+    }
+
+    pub async fn apply_created(&self, event: &TransactionCreatedEvent) {
+        let currency = match Currency::from_str(&event.currency) {
+            Ok(currency) => currency,
+            Err(e) => {
+                eprintln!("Skipping transaction {}: {}", event.transaction_id, e);
+                return;
+            }
+        };
+
+        let mut state = self.state.write().await;
+        state.get_or_insert(event.transaction_id, &event.merchant_id, event.timestamp);
+        state.reindex(event.transaction_id, &event.merchant_id, event.timestamp);
+        let transaction = state.transactions.get_mut(&event.transaction_id).expect("inserted above");
+
+        transaction.amount = event.amount as i64;
+        transaction.currency = currency;
+        transaction.customer_id = event.customer_id.clone();
+        transaction.metadata = event.metadata.clone();
+        // transfers may already have filled the legs in
+        if transaction.splits.is_empty() {
+            transaction.splits = event.splits.clone();
+        }
+    }
+
+    pub async fn apply_status(&self, event: &PaymentStatusUpdatedEvent) {
+        let mut state = self.state.write().await;
+        let transaction = state.get_or_insert(event.transaction_id, &event.merchant_id, event.timestamp);
+
+        if event.timestamp < transaction.update_at && transaction.status != TransactionStatus::Pending {
+            return;
+        }
+
+        transaction.status = event.status.clone();
+        transaction.update_at = event.timestamp;
+
+        if !event.stripe_payment_id.is_empty() {
+            transaction.provider_payment_id = Some(event.stripe_payment_id.clone());
+        }
+        if event.client_secret.is_some() {
+            transaction.client_secret = event.client_secret.clone();
+        }
+    }
+
+    pub async fn apply_fee(&self, event: &FeeAssessedEvent) {
+        let mut state = self.state.write().await;
+        let transaction = state.get_or_insert(event.transaction_id, &event.merchant_id, event.timestamp);
+
+        transaction.fee = Some(event.fee.clone());
+    }
+
+    pub async fn apply_transfers(&self, event: &SplitTransfersCreatedEvent) {
+        let mut state = self.state.write().await;
+        let transaction = state.get_or_insert(event.transaction_id, &event.merchant_id, event.timestamp);
+
+        transaction.splits = event.legs.clone();
+    }
+
+    pub async fn apply_refund(&self, event: &RefundCreatedEvent) {
+        let mut state = self.state.write().await;
+        // a redelivered refund must not count twice
+        if state.refunds.contains_key(&event.refund_id) {
+            return;
+        }
+
+        state.refunds.insert(event.refund_id, Refund {
+            id: event.refund_id,
+            transaction_id: event.transaction_id,
+            merchant_id: event.merchant_id.clone(),
+            amount: event.amount,
+            currency: event.currency.clone(),
+            reason: event.reason.clone(),
+            created_at: event.timestamp,
+        });
+        if let Some(transaction) = state.transactions.get_mut(&event.transaction_id) {
+            transaction.amount_refunded += event.amount as i64;
+        }
+    }
+
+    /// Up to `limit` refunds issued within `[from, to)`, of one merchant or all of them, oldest
+    /// first. Pass the last refund of a page as `after` for the next one.
+    pub async fn refunds_between(
+        &self,
+        merchant_id: Option<&str>,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        after: Option<&Refund>,
+        limit: usize,
+    ) -> Vec<Refund> {
+        let state = self.state.read().await;
+        let mut refunds: Vec<&Refund> = state
+            .refunds
+            .values()
+            .filter(|r| merchant_id.is_none_or(|m| r.merchant_id == m))
+            .filter(|r| r.created_at >= from && r.created_at < to)
+            .filter(|r| after.is_none_or(|a| (r.created_at, r.id) > (a.created_at, a.id)))
+            .collect();
+
+        refunds.sort_by_key(|r| (r.created_at, r.id));
+        refunds.into_iter().take(limit).cloned().collect()
+    }
+
+    /// Merchant's transactions created within `[from, to)`, oldest first.
+    pub async fn for_merchant(&self, merchant_id: &str, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<Transaction> {
+        self.state
+            .read()
+            .await
+            .created_between(Some(merchant_id), Some(from), Some(to))
+            .cloned()
+            .collect()
+    }
+
+    /// Everything that reached the provider, i.e. carries a provider payment id.
+    pub async fn with_provider_payment(&self) -> Vec<Transaction> {
+        self.state
+            .read()
+            .await
+            .transactions
+            .values()
+            .filter(|t| t.provider_payment_id.is_some())
+            .cloned()
+            .collect()
+    }
+
+    /// Split payments some seller still has a share of coming from.
+    pub async fn unpaid_splits(&self) -> Vec<Transaction> {
+        self.state
+            .read()
+            .await
+            .transactions
+            .values()
+            .filter(|t| !sellers_paid(&t.splits))
+            .cloned()
+            .collect()
+    }
+}
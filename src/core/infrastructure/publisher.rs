@@ -0,0 +1,116 @@
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::core::infrastructure::telemetry::trace_context;
+
+/// Where the api sends its events. Kafka in production, `InMemoryPublisher` in tests.
+#[axum::async_trait]
+pub trait EventPublisher: Send + Sync {
+    async fn publish(&self, topic: &str, key: &str, payload: String) -> Result<(), String>;
+
+    /// Waits for anything still buffered to be delivered, called on shutdown.
+    async fn flush(&self, _timeout: Duration) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl dyn EventPublisher {
+    pub async fn publish_event<T: Serialize + Sync>(&self, topic: &str, key: &str, event: &T) -> Result<(), String> {
+        let payload = serde_json::to_string(event).map_err(|e| format!("Failed to serialize event: {}", e))?;
+        self.publish(topic, key, payload).await
+    }
+}
+
+/// An event as it was read back from its topic.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+    pub key: Option<String>,
+    pub timestamp: Option<DateTime<Utc>>,
+    pub payload: String,
+}
+
+/// Where tooling reads the events back from, whole topics from the start. Kafka in production,
+/// `InMemoryPublisher` in tests.
+#[axum::async_trait]
+pub trait EventLog: Send + Sync {
+    async fn read(&self, topics: &[&str]) -> Result<Vec<RecordedEvent>, String>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PublishedEvent {
+    pub topic: String,
+    pub key: String,
+    pub payload: String,
+    // what a kafka publisher would have put in the message headers
+    pub headers: HashMap<String, String>,
+}
+
+/* keeps everything it is given, lets handlers be tested without a broker */
+#[derive(Default)]
+pub struct InMemoryPublisher {
+    events: Mutex<Vec<PublishedEvent>>,
+}
+
+impl InMemoryPublisher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Payloads published to `topic`, oldest first.
+    pub fn published(&self, topic: &str) -> Vec<serde_json::Value> {
+        self.events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|event| event.topic == topic)
+            .filter_map(|event| serde_json::from_str(&event.payload).ok())
+            .collect()
+    }
+
+    pub fn events(&self) -> Vec<PublishedEvent> {
+        self.events.lock().unwrap().clone()
+    }
+}
+
+#[axum::async_trait]
+impl EventPublisher for InMemoryPublisher {
+    async fn publish(&self, topic: &str, key: &str, payload: String) -> Result<(), String> {
+        self.events.lock().unwrap().push(PublishedEvent {
+            topic: topic.to_string(),
+            key: key.to_string(),
+            payload,
+            headers: trace_context(),
+        });
+        Ok(())
+    }
+}
+
+#[axum::async_trait]
+impl EventLog for InMemoryPublisher {
+    async fn read(&self, topics: &[&str]) -> Result<Vec<RecordedEvent>, String> {
+        let mut offsets: HashMap<&str, i64> = HashMap::new();
+        let events = self.events.lock().unwrap();
+
+        // a single partition per topic, offsets count up from 0 like kafka's
+        Ok(events
+            .iter()
+            .filter_map(|event| {
+                let offset = offsets.entry(event.topic.as_str()).or_default();
+                *offset += 1;
+                topics.contains(&event.topic.as_str()).then(|| RecordedEvent {
+                    topic: event.topic.clone(),
+                    partition: 0,
+                    offset: *offset - 1,
+                    key: Some(event.key.clone()),
+                    timestamp: None,
+                    payload: event.payload.clone(),
+                })
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,355 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::Serialize;
+use thiserror::Error;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::core::{
+    infrastructure::pricing::currency_key,
+    models::{Transaction, TransactionStatus},
+};
+
+#[derive(Debug, Error)]
+pub enum ReconciliationError {
+    #[error("Export is empty")]
+    Empty,
+    #[error("Export is missing the {0} column")]
+    MissingColumn(&'static str),
+    #[error("Line {0}: {1}")]
+    InvalidRow(usize, String),
+}
+
+/* one line of a stripe balance transaction export */
+#[derive(Debug, Clone, Serialize)]
+pub struct BalanceTransaction {
+    pub id: String,
+    pub category: String,
+    pub payment_intent_id: Option<String>,
+    // in minor units, refunds are negative
+    pub gross: i64,
+    pub fee: i64,
+    pub currency: String,
+    pub created: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+pub enum DiscrepancyKind {
+    // we have it as paid, stripe doesn't
+    Missing,
+    // stripe has it, we don't
+    Extra,
+    AmountMismatch,
+    StatusMismatch,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Discrepancy {
+    pub kind: DiscrepancyKind,
+    pub provider_payment_id: String,
+    pub transaction_id: Option<Uuid>,
+    pub ours: Option<String>,
+    pub provider: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReconciliationReport {
+    pub id: Uuid,
+    pub generated_at: DateTime<Utc>,
+    pub period_start: DateTime<Utc>,
+    pub period_end: DateTime<Utc>,
+    pub rows_read: usize,
+    // payouts, stripe fees, adjustments... anything not tied to a payment
+    pub rows_skipped: usize,
+    pub matched: usize,
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+// stripe has renamed export columns over the years, the first alias present wins
+const ID_COLUMNS: &[&str] = &["balance_transaction_id", "id"];
+const CATEGORY_COLUMNS: &[&str] = &["reporting_category", "type"];
+const PAYMENT_INTENT_COLUMNS: &[&str] = &["payment_intent_id", "payment_intent"];
+const GROSS_COLUMNS: &[&str] = &["gross", "amount"];
+const FEE_COLUMNS: &[&str] = &["fee"];
+const CURRENCY_COLUMNS: &[&str] = &["currency"];
+const CREATED_COLUMNS: &[&str] = &["created_utc", "created (utc)", "created"];
+
+const CHARGE_CATEGORIES: &[&str] = &["charge", "payment"];
+const REFUND_CATEGORIES: &[&str] = &["refund", "payment_refund"];
+
+/// Splits a CSV line, honouring quoted fields and doubled quotes inside them.
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', _) => quoted = !quoted,
+            (',', false) => fields.push(std::mem::take(&mut field)),
+            (c, _) => field.push(c),
+        }
+    }
+    fields.push(field);
+
+    fields
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// "12.34" in major units to 1234, every currency we support has two decimals.
+pub fn parse_minor_units(value: &str) -> Result<i64, String> {
+    let value = value.trim();
+    let (negative, digits) = match value.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, value),
+    };
+
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    if fraction.len() > 2 || whole.is_empty() && fraction.is_empty() {
+        return Err(format!("invalid amount {}", value));
+    }
+
+    let whole: i64 = if whole.is_empty() { 0 } else { whole.parse().map_err(|_| format!("invalid amount {}", value))? };
+    let fraction: i64 = format!("{:0<2}", fraction).parse().map_err(|_| format!("invalid amount {}", value))?;
+
+    let minor = whole * 100 + fraction;
+    Ok(if negative { -minor } else { minor })
+}
+
+fn parse_created(value: &str) -> Result<DateTime<Utc>, String> {
+    let value = value.trim();
+    if let Ok(timestamp) = value.parse::<i64>() {
+        return DateTime::from_timestamp(timestamp, 0).ok_or_else(|| format!("invalid timestamp {}", value));
+    }
+
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+        .map(|dt| dt.and_utc())
+        .map_err(|_| format!("invalid date {}", value))
+}
+
+pub fn parse_balance_transactions(csv: &str) -> Result<Vec<BalanceTransaction>, ReconciliationError> {
+    let mut lines = csv.lines().enumerate().filter(|(_, l)| !l.trim().is_empty());
+
+    let (_, header) = lines.next().ok_or(ReconciliationError::Empty)?;
+    let header: Vec<String> = split_csv_line(header).iter().map(|h| h.trim().to_lowercase()).collect();
+
+    let column = |aliases: &[&str], name: &'static str| {
+        aliases
+            .iter()
+            .find_map(|alias| header.iter().position(|h| h == alias))
+            .ok_or(ReconciliationError::MissingColumn(name))
+    };
+
+    let id = column(ID_COLUMNS, "balance_transaction_id")?;
+    let category = column(CATEGORY_COLUMNS, "reporting_category")?;
+    let payment_intent = column(PAYMENT_INTENT_COLUMNS, "payment_intent_id")?;
+    let gross = column(GROSS_COLUMNS, "gross")?;
+    let fee = column(FEE_COLUMNS, "fee")?;
+    let currency = column(CURRENCY_COLUMNS, "currency")?;
+    let created = column(CREATED_COLUMNS, "created_utc")?;
+
+    lines
+        .map(|(index, line)| {
+            let line_number = index + 1;
+            let fields = split_csv_line(line);
+            let get = |i: usize| {
+                fields
+                    .get(i)
+                    .map(|f| f.trim())
+                    .ok_or_else(|| ReconciliationError::InvalidRow(line_number, "too few columns".to_string()))
+            };
+            let invalid = |e: String| ReconciliationError::InvalidRow(line_number, e);
+
+            Ok(BalanceTransaction {
+                id: get(id)?.to_string(),
+                category: get(category)?.to_lowercase(),
+                payment_intent_id: Some(get(payment_intent)?.to_string()).filter(|pi| !pi.is_empty()),
+                gross: parse_minor_units(get(gross)?).map_err(invalid)?,
+                fee: parse_minor_units(get(fee)?).map_err(invalid)?,
+                currency: currency_key(get(currency)?),
+                created: parse_created(get(created)?).map_err(invalid)?,
+            })
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct ProviderPayment {
+    charged: i64,
+    refunded: i64,
+    currency: String,
+}
+
+fn describe_status(status: &TransactionStatus) -> String {
+    match status {
+        TransactionStatus::Failed { .. } => "Failed".to_string(),
+        TransactionStatus::RequiresAction { .. } => "RequiresAction".to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+/// Matches an export against our transactions created in `[period_start, period_end)`.
+pub fn reconcile(
+    rows: &[BalanceTransaction],
+    transactions: &[Transaction],
+    period_start: DateTime<Utc>,
+    period_end: DateTime<Utc>,
+) -> ReconciliationReport {
+    let mut provider: BTreeMap<String, ProviderPayment> = BTreeMap::new();
+    let mut rows_skipped = 0;
+
+    for row in rows {
+        let is_charge = CHARGE_CATEGORIES.contains(&row.category.as_str());
+        let is_refund = REFUND_CATEGORIES.contains(&row.category.as_str());
+
+        let Some(payment_intent_id) = row.payment_intent_id.clone().filter(|_| is_charge || is_refund) else {
+            rows_skipped += 1;
+            continue;
+        };
+
+        let payment = provider.entry(payment_intent_id).or_default();
+        payment.currency = row.currency.clone();
+        if is_charge {
+            payment.charged += row.gross;
+        } else {
+            payment.refunded += -row.gross;
+        }
+    }
+
+    let ours: HashMap<&str, &Transaction> = transactions
+        .iter()
+        .filter_map(|t| t.provider_payment_id.as_deref().map(|id| (id, t)))
+        .collect();
+
+    let mut discrepancies = Vec::new();
+    let mut matched = 0;
+
+    for (payment_intent_id, payment) in &provider {
+        let Some(transaction) = ours.get(payment_intent_id.as_str()) else {
+            discrepancies.push(Discrepancy {
+                kind: DiscrepancyKind::Extra,
+                provider_payment_id: payment_intent_id.clone(),
+                transaction_id: None,
+                ours: None,
+                provider: Some(format!("{} {}", payment.charged, payment.currency)),
+            });
+            continue;
+        };
+
+        let our_currency = format!("{:?}", transaction.currency);
+        if transaction.amount != payment.charged || our_currency != payment.currency {
+            discrepancies.push(Discrepancy {
+                kind: DiscrepancyKind::AmountMismatch,
+                provider_payment_id: payment_intent_id.clone(),
+                transaction_id: Some(transaction.id),
+                ours: Some(format!("{} {}", transaction.amount, our_currency)),
+                provider: Some(format!("{} {}", payment.charged, payment.currency)),
+            });
+            continue;
+        }
+
+        let provider_status = if payment.charged > 0 && payment.refunded >= payment.charged {
+            TransactionStatus::Refunded
+        } else {
+            TransactionStatus::Completed
+        };
+
+        // a partial refund leaves the payment completed on our side
+        if transaction.status == provider_status {
+            matched += 1;
+        } else {
+            discrepancies.push(Discrepancy {
+                kind: DiscrepancyKind::StatusMismatch,
+                provider_payment_id: payment_intent_id.clone(),
+                transaction_id: Some(transaction.id),
+                ours: Some(describe_status(&transaction.status)),
+                provider: Some(describe_status(&provider_status)),
+            });
+        }
+    }
+
+    // only payments that moved money are expected in a balance export
+    for transaction in transactions {
+        let Some(payment_intent_id) = &transaction.provider_payment_id else { continue };
+        let moved_money = matches!(transaction.status, TransactionStatus::Completed | TransactionStatus::Refunded);
+        let in_period = transaction.created_at >= period_start && transaction.created_at < period_end;
+
+        if moved_money && in_period && !provider.contains_key(payment_intent_id) {
+            discrepancies.push(Discrepancy {
+                kind: DiscrepancyKind::Missing,
+                provider_payment_id: payment_intent_id.clone(),
+                transaction_id: Some(transaction.id),
+                ours: Some(format!("{} {:?} {}", transaction.amount, transaction.currency, describe_status(&transaction.status))),
+                provider: None,
+            });
+        }
+    }
+
+    ReconciliationReport {
+        id: Uuid::new_v4(),
+        generated_at: Utc::now(),
+        period_start,
+        period_end,
+        rows_read: rows.len(),
+        rows_skipped,
+        matched,
+        discrepancies,
+    }
+}
+
+impl ReconciliationReport {
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("kind,provider_payment_id,transaction_id,ours,provider\n");
+
+        for d in &self.discrepancies {
+            let fields = [
+                format!("{:?}", d.kind),
+                d.provider_payment_id.clone(),
+                d.transaction_id.map(|id| id.to_string()).unwrap_or_default(),
+                d.ours.clone().unwrap_or_default(),
+                d.provider.clone().unwrap_or_default(),
+            ];
+            let line: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+            csv.push_str(&line.join(","));
+            csv.push('\n');
+        }
+
+        csv
+    }
+}
+
+// In production this would live in the database, for now it's kept in memory
+#[derive(Clone, Default)]
+pub struct ReconciliationStore {
+    reports: Arc<RwLock<HashMap<Uuid, ReconciliationReport>>>,
+}
+
+impl ReconciliationStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn save(&self, report: ReconciliationReport) {
+        self.reports.write().await.insert(report.id, report);
+    }
+
+    pub async fn get(&self, id: Uuid) -> Option<ReconciliationReport> {
+        self.reports.read().await.get(&id).cloned()
+    }
+}
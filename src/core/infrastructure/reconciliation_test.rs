@@ -0,0 +1,104 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Utc};
+
+    use crate::core::{
+        infrastructure::reconciliation::{parse_balance_transactions, parse_minor_units, reconcile, DiscrepancyKind, ReconciliationError},
+        models::{Currency, Transaction, TransactionStatus},
+    };
+
+    const EXPORT: &str = include_str!("../../../fixtures/stripe/balance_transactions.csv");
+
+    fn at(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value).unwrap().with_timezone(&Utc)
+    }
+
+    fn transaction(provider_payment_id: &str, amount: i64, status: TransactionStatus) -> Transaction {
+        Transaction {
+            amount,
+            currency: Currency::USD,
+            merchant_id: "merch_123".to_string(),
+            status,
+            provider_payment_id: Some(provider_payment_id.to_string()),
+            created_at: at("2024-03-05T09:00:00Z"),
+            ..Default::default()
+        }
+    }
+
+    fn ours() -> Vec<Transaction> {
+        vec![
+            transaction("pi_matched", 1000, TransactionStatus::Completed),
+            transaction("pi_amount", 2000, TransactionStatus::Completed),
+            transaction("pi_refunded", 500, TransactionStatus::Refunded),
+            transaction("pi_status", 1200, TransactionStatus::Failed { reason: "card_declined".to_string() }),
+            transaction("pi_missing", 800, TransactionStatus::Completed),
+            // failed payments never reach the balance, they aren't expected in the export
+            transaction("pi_declined", 300, TransactionStatus::Failed { reason: "card_declined".to_string() }),
+        ]
+    }
+
+    #[test]
+    fn test_parse_minor_units() {
+        assert_eq!(parse_minor_units("10.00"), Ok(1000));
+        assert_eq!(parse_minor_units("-5.5"), Ok(-550));
+        assert_eq!(parse_minor_units("7"), Ok(700));
+        assert!(parse_minor_units("1.234").is_err());
+        assert!(parse_minor_units("abc").is_err());
+    }
+
+    #[test]
+    fn test_parse_export_handles_quoted_fields() {
+        let rows = parse_balance_transactions(EXPORT).unwrap();
+
+        assert_eq!(rows.len(), 7);
+        assert_eq!(rows[3].category, "refund");
+        assert_eq!(rows[3].gross, -500);
+        assert_eq!(rows[0].currency, "USD");
+        assert_eq!(rows[6].payment_intent_id, None);
+    }
+
+    #[test]
+    fn test_parse_export_requires_payment_intent_column() {
+        let result = parse_balance_transactions("id,type,amount,fee,currency,created\n");
+
+        assert!(matches!(result, Err(ReconciliationError::MissingColumn("payment_intent_id"))));
+    }
+
+    #[test]
+    fn test_reconcile_reports_every_discrepancy() {
+        let rows = parse_balance_transactions(EXPORT).unwrap();
+        let report = reconcile(&rows, &ours(), at("2024-03-05T00:00:00Z"), at("2024-03-06T00:00:00Z"));
+
+        let kind_of = |id: &str| {
+            report
+                .discrepancies
+                .iter()
+                .find(|d| d.provider_payment_id == id)
+                .map(|d| d.kind)
+        };
+
+        assert_eq!(report.matched, 2);
+        assert_eq!(report.rows_skipped, 1);
+        assert_eq!(kind_of("pi_matched"), None);
+        assert_eq!(kind_of("pi_refunded"), None);
+        assert_eq!(kind_of("pi_amount"), Some(DiscrepancyKind::AmountMismatch));
+        assert_eq!(kind_of("pi_extra"), Some(DiscrepancyKind::Extra));
+        assert_eq!(kind_of("pi_status"), Some(DiscrepancyKind::StatusMismatch));
+        assert_eq!(kind_of("pi_missing"), Some(DiscrepancyKind::Missing));
+        assert_eq!(kind_of("pi_declined"), None);
+        assert_eq!(report.discrepancies.len(), 4);
+    }
+
+    #[test]
+    fn test_report_renders_as_csv() {
+        let rows = parse_balance_transactions(EXPORT).unwrap();
+        let report = reconcile(&rows, &ours(), at("2024-03-05T00:00:00Z"), at("2024-03-06T00:00:00Z"));
+
+        let csv = report.to_csv();
+        let mut lines = csv.lines();
+
+        assert_eq!(lines.next(), Some("kind,provider_payment_id,transaction_id,ours,provider"));
+        assert_eq!(lines.count(), 4);
+        assert!(csv.contains("Extra,pi_extra,,,750 USD"));
+    }
+}
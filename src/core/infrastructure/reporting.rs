@@ -0,0 +1,510 @@
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use chrono::{DateTime, Datelike, Days, Months, NaiveTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::{
+    fs::{self, File},
+    io::{AsyncWrite, AsyncWriteExt, BufWriter},
+    sync::RwLock,
+};
+use uuid::Uuid;
+
+use crate::core::{
+    infrastructure::{
+        projection::{PageCursor, TransactionFilter, TransactionProjection, TransactionSort},
+        settlement::SettlementStore,
+    },
+    models::{Refund, SettlementBatch, Transaction},
+};
+
+// transactions are read from the projection a page at a time, a report never holds all of them
+const PAGE_SIZE: usize = 500;
+
+const TRANSACTION_COLUMNS: [&str; 11] = [
+    "transaction_id",
+    "created_at",
+    "merchant_id",
+    "customer_id",
+    "status",
+    "amount",
+    "currency",
+    "amount_refunded",
+    "fee",
+    "net",
+    "provider_payment_id",
+];
+const REFUND_COLUMNS: [&str; 7] = ["refund_id", "created_at", "transaction_id", "merchant_id", "amount", "currency", "reason"];
+const FEE_COLUMNS: [&str; 10] = [
+    "transaction_id",
+    "created_at",
+    "merchant_id",
+    "currency",
+    "amount",
+    "percentage_bps",
+    "percentage_fee",
+    "fixed_fee",
+    "fee",
+    "net",
+];
+const SETTLEMENT_COLUMNS: [&str; 13] = [
+    "batch_id",
+    "settlement_date",
+    "available_on",
+    "merchant_id",
+    "currency",
+    "gross",
+    "fees",
+    "refunds",
+    "net",
+    "carried_in",
+    "status",
+    "payout_id",
+    "released_at",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportKind {
+    Transactions,
+    Refunds,
+    // assessed payments only, with how their fee was made up
+    Fees,
+    // batches by settlement date
+    Settlements,
+}
+
+impl ReportKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ReportKind::Transactions => "transactions",
+            ReportKind::Refunds => "refunds",
+            ReportKind::Fees => "fees",
+            ReportKind::Settlements => "settlements",
+        }
+    }
+
+    pub fn columns(&self) -> &'static [&'static str] {
+        match self {
+            ReportKind::Transactions => &TRANSACTION_COLUMNS,
+            ReportKind::Refunds => &REFUND_COLUMNS,
+            ReportKind::Fees => &FEE_COLUMNS,
+            ReportKind::Settlements => &SETTLEMENT_COLUMNS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportFormat {
+    #[default]
+    Csv,
+    JsonLines,
+    // csv that spreadsheets open as is: a byte order mark, CRLF and no cells they'd run as formulas
+    ExcelCsv,
+}
+
+impl ReportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ReportFormat::Csv | ReportFormat::ExcelCsv => "csv",
+            ReportFormat::JsonLines => "jsonl",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ReportFormat::Csv | ReportFormat::ExcelCsv => "text/csv; charset=utf-8",
+            ReportFormat::JsonLines => "application/x-ndjson",
+        }
+    }
+
+    fn preamble(&self) -> &'static [u8] {
+        match self {
+            ReportFormat::ExcelCsv => "\u{feff}".as_bytes(),
+            _ => b"",
+        }
+    }
+
+    fn line_ending(&self) -> &'static str {
+        match self {
+            ReportFormat::ExcelCsv => "\r\n",
+            _ => "\n",
+        }
+    }
+
+    fn header(&self, columns: &[&str]) -> Option<String> {
+        match self {
+            ReportFormat::JsonLines => None,
+            _ => Some(columns.join(",") + self.line_ending()),
+        }
+    }
+
+    /// One line of the report, `values` are in the order of `columns`.
+    pub fn row(&self, columns: &[&str], values: &[Value]) -> String {
+        match self {
+            // written by hand so the keys keep the column order
+            ReportFormat::JsonLines => {
+                let fields: Vec<String> = columns
+                    .iter()
+                    .zip(values)
+                    .map(|(column, value)| format!("{}:{}", json!(column), value))
+                    .collect();
+                format!("{{{}}}\n", fields.join(","))
+            }
+            _ => {
+                let cells: Vec<String> = values.iter().map(|value| self.cell(value)).collect();
+                cells.join(",") + self.line_ending()
+            }
+        }
+    }
+
+    fn cell(&self, value: &Value) -> String {
+        let mut text = match value {
+            Value::Null => String::new(),
+            Value::String(text) => text.clone(),
+            other => other.to_string(),
+        };
+
+        // merchant supplied text starting like a formula is shown as text instead of evaluated
+        let formula = matches!(text.chars().next(), Some('=' | '+' | '-' | '@' | '\t' | '\r'));
+        if *self == ReportFormat::ExcelCsv && value.is_string() && formula {
+            text.insert(0, '\'');
+        }
+
+        if text.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", text.replace('"', "\"\""))
+        } else {
+            text
+        }
+    }
+}
+
+/// Which whole period a scheduled report covers, relative to when it runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportPeriod {
+    PreviousDay,
+    // monday to monday
+    PreviousWeek,
+    PreviousMonth,
+}
+
+impl ReportPeriod {
+    /// `[from, to)` of the last full period before `at`, in UTC.
+    pub fn range_before(&self, at: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+        let today = at.date_naive();
+        let (from, to) = match self {
+            ReportPeriod::PreviousDay => (today - Days::new(1), today),
+            ReportPeriod::PreviousWeek => {
+                let monday = today - Days::new(today.weekday().num_days_from_monday() as u64);
+                (monday - Days::new(7), monday)
+            }
+            ReportPeriod::PreviousMonth => {
+                let first = today.with_day(1).expect("every month has a first");
+                (first - Months::new(1), first)
+            }
+        };
+
+        (from.and_time(NaiveTime::MIN).and_utc(), to.and_time(NaiveTime::MIN).and_utc())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReportRequest {
+    pub kind: ReportKind,
+    #[serde(default)]
+    pub format: ReportFormat,
+    // every merchant when absent
+    pub merchant_id: Option<String>,
+    // `[from, to)`, settlement reports go by the batches' settlement date
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+}
+
+impl ReportRequest {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.from >= self.to {
+            return Err("from must be before to".to_string());
+        }
+        Ok(())
+    }
+
+    /* e.g. fees-2024-03-01-2024-04-01.csv */
+    pub fn file_name(&self) -> String {
+        format!(
+            "{}-{}-{}.{}",
+            self.kind.name(),
+            self.from.format("%Y-%m-%d"),
+            self.to.format("%Y-%m-%d"),
+            self.format.extension()
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ReportJobStatus {
+    Pending,
+    Completed,
+    Failed { reason: String },
+}
+
+/// A report requested through the api, generated in the background.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportJob {
+    pub id: Uuid,
+    pub request: ReportRequest,
+    // the token subject that asked for it
+    pub requested_by: String,
+    pub status: ReportJobStatus,
+    // known once completed
+    pub rows: Option<u64>,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+/* generates reports from the query side stores, for api jobs and the scheduler alike */
+#[derive(Clone)]
+pub struct Reports {
+    projection: TransactionProjection,
+    settlements: SettlementStore,
+    output_dir: PathBuf,
+    // In production this would be the database, the files themselves would go to object storage
+    jobs: Arc<RwLock<HashMap<Uuid, ReportJob>>>,
+}
+
+impl Reports {
+    pub fn new(projection: TransactionProjection, settlements: SettlementStore, output_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            projection,
+            settlements,
+            output_dir: output_dir.into(),
+            jobs: Arc::default(),
+        }
+    }
+
+    pub fn output_dir(&self) -> &Path {
+        &self.output_dir
+    }
+
+    /// Writes the report to `path`. It is written next to it first and moved in place once
+    /// complete, whoever picks files up from the directory never sees half a report.
+    pub async fn generate(&self, request: &ReportRequest, path: &Path) -> io::Result<u64> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).await?;
+        }
+
+        let partial = path.with_extension("part");
+        let mut out = BufWriter::new(File::create(&partial).await?);
+        let rows = match self.write_report(request, &mut out).await {
+            Ok(rows) => rows,
+            Err(e) => {
+                let _ = fs::remove_file(&partial).await;
+                return Err(e);
+            }
+        };
+        out.into_inner().sync_all().await?;
+        fs::rename(&partial, path).await?;
+
+        Ok(rows)
+    }
+
+    /// Streams the report into `out` row by row, returns how many rows it had.
+    pub async fn write_report<W: AsyncWrite + Unpin>(&self, request: &ReportRequest, out: &mut W) -> io::Result<u64> {
+        let format = request.format;
+        let columns = request.kind.columns();
+        let merchant_id = request.merchant_id.as_deref();
+
+        out.write_all(format.preamble()).await?;
+        if let Some(header) = format.header(columns) {
+            out.write_all(header.as_bytes()).await?;
+        }
+
+        let mut rows = 0;
+        match request.kind {
+            ReportKind::Transactions | ReportKind::Fees => {
+                let filter = TransactionFilter {
+                    merchant_id: request.merchant_id.clone(),
+                    created_from: Some(request.from),
+                    created_to: Some(request.to),
+                    ..Default::default()
+                };
+
+                let mut cursor: Option<PageCursor> = None;
+                loop {
+                    let page = self.projection.search(&filter, TransactionSort::CreatedAtAsc, cursor.as_ref(), PAGE_SIZE).await;
+                    let values = page.transactions.iter().filter_map(|t| match request.kind {
+                        ReportKind::Fees => fee_row(t),
+                        _ => Some(transaction_row(t)),
+                    });
+                    rows += write_rows(out, format, columns, values).await?;
+
+                    match page.next_cursor {
+                        Some(next) => cursor = Some(next),
+                        None => break,
+                    }
+                }
+            }
+            ReportKind::Refunds => {
+                let mut last: Option<Refund> = None;
+                loop {
+                    let page = self.projection.refunds_between(merchant_id, request.from, request.to, last.as_ref(), PAGE_SIZE).await;
+                    rows += write_rows(out, format, columns, page.iter().map(refund_row)).await?;
+
+                    if page.len() < PAGE_SIZE {
+                        break;
+                    }
+                    last = page.into_iter().last();
+                }
+            }
+            ReportKind::Settlements => {
+                let (from, to) = (request.from.date_naive(), request.to.date_naive());
+                let mut last: Option<SettlementBatch> = None;
+                loop {
+                    let page = self.settlements.batches_between(merchant_id, from, to, last.as_ref(), PAGE_SIZE).await;
+                    rows += write_rows(out, format, columns, page.iter().map(settlement_row)).await?;
+
+                    if page.len() < PAGE_SIZE {
+                        break;
+                    }
+                    last = page.into_iter().last();
+                }
+            }
+        }
+
+        out.flush().await?;
+        Ok(rows)
+    }
+
+    /// Queues the report and returns straight away, poll the job for the outcome.
+    pub async fn submit(&self, request: ReportRequest, requested_by: &str) -> ReportJob {
+        let job = ReportJob {
+            id: Uuid::new_v4(),
+            request,
+            requested_by: requested_by.to_string(),
+            status: ReportJobStatus::Pending,
+            rows: None,
+            created_at: Utc::now(),
+            completed_at: None,
+        };
+        self.jobs.write().await.insert(job.id, job.clone());
+
+        let reports = self.clone();
+        let pending = job.clone();
+        tokio::spawn(async move {
+            let (status, rows) = match reports.generate(&pending.request, &reports.job_file(&pending)).await {
+                Ok(rows) => (ReportJobStatus::Completed, Some(rows)),
+                Err(e) => {
+                    eprintln!("Report job {} failed: {}", pending.id, e);
+                    (ReportJobStatus::Failed { reason: e.to_string() }, None)
+                }
+            };
+
+            if let Some(job) = reports.jobs.write().await.get_mut(&pending.id) {
+                job.status = status;
+                job.rows = rows;
+                job.completed_at = Some(Utc::now());
+            }
+        });
+
+        job
+    }
+
+    pub async fn job(&self, id: Uuid) -> Option<ReportJob> {
+        self.jobs.read().await.get(&id).cloned()
+    }
+
+    pub fn job_file(&self, job: &ReportJob) -> PathBuf {
+        self.output_dir.join("jobs").join(format!("{}.{}", job.id, job.request.format.extension()))
+    }
+}
+
+async fn write_rows<W: AsyncWrite + Unpin>(
+    out: &mut W,
+    format: ReportFormat,
+    columns: &[&str],
+    values: impl Iterator<Item = Vec<Value>>,
+) -> io::Result<u64> {
+    let mut chunk = String::new();
+    let mut rows = 0;
+    for row in values {
+        chunk.push_str(&format.row(columns, &row));
+        rows += 1;
+    }
+
+    out.write_all(chunk.as_bytes()).await?;
+    Ok(rows)
+}
+
+fn timestamp(at: &DateTime<Utc>) -> Value {
+    json!(at.to_rfc3339_opts(SecondsFormat::Secs, true))
+}
+
+fn transaction_row(t: &Transaction) -> Vec<Value> {
+    let fee = t.fee.as_ref().map(|fee| fee.total);
+    vec![
+        json!(t.id),
+        timestamp(&t.created_at),
+        json!(t.merchant_id),
+        json!(t.customer_id),
+        json!(t.status.kind()),
+        json!(t.amount),
+        json!(format!("{:?}", t.currency)),
+        json!(t.amount_refunded),
+        json!(fee),
+        json!(fee.map(|fee| t.amount - fee as i64)),
+        json!(t.provider_payment_id),
+    ]
+}
+
+// only payments that were assessed have a fee to report
+fn fee_row(t: &Transaction) -> Option<Vec<Value>> {
+    let fee = t.fee.as_ref()?;
+    Some(vec![
+        json!(t.id),
+        timestamp(&t.created_at),
+        json!(t.merchant_id),
+        json!(format!("{:?}", t.currency)),
+        json!(t.amount),
+        json!(fee.percentage_bps),
+        json!(fee.percentage_fee),
+        json!(fee.fixed_fee),
+        json!(fee.total),
+        json!(t.amount - fee.total as i64),
+    ])
+}
+
+fn refund_row(r: &Refund) -> Vec<Value> {
+    vec![
+        json!(r.id),
+        timestamp(&r.created_at),
+        json!(r.transaction_id),
+        json!(r.merchant_id),
+        json!(r.amount),
+        json!(r.currency),
+        json!(r.reason),
+    ]
+}
+
+fn settlement_row(b: &SettlementBatch) -> Vec<Value> {
+    vec![
+        json!(b.id),
+        json!(b.settlement_date),
+        json!(b.available_on),
+        json!(b.merchant_id),
+        json!(b.currency),
+        json!(b.gross),
+        json!(b.fees),
+        json!(b.refunds),
+        json!(b.net),
+        json!(b.carried_in),
+        json!(format!("{:?}", b.status)),
+        json!(b.payout_id),
+        json!(b.released_at.as_ref().map(|at| at.to_rfc3339_opts(SecondsFormat::Secs, true))),
+    ]
+}
@@ -0,0 +1,186 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+    use serde_json::{json, Value};
+    use uuid::Uuid;
+
+    use crate::core::{
+        events::{FeeAssessedEvent, RefundCreatedEvent, TransactionCreatedEvent},
+        infrastructure::{
+            projection::TransactionProjection,
+            reporting::{ReportFormat, ReportKind, ReportPeriod, ReportRequest, Reports},
+            settlement::{SettlementConfig, SettlementStore},
+        },
+        models::FeeBreakdown,
+    };
+
+    fn at(day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, day, hour, 0, 0).unwrap()
+    }
+
+    fn reports() -> (Reports, TransactionProjection, SettlementStore) {
+        let projection = TransactionProjection::new();
+        let settlements = SettlementStore::new(SettlementConfig::default());
+        let output_dir = std::env::temp_dir().join(format!("payme-reports-{}", Uuid::new_v4()));
+        (Reports::new(projection.clone(), settlements.clone(), output_dir), projection, settlements)
+    }
+
+    async fn create(projection: &TransactionProjection, merchant_id: &str, amount: u64, created_at: DateTime<Utc>) -> Uuid {
+        let id = Uuid::new_v4();
+        let mut event = TransactionCreatedEvent::new(id, amount, "USD".to_string(), merchant_id.to_string(), "cust_1".to_string());
+        event.timestamp = created_at;
+        projection.apply_created(&event).await;
+        id
+    }
+
+    fn request(kind: ReportKind, format: ReportFormat) -> ReportRequest {
+        ReportRequest { kind, format, merchant_id: None, from: at(1, 0), to: at(2, 0) }
+    }
+
+    async fn render(reports: &Reports, request: &ReportRequest) -> (u64, String) {
+        let mut out = Vec::new();
+        let rows = reports.write_report(request, &mut out).await.unwrap();
+        (rows, String::from_utf8(out).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_transactions_report_covers_the_range_across_pages() {
+        let (reports, projection, _) = reports();
+        for minute in 0..1200 {
+            create(&projection, "merch_pages", 100, at(1, 0) + Duration::seconds(minute)).await;
+        }
+        create(&projection, "merch_pages", 100, at(2, 0)).await;
+        create(&projection, "merch_pages", 100, at(1, 0) - Duration::seconds(1)).await;
+
+        let (rows, csv) = render(&reports, &request(ReportKind::Transactions, ReportFormat::Csv)).await;
+
+        assert_eq!(rows, 1200);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 1201);
+        assert_eq!(lines[0], "transaction_id,created_at,merchant_id,customer_id,status,amount,currency,amount_refunded,fee,net,provider_payment_id");
+        assert!(lines[1].contains(",2024-03-01T00:00:00Z,merch_pages,cust_1,pending,100,USD,0,,,"));
+        assert!(lines[1200].contains(",2024-03-01T00:19:59Z,"));
+    }
+
+    #[tokio::test]
+    async fn test_fee_report_only_has_assessed_payments() {
+        let (reports, projection, _) = reports();
+        let assessed = create(&projection, "merch_fees", 10_000, at(1, 9)).await;
+        create(&projection, "merch_fees", 5_000, at(1, 10)).await;
+        let fee = FeeBreakdown { percentage_bps: 290, percentage_fee: 290, fixed_fee: 30, total: 320, volume_tier: None };
+        projection
+            .apply_fee(&FeeAssessedEvent::new(assessed, "merch_fees".to_string(), 10_000, "USD".to_string(), fee))
+            .await;
+
+        let (rows, lines) = render(&reports, &request(ReportKind::Fees, ReportFormat::JsonLines)).await;
+
+        assert_eq!(rows, 1);
+        let row: Value = serde_json::from_str(lines.trim()).unwrap();
+        assert_eq!(
+            row,
+            json!({
+                "transaction_id": assessed,
+                "created_at": "2024-03-01T09:00:00Z",
+                "merchant_id": "merch_fees",
+                "currency": "USD",
+                "amount": 10_000,
+                "percentage_bps": 290,
+                "percentage_fee": 290,
+                "fixed_fee": 30,
+                "fee": 320,
+                "net": 9_680,
+            })
+        );
+        // keys keep the column order
+        assert!(lines.starts_with("{\"transaction_id\":"));
+    }
+
+    #[tokio::test]
+    async fn test_refund_report_is_scoped_to_the_merchant() {
+        let (reports, projection, _) = reports();
+        let transaction_id = create(&projection, "merch_refunds", 2_000, at(1, 8)).await;
+        let mut refund = RefundCreatedEvent::new(transaction_id, "merch_refunds".to_string(), 500, "USD".to_string(), Some("damaged, \"returned\"".to_string()));
+        refund.timestamp = at(1, 12);
+        projection.apply_refund(&refund).await;
+        // redelivered
+        projection.apply_refund(&refund).await;
+        let mut other = RefundCreatedEvent::new(Uuid::new_v4(), "merch_other".to_string(), 700, "USD".to_string(), None);
+        other.timestamp = at(1, 13);
+        projection.apply_refund(&other).await;
+
+        let request = ReportRequest { merchant_id: Some("merch_refunds".to_string()), ..request(ReportKind::Refunds, ReportFormat::Csv) };
+        let (rows, csv) = render(&reports, &request).await;
+
+        assert_eq!(rows, 1);
+        assert!(csv.ends_with(&format!(",2024-03-01T12:00:00Z,{},merch_refunds,500,USD,\"damaged, \"\"returned\"\"\"\n", transaction_id)));
+        assert_eq!(projection.get(transaction_id).await.unwrap().amount_refunded, 500);
+    }
+
+    #[tokio::test]
+    async fn test_refund_report_covers_the_range_across_pages() {
+        let (reports, projection, _) = reports();
+        // refunds issued in the same second are only told apart by their id
+        for second in 0..1200 {
+            let mut refund = RefundCreatedEvent::new(Uuid::new_v4(), "merch_pages".to_string(), 100, "USD".to_string(), None);
+            refund.timestamp = at(1, 0) + Duration::seconds(second / 3);
+            projection.apply_refund(&refund).await;
+        }
+
+        let (rows, csv) = render(&reports, &request(ReportKind::Refunds, ReportFormat::Csv)).await;
+
+        assert_eq!(rows, 1200);
+        let ids: std::collections::HashSet<&str> = csv.lines().skip(1).map(|line| line.split(',').next().unwrap()).collect();
+        assert_eq!(ids.len(), 1200);
+    }
+
+    #[tokio::test]
+    async fn test_excel_csv_has_a_bom_crlf_and_no_formulas() {
+        let (reports, projection, _) = reports();
+        create(&projection, "=HYPERLINK(\"http://evil\")", 100, at(1, 1)).await;
+
+        let (_, csv) = render(&reports, &request(ReportKind::Transactions, ReportFormat::ExcelCsv)).await;
+
+        assert!(csv.starts_with('\u{feff}'));
+        assert!(csv.contains("provider_payment_id\r\n"));
+        assert!(csv.contains(",\"'=HYPERLINK(\"\"http://evil\"\")\",cust_1,"));
+    }
+
+    #[tokio::test]
+    async fn test_settlement_report_goes_by_settlement_date() {
+        let (reports, _, settlements) = reports();
+        let breakdown = FeeBreakdown { percentage_bps: 0, percentage_fee: 0, fixed_fee: 50, total: 50, volume_tier: None };
+        let payment = FeeAssessedEvent::new(Uuid::new_v4(), "merch_settle".to_string(), 1_000, "USD".to_string(), breakdown);
+        settlements.add_payment(&payment).await;
+        let today = Utc::now().date_naive().and_hms_opt(0, 0, 0).unwrap().and_utc();
+
+        let request = ReportRequest { from: today, to: today + Duration::days(1), ..request(ReportKind::Settlements, ReportFormat::Csv) };
+        let (rows, csv) = render(&reports, &request).await;
+
+        assert_eq!(rows, 1);
+        assert!(csv.lines().nth(1).unwrap().contains(",merch_settle,USD,1000,50,0,950,0,Open,,"));
+    }
+
+    #[tokio::test]
+    async fn test_generate_moves_the_finished_file_in_place() {
+        let (reports, projection, _) = reports();
+        create(&projection, "merch_file", 100, at(1, 1)).await;
+        let path = reports.output_dir().join("out").join("transactions.csv");
+
+        let rows = reports.generate(&request(ReportKind::Transactions, ReportFormat::Csv), &path).await.unwrap();
+
+        assert_eq!(rows, 1);
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2);
+        assert!(!path.with_extension("part").exists());
+        std::fs::remove_dir_all(reports.output_dir()).unwrap();
+    }
+
+    #[test]
+    fn test_periods_are_the_last_full_ones() {
+        let wednesday = Utc.with_ymd_and_hms(2024, 3, 13, 6, 30, 0).unwrap();
+        let day = |d: u32, m: u32| Utc.with_ymd_and_hms(2024, m, d, 0, 0, 0).unwrap();
+
+        assert_eq!(ReportPeriod::PreviousDay.range_before(wednesday), (day(12, 3), day(13, 3)));
+        assert_eq!(ReportPeriod::PreviousWeek.range_before(wednesday), (day(4, 3), day(11, 3)));
+        assert_eq!(ReportPeriod::PreviousMonth.range_before(wednesday), (day(1, 2), day(1, 3)));
+    }
+}
@@ -0,0 +1,493 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    str::FromStr,
+    sync::Arc,
+};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use thiserror::Error;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::core::{
+    events::TransactionCreatedEvent,
+    models::{RiskAction, RiskDecision, RiskDecisionSource},
+};
+
+/// Rules every store starts with, amounts are in minor units.
+pub const DEFAULT_RULES: &str = "\
+block when amount > 5000000
+review when amount > 1000000
+review when velocity(customer, 1h) > 5
+block when velocity(ip, 10m) > 20
+review when country_mismatch
+block when customer in blocklist
+block when ip in blocklist
+block when country in blocklist
+";
+
+#[derive(Debug, Error)]
+pub enum RiskError {
+    #[error("Invalid rule on line {0}: {1}")]
+    InvalidRule(usize, String),
+    #[error("Unknown blocklist {0}, expected customer, ip, country or merchant")]
+    UnknownBlocklist(String),
+    #[error("Transaction {0} is not waiting for review")]
+    NotUnderReview(Uuid),
+    #[error("Transaction {0} waited too long for review and is being failed")]
+    ReviewExpired(Uuid),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum BlocklistKind {
+    Customer,
+    Ip,
+    Country,
+    Merchant,
+}
+
+impl FromStr for BlocklistKind {
+    type Err = RiskError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "customer" => Ok(BlocklistKind::Customer),
+            "ip" => Ok(BlocklistKind::Ip),
+            "country" => Ok(BlocklistKind::Country),
+            "merchant" => Ok(BlocklistKind::Merchant),
+            other => Err(RiskError::UnknownBlocklist(other.to_string())),
+        }
+    }
+}
+
+impl BlocklistKind {
+    // countries are compared case insensitively, everything else as given
+    fn normalise(&self, value: &str) -> String {
+        match self {
+            BlocklistKind::Country => value.trim().to_uppercase(),
+            _ => value.trim().to_string(),
+        }
+    }
+
+    fn value_of(&self, event: &TransactionCreatedEvent) -> Option<String> {
+        match self {
+            BlocklistKind::Customer => Some(event.customer_id.clone()),
+            BlocklistKind::Ip => event.risk_context.ip_address.clone(),
+            BlocklistKind::Country => event.risk_context.billing_country.clone(),
+            BlocklistKind::Merchant => Some(event.merchant_id.clone()),
+        }
+        .map(|value| self.normalise(&value))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+    Equal,
+}
+
+impl Comparison {
+    fn holds(&self, left: u64, right: u64) -> bool {
+        match self {
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Equal => left == right,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Amount(Comparison, u64),
+    // transactions seen for the same key within the window, the current one included
+    Velocity(BlocklistKind, Duration, Comparison, u64),
+    CountryMismatch,
+    Blocklisted(BlocklistKind),
+}
+
+#[derive(Debug, Clone)]
+pub struct RiskRule {
+    pub source: String,
+    pub action: RiskAction,
+    pub conditions: Vec<Condition>,
+}
+
+/// Parses one rule per line in the form `<review|block> when <condition> [and <condition>...]`.
+/// Conditions are `amount <op> <n>`, `velocity(<customer|ip|country|merchant>, <n><s|m|h|d>) <op> <n>`,
+/// `country_mismatch` and `<customer|ip|country|merchant> in blocklist`. Blank lines and `#` comments are skipped.
+/// The most severe matching rule decides and a payment no rule matches is allowed, so there are no `allow` rules.
+pub fn parse_rules(text: &str) -> Result<Vec<RiskRule>, RiskError> {
+    let mut rules = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let invalid = |message: String| RiskError::InvalidRule(index + 1, message);
+
+        let (action, conditions) = line
+            .split_once(" when ")
+            .ok_or_else(|| invalid("expected `<action> when <condition>`".to_string()))?;
+
+        let action = match action.trim() {
+            "allow" => return Err(invalid("allow rules can't override review or block, payments no rule matches are allowed".to_string())),
+            "review" => RiskAction::Review,
+            "block" => RiskAction::Block,
+            other => return Err(invalid(format!("unknown action {}", other))),
+        };
+
+        let conditions = conditions
+            .split(" and ")
+            .map(|condition| parse_condition(condition.trim()).map_err(&invalid))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        rules.push(RiskRule {
+            source: line.to_string(),
+            action,
+            conditions,
+        });
+    }
+
+    Ok(rules)
+}
+
+fn parse_condition(condition: &str) -> Result<Condition, String> {
+    if condition == "country_mismatch" {
+        return Ok(Condition::CountryMismatch);
+    }
+
+    if let Some(kind) = condition.strip_suffix(" in blocklist") {
+        return kind.trim().parse().map(Condition::Blocklisted).map_err(|e: RiskError| e.to_string());
+    }
+
+    if let Some(rest) = condition.strip_prefix("amount") {
+        let (comparison, value) = parse_comparison(rest)?;
+        return Ok(Condition::Amount(comparison, value));
+    }
+
+    if let Some(rest) = condition.strip_prefix("velocity(") {
+        let (arguments, rest) = rest.split_once(')').ok_or("velocity is missing its closing parenthesis")?;
+        let (kind, window) = arguments.split_once(',').ok_or("velocity takes a key and a window")?;
+        let kind: BlocklistKind = kind.trim().parse().map_err(|e: RiskError| e.to_string())?;
+        let window = parse_window(window.trim())?;
+        let (comparison, value) = parse_comparison(rest)?;
+        return Ok(Condition::Velocity(kind, window, comparison, value));
+    }
+
+    Err(format!("unknown condition `{}`", condition))
+}
+
+fn parse_comparison(text: &str) -> Result<(Comparison, u64), String> {
+    let text = text.trim();
+    // two character operators first so `>=` isn't read as `>`
+    let operators = [
+        (">=", Comparison::GreaterOrEqual),
+        ("<=", Comparison::LessOrEqual),
+        ("==", Comparison::Equal),
+        (">", Comparison::Greater),
+        ("<", Comparison::Less),
+    ];
+
+    let (comparison, value) = operators
+        .iter()
+        .find_map(|(operator, comparison)| text.strip_prefix(operator).map(|value| (*comparison, value)))
+        .ok_or_else(|| format!("expected a comparison in `{}`", text))?;
+
+    let value = value.trim().parse().map_err(|_| format!("`{}` is not a whole number", value.trim()))?;
+    Ok((comparison, value))
+}
+
+fn parse_window(text: &str) -> Result<Duration, String> {
+    let invalid = || format!("window `{}` should look like 30s, 10m, 1h or 1d", text);
+    if text.len() < 2 {
+        return Err(invalid());
+    }
+
+    let (value, unit) = text.split_at(text.len() - 1);
+    let value: i64 = value.parse().map_err(|_| invalid())?;
+    match unit {
+        "s" => Ok(Duration::seconds(value)),
+        "m" => Ok(Duration::minutes(value)),
+        "h" => Ok(Duration::hours(value)),
+        "d" => Ok(Duration::days(value)),
+        _ => Err(invalid()),
+    }
+}
+
+#[derive(Default)]
+struct RiskState {
+    rules: Vec<RiskRule>,
+    blocklists: HashMap<BlocklistKind, HashSet<String>>,
+    // when each customer, ip, country and merchant was last seen
+    history: HashMap<(BlocklistKind, String), VecDeque<DateTime<Utc>>>,
+    // payments held until someone approves or declines them
+    reviews: HashMap<Uuid, TransactionCreatedEvent>,
+    // the screening and reviews of recent payments, older trails live on in the audit log
+    decisions: HashMap<Uuid, Vec<RiskDecision>>,
+    last_pruned: Option<DateTime<Utc>>,
+}
+
+// the sweeper fails held payments after `sweeper.review_sla_secs`, this is its default
+const DEFAULT_REVIEW_SLA_HOURS: i64 = 24;
+// how often screening drops history and decisions nothing looks at any more
+const PRUNE_INTERVAL_SECS: i64 = 60;
+
+// In production this would live in the database, for now it's kept in memory. Held payments lost
+// with it are failed by the sweeper once the review SLA is up.
+#[derive(Clone)]
+pub struct RiskStore {
+    state: Arc<RwLock<RiskState>>,
+    review_sla: Duration,
+}
+
+impl Default for RiskStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RiskStore {
+    pub fn new() -> Self {
+        let state = RiskState {
+            rules: parse_rules(DEFAULT_RULES).expect("default risk rules parse"),
+            ..Default::default()
+        };
+
+        Self {
+            state: Arc::new(RwLock::new(state)),
+            review_sla: Duration::hours(DEFAULT_REVIEW_SLA_HOURS),
+        }
+    }
+
+    /// How long a payment can wait for review, past it the sweeper fails it and it can't be approved.
+    pub fn with_review_sla(mut self, review_sla: Duration) -> Self {
+        self.review_sla = review_sla;
+        self
+    }
+
+    /// Replaces the rule set, the old rules stay in place if the new ones don't parse.
+    pub async fn set_rules(&self, text: &str) -> Result<Vec<String>, RiskError> {
+        let rules = parse_rules(text)?;
+        let sources = rules.iter().map(|rule| rule.source.clone()).collect();
+        self.state.write().await.rules = rules;
+        Ok(sources)
+    }
+
+    pub async fn rules(&self) -> Vec<String> {
+        self.state.read().await.rules.iter().map(|rule| rule.source.clone()).collect()
+    }
+
+    pub async fn add_to_blocklist(&self, kind: BlocklistKind, value: &str) {
+        self.state.write().await.blocklists.entry(kind).or_default().insert(kind.normalise(value));
+    }
+
+    pub async fn remove_from_blocklist(&self, kind: BlocklistKind, value: &str) -> bool {
+        self.state
+            .write()
+            .await
+            .blocklists
+            .get_mut(&kind)
+            .map(|values| values.remove(&kind.normalise(value)))
+            .unwrap_or(false)
+    }
+
+    pub async fn blocklist(&self, kind: BlocklistKind) -> Vec<String> {
+        let mut values: Vec<String> = self
+            .state
+            .read()
+            .await
+            .blocklists
+            .get(&kind)
+            .map(|values| values.iter().cloned().collect())
+            .unwrap_or_default();
+        values.sort();
+        values
+    }
+
+    /// Scores a new payment against the rules, the most severe matching rule wins.
+    /// Payments sent to review are held here. Returns `None` for a payment that was already screened.
+    pub async fn screen(&self, event: &TransactionCreatedEvent, now: DateTime<Utc>) -> Option<RiskDecision> {
+        let mut state = self.state.write().await;
+        if state.decisions.contains_key(&event.transaction_id) {
+            return None;
+        }
+
+        record_velocity(&mut state, event, now);
+        if state.last_pruned.is_none_or(|at| now - at >= Duration::seconds(PRUNE_INTERVAL_SECS)) {
+            prune(&mut state, now, self.review_sla);
+        }
+
+        let mut action = RiskAction::Allow;
+        let mut reasons = Vec::new();
+        for rule in &state.rules {
+            if rule.conditions.iter().all(|condition| matches(&state, condition, event, now)) {
+                action = action.max(rule.action);
+                reasons.push(rule.source.clone());
+            }
+        }
+
+        let decision = RiskDecision {
+            id: Uuid::new_v4(),
+            transaction_id: event.transaction_id,
+            merchant_id: event.merchant_id.clone(),
+            action,
+            source: RiskDecisionSource::Engine,
+            reasons,
+            decided_at: now,
+        };
+
+        if action == RiskAction::Review {
+            state.reviews.insert(event.transaction_id, event.clone());
+        }
+        state.decisions.entry(event.transaction_id).or_default().push(decision.clone());
+
+        Some(decision)
+    }
+
+    /// Takes a held payment out of the review queue so it can be released or declined.
+    /// One held past the review SLA is dropped instead, the sweeper fails it.
+    pub async fn take_review(&self, transaction_id: Uuid) -> Result<TransactionCreatedEvent, RiskError> {
+        let event = self
+            .state
+            .write()
+            .await
+            .reviews
+            .remove(&transaction_id)
+            .ok_or(RiskError::NotUnderReview(transaction_id))?;
+
+        if Utc::now() - event.timestamp > self.review_sla {
+            return Err(RiskError::ReviewExpired(transaction_id));
+        }
+        Ok(event)
+    }
+
+    /// Puts a payment back in the queue when acting on the review didn't go through.
+    pub async fn return_review(&self, event: TransactionCreatedEvent) {
+        self.state.write().await.reviews.insert(event.transaction_id, event);
+    }
+
+    pub async fn record_review(
+        &self,
+        event: &TransactionCreatedEvent,
+        action: RiskAction,
+        reviewer: String,
+        note: Option<String>,
+    ) -> RiskDecision {
+        let decision = RiskDecision {
+            id: Uuid::new_v4(),
+            transaction_id: event.transaction_id,
+            merchant_id: event.merchant_id.clone(),
+            action,
+            source: RiskDecisionSource::Manual { reviewer },
+            reasons: note.into_iter().collect(),
+            decided_at: Utc::now(),
+        };
+
+        self.state
+            .write()
+            .await
+            .decisions
+            .entry(event.transaction_id)
+            .or_default()
+            .push(decision.clone());
+
+        decision
+    }
+
+    /// Payments waiting for a reviewer, oldest first, without the ones past the review SLA.
+    pub async fn pending_reviews(&self) -> Vec<TransactionCreatedEvent> {
+        let oldest = Utc::now() - self.review_sla;
+        let mut reviews: Vec<TransactionCreatedEvent> = self
+            .state
+            .read()
+            .await
+            .reviews
+            .values()
+            .filter(|event| event.timestamp >= oldest)
+            .cloned()
+            .collect();
+        reviews.sort_by_key(|event| event.timestamp);
+        reviews
+    }
+
+    /// What was decided about a payment, kept for the longer of the velocity windows and the review SLA.
+    pub async fn decisions(&self, transaction_id: Uuid) -> Vec<RiskDecision> {
+        self.state.read().await.decisions.get(&transaction_id).cloned().unwrap_or_default()
+    }
+}
+
+// nothing older than the longest window is ever looked at again
+fn longest_window(rules: &[RiskRule]) -> Duration {
+    rules
+        .iter()
+        .flat_map(|rule| rule.conditions.iter())
+        .filter_map(|condition| match condition {
+            Condition::Velocity(_, window, _, _) => Some(*window),
+            _ => None,
+        })
+        .max()
+        .unwrap_or_else(Duration::zero)
+}
+
+fn record_velocity(state: &mut RiskState, event: &TransactionCreatedEvent, now: DateTime<Utc>) {
+    let longest = longest_window(&state.rules);
+
+    for kind in [BlocklistKind::Customer, BlocklistKind::Ip, BlocklistKind::Country, BlocklistKind::Merchant] {
+        let Some(value) = kind.value_of(event) else {
+            continue;
+        };
+
+        let seen = state.history.entry((kind, value)).or_default();
+        seen.push_back(now);
+        while seen.front().map(|at| *at < now - longest).unwrap_or(false) {
+            seen.pop_front();
+        }
+    }
+}
+
+// keys that weren't seen within the longest window, and decisions of payments no longer held or
+// redelivered, the review sla bounds both
+fn prune(state: &mut RiskState, now: DateTime<Utc>, review_sla: Duration) {
+    let longest = longest_window(&state.rules);
+    state.history.retain(|_, seen| {
+        while seen.front().map(|at| *at < now - longest).unwrap_or(false) {
+            seen.pop_front();
+        }
+        !seen.is_empty()
+    });
+
+    let oldest = now - longest.max(review_sla);
+    let RiskState { decisions, reviews, .. } = state;
+    decisions.retain(|id, trail| reviews.contains_key(id) || trail.last().is_some_and(|decision| decision.decided_at >= oldest));
+
+    state.last_pruned = Some(now);
+}
+
+fn matches(state: &RiskState, condition: &Condition, event: &TransactionCreatedEvent, now: DateTime<Utc>) -> bool {
+    match condition {
+        Condition::Amount(comparison, value) => comparison.holds(event.amount, *value),
+        Condition::Velocity(kind, window, comparison, value) => {
+            let count = kind
+                .value_of(event)
+                .and_then(|key| state.history.get(&(*kind, key)))
+                .map(|seen| seen.iter().filter(|at| **at >= now - *window).count() as u64)
+                .unwrap_or(0);
+            comparison.holds(count, *value)
+        }
+        Condition::CountryMismatch => match (&event.risk_context.billing_country, &event.risk_context.card_country) {
+            (Some(billing), Some(card)) => !billing.eq_ignore_ascii_case(card),
+            _ => false,
+        },
+        Condition::Blocklisted(kind) => kind
+            .value_of(event)
+            .map(|value| state.blocklists.get(kind).map(|values| values.contains(&value)).unwrap_or(false))
+            .unwrap_or(false),
+    }
+}
@@ -0,0 +1,161 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use uuid::Uuid;
+
+    use crate::core::{
+        events::TransactionCreatedEvent,
+        infrastructure::risk::{parse_rules, BlocklistKind, Comparison, Condition, RiskError, RiskStore},
+        models::{RiskAction, RiskContext, RiskDecisionSource},
+    };
+
+    fn payment(amount: u64, customer_id: &str) -> TransactionCreatedEvent {
+        TransactionCreatedEvent::new(Uuid::new_v4(), amount, "USD".to_string(), "merch_123".to_string(), customer_id.to_string())
+    }
+
+    async fn store_with(rules: &str) -> RiskStore {
+        let store = RiskStore::new();
+        store.set_rules(rules).await.unwrap();
+        store
+    }
+
+    #[test]
+    fn test_parses_rules_and_skips_comments() {
+        let rules = parse_rules("# thresholds\nblock when amount >= 1000 and velocity(ip, 10m) > 3\n\nreview when country_mismatch # cards abroad\n").unwrap();
+
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].action, RiskAction::Block);
+        assert_eq!(rules[0].conditions, vec![
+            Condition::Amount(Comparison::GreaterOrEqual, 1000),
+            Condition::Velocity(BlocklistKind::Ip, Duration::minutes(10), Comparison::Greater, 3),
+        ]);
+        assert_eq!(rules[1].source, "review when country_mismatch");
+    }
+
+    #[test]
+    fn test_reports_the_line_of_an_invalid_rule() {
+        let error = parse_rules("block when amount > 10\nreview when velocity(card, 1h) > 2").unwrap_err();
+        assert!(matches!(error, RiskError::InvalidRule(2, _)));
+
+        assert!(parse_rules("deny when amount > 10").is_err());
+        assert!(parse_rules("block when amount is big").is_err());
+        assert!(parse_rules("block when velocity(ip, 10w) > 2").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_most_severe_matching_rule_wins() {
+        let store = store_with("review when amount > 100\nblock when amount > 1000").await;
+
+        let small = store.screen(&payment(50, "cust_1"), Utc::now()).await.unwrap();
+        let large = store.screen(&payment(5000, "cust_1"), Utc::now()).await.unwrap();
+
+        assert_eq!(small.action, RiskAction::Allow);
+        assert!(small.reasons.is_empty());
+        assert_eq!(large.action, RiskAction::Block);
+        assert_eq!(large.reasons.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_velocity_counts_recent_payments_per_customer() {
+        let store = store_with("review when velocity(customer, 1h) > 2").await;
+        let now = Utc::now();
+
+        // the first one falls outside the window by the time the last arrives
+        store.screen(&payment(10, "cust_1"), now - Duration::hours(2)).await;
+        store.screen(&payment(10, "cust_1"), now - Duration::minutes(10)).await;
+        let second = store.screen(&payment(10, "cust_1"), now - Duration::minutes(5)).await.unwrap();
+        let other_customer = store.screen(&payment(10, "cust_2"), now).await.unwrap();
+        let third = store.screen(&payment(10, "cust_1"), now).await.unwrap();
+
+        assert_eq!(second.action, RiskAction::Allow);
+        assert_eq!(other_customer.action, RiskAction::Allow);
+        assert_eq!(third.action, RiskAction::Review);
+    }
+
+    #[tokio::test]
+    async fn test_country_mismatch_and_blocklists() {
+        let store = store_with("review when country_mismatch\nblock when ip in blocklist").await;
+        store.add_to_blocklist(BlocklistKind::Ip, "10.0.0.1").await;
+
+        let abroad = payment(10, "cust_1").with_risk_context(RiskContext {
+            ip_address: None,
+            billing_country: Some("de".to_string()),
+            card_country: Some("US".to_string()),
+        });
+        let blocked = payment(10, "cust_1").with_risk_context(RiskContext {
+            ip_address: Some("10.0.0.1".to_string()),
+            billing_country: Some("US".to_string()),
+            card_country: Some("us".to_string()),
+        });
+
+        assert_eq!(store.screen(&abroad, Utc::now()).await.unwrap().action, RiskAction::Review);
+        assert_eq!(store.screen(&blocked, Utc::now()).await.unwrap().action, RiskAction::Block);
+
+        assert!(store.remove_from_blocklist(BlocklistKind::Ip, "10.0.0.1").await);
+        assert!(store.blocklist(BlocklistKind::Ip).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reviewed_payment_leaves_an_audit_trail() {
+        let store = store_with("review when amount > 100").await;
+        let event = payment(500, "cust_1");
+
+        store.screen(&event, Utc::now()).await.unwrap();
+        // redeliveries aren't screened twice
+        assert!(store.screen(&event, Utc::now()).await.is_none());
+        assert_eq!(store.pending_reviews().await.len(), 1);
+
+        let held = store.take_review(event.transaction_id).await.unwrap();
+        store.record_review(&held, RiskAction::Allow, "ana".to_string(), Some("known customer".to_string())).await;
+
+        assert!(store.pending_reviews().await.is_empty());
+        assert!(matches!(store.take_review(event.transaction_id).await, Err(RiskError::NotUnderReview(_))));
+
+        let trail = store.decisions(event.transaction_id).await;
+        assert_eq!(trail.len(), 2);
+        assert_eq!(trail[0].action, RiskAction::Review);
+        assert_eq!(trail[0].source, RiskDecisionSource::Engine);
+        assert_eq!(trail[1].source, RiskDecisionSource::Manual { reviewer: "ana".to_string() });
+        assert_eq!(trail[1].reasons, vec!["known customer".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_allow_rules_are_rejected() {
+        let store = store_with("block when customer in blocklist").await;
+
+        let error = store.set_rules("block when customer in blocklist\nallow when amount < 1000").await.unwrap_err();
+
+        assert!(matches!(error, RiskError::InvalidRule(2, _)));
+        assert_eq!(store.rules().await, vec!["block when customer in blocklist".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_decisions_are_forgotten_after_the_review_sla() {
+        let store = RiskStore::new().with_review_sla(Duration::hours(1));
+        store.set_rules("review when amount > 100\nreview when velocity(customer, 10m) > 5").await.unwrap();
+        let start = Utc::now();
+        let allowed = payment(50, "cust_1");
+        let held = payment(500, "cust_2");
+
+        store.screen(&allowed, start).await.unwrap();
+        store.screen(&held, start).await.unwrap();
+        store.screen(&payment(50, "cust_3"), start + Duration::hours(2)).await.unwrap();
+
+        assert!(store.decisions(allowed.transaction_id).await.is_empty());
+        // still waiting for a reviewer, the sweeper fails it
+        assert_eq!(store.decisions(held.transaction_id).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_reviews_past_the_sla_cant_be_taken() {
+        let store = RiskStore::new().with_review_sla(Duration::hours(1));
+        store.set_rules("review when amount > 100").await.unwrap();
+        let mut event = payment(500, "cust_1");
+        event.timestamp = Utc::now() - Duration::hours(2);
+
+        store.screen(&event, Utc::now()).await.unwrap();
+
+        assert!(store.pending_reviews().await.is_empty());
+        assert!(matches!(store.take_review(event.transaction_id).await, Err(RiskError::ReviewExpired(_))));
+    }
+}
@@ -0,0 +1,132 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, Days, Duration, Months, NaiveTime, Timelike, Utc};
+
+// a schedule that can't fire within this many years never will, e.g. the 31st of february
+const SEARCH_YEARS: i32 = 5;
+
+/// A five field cron expression, `minute hour day-of-month month day-of-week`, in UTC.
+/// Fields take `*`, numbers, `a-b` ranges, `/n` steps and comma separated lists of them.
+/// Sunday is 0 or 7. As in cron, when both day fields are restricted either one matching is enough.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    any_day_of_month: bool,
+    any_day_of_week: bool,
+}
+
+impl FromStr for CronSchedule {
+    type Err = String;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day_of_month, month, day_of_week] = fields[..] else {
+            return Err(format!("{} should have 5 fields: minute hour day-of-month month day-of-week", expression));
+        };
+
+        let mut days_of_week = parse_field(day_of_week, 0, 7)?;
+        // 7 is another name for sunday
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week = (days_of_week | 1) & !(1 << 7);
+        }
+
+        Ok(Self {
+            minutes: parse_field(minute, 0, 59)?,
+            hours: parse_field(hour, 0, 23)?,
+            days_of_month: parse_field(day_of_month, 1, 31)?,
+            months: parse_field(month, 1, 12)?,
+            days_of_week,
+            any_day_of_month: day_of_month == "*",
+            any_day_of_week: day_of_week == "*",
+        })
+    }
+}
+
+impl CronSchedule {
+    /// The first minute strictly after `after` the schedule fires at.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let give_up = start.year() + SEARCH_YEARS;
+
+        let mut at = start;
+        while at.year() < give_up {
+            let midnight = at.date_naive().and_time(NaiveTime::MIN).and_utc();
+
+            if !contains(self.months, at.month()) {
+                let first = at.date_naive().with_day(1)? + Months::new(1);
+                at = first.and_time(NaiveTime::MIN).and_utc();
+            } else if !self.day_matches(at) {
+                at = midnight + Days::new(1);
+            } else if !contains(self.hours, at.hour()) {
+                at = at.with_minute(0)? + Duration::hours(1);
+            } else if !contains(self.minutes, at.minute()) {
+                at += Duration::minutes(1);
+            } else {
+                return Some(at);
+            }
+        }
+
+        None
+    }
+
+    fn day_matches(&self, at: DateTime<Utc>) -> bool {
+        let day_of_month = contains(self.days_of_month, at.day());
+        let day_of_week = contains(self.days_of_week, at.weekday().num_days_from_sunday());
+
+        match (self.any_day_of_month, self.any_day_of_week) {
+            (false, false) => day_of_month || day_of_week,
+            _ => day_of_month && day_of_week,
+        }
+    }
+}
+
+fn contains(set: u64, value: u32) -> bool {
+    set & (1 << value) != 0
+}
+
+/* the values a field allows as a bit set */
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut set = 0;
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step.parse().map_err(|_| format!("{} has an invalid step", part))?;
+                if step == 0 {
+                    return Err(format!("{} has a step of 0", part));
+                }
+                (range, step)
+            }
+            None => (part, 1),
+        };
+
+        let number = |value: &str| -> Result<u32, String> {
+            match value.parse::<u32>() {
+                Ok(n) if (min..=max).contains(&n) => Ok(n),
+                _ => Err(format!("{} is not a number between {} and {}", value, min, max)),
+            }
+        };
+        let (from, to) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((from, to)) => (number(from)?, number(to)?),
+                // `5/15` runs from 5 to the end of the field
+                None if step > 1 => (number(range)?, max),
+                None => (number(range)?, number(range)?),
+            },
+        };
+        if from > to {
+            return Err(format!("{} is an empty range", part));
+        }
+
+        for value in (from..=to).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+
+    Ok(set)
+}
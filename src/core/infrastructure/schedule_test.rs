@@ -0,0 +1,52 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, TimeZone, Utc};
+
+    use crate::core::infrastructure::schedule::CronSchedule;
+
+    fn at(month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, month, day, hour, minute, 0).unwrap()
+    }
+
+    fn next(expression: &str, after: DateTime<Utc>) -> DateTime<Utc> {
+        expression.parse::<CronSchedule>().unwrap().next_after(after).unwrap()
+    }
+
+    #[test]
+    fn test_next_run_times() {
+        // 06:00 on the 1st of every month
+        assert_eq!(next("0 6 1 * *", at(3, 13, 6, 30)), at(4, 1, 6, 0));
+        // strictly after, a run on the exact minute is the next one
+        assert_eq!(next("0 6 1 * *", at(4, 1, 6, 0)), at(5, 1, 6, 0));
+        // every 15 minutes
+        assert_eq!(next("*/15 * * * *", at(3, 13, 6, 31)), at(3, 13, 6, 45));
+        // weekdays at 02:30, 2024-03-16 is a saturday
+        assert_eq!(next("30 2 * * 1-5", at(3, 15, 3, 0)), at(3, 18, 2, 30));
+        // sunday as 7
+        assert_eq!(next("0 0 * * 7", at(3, 13, 0, 0)), at(3, 17, 0, 0));
+        // lists, and the end of the year
+        assert_eq!(next("0 8,20 31 12 *", at(12, 31, 9, 0)), at(12, 31, 20, 0));
+    }
+
+    #[test]
+    fn test_either_day_field_matches_when_both_are_restricted() {
+        // the 15th, or any monday; 2024-03-11 is a monday
+        assert_eq!(next("0 0 15 * 1", at(3, 9, 0, 0)), at(3, 11, 0, 0));
+        assert_eq!(next("0 0 15 * 1", at(3, 11, 0, 0)), at(3, 15, 0, 0));
+    }
+
+    #[test]
+    fn test_impossible_schedules_never_fire() {
+        let schedule: CronSchedule = "0 0 31 2 *".parse().unwrap();
+        assert_eq!(schedule.next_after(at(1, 1, 0, 0)), None);
+    }
+
+    #[test]
+    fn test_invalid_expressions_are_rejected() {
+        assert!("0 6 1 *".parse::<CronSchedule>().unwrap_err().contains("5 fields"));
+        assert!("60 * * * *".parse::<CronSchedule>().is_err());
+        assert!("*/0 * * * *".parse::<CronSchedule>().is_err());
+        assert!("0 9-5 * * *".parse::<CronSchedule>().is_err());
+        assert!("0 0 0 * *".parse::<CronSchedule>().is_err());
+    }
+}
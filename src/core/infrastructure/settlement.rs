@@ -0,0 +1,355 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use chrono::{DateTime, Days, NaiveDate, Timelike, Utc};
+use thiserror::Error;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::core::{
+    events::{FeeAssessedEvent, RefundCreatedEvent},
+    infrastructure::pricing::currency_key,
+    models::{Payout, PayoutStatus, SettlementBatch, SettlementItem, SettlementItemKind, SettlementStatus},
+};
+
+#[derive(Debug, Error)]
+pub enum SettlementError {
+    #[error("Payout {0} not found")]
+    UnknownPayout(Uuid),
+    #[error("Payout {0} is already {1}")]
+    PayoutFinalised(Uuid, String),
+}
+
+#[derive(Debug, Clone)]
+pub struct SettlementConfig {
+    // days between a batch's settlement date and its payout, 2 for T+2
+    pub delay_days: u64,
+    // utc hour a day's batch closes at, later items settle with the next day
+    pub cutoff_hour: u32,
+}
+
+impl Default for SettlementConfig {
+    fn default() -> Self {
+        Self { delay_days: 2, cutoff_hour: 0 }
+    }
+}
+
+impl SettlementConfig {
+    pub fn new(delay_days: u64) -> Self {
+        Self { delay_days, ..Self::default() }
+    }
+
+    pub fn with_cutoff_hour(mut self, cutoff_hour: u32) -> Self {
+        self.cutoff_hour = cutoff_hour;
+        self
+    }
+
+    /// The day whose batch something happening at `at` belongs to.
+    pub fn settlement_date(&self, at: DateTime<Utc>) -> NaiveDate {
+        let date = at.date_naive();
+        if self.cutoff_hour > 0 && at.hour() >= self.cutoff_hour {
+            date.succ_opt().expect("settlement date out of range")
+        } else {
+            date
+        }
+    }
+
+    pub fn available_on(&self, settlement_date: NaiveDate) -> NaiveDate {
+        settlement_date
+            .checked_add_days(Days::new(self.delay_days))
+            .expect("settlement date out of range")
+    }
+}
+
+// merchant, currency
+type BalanceKey = (String, String);
+
+#[derive(Default)]
+struct SettlementState {
+    batches: HashMap<Uuid, SettlementBatch>,
+    // (merchant, currency, day) -> batch still collecting
+    open: HashMap<(String, String, NaiveDate), Uuid>,
+    payouts: HashMap<Uuid, Payout>,
+    // negative nets and failed payouts waiting for the next batch
+    carried: HashMap<BalanceKey, i64>,
+    // events already settled, redeliveries are ignored
+    sources: HashSet<Uuid>,
+}
+
+// In production this would live in the database, for now it's kept in memory
+#[derive(Clone, Default)]
+pub struct SettlementStore {
+    config: SettlementConfig,
+    state: Arc<RwLock<SettlementState>>,
+}
+
+impl SettlementStore {
+    pub fn new(config: SettlementConfig) -> Self {
+        Self {
+            config,
+            state: Arc::default(),
+        }
+    }
+
+    /// Adds a completed payment, net of its fee, to the batch of the day it completed.
+    pub async fn add_payment(&self, event: &FeeAssessedEvent) {
+        let item = SettlementItem {
+            transaction_id: event.transaction_id,
+            kind: SettlementItemKind::Payment,
+            amount: event.amount,
+            fee: event.fee.total,
+            net: event.amount as i64 - event.fee.total as i64,
+            occurred_at: event.timestamp,
+        };
+
+        self.add_item(event.event_id, &event.merchant_id, &event.currency, item).await;
+    }
+
+    /// Refunds come out of the batch of the day they were issued, not the original payment's.
+    pub async fn add_refund(&self, event: &RefundCreatedEvent) {
+        // split payments are reversed from the sellers' connected accounts, not from a payout
+        if !event.reversals.is_empty() {
+            return;
+        }
+
+        let item = SettlementItem {
+            transaction_id: event.transaction_id,
+            kind: SettlementItemKind::Refund,
+            amount: event.amount,
+            fee: 0,
+            net: -(event.amount as i64),
+            occurred_at: event.timestamp,
+        };
+
+        self.add_item(event.event_id, &event.merchant_id, &event.currency, item).await;
+    }
+
+    async fn add_item(&self, source: Uuid, merchant_id: &str, currency: &str, item: SettlementItem) {
+        let mut state = self.state.write().await;
+        if !state.sources.insert(source) {
+            return;
+        }
+
+        let currency = currency_key(currency);
+        let today = self.config.settlement_date(Utc::now());
+        // a day that was already paid out can't take more items, they land in today's batch
+        let mut date = self.config.settlement_date(item.occurred_at);
+        if date < today && !state.open.contains_key(&(merchant_id.to_string(), currency.clone(), date)) {
+            let released = state.batches.values().any(|b| {
+                b.merchant_id == merchant_id && b.currency == currency && b.settlement_date == date
+            });
+            if released {
+                date = today;
+            }
+        }
+
+        let key = (merchant_id.to_string(), currency.clone(), date);
+        let batch_id = match state.open.get(&key) {
+            Some(id) => *id,
+            None => {
+                let batch = SettlementBatch {
+                    id: Uuid::new_v4(),
+                    merchant_id: merchant_id.to_string(),
+                    currency,
+                    settlement_date: date,
+                    available_on: self.config.available_on(date),
+                    items: Vec::new(),
+                    gross: 0,
+                    fees: 0,
+                    refunds: 0,
+                    net: 0,
+                    carried_in: 0,
+                    status: SettlementStatus::Open,
+                    payout_id: None,
+                    created_at: Utc::now(),
+                    released_at: None,
+                };
+                let id = batch.id;
+                state.batches.insert(id, batch);
+                state.open.insert(key, id);
+                id
+            }
+        };
+
+        let batch = state.batches.get_mut(&batch_id).expect("open batch is stored");
+        match item.kind {
+            SettlementItemKind::Payment => {
+                batch.gross += item.amount;
+                batch.fees += item.fee;
+            }
+            SettlementItemKind::Refund => batch.refunds += item.amount,
+        }
+        batch.net += item.net;
+        batch.items.push(item);
+    }
+
+    /// Closes every open batch whose payout date has come and creates its payout.
+    /// Batches that net to nothing carry their balance into the merchant's next one.
+    pub async fn release_due(&self, now: DateTime<Utc>) -> Vec<(Payout, SettlementBatch)> {
+        let mut state = self.state.write().await;
+        let today = self.config.settlement_date(now);
+
+        let mut due: Vec<(String, String, NaiveDate)> = state
+            .open
+            .keys()
+            .filter(|(_, _, date)| *date < today && self.config.available_on(*date) <= today)
+            .cloned()
+            .collect();
+        // oldest first so balances carry forward in order
+        due.sort_by_key(|(_, _, date)| *date);
+
+        let mut released = Vec::new();
+        for key in due {
+            let batch_id = state.open.remove(&key).expect("due batch is open");
+            let balance_key = (key.0.clone(), key.1.clone());
+            let carried_in = state.carried.remove(&balance_key).unwrap_or(0);
+
+            let batch = state.batches.get_mut(&batch_id).expect("open batch is stored");
+            batch.carried_in = carried_in;
+            batch.released_at = Some(now);
+
+            let payable = batch.net + carried_in;
+            if payable <= 0 {
+                batch.status = SettlementStatus::CarriedForward;
+                if payable < 0 {
+                    state.carried.insert(balance_key, payable);
+                }
+                continue;
+            }
+
+            let payout = Payout {
+                id: Uuid::new_v4(),
+                batch_id,
+                merchant_id: batch.merchant_id.clone(),
+                currency: batch.currency.clone(),
+                amount: payable as u64,
+                status: PayoutStatus::Pending,
+                created_at: now,
+                updated_at: now,
+            };
+            batch.status = SettlementStatus::PaidOut;
+            batch.payout_id = Some(payout.id);
+
+            released.push((payout.clone(), batch.clone()));
+            state.payouts.insert(payout.id, payout);
+        }
+
+        released
+    }
+
+    /// Puts a released batch back, with the balance it carried in, and drops its payout.
+    /// Used when the payout couldn't be handed over, the batch is released again next time.
+    pub async fn reopen(&self, payout_id: Uuid) -> Result<SettlementBatch, SettlementError> {
+        let mut state = self.state.write().await;
+
+        let payout = state
+            .payouts
+            .remove(&payout_id)
+            .ok_or(SettlementError::UnknownPayout(payout_id))?;
+
+        let batch = state.batches.get_mut(&payout.batch_id).expect("paid out batch is stored");
+        let carried_in = std::mem::take(&mut batch.carried_in);
+        batch.status = SettlementStatus::Open;
+        batch.payout_id = None;
+        batch.released_at = None;
+        let batch = batch.clone();
+
+        let balance_key = (batch.merchant_id.clone(), batch.currency.clone());
+        if carried_in != 0 {
+            *state.carried.entry(balance_key.clone()).or_insert(0) += carried_in;
+        }
+        state.open.insert((balance_key.0, balance_key.1, batch.settlement_date), batch.id);
+
+        Ok(batch)
+    }
+
+    /// Records the bank's answer for a payout. A failed payout's amount goes back
+    /// into the merchant's balance and is retried with their next batch.
+    pub async fn update_payout(&self, payout_id: Uuid, status: PayoutStatus) -> Result<Payout, SettlementError> {
+        let mut state = self.state.write().await;
+
+        let payout = state
+            .payouts
+            .get_mut(&payout_id)
+            .ok_or(SettlementError::UnknownPayout(payout_id))?;
+
+        if payout.status != PayoutStatus::Pending {
+            return Err(SettlementError::PayoutFinalised(payout_id, format!("{:?}", payout.status)));
+        }
+
+        payout.status = status;
+        payout.updated_at = Utc::now();
+        let payout = payout.clone();
+
+        if matches!(payout.status, PayoutStatus::Failed { .. }) {
+            *state
+                .carried
+                .entry((payout.merchant_id.clone(), payout.currency.clone()))
+                .or_insert(0) += payout.amount as i64;
+        }
+
+        Ok(payout)
+    }
+
+    pub async fn batch(&self, id: Uuid) -> Option<SettlementBatch> {
+        self.state.read().await.batches.get(&id).cloned()
+    }
+
+    pub async fn payout(&self, id: Uuid) -> Option<Payout> {
+        self.state.read().await.payouts.get(&id).cloned()
+    }
+
+    /// The merchant's batches, newest settlement date first.
+    pub async fn batches_for_merchant(&self, merchant_id: &str, currency: Option<&str>) -> Vec<SettlementBatch> {
+        let currency = currency.map(currency_key);
+        let mut batches: Vec<SettlementBatch> = self
+            .state
+            .read()
+            .await
+            .batches
+            .values()
+            .filter(|b| b.merchant_id == merchant_id)
+            .filter(|b| currency.as_ref().map(|c| &b.currency == c).unwrap_or(true))
+            .cloned()
+            .collect();
+
+        batches.sort_by_key(|b| std::cmp::Reverse(b.settlement_date));
+        batches
+    }
+
+    /// Up to `limit` batches settling within `[from, to)`, of one merchant or all of them, oldest
+    /// first. Pass the last batch of a page as `after` for the next one.
+    pub async fn batches_between(
+        &self,
+        merchant_id: Option<&str>,
+        from: NaiveDate,
+        to: NaiveDate,
+        after: Option<&SettlementBatch>,
+        limit: usize,
+    ) -> Vec<SettlementBatch> {
+        let state = self.state.read().await;
+        let mut batches: Vec<&SettlementBatch> = state
+            .batches
+            .values()
+            .filter(|b| merchant_id.is_none_or(|m| b.merchant_id == m))
+            .filter(|b| b.settlement_date >= from && b.settlement_date < to)
+            .filter(|b| after.is_none_or(|a| report_order(b) > report_order(a)))
+            .collect();
+
+        batches.sort_by(|a, b| report_order(a).cmp(&report_order(b)));
+        batches.into_iter().take(limit).cloned().collect()
+    }
+
+    /// Balance carried into the merchant's next payout, negative when they owe us.
+    pub async fn carried_balance(&self, merchant_id: &str, currency: &str) -> i64 {
+        let key = (merchant_id.to_string(), currency_key(currency));
+        self.state.read().await.carried.get(&key).copied().unwrap_or(0)
+    }
+}
+
+// the order reports list batches in, the id keeps pages from skipping or repeating one
+fn report_order(batch: &SettlementBatch) -> (NaiveDate, &str, &str, Uuid) {
+    (batch.settlement_date, &batch.merchant_id, &batch.currency, batch.id)
+}
@@ -0,0 +1,134 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+    use uuid::Uuid;
+
+    use crate::core::{
+        events::{FeeAssessedEvent, RefundCreatedEvent},
+        infrastructure::settlement::{SettlementConfig, SettlementStore},
+        models::{FeeBreakdown, PayoutStatus, SettlementStatus},
+    };
+
+    fn assessed(amount: u64, fee: u64, days_ago: i64) -> FeeAssessedEvent {
+        let breakdown = FeeBreakdown { percentage_bps: 0, percentage_fee: 0, fixed_fee: fee, total: fee, volume_tier: None };
+        let mut event = FeeAssessedEvent::new(Uuid::new_v4(), "merch_123".to_string(), amount, "USD".to_string(), breakdown);
+        event.timestamp = Utc::now() - Duration::days(days_ago);
+        event
+    }
+
+    fn refund(transaction_id: Uuid, amount: u64, days_ago: i64) -> RefundCreatedEvent {
+        let mut event = RefundCreatedEvent::new(transaction_id, "merch_123".to_string(), amount, "USD".to_string(), None);
+        event.timestamp = Utc::now() - Duration::days(days_ago);
+        event
+    }
+
+    #[tokio::test]
+    async fn test_batch_nets_fees_and_refunds() {
+        let store = SettlementStore::new(SettlementConfig::default());
+        let payment = assessed(1000, 50, 0);
+
+        store.add_payment(&payment).await;
+        store.add_payment(&assessed(2000, 80, 0)).await;
+        store.add_refund(&refund(payment.transaction_id, 300, 0)).await;
+
+        let batches = store.batches_for_merchant("merch_123", None).await;
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].gross, 3000);
+        assert_eq!(batches[0].fees, 130);
+        assert_eq!(batches[0].refunds, 300);
+        assert_eq!(batches[0].net, 2570);
+        assert_eq!(batches[0].items.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_batch_is_paid_out_after_delay() {
+        let store = SettlementStore::new(SettlementConfig::new(2));
+
+        store.add_payment(&assessed(1000, 50, 1)).await;
+        assert!(store.release_due(Utc::now()).await.is_empty());
+
+        let released = store.release_due(Utc::now() + Duration::days(1)).await;
+        assert_eq!(released.len(), 1);
+
+        let (payout, batch) = &released[0];
+        assert_eq!(payout.amount, 950);
+        assert_eq!(payout.status, PayoutStatus::Pending);
+        assert_eq!(batch.status, SettlementStatus::PaidOut);
+        assert_eq!(batch.payout_id, Some(payout.id));
+    }
+
+    #[tokio::test]
+    async fn test_negative_batch_carries_forward() {
+        let store = SettlementStore::new(SettlementConfig::new(0));
+        let payment = assessed(1000, 0, 3);
+
+        store.add_refund(&refund(payment.transaction_id, 400, 2)).await;
+        store.add_payment(&assessed(1000, 0, 1)).await;
+
+        let released = store.release_due(Utc::now()).await;
+
+        assert_eq!(released.len(), 1);
+        assert_eq!(released[0].0.amount, 600);
+        assert_eq!(released[0].1.carried_in, -400);
+        assert_eq!(store.carried_balance("merch_123", "USD").await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_failed_payout_is_retried_with_next_batch() {
+        let store = SettlementStore::new(SettlementConfig::new(0));
+
+        store.add_payment(&assessed(1000, 0, 2)).await;
+        let (payout, _) = store.release_due(Utc::now()).await.remove(0);
+
+        store.update_payout(payout.id, PayoutStatus::Failed { reason: "account closed".to_string() }).await.unwrap();
+        assert!(store.update_payout(payout.id, PayoutStatus::Paid).await.is_err());
+
+        store.add_payment(&assessed(500, 0, 1)).await;
+        let released = store.release_due(Utc::now()).await;
+
+        assert_eq!(released[0].0.amount, 1500);
+    }
+
+    #[tokio::test]
+    async fn test_reopened_batch_is_released_again() {
+        let store = SettlementStore::new(SettlementConfig::new(0));
+        let payment = assessed(1000, 0, 3);
+
+        store.add_refund(&refund(payment.transaction_id, 400, 2)).await;
+        store.add_payment(&assessed(1000, 0, 1)).await;
+        let (payout, batch) = store.release_due(Utc::now()).await.remove(0);
+
+        let reopened = store.reopen(payout.id).await.unwrap();
+        assert_eq!(reopened.status, SettlementStatus::Open);
+        assert_eq!(reopened.payout_id, None);
+        assert!(store.payout(payout.id).await.is_none());
+        assert!(store.reopen(payout.id).await.is_err());
+
+        let released = store.release_due(Utc::now()).await;
+        assert_eq!(released.len(), 1);
+        assert_eq!(released[0].1.id, batch.id);
+        assert_eq!(released[0].0.amount, 600);
+    }
+
+    #[tokio::test]
+    async fn test_redelivered_payment_is_settled_once() {
+        let store = SettlementStore::new(SettlementConfig::default());
+        let payment = assessed(1000, 50, 0);
+
+        store.add_payment(&payment).await;
+        store.add_payment(&payment).await;
+
+        assert_eq!(store.batches_for_merchant("merch_123", Some("usd")).await[0].items.len(), 1);
+    }
+
+    #[test]
+    fn test_items_after_the_cutoff_settle_with_the_next_day() {
+        let config = SettlementConfig::new(2).with_cutoff_hour(18);
+        let before = Utc.with_ymd_and_hms(2024, 3, 1, 17, 59, 0).unwrap();
+        let after = Utc.with_ymd_and_hms(2024, 3, 1, 18, 0, 0).unwrap();
+
+        assert_eq!(config.settlement_date(before), before.date_naive());
+        assert_eq!(config.settlement_date(after), before.date_naive().succ_opt().unwrap());
+        assert_eq!(SettlementConfig::default().settlement_date(after), after.date_naive());
+    }
+}
@@ -0,0 +1,95 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+use rdkafka::consumer::{CommitMode, Consumer};
+use tokio::sync::watch;
+
+/* handed to every loop of a process, once triggered they finish what they are doing and return */
+#[derive(Clone)]
+pub struct Shutdown {
+    sender: Arc<watch::Sender<bool>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        let (sender, _) = watch::channel(false);
+        Self { sender: Arc::new(sender) }
+    }
+
+    pub fn trigger(&self) {
+        self.sender.send_replace(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.sender.borrow()
+    }
+
+    /// Resolves once shutdown has begun, straight away if it already has.
+    pub async fn wait(&self) {
+        let mut receiver = self.sender.subscribe();
+        // the sender lives as long as self, so this can't fail
+        let _ = receiver.wait_for(|stopping| *stopping).await;
+    }
+
+    /// Triggers the shutdown on SIGTERM or ctrl-c.
+    pub fn trigger_on_signal(&self) {
+        let shutdown = self.clone();
+        tokio::spawn(async move {
+            termination_signal().await;
+            println!("Shutdown requested, draining...");
+            shutdown.trigger();
+        });
+    }
+
+    /// Runs `work` to completion, but gives it at most `grace` once shutdown begins.
+    /// `None` means the deadline passed first and whatever was still running got dropped.
+    pub async fn drain<F: Future>(&self, work: F, grace: Duration) -> Option<F::Output> {
+        tokio::pin!(work);
+        tokio::select! {
+            output = &mut work => return Some(output),
+            _ = self.wait() => {}
+        }
+
+        match tokio::time::timeout(grace, work).await {
+            Ok(output) => Some(output),
+            Err(_) => {
+                eprintln!("Shutdown deadline of {}s passed, giving up on what is still running", grace.as_secs());
+                None
+            }
+        }
+    }
+}
+
+async fn termination_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.expect("Failed to listen for ctrl-c");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+/// Commits what a stopping consumer has handled so its group picks up right after it.
+pub fn commit_offsets<C: Consumer>(consumer_name: &str, consumer: &C) {
+    match consumer.commit_consumer_state(CommitMode::Sync) {
+        Ok(()) => println!("{} committed its offsets", consumer_name),
+        Err(e) => eprintln!("{} failed to commit its offsets: {}", consumer_name, e),
+    }
+}
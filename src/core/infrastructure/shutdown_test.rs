@@ -0,0 +1,48 @@
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::core::infrastructure::shutdown::Shutdown;
+
+    #[tokio::test]
+    async fn test_every_clone_sees_the_trigger() {
+        let shutdown = Shutdown::new();
+        let waiting = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.wait().await }
+        });
+
+        shutdown.clone().trigger();
+
+        tokio::time::timeout(Duration::from_secs(1), waiting).await.unwrap().unwrap();
+        assert!(shutdown.is_triggered());
+        // late waiters don't hang either
+        tokio::time::timeout(Duration::from_secs(1), shutdown.wait()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_drain_lets_work_finish_after_the_trigger() {
+        let shutdown = Shutdown::new();
+        let stopping = shutdown.clone();
+        let work = async move {
+            stopping.wait().await;
+            // the message being handled when the signal came
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            "committed"
+        };
+
+        shutdown.trigger();
+
+        assert_eq!(shutdown.drain(work, Duration::from_secs(5)).await, Some("committed"));
+    }
+
+    #[tokio::test]
+    async fn test_drain_gives_up_at_the_deadline() {
+        let shutdown = Shutdown::new();
+        shutdown.trigger();
+
+        let drained = shutdown.drain(tokio::time::sleep(Duration::from_secs(60)), Duration::from_millis(50)).await;
+
+        assert_eq!(drained, None);
+    }
+}
@@ -0,0 +1,129 @@
+use std::{collections::HashMap, sync::Arc};
+
+use tokio::sync::RwLock;
+
+use crate::core::models::{ConnectedAccount, SplitLeg, SplitReversal};
+
+const BPS_DENOMINATOR: u64 = 10_000;
+
+/* a seller's share as the merchant asked for it, before it is turned into an amount */
+#[derive(Debug, Clone)]
+pub struct SplitShare {
+    pub destination_merchant_id: String,
+    pub amount: Option<u64>,
+    pub percentage_bps: Option<u32>,
+    pub application_fee: u64,
+}
+
+/// Turns the requested shares into amounts that add up to exactly `total`.
+/// Rounding dust from percentage shares goes to the last percentage share.
+pub fn allocate_splits(total: u64, shares: &[SplitShare]) -> Result<Vec<u64>, String> {
+    let mut amounts = Vec::with_capacity(shares.len());
+    let mut last_percentage = None;
+
+    for (index, share) in shares.iter().enumerate() {
+        let amount = match (share.amount, share.percentage_bps) {
+            (Some(amount), None) => amount,
+            (None, Some(bps)) if bps as u64 <= BPS_DENOMINATOR => {
+                last_percentage = Some(index);
+                (total as u128 * bps as u128 / BPS_DENOMINATOR as u128) as u64
+            }
+            (None, Some(_)) => return Err("split percentage_bps can't exceed 10000".to_string()),
+            _ => {
+                return Err(format!(
+                    "split for {} needs exactly one of amount or percentage_bps",
+                    share.destination_merchant_id
+                ))
+            }
+        };
+
+        if amount == 0 {
+            return Err(format!("split for {} is empty", share.destination_merchant_id));
+        }
+        if share.application_fee > amount {
+            return Err(format!("application fee for {} exceeds its split", share.destination_merchant_id));
+        }
+        amounts.push(amount);
+    }
+
+    let allocated: u64 = amounts.iter().sum();
+    let dust = total.checked_sub(allocated).unwrap_or(u64::MAX);
+
+    match last_percentage {
+        // flooring loses less than a minor unit per percentage share
+        Some(index) if dust < shares.len() as u64 => amounts[index] += dust,
+        _ if dust == 0 => {}
+        _ => return Err(format!("splits add up to {}, expected {}", allocated, total)),
+    }
+
+    Ok(amounts)
+}
+
+/// Shares a refund out over the legs in proportion to what each leg received,
+/// each leg's part split again between the seller and our application fee.
+pub fn reverse_proportionally(legs: &[SplitLeg], total: u64, refund: u64) -> Vec<SplitReversal> {
+    let mut remaining = refund;
+
+    legs.iter()
+        .enumerate()
+        .map(|(index, leg)| {
+            let share = if index == legs.len() - 1 {
+                remaining
+            } else {
+                (refund as u128 * leg.amount as u128 / total.max(1) as u128) as u64
+            };
+            remaining -= share;
+
+            let application_fee_refund = (share as u128 * leg.application_fee as u128 / leg.amount.max(1) as u128) as u64;
+
+            SplitReversal {
+                destination_merchant_id: leg.destination_merchant_id.clone(),
+                provider_account_id: leg.provider_account_id.clone(),
+                transfer_reversal: share - application_fee_refund,
+                application_fee_refund,
+            }
+        })
+        .collect()
+}
+
+/// The transfer each seller's part of a refund comes back from, with the amount to reverse.
+/// Fails when a seller with something to give back hasn't been paid yet, so it can be checked
+/// before the customer is refunded.
+pub fn transfers_to_reverse(legs: &[SplitLeg], reversals: &[SplitReversal]) -> Result<Vec<(String, u64)>, String> {
+    reversals
+        .iter()
+        .filter(|reversal| reversal.transfer_reversal > 0)
+        .map(|reversal| {
+            legs.iter()
+                .find(|leg| leg.provider_account_id == reversal.provider_account_id)
+                .and_then(|leg| leg.transfer_id.clone())
+                .map(|transfer_id| (transfer_id, reversal.transfer_reversal))
+                .ok_or_else(|| format!("{} hasn't been paid their share yet, try again once it is", reversal.destination_merchant_id))
+        })
+        .collect()
+}
+
+/// Whether every seller with something coming has been sent their share.
+pub fn sellers_paid(legs: &[SplitLeg]) -> bool {
+    legs.iter().all(|leg| leg.transfer_id.is_some() || leg.seller_amount() == 0)
+}
+
+// In production this would live in the database, for now it's kept in memory
+#[derive(Clone, Default)]
+pub struct ConnectedAccountStore {
+    accounts: Arc<RwLock<HashMap<String, ConnectedAccount>>>,
+}
+
+impl ConnectedAccountStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn save(&self, account: ConnectedAccount) {
+        self.accounts.write().await.insert(account.merchant_id.clone(), account);
+    }
+
+    pub async fn get(&self, merchant_id: &str) -> Option<ConnectedAccount> {
+        self.accounts.read().await.get(merchant_id).cloned()
+    }
+}
@@ -0,0 +1,93 @@
+#[cfg(test)]
+mod tests {
+    use crate::core::{
+        infrastructure::splits::{allocate_splits, reverse_proportionally, sellers_paid, transfers_to_reverse, SplitShare},
+        models::SplitLeg,
+    };
+
+    fn by_amount(merchant_id: &str, amount: u64, application_fee: u64) -> SplitShare {
+        SplitShare {
+            destination_merchant_id: merchant_id.to_string(),
+            amount: Some(amount),
+            percentage_bps: None,
+            application_fee,
+        }
+    }
+
+    fn by_percentage(merchant_id: &str, bps: u32) -> SplitShare {
+        SplitShare {
+            destination_merchant_id: merchant_id.to_string(),
+            amount: None,
+            percentage_bps: Some(bps),
+            application_fee: 0,
+        }
+    }
+
+    fn leg(merchant_id: &str, amount: u64, application_fee: u64) -> SplitLeg {
+        SplitLeg {
+            destination_merchant_id: merchant_id.to_string(),
+            provider_account_id: format!("acct_{}", merchant_id),
+            amount,
+            application_fee,
+            transfer_id: None,
+        }
+    }
+
+    #[test]
+    fn test_amount_splits_must_cover_the_total() {
+        let shares = vec![by_amount("seller_a", 700, 50), by_amount("seller_b", 300, 0)];
+        assert_eq!(allocate_splits(1000, &shares).unwrap(), vec![700, 300]);
+
+        assert!(allocate_splits(1200, &shares).is_err());
+    }
+
+    #[test]
+    fn test_percentage_dust_goes_to_last_percentage_share() {
+        let shares = vec![by_percentage("seller_a", 3333), by_percentage("seller_b", 3333), by_percentage("seller_c", 3334)];
+
+        let amounts = allocate_splits(1001, &shares).unwrap();
+
+        assert_eq!(amounts, vec![333, 333, 335]);
+        assert_eq!(amounts.iter().sum::<u64>(), 1001);
+    }
+
+    #[test]
+    fn test_invalid_shares_are_rejected() {
+        assert!(allocate_splits(1000, &[by_amount("seller_a", 500, 600), by_amount("seller_b", 500, 0)]).is_err());
+        assert!(allocate_splits(1000, &[by_amount("seller_a", 0, 0), by_amount("seller_b", 1000, 0)]).is_err());
+        assert!(allocate_splits(1000, &[by_percentage("seller_a", 12_000)]).is_err());
+
+        let both = SplitShare { percentage_bps: Some(5000), ..by_amount("seller_a", 500, 0) };
+        assert!(allocate_splits(1000, &[both]).is_err());
+    }
+
+    #[test]
+    fn test_refund_is_shared_in_proportion_to_the_legs() {
+        let legs = vec![leg("seller_a", 600, 60), leg("seller_b", 400, 40)];
+
+        let reversals = reverse_proportionally(&legs, 1000, 333);
+        let refunded: u64 = reversals.iter().map(|r| r.transfer_reversal + r.application_fee_refund).sum();
+
+        assert_eq!(refunded, 333);
+        assert_eq!(reversals[0].transfer_reversal + reversals[0].application_fee_refund, 199);
+        assert_eq!(reversals[0].application_fee_refund, 19);
+        assert_eq!(reversals[1].transfer_reversal + reversals[1].application_fee_refund, 134);
+    }
+
+    #[test]
+    fn test_a_refund_needs_every_seller_paid_first() {
+        let mut legs = vec![leg("seller_a", 600, 60), leg("seller_b", 400, 40)];
+        legs[0].transfer_id = Some("tr_a".to_string());
+        let reversals = reverse_proportionally(&legs, 1000, 500);
+
+        assert!(!sellers_paid(&legs));
+        assert!(transfers_to_reverse(&legs, &reversals).unwrap_err().contains("seller_b"));
+
+        legs[1].transfer_id = Some("tr_b".to_string());
+        assert!(sellers_paid(&legs));
+        assert_eq!(
+            transfers_to_reverse(&legs, &reversals).unwrap(),
+            vec![("tr_a".to_string(), reversals[0].transfer_reversal), ("tr_b".to_string(), reversals[1].transfer_reversal)]
+        );
+    }
+}
@@ -0,0 +1,192 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::core::{
+    events::PaymentStatusUpdatedEvent,
+    infrastructure::shutdown::Shutdown,
+    models::{Transaction, TransactionStatus},
+};
+
+// how far back a reconnecting client can resume from, across all transactions
+pub const REPLAY_CAPACITY: usize = 4096;
+// a subscriber further behind than this has missed changes and is dropped, it resumes from the replay
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// A status a transaction moved to, as pushed to the clients following it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct StatusChange {
+    // what clients send back as `Last-Event-ID` to resume
+    pub sequence: u64,
+    pub transaction_id: Uuid,
+    pub merchant_id: String,
+    pub status: TransactionStatus,
+    pub provider_payment_id: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl StatusChange {
+    /* where a transaction stands right now, for clients that can't be caught up from the replay */
+    pub fn snapshot(transaction: &Transaction, sequence: u64) -> Self {
+        Self {
+            sequence,
+            transaction_id: transaction.id,
+            merchant_id: transaction.merchant_id.clone(),
+            status: transaction.status.clone(),
+            provider_payment_id: transaction.provider_payment_id.clone(),
+            timestamp: transaction.update_at,
+        }
+    }
+}
+
+/* in-process fan out of status changes, the status consumer publishes and every open stream subscribes */
+#[derive(Clone)]
+pub struct StatusHub {
+    inner: Arc<HubInner>,
+}
+
+struct HubInner {
+    sender: broadcast::Sender<StatusChange>,
+    // sequences are handed out under this lock so the replay and the channel agree on the order
+    recent: Mutex<Replay>,
+    closed: Shutdown,
+}
+
+struct Replay {
+    last_sequence: u64,
+    changes: VecDeque<StatusChange>,
+}
+
+impl Default for StatusHub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StatusHub {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        // seeded from the clock so ids keep growing across restarts and old ones never look new
+        let last_sequence = Utc::now().timestamp_micros().max(0) as u64;
+
+        Self {
+            inner: Arc::new(HubInner {
+                sender,
+                recent: Mutex::new(Replay { last_sequence, changes: VecDeque::new() }),
+                closed: Shutdown::new(),
+            }),
+        }
+    }
+
+    pub fn publish(&self, event: &PaymentStatusUpdatedEvent) -> StatusChange {
+        let mut replay = self.inner.recent.lock().expect("status hub lock poisoned");
+        replay.last_sequence += 1;
+
+        let change = StatusChange {
+            sequence: replay.last_sequence,
+            transaction_id: event.transaction_id,
+            merchant_id: event.merchant_id.clone(),
+            status: event.status.clone(),
+            provider_payment_id: Some(event.stripe_payment_id.clone()).filter(|id| !id.is_empty()),
+            timestamp: event.timestamp,
+        };
+
+        if replay.changes.len() == REPLAY_CAPACITY {
+            replay.changes.pop_front();
+        }
+        replay.changes.push_back(change.clone());
+        // nobody listening is fine
+        let _ = self.inner.sender.send(change.clone());
+
+        change
+    }
+
+    /// Follows one transaction. With `after`, the changes since that sequence are replayed first,
+    /// as long as the replay still reaches back that far and the hub handed that sequence out;
+    /// `resumed` says whether it did.
+    pub fn subscribe(&self, transaction_id: Uuid, after: Option<u64>) -> StatusSubscription {
+        let replay = self.inner.recent.lock().expect("status hub lock poisoned");
+        let receiver = self.inner.sender.subscribe();
+
+        // a sequence we never handed out comes from before a restart, the numbering started over
+        let resumed = after.is_some_and(|after| {
+            after <= replay.last_sequence
+                && match replay.changes.front() {
+                    Some(oldest) => oldest.sequence <= after + 1,
+                    None => after == replay.last_sequence,
+                }
+        });
+        let backlog = match after {
+            Some(after) if resumed => replay
+                .changes
+                .iter()
+                .filter(|change| change.transaction_id == transaction_id && change.sequence > after)
+                .cloned()
+                .collect(),
+            _ => VecDeque::new(),
+        };
+
+        StatusSubscription {
+            transaction_id,
+            position: replay.last_sequence,
+            resumed,
+            backlog,
+            receiver,
+            closed: self.inner.closed.clone(),
+        }
+    }
+
+    /// Ends every subscription, open streams close instead of holding the server up on shutdown.
+    pub fn close(&self) {
+        self.inner.closed.trigger();
+    }
+}
+
+pub struct StatusSubscription {
+    transaction_id: Uuid,
+    // the last sequence handed out when subscribing, everything after it comes through the channel
+    pub position: u64,
+    pub resumed: bool,
+    backlog: VecDeque<StatusChange>,
+    receiver: broadcast::Receiver<StatusChange>,
+    closed: Shutdown,
+}
+
+impl StatusSubscription {
+    /// Starts the subscription from where the transaction stands instead of a replay.
+    pub fn start_from(&mut self, snapshot: StatusChange) {
+        self.backlog = VecDeque::from([snapshot]);
+    }
+
+    /// The next change of the transaction. `None` when the hub closed or this subscriber fell so
+    /// far behind that changes were lost, the client then resumes from its last event id.
+    pub async fn next(&mut self) -> Option<StatusChange> {
+        if let Some(change) = self.backlog.pop_front() {
+            return Some(change);
+        }
+
+        loop {
+            let received = tokio::select! {
+                received = self.receiver.recv() => received,
+                _ = self.closed.wait() => return None,
+            };
+
+            match received {
+                Ok(change) if change.transaction_id == self.transaction_id => return Some(change),
+                Ok(_) => continue,
+                Err(RecvError::Lagged(missed)) => {
+                    eprintln!("Status stream for {} fell {} changes behind, closing it", self.transaction_id, missed);
+                    return None;
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
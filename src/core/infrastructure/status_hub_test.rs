@@ -0,0 +1,86 @@
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use uuid::Uuid;
+
+    use crate::core::{
+        events::PaymentStatusUpdatedEvent,
+        infrastructure::status_hub::{StatusHub, REPLAY_CAPACITY},
+        models::TransactionStatus,
+    };
+
+    fn update(transaction_id: Uuid, status: TransactionStatus) -> PaymentStatusUpdatedEvent {
+        PaymentStatusUpdatedEvent::new(transaction_id, "merch_hub".to_string(), status, "pi_hub".to_string())
+    }
+
+    #[tokio::test]
+    async fn test_subscribers_only_get_their_transaction() {
+        let hub = StatusHub::new();
+        let followed = Uuid::new_v4();
+        let mut subscription = hub.subscribe(followed, None);
+
+        hub.publish(&update(Uuid::new_v4(), TransactionStatus::Completed));
+        let published = hub.publish(&update(followed, TransactionStatus::Completed));
+
+        let received = subscription.next().await.unwrap();
+        assert_eq!(received, published);
+        assert!(received.sequence > subscription.position);
+        assert_eq!(received.provider_payment_id.as_deref(), Some("pi_hub"));
+    }
+
+    #[tokio::test]
+    async fn test_resume_replays_what_came_after_the_last_event() {
+        let hub = StatusHub::new();
+        let transaction_id = Uuid::new_v4();
+        let seen = hub.publish(&update(transaction_id, TransactionStatus::RequiresAction { next_action_url: "https://3ds".to_string() }));
+        let missed = hub.publish(&update(transaction_id, TransactionStatus::Completed));
+
+        let mut subscription = hub.subscribe(transaction_id, Some(seen.sequence));
+
+        assert!(subscription.resumed);
+        assert_eq!(subscription.next().await.unwrap(), missed);
+        // nothing else is pending
+        assert!(tokio::time::timeout(Duration::from_millis(50), subscription.next()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resume_from_beyond_the_replay_is_not_resumed() {
+        let hub = StatusHub::new();
+        let transaction_id = Uuid::new_v4();
+        let first = hub.publish(&update(transaction_id, TransactionStatus::Pending));
+        for _ in 0..REPLAY_CAPACITY {
+            hub.publish(&update(Uuid::new_v4(), TransactionStatus::Pending));
+        }
+
+        let subscription = hub.subscribe(transaction_id, Some(first.sequence - 1));
+
+        assert!(!subscription.resumed);
+        assert!(!hub.subscribe(transaction_id, None).resumed);
+    }
+
+    #[tokio::test]
+    async fn test_resume_from_a_sequence_the_hub_never_handed_out_is_not_resumed() {
+        // what a client last saw before the api restarted and the sequences started over
+        let hub = StatusHub::new();
+        let transaction_id = Uuid::new_v4();
+        let latest = hub.publish(&update(transaction_id, TransactionStatus::Pending));
+
+        assert!(!hub.subscribe(transaction_id, Some(latest.sequence + 40)).resumed);
+
+        let fresh = StatusHub::new();
+        let position = fresh.subscribe(transaction_id, None).position;
+        assert!(fresh.subscribe(transaction_id, Some(position)).resumed);
+        assert!(!fresh.subscribe(transaction_id, Some(position + 1)).resumed);
+    }
+
+    #[tokio::test]
+    async fn test_close_ends_subscriptions() {
+        let hub = StatusHub::new();
+        let mut subscription = hub.subscribe(Uuid::new_v4(), None);
+
+        hub.close();
+
+        assert_eq!(subscription.next().await, None);
+    }
+}
@@ -1,35 +1,284 @@
-use std::{collections::HashMap, str::FromStr};
-use stripe::{Client, CreatePaymentIntent, PaymentIntent, StripeError};
+use std::{future::Future, str::FromStr, time::{Duration, Instant}};
+use serde::{Deserialize, Serialize};
+use tracing::{info_span, Instrument};
+use stripe::{
+    AttachPaymentMethod, CancelPaymentIntent, Client, CreateCustomer, CreatePaymentIntent, CreateRefund, CreateTransfer,
+    Customer, CustomerId, PaymentIntent, PaymentIntentCancellationReason, PaymentIntentConfirmParams, PaymentIntentId,
+    PaymentIntentStatus, PaymentMethod, PaymentMethodId, Refund, RequestStrategy, StripeError, Transfer,
+};
+use uuid::Uuid;
 
-use crate::core::events::TransactionCreatedEvent;
+use crate::core::{events::TransactionCreatedEvent, infrastructure::metrics::metrics, models::{SplitLeg, TransactionStatus}};
+
+// a hung call would hold up the processor's consumer, what it leaves pending the sweeper sorts out
+const PROVIDER_CALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What the sweeper needs from a payment provider, tests stand in a mock for stripe.
+#[axum::async_trait]
+pub trait PaymentProvider: Send + Sync {
+    /// The payment's status at the provider, None when the provider has no record of it.
+    async fn payment_status(&self, provider_payment_id: &str) -> Result<Option<TransactionStatus>, String>;
+
+    /// Cancels a payment nobody finished, so it can't go through after we failed it.
+    async fn cancel_payment(&self, provider_payment_id: &str) -> Result<(), String>;
+}
+
+/* async-stripe has no typed endpoint for reversing a transfer, so it is posted by hand */
+#[derive(Serialize)]
+struct CreateTransferReversal {
+    amount: i64,
+}
+
+#[derive(Deserialize)]
+pub struct TransferReversal {
+    pub id: String,
+}
 
 pub struct StripeService {
-    client: Client
+    client: Client,
+    // where the customer lands after authenticating, see `return_url`
+    public_base_url: String
 }
 
 impl StripeService {
-    pub fn new(stripe_secret_key: &str) -> Self {
+    pub fn new(stripe_secret_key: &str, public_base_url: &str) -> Self {
         let client = Client::new(stripe_secret_key);
 
         Self{
-            client
+            client,
+            public_base_url: public_base_url.trim_end_matches('/').to_string()
         }
     }
 
-    pub async fn process_payment(&self, event: TransactionCreatedEvent) -> 
+    pub async fn process_payment(&self, event: TransactionCreatedEvent) ->
         Result<PaymentIntent, StripeError> {
 
-            let _metadata = [
+            let mut metadata = event.metadata.clone();
+            // our keys win over anything the merchant sent
+            metadata.extend([
                 ("transaction_id".to_string(), event.transaction_id.to_string()),
                 ("merchant_id".to_string(), event.merchant_id.clone()),
                 ("customer_id".to_string(), event.customer_id.clone()),
-            ].iter()
-            .cloned()
-            .collect::<HashMap<String, String>>();
+            ]);
+
+
+            let mut params = CreatePaymentIntent::new(event.amount as i64, parse_currency(&event.currency)?);
+            // webhooks from stripe are mapped back to our transaction through this
+            params.metadata = Some(metadata);
+
+            // sellers are paid with separate transfers once the charge succeeded, grouped by our transaction
+            let transfer_group = event.transaction_id.to_string();
+            if !event.splits.is_empty() {
+                params.transfer_group = Some(&transfer_group);
+            }
+
+            // with a saved method we can confirm right away, otherwise the client confirms with the secret
+            let return_url = self.return_url(event.transaction_id);
+            if let (Some(customer), Some(payment_method)) = (&event.provider_customer_id, &event.payment_method_id) {
+                params.customer = Some(parse_id::<CustomerId>(customer)?);
+                params.payment_method = Some(parse_id::<PaymentMethodId>(payment_method)?);
+                params.confirm = Some(true);
+                params.return_url = Some(&return_url);
+            }
+
+            // a redelivered event gets the intent the first delivery created back, instead of a second charge
+            let client = self.idempotent(format!("payment-{}", event.transaction_id));
+            observed("create_payment_intent", PaymentIntent::create(&client, params)).await
+    }
+
+    pub async fn create_customer(&self, customer_id: &str, merchant_id: &str, email: Option<&str>, name: Option<&str>) -> Result<Customer, StripeError> {
+        let mut params = CreateCustomer::new();
+        params.email = email;
+        params.name = name;
+        params.metadata = Some([
+            ("customer_id".to_string(), customer_id.to_string()),
+            ("merchant_id".to_string(), merchant_id.to_string()),
+        ].into_iter().collect());
+
+        observed("create_customer", Customer::create(&self.client, params)).await
+    }
+
+    pub async fn delete_customer(&self, provider_customer_id: &str) -> Result<(), StripeError> {
+        let customer_id = parse_id::<CustomerId>(provider_customer_id)?;
+        observed("delete_customer", Customer::delete(&self.client, &customer_id)).await?;
+        Ok(())
+    }
+
+    pub async fn attach_payment_method(&self, payment_method_id: &str, provider_customer_id: &str) -> Result<PaymentMethod, StripeError> {
+        let params = AttachPaymentMethod {
+            customer: parse_id::<CustomerId>(provider_customer_id)?
+        };
+
+        let payment_method_id = parse_id::<PaymentMethodId>(payment_method_id)?;
+        observed("attach_payment_method", PaymentMethod::attach(&self.client, &payment_method_id, params)).await
+    }
+
+    pub async fn detach_payment_method(&self, payment_method_id: &str) -> Result<PaymentMethod, StripeError> {
+        let payment_method_id = parse_id::<PaymentMethodId>(payment_method_id)?;
+        observed("detach_payment_method", PaymentMethod::detach(&self.client, &payment_method_id)).await
+    }
+
+    pub fn return_url(&self, transaction_id: Uuid) -> String {
+        format!("{}/api/v1/transaction/{}/return", self.public_base_url, transaction_id)
+    }
+
+    pub async fn retrieve_intent(&self, payment_intent_id: &str) -> Result<PaymentIntent, StripeError> {
+        let payment_intent_id = parse_id::<PaymentIntentId>(payment_intent_id)?;
+        observed("retrieve_payment_intent", PaymentIntent::retrieve(&self.client, &payment_intent_id, &[])).await
+    }
+
+    /// Called once the customer is back from the authentication redirect, the intent
+    /// usually moves on by itself but one that still needs confirmation gets confirmed here.
+    pub async fn reconfirm_intent(&self, payment_intent_id: &str, transaction_id: Uuid) -> Result<PaymentIntent, StripeError> {
+        let intent = self.retrieve_intent(payment_intent_id).await?;
+
+        if intent.status != PaymentIntentStatus::RequiresConfirmation {
+            return Ok(intent);
+        }
+
+        let return_url = self.return_url(transaction_id);
+        let params = PaymentIntentConfirmParams {
+            return_url: Some(&return_url),
+            ..Default::default()
+        };
+
+        observed("confirm_payment_intent", PaymentIntent::confirm(&self.client, payment_intent_id, params)).await
+    }
+
+    /// Pays every seller their share of a succeeded split payment out of its charge,
+    /// returning the legs with their transfer ids. Legs already transferred are skipped.
+    pub async fn create_split_transfers(&self, payment_intent_id: &str, transaction_id: Uuid, currency: &str, legs: &[SplitLeg]) -> Result<Vec<SplitLeg>, StripeError> {
+        let intent = self.retrieve_intent(payment_intent_id).await?;
+        let charge_id = intent.latest_charge
+            .as_ref()
+            .map(|charge| charge.id())
+            .ok_or_else(|| StripeError::ClientError(format!("payment intent {} has no charge", payment_intent_id)))?;
+
+        let currency = parse_currency(currency)?;
+        let transfer_group = transaction_id.to_string();
+
+        let mut transferred = Vec::with_capacity(legs.len());
+        for leg in legs {
+            let mut leg = leg.clone();
+
+            if leg.transfer_id.is_none() && leg.seller_amount() > 0 {
+                let mut params = CreateTransfer::new(currency, leg.provider_account_id.clone());
+                params.amount = Some(leg.seller_amount() as i64);
+                params.source_transaction = Some(charge_id.clone());
+                params.transfer_group = Some(&transfer_group);
+                params.metadata = Some([
+                    ("transaction_id".to_string(), transaction_id.to_string()),
+                    ("merchant_id".to_string(), leg.destination_merchant_id.clone()),
+                ].into_iter().collect());
 
+                // a retry after a failure halfway through gets back the transfers already made
+                let client = self.idempotent(format!("transfer-{}-{}", transaction_id, leg.provider_account_id));
+                leg.transfer_id = Some(observed("create_transfer", Transfer::create(&client, params)).await?.id.to_string());
+            }
+
+            transferred.push(leg);
+        }
+
+        Ok(transferred)
+    }
 
-            let params = CreatePaymentIntent::new(event.amount as i64, stripe::Currency::from_str(&event.currency).unwrap());
+    /// Refunds the customer. The sellers' shares come back separately, see `reverse_transfer`.
+    pub async fn refund_payment(&self, payment_intent_id: &str, transaction_id: Uuid, amount: u64) -> Result<Refund, StripeError> {
+        let mut params = CreateRefund::new();
+        params.payment_intent = Some(parse_id::<PaymentIntentId>(payment_intent_id)?);
+        params.amount = Some(amount as i64);
+        params.metadata = Some([
+            ("transaction_id".to_string(), transaction_id.to_string()),
+        ].into_iter().collect());
 
-            PaymentIntent::create(&self.client, params).await
+        observed("create_refund", Refund::create(&self.client, params)).await
     }
-}
\ No newline at end of file
+
+    /// Pulls `amount` back from a seller's transfer, `refund_id` keeps a retried reversal from taking it twice.
+    pub async fn reverse_transfer(&self, transfer_id: &str, amount: u64, refund_id: Uuid) -> Result<TransferReversal, StripeError> {
+        let client = self.idempotent(format!("reversal-{}-{}", refund_id, transfer_id));
+        observed("create_transfer_reversal", client
+            .post_form(&format!("/transfers/{}/reversals", transfer_id), CreateTransferReversal {
+                amount: amount as i64
+            }))
+            .await
+    }
+
+    pub async fn cancel_abandoned_intent(&self, payment_intent_id: &str) -> Result<PaymentIntent, StripeError> {
+        let params = CancelPaymentIntent {
+            cancellation_reason: Some(PaymentIntentCancellationReason::Abandoned)
+        };
+
+        observed("cancel_payment_intent", PaymentIntent::cancel(&self.client, payment_intent_id, params)).await
+    }
+
+    // stripe answers a repeated key with the first response instead of doing it again
+    fn idempotent(&self, key: String) -> Client {
+        self.client.clone().with_strategy(RequestStrategy::Idempotent(key))
+    }
+}
+
+#[axum::async_trait]
+impl PaymentProvider for StripeService {
+    async fn payment_status(&self, provider_payment_id: &str) -> Result<Option<TransactionStatus>, String> {
+        match self.retrieve_intent(provider_payment_id).await {
+            Ok(intent) => Ok(Some(status_from_intent(&intent))),
+            Err(StripeError::Stripe(e)) if e.http_status == 404 => Ok(None),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    async fn cancel_payment(&self, provider_payment_id: &str) -> Result<(), String> {
+        self.cancel_abandoned_intent(provider_payment_id).await.map(|_| ()).map_err(|e| e.to_string())
+    }
+}
+
+// times a provider call for the latency and error rate metrics, in its own span of the payment's trace
+async fn observed<T>(operation: &str, call: impl Future<Output = Result<T, StripeError>>) -> Result<T, StripeError> {
+    let started = Instant::now();
+    let result = tokio::time::timeout(PROVIDER_CALL_TIMEOUT, call.instrument(info_span!("stripe", operation)))
+        .await
+        .unwrap_or(Err(StripeError::Timeout));
+    metrics().observe_provider_call("stripe", operation, started.elapsed(), result.is_ok());
+    result
+}
+
+fn parse_id<T>(id: &str) -> Result<T, StripeError>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    T::from_str(id).map_err(|e| StripeError::ClientError(e.to_string()))
+}
+
+// stripe's codes are lowercase, ours are whatever the merchant sent
+fn parse_currency(code: &str) -> Result<stripe::Currency, StripeError> {
+    stripe::Currency::from_str(&code.to_lowercase())
+        .map_err(|_| StripeError::ClientError(format!("unsupported currency {}", code)))
+}
+
+/* what a payment intent's state means for our transaction */
+pub fn status_from_intent(intent: &PaymentIntent) -> TransactionStatus {
+    match intent.status {
+        PaymentIntentStatus::Succeeded => TransactionStatus::Completed,
+        PaymentIntentStatus::RequiresAction => {
+            let next_action_url = intent.next_action.as_ref()
+                .and_then(|action| action.redirect_to_url.as_ref())
+                .and_then(|redirect| redirect.url.clone())
+                .unwrap_or_default();
+
+            TransactionStatus::RequiresAction { next_action_url }
+        },
+        PaymentIntentStatus::Canceled => TransactionStatus::Failed {
+            reason: "payment intent canceled".to_string()
+        },
+        // a declined confirmation sends the intent back to requires_payment_method
+        PaymentIntentStatus::RequiresPaymentMethod if intent.last_payment_error.is_some() => TransactionStatus::Failed {
+            reason: intent.last_payment_error.as_ref()
+                .and_then(|error| error.message.clone())
+                .unwrap_or_else(|| "payment declined".to_string())
+        },
+        // nothing has been charged yet, still in flight as far as we're concerned
+        _ => TransactionStatus::Pending
+    }
+}
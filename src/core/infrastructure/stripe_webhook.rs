@@ -0,0 +1,155 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::core::{
+    events::PaymentStatusUpdatedEvent, infrastructure::webhook::sign_payload,
+    models::TransactionStatus,
+};
+
+pub const STRIPE_SIGNATURE_HEADER: &str = "stripe-signature";
+
+// same default Stripe's own libraries use for replay protection
+pub const DEFAULT_TOLERANCE_SECS: i64 = 300;
+
+#[derive(Debug, Error)]
+pub enum StripeWebhookError {
+    #[error("Missing Stripe-Signature header")]
+    MissingSignature,
+    #[error("Stripe webhook secret is not configured")]
+    MissingSecret,
+    #[error("Invalid Stripe signature")]
+    InvalidSignature,
+    #[error("Stripe signature timestamp outside of tolerance")]
+    TimestampOutsideTolerance,
+    #[error("Malformed Stripe event: {0}")]
+    MalformedPayload(String),
+    #[error("Failed to publish status update: {0}")]
+    Publish(String),
+}
+
+/* the parts of a Stripe event we care about, the object is kept raw since its shape depends on the type */
+#[derive(Debug, Deserialize)]
+pub struct StripeEvent {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub event_type: String,
+    pub created: i64,
+    pub data: StripeEventData,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StripeEventData {
+    pub object: Value,
+}
+
+/// Verifies a `Stripe-Signature` header of the form `t=<ts>,v1=<sig>[,v1=<sig>]`
+/// against the raw request body.
+pub fn verify_signature(
+    payload: &str,
+    header: &str,
+    secret: &str,
+    now: DateTime<Utc>,
+    tolerance_secs: i64,
+) -> Result<(), StripeWebhookError> {
+    if secret.is_empty() {
+        return Err(StripeWebhookError::MissingSecret);
+    }
+
+    let mut timestamp = None;
+    let mut signatures = Vec::new();
+
+    for part in header.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", value)) => timestamp = value.parse::<i64>().ok(),
+            Some(("v1", value)) => signatures.push(value),
+            _ => {}
+        }
+    }
+
+    let timestamp = timestamp.ok_or(StripeWebhookError::InvalidSignature)?;
+
+    let expected = sign_payload(secret, timestamp, payload);
+    // Stripe sends several v1 signatures while a secret is being rolled
+    if !signatures.iter().any(|sig| constant_time_eq(sig.as_bytes(), expected.as_bytes())) {
+        return Err(StripeWebhookError::InvalidSignature);
+    }
+
+    if (now.timestamp() - timestamp).abs() > tolerance_secs {
+        return Err(StripeWebhookError::TimestampOutsideTolerance);
+    }
+
+    Ok(())
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+pub fn parse_event(payload: &[u8]) -> Result<StripeEvent, StripeWebhookError> {
+    serde_json::from_slice(payload).map_err(|e| StripeWebhookError::MalformedPayload(e.to_string()))
+}
+
+/// Maps the Stripe events we act on to a status update, `None` for anything else
+/// (including objects that weren't created by us and so carry no transaction id).
+pub fn to_status_event(event: &StripeEvent) -> Option<PaymentStatusUpdatedEvent> {
+    let object = &event.data.object;
+
+    let (status, payment_intent_id) = match event.event_type.as_str() {
+        "payment_intent.succeeded" => (TransactionStatus::Completed, str_field(object, "id")),
+        "payment_intent.payment_failed" => {
+            let reason = object
+                .pointer("/last_payment_error/message")
+                .and_then(Value::as_str)
+                .unwrap_or("payment failed")
+                .to_string();
+
+            (TransactionStatus::Failed { reason }, str_field(object, "id"))
+        }
+        "payment_intent.canceled" => {
+            let reason = match str_field(object, "cancellation_reason") {
+                Some(reason) => format!("canceled: {}", reason),
+                None => "canceled".to_string(),
+            };
+
+            (TransactionStatus::Failed { reason }, str_field(object, "id"))
+        }
+        // partial refunds leave the transaction as it is
+        "charge.refunded" if object.get("refunded").and_then(Value::as_bool) == Some(true) => {
+            (TransactionStatus::Refunded, str_field(object, "payment_intent"))
+        }
+        _ => return None,
+    };
+
+    let transaction_id = object
+        .pointer("/metadata/transaction_id")
+        .and_then(Value::as_str)
+        .and_then(|id| Uuid::from_str(id).ok())?;
+
+    let merchant_id = object
+        .pointer("/metadata/merchant_id")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string();
+
+    let mut status_event = PaymentStatusUpdatedEvent::new(
+        transaction_id,
+        merchant_id,
+        status,
+        payment_intent_id.unwrap_or_default(),
+    );
+
+    if let Some(created) = DateTime::from_timestamp(event.created, 0) {
+        status_event.timestamp = created;
+    }
+
+    Some(status_event)
+}
+
+fn str_field(object: &Value, field: &str) -> Option<String> {
+    object.get(field).and_then(Value::as_str).map(str::to_string)
+}
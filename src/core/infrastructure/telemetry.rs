@@ -0,0 +1,142 @@
+use std::{collections::HashMap, time::UNIX_EPOCH};
+
+use opentelemetry::{global, trace::TracerProvider as _, Context};
+use opentelemetry_otlp::{SpanExporter as OtlpExporter, WithExportConfig};
+use opentelemetry_sdk::{
+    error::OTelSdkResult,
+    propagation::TraceContextPropagator,
+    trace::{SdkTracerProvider, SpanData, SpanExporter},
+    Resource,
+};
+use rdkafka::{
+    message::{Header, Headers, OwnedHeaders},
+    Message,
+};
+use tracing::{info_span, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+use crate::core::config::TelemetryConfig;
+
+/* keeps the exporter alive, spans still buffered are flushed when it is shut down */
+pub struct Telemetry {
+    provider: Option<SdkTracerProvider>,
+}
+
+impl Telemetry {
+    pub fn shutdown(self) {
+        if let Some(provider) = self.provider {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Failed to flush traces: {}", e);
+            }
+        }
+    }
+}
+
+/// Sets up logging and tracing for a process. Trace context travels between services as a
+/// W3C `traceparent`, over http headers and kafka message headers alike.
+pub fn init_telemetry(service_name: &str, config: &TelemetryConfig) -> Telemetry {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let resource = Resource::builder().with_service_name(service_name.to_string()).build();
+    let provider = match config.exporter.as_str() {
+        "otlp" => match OtlpExporter::builder().with_http().with_endpoint(&config.otlp_endpoint).build() {
+            Ok(exporter) => Some(SdkTracerProvider::builder().with_batch_exporter(exporter).with_resource(resource).build()),
+            Err(e) => {
+                eprintln!("Failed to create the OTLP exporter, traces won't be exported: {}", e);
+                None
+            }
+        },
+        "stdout" => Some(SdkTracerProvider::builder().with_simple_exporter(StdoutExporter).with_resource(resource).build()),
+        _ => None,
+    };
+
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&config.log_filter));
+    let otel_layer = provider
+        .as_ref()
+        .map(|provider| tracing_opentelemetry::layer().with_tracer(provider.tracer(service_name.to_string())));
+
+    let initialized = tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .with(otel_layer)
+        .try_init();
+    if let Err(e) = initialized {
+        eprintln!("Tracing was already initialized: {}", e);
+    }
+
+    Telemetry { provider }
+}
+
+/// The current span's trace context, as the headers that carry it.
+pub fn trace_context() -> HashMap<String, String> {
+    let mut carrier = HashMap::new();
+    let context = Span::current().context();
+    global::get_text_map_propagator(|propagator| propagator.inject_context(&context, &mut carrier));
+    carrier
+}
+
+/// `trace_context` as kafka headers, for every record we produce.
+pub fn trace_headers() -> OwnedHeaders {
+    trace_context().iter().fold(OwnedHeaders::new(), |headers, (key, value)| {
+        headers.insert(Header { key, value: Some(value) })
+    })
+}
+
+/// The trace context carried by `traceparent`/`tracestate`, empty when there is none.
+pub fn remote_context(carrier: &HashMap<String, String>) -> Context {
+    global::get_text_map_propagator(|propagator| propagator.extract(carrier))
+}
+
+/// The trace context a message was produced under.
+pub fn message_context<M: Message>(msg: &M) -> Context {
+    let mut carrier = HashMap::new();
+    if let Some(headers) = msg.headers() {
+        for header in headers.iter() {
+            if let Some(value) = header.value.and_then(|value| std::str::from_utf8(value).ok()) {
+                carrier.insert(header.key.to_lowercase(), value.to_string());
+            }
+        }
+    }
+    remote_context(&carrier)
+}
+
+/// A span for handling one message, continuing the trace of whoever produced it.
+pub fn consumer_span<M: Message>(consumer: &str, msg: &M) -> Span {
+    let span = info_span!(
+        "consume",
+        consumer,
+        topic = msg.topic(),
+        partition = msg.partition(),
+        offset = msg.offset()
+    );
+    // only fails when no tracer is installed, then there is nothing to continue
+    let _ = span.set_parent(message_context(msg));
+    span
+}
+
+/* prints finished spans one per line, for local runs without a collector */
+#[derive(Debug)]
+struct StdoutExporter;
+
+impl SpanExporter for StdoutExporter {
+    async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+        for span in batch {
+            let started = span.start_time.duration_since(UNIX_EPOCH).unwrap_or_default();
+            let elapsed = span.end_time.duration_since(span.start_time).unwrap_or_default();
+            let attributes: Vec<String> = span.attributes.iter().map(|kv| format!("{}={}", kv.key, kv.value)).collect();
+
+            println!(
+                "span trace_id={} span_id={} parent_id={} name={} start={}ms duration={}us {}",
+                span.span_context.trace_id(),
+                span.span_context.span_id(),
+                span.parent_span_id,
+                span.name,
+                started.as_millis(),
+                elapsed.as_micros(),
+                attributes.join(" ")
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,59 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use opentelemetry::{
+        global,
+        trace::{TraceContextExt, TracerProvider as _},
+    };
+    use opentelemetry_sdk::{propagation::TraceContextPropagator, trace::SdkTracerProvider};
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use crate::core::infrastructure::telemetry::{remote_context, trace_context};
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    fn subscriber() -> impl tracing::Subscriber + Send + Sync {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        let provider = SdkTracerProvider::builder().build();
+        tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")))
+    }
+
+    #[test]
+    fn test_trace_context_is_empty_outside_a_span() {
+        tracing::subscriber::with_default(subscriber(), || {
+            assert!(!trace_context().contains_key("traceparent"));
+        });
+    }
+
+    #[test]
+    fn test_span_continues_the_trace_it_was_handed() {
+        tracing::subscriber::with_default(subscriber(), || {
+            let carrier = HashMap::from([("traceparent".to_string(), TRACEPARENT.to_string())]);
+
+            let span = tracing::info_span!("consume");
+            span.set_parent(remote_context(&carrier)).unwrap();
+            let headers = span.in_scope(trace_context);
+
+            let traceparent = &headers["traceparent"];
+            assert!(traceparent.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+            // a new span of the same trace, not the caller's span passed along
+            assert!(!traceparent.contains("00f067aa0ba902b7"));
+        });
+    }
+
+    #[test]
+    fn test_injected_context_extracts_to_the_same_span() {
+        tracing::subscriber::with_default(subscriber(), || {
+            let span = tracing::info_span!("http_request");
+            let headers = span.in_scope(trace_context);
+
+            let extracted = remote_context(&headers);
+            let expected = span.context();
+            assert_eq!(extracted.span().span_context().trace_id(), expected.span().span_context().trace_id());
+            assert_eq!(extracted.span().span_context().span_id(), expected.span().span_context().span_id());
+            assert!(extracted.span().span_context().is_remote());
+        });
+    }
+}
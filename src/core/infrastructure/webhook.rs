@@ -0,0 +1,453 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex as StdMutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use thiserror::Error;
+use tokio::{
+    io::AsyncWriteExt,
+    sync::{Mutex, Notify, RwLock},
+};
+use uuid::Uuid;
+
+use crate::core::{
+    events::{MerchantWebhookEvent, PaymentStatusUpdatedEvent},
+    models::{DeliveryAttempt, DeliveryState, WebhookDelivery, WebhookEndpoint},
+};
+
+pub const SIGNATURE_HEADER: &str = "payme-signature";
+
+// merchants get retried for up to 72 hours after the first attempt
+const MAX_RETRY_WINDOW_HOURS: i64 = 72;
+const BASE_BACKOFF_SECS: i64 = 60;
+const MAX_BACKOFF_SECS: i64 = 6 * 60 * 60;
+// the journal is compacted once it holds more than twice the live records, and at least this many lines
+const COMPACT_AFTER_LINES: usize = 10_000;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, Error)]
+pub enum WebhookError {
+    #[error("Unknown delivery {0}")]
+    UnknownDelivery(Uuid),
+    #[error("No webhook endpoint registered for merchant {0}")]
+    MissingEndpoint(String),
+    #[error("Failed to serialize webhook payload: {0}")]
+    Serialization(String),
+    #[error("Failed to save webhooks: {0}")]
+    Io(String),
+    #[error("Delivery {0} is no longer pending")]
+    NotPending(Uuid),
+}
+
+/* signs `{timestamp}.{payload}` so merchants can reject replayed deliveries */
+pub fn sign_payload(secret: &str, timestamp: i64, payload: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any size");
+    mac.update(format!("{}.{}", timestamp, payload).as_bytes());
+
+    hex::encode(mac.finalize().into_bytes())
+}
+
+pub fn signature_header(secret: &str, timestamp: i64, payload: &str) -> String {
+    format!("t={},v1={}", timestamp, sign_payload(secret, timestamp, payload))
+}
+
+/// Exponential backoff starting at a minute, capped at 6 hours between attempts.
+/// Returns `None` once the next attempt would fall outside the retry window.
+pub fn next_attempt_at(
+    created_at: DateTime<Utc>,
+    attempts_made: u32,
+    now: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    let exponent = attempts_made.saturating_sub(1).min(16);
+    let backoff = (BASE_BACKOFF_SECS * 2i64.pow(exponent)).min(MAX_BACKOFF_SECS);
+    let next = now + chrono::Duration::seconds(backoff);
+
+    if next > created_at + chrono::Duration::hours(MAX_RETRY_WINDOW_HOURS) {
+        None
+    } else {
+        Some(next)
+    }
+}
+
+/// Endpoints and deliveries. Kept in a journal file when opened with a path, so pending retries and
+/// the delivery history survive a restart of the dispatcher, which is the only process using it.
+/// Every change appends a line, the file is only rewritten when it's compacted.
+#[derive(Clone, Default)]
+pub struct WebhookStore {
+    path: Option<PathBuf>,
+    endpoints: Arc<RwLock<HashMap<String, WebhookEndpoint>>>,
+    deliveries: Arc<RwLock<Deliveries>>,
+    // held from changing the maps until the change is appended, so the file sees changes in the
+    // order they were made. None when there's no file
+    journal: Arc<Mutex<Option<Journal>>>,
+}
+
+#[derive(Default)]
+struct Deliveries {
+    by_id: HashMap<Uuid, WebhookDelivery>,
+    // events that already have a delivery
+    events: HashSet<Uuid>,
+}
+
+struct Journal {
+    file: tokio::fs::File,
+    lines: usize,
+}
+
+// `WebhookEndpoint` never serializes its secret, the file has to keep it
+#[derive(Serialize, Deserialize)]
+struct StoredEndpoint {
+    merchant_id: String,
+    url: String,
+    secret: String,
+    created_at: DateTime<Utc>,
+}
+
+impl From<&WebhookEndpoint> for StoredEndpoint {
+    fn from(e: &WebhookEndpoint) -> Self {
+        Self { merchant_id: e.merchant_id.clone(), url: e.url.clone(), secret: e.secret.clone(), created_at: e.created_at }
+    }
+}
+
+// one per line of the journal, the last line about an endpoint or delivery is what it looks like now
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Record {
+    Endpoint(StoredEndpoint),
+    Delivery(WebhookDelivery),
+}
+
+impl WebhookStore {
+    /// Kept in memory only, for tests and local runs.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// What `webhooks.store_path` asks for, an empty path keeps everything in memory.
+    pub fn from_path(path: &str) -> Result<Self, WebhookError> {
+        match path.trim() {
+            "" => Ok(Self::new()),
+            path => Self::open(path),
+        }
+    }
+
+    /// A missing file is an empty store, it's created with the first endpoint. The journal is
+    /// compacted on the way.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, WebhookError> {
+        let path = path.as_ref().to_path_buf();
+        let text = match path.exists() {
+            true => fs::read_to_string(&path).map_err(|e| WebhookError::Io(format!("{}: {}", path.display(), e)))?,
+            false => String::new(),
+        };
+
+        let mut endpoints = HashMap::new();
+        let mut deliveries = Deliveries::default();
+        let lines: Vec<&str> = text.lines().filter(|line| !line.trim().is_empty()).collect();
+        for (index, line) in lines.iter().enumerate() {
+            match serde_json::from_str::<Record>(line) {
+                Ok(Record::Endpoint(e)) => {
+                    let endpoint = WebhookEndpoint { merchant_id: e.merchant_id, url: e.url, secret: e.secret, created_at: e.created_at };
+                    endpoints.insert(endpoint.merchant_id.clone(), endpoint);
+                }
+                Ok(Record::Delivery(delivery)) => {
+                    deliveries.events.insert(delivery.event_id);
+                    deliveries.by_id.insert(delivery.id, delivery);
+                }
+                // a crash while appending leaves the last line cut short, everything before it holds
+                Err(e) if index + 1 == lines.len() => eprintln!("Skipping the unfinished last line of {}: {}", path.display(), e),
+                Err(e) => return Err(WebhookError::Io(format!("{} line {}: {}", path.display(), index + 1, e))),
+            }
+        }
+
+        let journal = compact(&path, records(&endpoints, &deliveries))?;
+        Ok(Self {
+            path: Some(path),
+            endpoints: Arc::new(RwLock::new(endpoints)),
+            deliveries: Arc::new(RwLock::new(deliveries)),
+            journal: Arc::new(Mutex::new(Some(journal))),
+        })
+    }
+
+    pub async fn upsert_endpoint(&self, endpoint: WebhookEndpoint) -> Result<(), WebhookError> {
+        let mut journal = self.journal.lock().await;
+        let record = Record::Endpoint(StoredEndpoint::from(&endpoint));
+        self.endpoints
+            .write()
+            .await
+            .insert(endpoint.merchant_id.clone(), endpoint);
+        self.append(&mut journal, record).await
+    }
+
+    pub async fn endpoint(&self, merchant_id: &str) -> Option<WebhookEndpoint> {
+        self.endpoints.read().await.get(merchant_id).cloned()
+    }
+
+    pub async fn save_delivery(&self, delivery: WebhookDelivery) -> Result<(), WebhookError> {
+        let mut journal = self.journal.lock().await;
+        {
+            let mut deliveries = self.deliveries.write().await;
+            deliveries.events.insert(delivery.event_id);
+            deliveries.by_id.insert(delivery.id, delivery.clone());
+        }
+        self.append(&mut journal, Record::Delivery(delivery)).await
+    }
+
+    /// Saves a new delivery unless one for the same event already exists, so an event that's
+    /// consumed again (a replay, or a redelivery after a rebalance) isn't sent twice.
+    pub async fn save_new_delivery(&self, delivery: WebhookDelivery) -> Result<bool, WebhookError> {
+        let mut journal = self.journal.lock().await;
+        {
+            let mut deliveries = self.deliveries.write().await;
+            if !deliveries.events.insert(delivery.event_id) {
+                return Ok(false);
+            }
+            deliveries.by_id.insert(delivery.id, delivery.clone());
+        }
+        self.append(&mut journal, Record::Delivery(delivery)).await?;
+        Ok(true)
+    }
+
+    pub async fn delivery(&self, id: Uuid) -> Option<WebhookDelivery> {
+        self.deliveries.read().await.by_id.get(&id).cloned()
+    }
+
+    pub async fn deliveries_for_merchant(&self, merchant_id: &str) -> Vec<WebhookDelivery> {
+        let mut deliveries: Vec<WebhookDelivery> = self
+            .deliveries
+            .read()
+            .await
+            .by_id
+            .values()
+            .filter(|d| d.merchant_id == merchant_id)
+            .cloned()
+            .collect();
+
+        deliveries.sort_by_key(|d| std::cmp::Reverse(d.created_at));
+        deliveries
+    }
+
+    pub async fn due_deliveries(&self, now: DateTime<Utc>) -> Vec<Uuid> {
+        self.deliveries
+            .read()
+            .await
+            .by_id
+            .values()
+            .filter(|d| d.state == DeliveryState::Pending)
+            .filter(|d| d.next_attempt_at.map(|at| at <= now).unwrap_or(false))
+            .map(|d| d.id)
+            .collect()
+    }
+
+    async fn append(&self, journal: &mut Option<Journal>, record: Record) -> Result<(), WebhookError> {
+        let (Some(path), Some(open)) = (&self.path, journal.as_mut()) else {
+            return Ok(());
+        };
+
+        let mut line = serde_json::to_string(&record).map_err(|e| WebhookError::Serialization(e.to_string()))?;
+        line.push('\n');
+        let written = match open.file.write_all(line.as_bytes()).await {
+            Ok(()) => open.file.flush().await,
+            Err(e) => Err(e),
+        };
+        written.map_err(|e| WebhookError::Io(format!("{}: {}", path.display(), e)))?;
+        open.lines += 1;
+
+        let live = self.endpoints.read().await.len() + self.deliveries.read().await.by_id.len();
+        if open.lines > COMPACT_AFTER_LINES.max(live * 2) {
+            let records = records(&*self.endpoints.read().await, &*self.deliveries.read().await);
+            let path = path.clone();
+            *journal = Some(
+                tokio::task::spawn_blocking(move || compact(&path, records))
+                    .await
+                    .map_err(|e| WebhookError::Io(e.to_string()))??,
+            );
+        }
+        Ok(())
+    }
+}
+
+fn records(endpoints: &HashMap<String, WebhookEndpoint>, deliveries: &Deliveries) -> Vec<Record> {
+    endpoints
+        .values()
+        .map(|e| Record::Endpoint(StoredEndpoint::from(e)))
+        .chain(deliveries.by_id.values().cloned().map(Record::Delivery))
+        .collect()
+}
+
+// written next to the journal and moved over it, a crash never leaves half a file behind
+fn compact(path: &Path, records: Vec<Record>) -> Result<Journal, WebhookError> {
+    let io = |e: std::io::Error| WebhookError::Io(format!("{}: {}", path.display(), e));
+
+    let mut text = String::new();
+    for record in &records {
+        text.push_str(&serde_json::to_string(record).map_err(|e| WebhookError::Serialization(e.to_string()))?);
+        text.push('\n');
+    }
+    let part = path.with_extension("part");
+    fs::write(&part, text).and_then(|_| fs::rename(&part, path)).map_err(io)?;
+
+    let file = fs::OpenOptions::new().append(true).open(path).map_err(io)?;
+    Ok(Journal { file: tokio::fs::File::from_std(file), lines: records.len() })
+}
+
+/* performs the signed HTTP delivery and records every attempt in the store */
+#[derive(Clone)]
+pub struct WebhookSender {
+    store: WebhookStore,
+    http: reqwest::Client,
+    // one attempt per delivery at a time, a retry and a manual redelivery would otherwise
+    // both send it and the last one to save would drop the other's attempt
+    in_flight: Arc<StdMutex<HashMap<Uuid, Arc<Mutex<()>>>>>,
+    // wakes the retry loop for a delivery that was just queued
+    queued: Arc<Notify>,
+}
+
+impl WebhookSender {
+    pub fn new(store: WebhookStore) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            // a redirect could point the delivery at a host the endpoint check refused
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .expect("Failed to build the webhook http client");
+
+        Self { store, http, in_flight: Arc::default(), queued: Arc::default() }
+    }
+
+    pub fn store(&self) -> &WebhookStore {
+        &self.store
+    }
+
+    /// Returns once a delivery was queued, right away if one was queued since the last call.
+    pub async fn wait_for_queued(&self) {
+        self.queued.notified().await;
+    }
+
+    /// Queues a delivery for the merchant the event belongs to, returns `None`
+    /// when the merchant hasn't registered an endpoint or the event was already queued.
+    pub async fn enqueue(
+        &self,
+        event: &PaymentStatusUpdatedEvent,
+    ) -> Result<Option<Uuid>, WebhookError> {
+        if self.store.endpoint(&event.merchant_id).await.is_none() {
+            return Ok(None);
+        }
+
+        let payload = serde_json::to_string(&MerchantWebhookEvent::from(event))
+            .map_err(|e| WebhookError::Serialization(e.to_string()))?;
+
+        let now = Utc::now();
+        let delivery = WebhookDelivery {
+            id: Uuid::new_v4(),
+            event_id: event.event_id,
+            merchant_id: event.merchant_id.clone(),
+            transaction_id: event.transaction_id,
+            payload,
+            state: DeliveryState::Pending,
+            created_at: now,
+            next_attempt_at: Some(now),
+            attempts: Vec::new(),
+        };
+
+        let id = delivery.id;
+        if !self.store.save_new_delivery(delivery).await? {
+            return Ok(None);
+        }
+        self.queued.notify_one();
+        Ok(Some(id))
+    }
+
+    pub async fn attempt(
+        &self,
+        delivery_id: Uuid,
+        manual: bool,
+    ) -> Result<DeliveryAttempt, WebhookError> {
+        let lock = self.in_flight.lock().expect("in flight lock poisoned").entry(delivery_id).or_default().clone();
+        let result = {
+            let _attempting = lock.lock().await;
+            self.attempt_locked(delivery_id, manual).await
+        };
+
+        // the last one out takes the lock away again
+        let mut in_flight = self.in_flight.lock().expect("in flight lock poisoned");
+        if Arc::strong_count(&lock) == 2 {
+            in_flight.remove(&delivery_id);
+        }
+        result
+    }
+
+    async fn attempt_locked(&self, delivery_id: Uuid, manual: bool) -> Result<DeliveryAttempt, WebhookError> {
+        // read once the lock is held, so it includes whatever the previous attempt saved
+        let mut delivery = self
+            .store
+            .delivery(delivery_id)
+            .await
+            .ok_or(WebhookError::UnknownDelivery(delivery_id))?;
+        if !manual && delivery.state != DeliveryState::Pending {
+            return Err(WebhookError::NotPending(delivery_id));
+        }
+
+        let endpoint = self
+            .store
+            .endpoint(&delivery.merchant_id)
+            .await
+            .ok_or_else(|| WebhookError::MissingEndpoint(delivery.merchant_id.clone()))?;
+
+        let now = Utc::now();
+        let header = signature_header(&endpoint.secret, now.timestamp(), &delivery.payload);
+
+        let result = self
+            .http
+            .post(&endpoint.url)
+            .header("content-type", "application/json")
+            .header(SIGNATURE_HEADER, header)
+            .body(delivery.payload.clone())
+            .send()
+            .await;
+
+        let (status_code, error) = match result {
+            Ok(res) if res.status().is_success() => (Some(res.status().as_u16()), None),
+            Ok(res) => (
+                Some(res.status().as_u16()),
+                Some(format!("Endpoint responded with {}", res.status())),
+            ),
+            Err(e) => (None, Some(e.to_string())),
+        };
+
+        let attempt = DeliveryAttempt {
+            attempt: delivery.attempts.len() as u32 + 1,
+            attempted_at: now,
+            status_code,
+            succeeded: error.is_none(),
+            error,
+            manual,
+        };
+
+        delivery.attempts.push(attempt.clone());
+
+        if attempt.succeeded {
+            delivery.state = DeliveryState::Delivered;
+            delivery.next_attempt_at = None;
+        } else if !manual {
+            delivery.next_attempt_at =
+                next_attempt_at(delivery.created_at, attempt.attempt, now);
+
+            if delivery.next_attempt_at.is_none() {
+                delivery.state = DeliveryState::Failed;
+            }
+        }
+
+        self.store.save_delivery(delivery).await?;
+
+        Ok(attempt)
+    }
+}
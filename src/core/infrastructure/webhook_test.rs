@@ -0,0 +1,125 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use uuid::Uuid;
+
+    use crate::core::{
+        events::PaymentStatusUpdatedEvent,
+        infrastructure::webhook::{next_attempt_at, sign_payload, signature_header, WebhookSender, WebhookStore},
+        models::{DeliveryState, TransactionStatus, WebhookEndpoint},
+    };
+
+    #[test]
+    fn test_signature_is_deterministic() {
+        let payload = r#"{"id":"evt_1"}"#;
+
+        assert_eq!(
+            sign_payload("whsec_test", 1700000000, payload),
+            sign_payload("whsec_test", 1700000000, payload)
+        );
+        assert_ne!(
+            sign_payload("whsec_test", 1700000000, payload),
+            sign_payload("whsec_other", 1700000000, payload)
+        );
+    }
+
+    #[test]
+    fn test_signature_header_format() {
+        let header = signature_header("whsec_test", 1700000000, "{}");
+
+        assert!(header.starts_with("t=1700000000,v1="));
+        assert_eq!(header.len(), "t=1700000000,v1=".len() + 64);
+    }
+
+    #[test]
+    fn test_backoff_grows_and_stops_after_window() {
+        let created_at = Utc::now();
+
+        let first = next_attempt_at(created_at, 1, created_at).unwrap();
+        let second = next_attempt_at(created_at, 2, created_at).unwrap();
+        assert_eq!(first - created_at, Duration::seconds(60));
+        assert_eq!(second - created_at, Duration::seconds(120));
+
+        let late = created_at + Duration::hours(71);
+        assert!(next_attempt_at(created_at, 20, late).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_an_event_is_queued_once() {
+        let store = WebhookStore::new();
+        store
+            .upsert_endpoint(WebhookEndpoint {
+                merchant_id: "merch_1".to_string(),
+                url: "https://merchant.example/hooks".to_string(),
+                secret: "whsec_test".to_string(),
+                created_at: Utc::now(),
+            })
+            .await
+            .unwrap();
+        let sender = WebhookSender::new(store.clone());
+        let event = PaymentStatusUpdatedEvent::new(Uuid::new_v4(), "merch_1".to_string(), TransactionStatus::Completed, "pi_1".to_string());
+
+        assert!(sender.enqueue(&event).await.unwrap().is_some());
+        // replayed, or consumed again after a rebalance
+        assert!(sender.enqueue(&event).await.unwrap().is_none());
+        assert_eq!(store.deliveries_for_merchant("merch_1").await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_deliveries_and_secrets_survive_a_restart() {
+        let path = std::env::temp_dir().join(format!("payme-webhooks-{}.json", Uuid::new_v4()));
+        let store = WebhookStore::open(&path).unwrap();
+        store
+            .upsert_endpoint(WebhookEndpoint {
+                merchant_id: "merch_1".to_string(),
+                url: "https://merchant.example/hooks".to_string(),
+                secret: "whsec_test".to_string(),
+                created_at: Utc::now(),
+            })
+            .await
+            .unwrap();
+        let event = PaymentStatusUpdatedEvent::new(Uuid::new_v4(), "merch_1".to_string(), TransactionStatus::Completed, "pi_1".to_string());
+        let delivery_id = WebhookSender::new(store).enqueue(&event).await.unwrap().unwrap();
+
+        let reopened = WebhookStore::open(&path).unwrap();
+
+        assert_eq!(reopened.endpoint("merch_1").await.unwrap().secret, "whsec_test");
+        assert_eq!(reopened.due_deliveries(Utc::now()).await, [delivery_id]);
+        // and the event isn't queued a second time when it's consumed again after the restart
+        assert!(WebhookSender::new(reopened).enqueue(&event).await.unwrap().is_none());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_changes_are_appended_and_compacted_on_open() {
+        let path = std::env::temp_dir().join(format!("payme-webhooks-{}.jsonl", Uuid::new_v4()));
+        let store = WebhookStore::open(&path).unwrap();
+        store
+            .upsert_endpoint(WebhookEndpoint {
+                merchant_id: "merch_1".to_string(),
+                url: "https://merchant.example/hooks".to_string(),
+                secret: "whsec_test".to_string(),
+                created_at: Utc::now(),
+            })
+            .await
+            .unwrap();
+        let sender = WebhookSender::new(store.clone());
+        let event = PaymentStatusUpdatedEvent::new(Uuid::new_v4(), "merch_1".to_string(), TransactionStatus::Completed, "pi_1".to_string());
+        let delivery_id = sender.enqueue(&event).await.unwrap().unwrap();
+        // a new delivery wakes the retry loop, which makes the first attempt
+        tokio::time::timeout(std::time::Duration::from_secs(1), sender.wait_for_queued()).await.unwrap();
+
+        let mut delivery = store.delivery(delivery_id).await.unwrap();
+        delivery.state = DeliveryState::Delivered;
+        delivery.next_attempt_at = None;
+        store.save_delivery(delivery).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 3);
+
+        let reopened = WebhookStore::open(&path).unwrap();
+
+        assert_eq!(reopened.delivery(delivery_id).await.unwrap().state, DeliveryState::Delivered);
+        assert!(reopened.due_deliveries(Utc::now()).await.is_empty());
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2);
+        std::fs::remove_file(path).unwrap();
+    }
+}
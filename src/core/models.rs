@@ -1,41 +1,102 @@
+use std::{collections::HashMap, str::FromStr};
+
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use uuid::Uuid;
 
-#[derive(Debug, Serialize , Deserialize)]
+#[derive(Debug, Serialize , Deserialize, Clone, ToSchema)]
 pub struct Transaction {
     pub id: Uuid,
     pub amount : i64,
     pub currency: Currency,
+    pub merchant_id: String,
+    pub customer_id: String,
     pub status : TransactionStatus,
+    pub provider_payment_id: Option<String>,
+    // handed to the client so it can finish authentication with stripe.js
+    pub client_secret: Option<String>,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    // set once the transaction completed and fees were assessed
+    #[serde(default)]
+    pub fee: Option<FeeBreakdown>,
+    // marketplace payments, how the charge is shared between sellers
+    #[serde(default)]
+    pub splits: Vec<SplitLeg>,
+    #[serde(default)]
+    pub amount_refunded: i64,
     pub created_at: chrono::DateTime<Utc>,
     pub update_at : chrono::DateTime<Utc>
 }
 
-#[derive(Debug, Serialize , Deserialize,PartialEq)]
+#[derive(Debug, Serialize , Deserialize,PartialEq, Clone, ToSchema)]
 pub enum TransactionStatus {
     Pending,
     Completed,
     Failed {
         reason: String
     },
+    // customer has to complete 3DS/SCA before the provider will settle the payment
+    RequiresAction {
+        next_action_url: String
+    },
+    // held by the risk engine until someone approves or declines it
+    UnderReview,
     Refunded
 }
 
-#[derive(Debug , Serialize , Deserialize)]
+impl TransactionStatus {
+    pub const KINDS: [&'static str; 6] = ["pending", "completed", "failed", "requires_action", "under_review", "refunded"];
+
+    /// The variant without its data, what filters and metric labels go by.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TransactionStatus::Pending => "pending",
+            TransactionStatus::Completed => "completed",
+            TransactionStatus::Failed { .. } => "failed",
+            TransactionStatus::RequiresAction { .. } => "requires_action",
+            TransactionStatus::UnderReview => "under_review",
+            TransactionStatus::Refunded => "refunded",
+        }
+    }
+}
+
+#[derive(Debug , Serialize , Deserialize, Clone, Copy, PartialEq, ToSchema)]
 pub enum Currency {
     USD,
     EURO,
     INR   
 }
 
+impl FromStr for Currency {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "USD" => Ok(Currency::USD),
+            "EUR" | "EURO" => Ok(Currency::EURO),
+            "INR" => Ok(Currency::INR),
+            other => Err(format!("Unsupported currency {}", other))
+        }
+    }
+}
+
 impl Default for Transaction {
     fn default() -> Self {
         Self { 
             id: Uuid::new_v4(), 
             amount: 0, 
             currency: Currency::USD,
+            merchant_id: String::new(),
+            customer_id: String::new(),
             status: TransactionStatus::Pending, 
+            provider_payment_id: None,
+            client_secret: None,
+            metadata: HashMap::new(),
+            fee: None,
+            splits: Vec::new(),
+            amount_refunded: 0,
             created_at: Utc::now(), 
             update_at: Utc::now()
         }
@@ -47,7 +108,35 @@ pub struct PaymentCommand {
     pub customer_id : String,
     pub amount: i64,
     pub currency : Currency,
-    // todo: add other details later to include payment methods n all
+    // provider token of a method saved on the customer, falls back to their default
+    pub payment_method_id: Option<String>,
+}
+
+// customers and their saved payment methods
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Customer {
+    pub id: String,
+    pub merchant_id: String,
+    pub email: Option<String>,
+    pub name: Option<String>,
+    // the customer as stripe knows it, payment methods are attached to this one
+    pub provider_customer_id: String,
+    pub default_payment_method_id: Option<String>,
+    pub payment_methods: Vec<SavedPaymentMethod>,
+    pub created_at: chrono::DateTime<Utc>,
+    pub updated_at: chrono::DateTime<Utc>
+}
+
+impl Customer {
+    pub fn has_payment_method(&self, payment_method_id: &str) -> bool {
+        self.payment_methods.iter().any(|pm| pm.id == payment_method_id)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedPaymentMethod {
+    pub id: String,
+    pub attached_at: chrono::DateTime<Utc>
 }
 
 #[derive(Debug , Serialize , Deserialize)]
@@ -76,4 +165,477 @@ impl IdempotencyKey {
     pub fn from_string(key: String) -> Self {
         Self(key)
     }
-}
\ No newline at end of file
+}
+
+// merchant webhooks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookEndpoint {
+    pub merchant_id: String,
+    pub url: String,
+    #[serde(skip_serializing)]
+    pub secret: String,
+    pub created_at: chrono::DateTime<Utc>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum DeliveryState {
+    Pending,
+    Delivered,
+    // retries exhausted, only a manual redelivery can move it on
+    Failed
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeliveryAttempt {
+    pub attempt: u32,
+    pub attempted_at: chrono::DateTime<Utc>,
+    pub status_code: Option<u16>,
+    pub error: Option<String>,
+    pub succeeded: bool,
+    pub manual: bool
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    pub event_id: Uuid,
+    pub merchant_id: String,
+    pub transaction_id: Uuid,
+    pub payload: String,
+    pub state: DeliveryState,
+    pub created_at: chrono::DateTime<Utc>,
+    pub next_attempt_at: Option<chrono::DateTime<Utc>>,
+    pub attempts: Vec<DeliveryAttempt>
+}
+
+// recurring billing
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum BillingInterval {
+    Day,
+    Week,
+    Month,
+    Year
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Plan {
+    pub id: Uuid,
+    pub merchant_id: String,
+    pub name: String,
+    pub amount: u64,
+    pub currency: String,
+    pub interval: BillingInterval,
+    pub interval_count: u32,
+    pub trial_days: u32,
+    pub created_at: chrono::DateTime<Utc>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum SubscriptionStatus {
+    Trialing,
+    Active,
+    // latest invoice failed, dunning is retrying it
+    PastDue,
+    // dunning gave up, the subscription stays around but nothing is billed
+    Unpaid,
+    Canceled
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Subscription {
+    pub id: Uuid,
+    pub merchant_id: String,
+    pub customer_id: String,
+    pub plan_id: Uuid,
+    pub status: SubscriptionStatus,
+    // period boundaries are counted from the anchor so short months don't drift the billing day
+    pub billing_anchor: chrono::DateTime<Utc>,
+    pub current_period_start: chrono::DateTime<Utc>,
+    pub current_period_end: chrono::DateTime<Utc>,
+    pub trial_end: Option<chrono::DateTime<Utc>>,
+    pub cancel_at_period_end: bool,
+    // signed amount carried onto the next invoice after a mid-period plan change
+    pub pending_proration: i64,
+    pub canceled_at: Option<chrono::DateTime<Utc>>,
+    pub created_at: chrono::DateTime<Utc>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum InvoiceStatus {
+    Open,
+    Paid,
+    Uncollectible,
+    // nothing to charge, e.g. a credit from proration covered the period
+    Void
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Invoice {
+    pub id: Uuid,
+    pub subscription_id: Uuid,
+    pub merchant_id: String,
+    pub customer_id: String,
+    pub amount: u64,
+    pub currency: String,
+    pub period_start: chrono::DateTime<Utc>,
+    pub period_end: chrono::DateTime<Utc>,
+    pub status: InvoiceStatus,
+    // every charge attempt is its own transaction
+    pub transaction_ids: Vec<Uuid>,
+    pub attempt_count: u32,
+    pub next_retry_at: Option<chrono::DateTime<Utc>>,
+    pub created_at: chrono::DateTime<Utc>
+}
+
+// double-entry ledger
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum LedgerAccount {
+    // what we owe the merchant for settled payments
+    MerchantBalance { merchant_id: String },
+    // authorised but not yet settled
+    MerchantPending { merchant_id: String },
+    PlatformFees,
+    // funds sitting with the provider on our behalf
+    ProviderClearing,
+    // expected from the provider once a pending payment settles
+    ProviderReceivable,
+    RefundsPayable { merchant_id: String }
+}
+
+impl LedgerAccount {
+    /// The merchant whose money the account holds, None for the platform's own accounts.
+    pub fn merchant_id(&self) -> Option<&str> {
+        match self {
+            LedgerAccount::MerchantBalance { merchant_id }
+            | LedgerAccount::MerchantPending { merchant_id }
+            | LedgerAccount::RefundsPayable { merchant_id } => Some(merchant_id),
+            LedgerAccount::PlatformFees | LedgerAccount::ProviderClearing | LedgerAccount::ProviderReceivable => None,
+        }
+    }
+
+    /// Assets grow with debits, liabilities and revenue with credits.
+    pub fn is_debit_normal(&self) -> bool {
+        matches!(self, LedgerAccount::ProviderClearing | LedgerAccount::ProviderReceivable)
+    }
+
+    pub fn code(&self) -> String {
+        match self {
+            LedgerAccount::MerchantBalance { merchant_id } => format!("merchant_balance:{}", merchant_id),
+            LedgerAccount::MerchantPending { merchant_id } => format!("merchant_pending:{}", merchant_id),
+            LedgerAccount::PlatformFees => "platform_fees".to_string(),
+            LedgerAccount::ProviderClearing => "provider_clearing".to_string(),
+            LedgerAccount::ProviderReceivable => "provider_receivable".to_string(),
+            LedgerAccount::RefundsPayable { merchant_id } => format!("refunds_payable:{}", merchant_id),
+        }
+    }
+}
+
+impl FromStr for LedgerAccount {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, merchant_id) = match s.split_once(':') {
+            Some((kind, merchant_id)) => (kind, Some(merchant_id.to_string())),
+            None => (s, None),
+        };
+
+        match (kind, merchant_id) {
+            ("merchant_balance", Some(merchant_id)) => Ok(LedgerAccount::MerchantBalance { merchant_id }),
+            ("merchant_pending", Some(merchant_id)) => Ok(LedgerAccount::MerchantPending { merchant_id }),
+            ("refunds_payable", Some(merchant_id)) => Ok(LedgerAccount::RefundsPayable { merchant_id }),
+            ("platform_fees", None) => Ok(LedgerAccount::PlatformFees),
+            ("provider_clearing", None) => Ok(LedgerAccount::ProviderClearing),
+            ("provider_receivable", None) => Ok(LedgerAccount::ProviderReceivable),
+            _ => Err(format!("Unknown ledger account {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Posting {
+    pub account: LedgerAccount,
+    // positive debits, negative credits, in minor units
+    pub amount: i64
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub id: Uuid,
+    pub transaction_id: Uuid,
+    // the event that caused the entry, entries are never posted twice for it
+    pub source: String,
+    pub description: String,
+    pub currency: String,
+    pub postings: Vec<Posting>,
+    pub effective_at: chrono::DateTime<Utc>,
+    pub recorded_at: chrono::DateTime<Utc>
+}
+
+impl JournalEntry {
+    pub fn total(&self) -> i64 {
+        self.postings.iter().map(|p| p.amount).sum()
+    }
+}
+
+// merchant pricing
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct FeeRate {
+    // 1 bps = 0.01%
+    pub percentage_bps: u32,
+    pub fixed_fee: u64
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VolumeTier {
+    // month to date volume this tier applies up to, `None` for the last tier
+    pub up_to: Option<u64>,
+    pub percentage_bps: u32
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PricingPlan {
+    pub merchant_id: String,
+    pub default_rate: FeeRate,
+    #[serde(default)]
+    pub currency_overrides: HashMap<String, FeeRate>,
+    // when set, the month's volume picks the percentage instead of the rate's own
+    #[serde(default)]
+    pub volume_tiers: Vec<VolumeTier>,
+    pub updated_at: chrono::DateTime<Utc>
+}
+
+impl PricingPlan {
+    // what merchants pay until they're put on their own plan
+    pub fn standard(merchant_id: String) -> Self {
+        Self {
+            merchant_id,
+            default_rate: FeeRate { percentage_bps: 290, fixed_fee: 30 },
+            currency_overrides: HashMap::new(),
+            volume_tiers: Vec::new(),
+            updated_at: Utc::now()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct FeeBreakdown {
+    pub percentage_bps: u32,
+    pub percentage_fee: u64,
+    pub fixed_fee: u64,
+    pub total: u64,
+    // index into the plan's volume tiers, if one applied
+    pub volume_tier: Option<usize>
+}
+
+// a refund as the query side keeps it, amounts in the minor unit like the transaction's
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Refund {
+    pub id: Uuid,
+    pub transaction_id: Uuid,
+    pub merchant_id: String,
+    pub amount: u64,
+    pub currency: String,
+    pub reason: Option<String>,
+    pub created_at: chrono::DateTime<Utc>
+}
+
+// settlement and payouts
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum SettlementItemKind {
+    Payment,
+    Refund
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SettlementItem {
+    pub transaction_id: Uuid,
+    pub kind: SettlementItemKind,
+    pub amount: u64,
+    pub fee: u64,
+    // what the item adds to the payout, negative for refunds
+    pub net: i64,
+    pub occurred_at: chrono::DateTime<Utc>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum SettlementStatus {
+    // still collecting the day's payments
+    Open,
+    PaidOut,
+    // nothing was owed, the (negative) net moves into the next payout
+    CarriedForward
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SettlementBatch {
+    pub id: Uuid,
+    pub merchant_id: String,
+    pub currency: String,
+    // the day the batch's payments completed
+    pub settlement_date: chrono::NaiveDate,
+    // settlement date plus the payout delay
+    pub available_on: chrono::NaiveDate,
+    pub items: Vec<SettlementItem>,
+    pub gross: u64,
+    pub fees: u64,
+    pub refunds: u64,
+    pub net: i64,
+    // balance left over from earlier batches or failed payouts, known once released
+    pub carried_in: i64,
+    pub status: SettlementStatus,
+    pub payout_id: Option<Uuid>,
+    pub created_at: chrono::DateTime<Utc>,
+    pub released_at: Option<chrono::DateTime<Utc>>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum PayoutStatus {
+    Pending,
+    Paid,
+    Failed { reason: String }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Payout {
+    pub id: Uuid,
+    pub batch_id: Uuid,
+    pub merchant_id: String,
+    pub currency: String,
+    pub amount: u64,
+    pub status: PayoutStatus,
+    pub created_at: chrono::DateTime<Utc>,
+    pub updated_at: chrono::DateTime<Utc>
+}
+
+// marketplace split payments
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectedAccount {
+    pub merchant_id: String,
+    // the seller's stripe connect account, `acct_...`
+    pub provider_account_id: String,
+    pub created_at: chrono::DateTime<Utc>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct SplitLeg {
+    pub destination_merchant_id: String,
+    pub provider_account_id: String,
+    // the leg's share of the charge, the application fee included
+    pub amount: u64,
+    // kept by the platform, the seller receives `amount - application_fee`
+    pub application_fee: u64,
+    // set once the funds were transferred to the seller
+    #[serde(default)]
+    pub transfer_id: Option<String>
+}
+
+impl SplitLeg {
+    pub fn seller_amount(&self) -> u64 {
+        self.amount - self.application_fee
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct SplitReversal {
+    pub destination_merchant_id: String,
+    pub provider_account_id: String,
+    // pulled back from the seller
+    pub transfer_reversal: u64,
+    // given up by the platform
+    pub application_fee_refund: u64
+}
+
+/* what the client told us about the buyer, only used for risk scoring */
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct RiskContext {
+    pub ip_address: Option<String>,
+    pub billing_country: Option<String>,
+    // where the card was issued, compared against the billing country
+    pub card_country: Option<String>
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RiskAction {
+    Allow,
+    Review,
+    Block
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum RiskDecisionSource {
+    Engine,
+    Manual {
+        reviewer: String
+    }
+}
+
+/* one entry of a transaction's risk audit trail */
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RiskDecision {
+    pub id: Uuid,
+    pub transaction_id: Uuid,
+    pub merchant_id: String,
+    pub action: RiskAction,
+    pub source: RiskDecisionSource,
+    // the rules that matched, or the reviewer's note
+    pub reasons: Vec<String>,
+    pub decided_at: chrono::DateTime<Utc>
+}
+
+/* who did something, from the token they called us with */
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Actor {
+    pub subject: String,
+    pub role: String
+}
+
+impl Actor {
+    // requests that got past the api without a token, tests and local runs
+    pub fn anonymous() -> Self {
+        Self {
+            subject: "anonymous".to_string(),
+            role: "none".to_string()
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Login,
+    LoginFailed,
+    TransactionCreated,
+    RefundCreated,
+    PricingPlanChanged,
+    RiskRulesChanged,
+    BlocklistChanged,
+    ReviewApproved,
+    ReviewDeclined,
+    PayoutStatusChanged,
+    ConnectedAccountRegistered,
+    // what support does with payme-admin
+    TransactionForceFailed,
+    EventsReplayed,
+    MerchantCreated,
+    UserCreated,
+    ApiKeyCreated,
+    JwtKeyRotated
+}
+
+/* one link of the audit chain, `hash` covers the rest of the entry and the previous entry's hash */
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AuditEntry {
+    pub sequence: u64,
+    pub id: Uuid,
+    pub recorded_at: chrono::DateTime<Utc>,
+    pub actor: Actor,
+    pub action: AuditAction,
+    // the transaction, merchant or payout acted on
+    pub target: Option<String>,
+    pub request_id: String,
+    pub ip_address: Option<String>,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+    pub previous_hash: String,
+    pub hash: String
+}
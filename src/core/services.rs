@@ -1,2 +1,13 @@
+pub mod admin;
+pub mod admin_test;
+pub mod fee_calculator;
+pub mod ledger_consumer;
+pub mod report_scheduler;
 pub mod payment_processor;
-pub mod status_consumer;
\ No newline at end of file
+pub mod risk_engine;
+pub mod settlement_service;
+pub mod status_consumer;
+pub mod subscription_scheduler;
+pub mod transaction_sweeper;
+pub mod transaction_sweeper_test;
+pub mod webhook_dispatcher;
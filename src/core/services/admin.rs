@@ -0,0 +1,245 @@
+use std::sync::Arc;
+
+use chrono::Utc;
+use serde_json::{json, Value};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::{
+    api::authentication::keys::KeyRing,
+    core::{
+        api::commands::{CommandError, RefundState},
+        config::{Config, Topics},
+        events::{PaymentStatusUpdatedEvent, RefundCreatedEvent},
+        infrastructure::{
+            accounts::{AccountError, AccountStore, ApiKey, Merchant, User},
+            audit::{AuditLog, AuditRecord},
+            dead_letter::DeadLetter,
+            projection::{ProjectionEvent, TransactionProjection},
+            publisher::{EventLog, EventPublisher, RecordedEvent},
+            stripe::StripeService,
+        },
+        models::{Actor, AuditAction, Transaction, TransactionStatus},
+    },
+};
+
+#[derive(Debug, Error)]
+pub enum AdminError {
+    #[error("No events for transaction {0}")]
+    NotFound(Uuid),
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("Payment provider error: {0}")]
+    Provider(String),
+    #[error("Failed to read events: {0}")]
+    Events(String),
+    #[error("Failed to publish event: {0}")]
+    Publish(String),
+    #[error("Key ring: {0}")]
+    Keys(String),
+    #[error(transparent)]
+    Accounts(#[from] AccountError),
+    #[error(transparent)]
+    Command(#[from] CommandError),
+}
+
+/// A transaction as its events have it, and the events themselves in the order they happened.
+pub struct TransactionHistory {
+    pub transaction: Transaction,
+    pub events: Vec<RecordedEvent>,
+}
+
+/* what payme-admin does, through the same stores, events and provider calls as the api, every change audited */
+pub struct AdminService {
+    topics: Topics,
+    events: Arc<dyn EventLog>,
+    publisher: Arc<dyn EventPublisher>,
+    stripe_service: Arc<StripeService>,
+    accounts: AccountStore,
+    keys_path: String,
+    audit: AuditLog,
+    actor: Actor,
+    // every entry of one run shares it, like the entries of one api request
+    run_id: String,
+}
+
+impl AdminService {
+    pub fn new(config: &Config, events: Arc<dyn EventLog>, publisher: Arc<dyn EventPublisher>, audit: AuditLog, actor: Actor) -> Self {
+        Self {
+            topics: config.kafka.topics.clone(),
+            events,
+            publisher,
+            stripe_service: Arc::new(StripeService::new(config.stripe.secret_key.expose(), &config.server.public_url)),
+            accounts: AccountStore::from_path(&config.auth.accounts_path),
+            keys_path: config.auth.keys_path.clone(),
+            audit,
+            actor,
+            run_id: Uuid::new_v4().to_string(),
+        }
+    }
+
+    /// Reads every topic the transaction's state comes from, so it can take a while on a big cluster.
+    pub async fn history(&self, transaction_id: Uuid) -> Result<TransactionHistory, AdminError> {
+        let (projection, events) = self.rebuild(transaction_id).await?;
+        let transaction = projection.get(transaction_id).await.ok_or(AdminError::NotFound(transaction_id))?;
+        Ok(TransactionHistory { transaction, events })
+    }
+
+    /// Fails a payment that's stuck pending. A payment the provider already has is canceled there
+    /// first, when that doesn't work it may have gone through and nothing is changed.
+    pub async fn force_fail(&self, transaction_id: Uuid, reason: &str) -> Result<PaymentStatusUpdatedEvent, AdminError> {
+        let transaction = self.history(transaction_id).await?.transaction;
+        if transaction.status != TransactionStatus::Pending {
+            return Err(AdminError::InvalidRequest(format!("transaction is {}, only pending ones can be failed", transaction.status.kind())));
+        }
+
+        let provider_payment_id = transaction.provider_payment_id.clone().unwrap_or_default();
+        if !provider_payment_id.is_empty() {
+            self.stripe_service
+                .cancel_abandoned_intent(&provider_payment_id)
+                .await
+                .map_err(|e| AdminError::Provider(e.to_string()))?;
+        }
+
+        let status = TransactionStatus::Failed { reason: reason.to_string() };
+        let event = PaymentStatusUpdatedEvent::new(transaction_id, transaction.merchant_id.clone(), status, provider_payment_id);
+        self.publisher
+            .publish_event(&self.topics.payment_status, &transaction_id.to_string(), &event)
+            .await
+            .map_err(AdminError::Publish)?;
+
+        self.record(self.entry(AuditAction::TransactionForceFailed).target(transaction_id).before(&transaction).after(&event)).await;
+        Ok(event)
+    }
+
+    /// The api's refund, against the transaction as rebuilt from its events.
+    pub async fn refund(&self, transaction_id: Uuid, amount: Option<u64>, reason: Option<String>) -> Result<RefundCreatedEvent, AdminError> {
+        let (projection, _) = self.rebuild(transaction_id).await?;
+        let refunds = RefundState {
+            stripe_service: self.stripe_service.clone(),
+            projection,
+            publisher: self.publisher.clone(),
+            topics: Arc::new(self.topics.clone()),
+        };
+        let (transaction, event) = refunds.refund(transaction_id, amount, reason).await?;
+
+        self.record(self.entry(AuditAction::RefundCreated).target(transaction_id).before(&transaction).after(&event)).await;
+        Ok(event)
+    }
+
+    /// Publishes the transaction's events again, to their topics and keys, for consumers that missed
+    /// or mishandled them. Consumers skip events they've already applied, the webhook dispatcher
+    /// by event id so merchants aren't notified twice. The creation isn't
+    /// replayed, the processor would charge the payment a second time.
+    pub async fn replay(&self, transaction_id: Uuid) -> Result<Vec<RecordedEvent>, AdminError> {
+        let replayed: Vec<RecordedEvent> = self
+            .history(transaction_id)
+            .await?
+            .events
+            .into_iter()
+            .filter(|event| event.topic != self.topics.transactions)
+            .collect();
+
+        for event in &replayed {
+            let payload: Value = serde_json::from_str(&event.payload).map_err(|e| AdminError::Events(e.to_string()))?;
+            let key = event.key.clone().unwrap_or_else(|| transaction_id.to_string());
+            self.publisher.publish_event(&event.topic, &key, &payload).await.map_err(AdminError::Publish)?;
+        }
+
+        let summary: Vec<Value> = replayed.iter().map(|event| json!({ "topic": event.topic, "offset": event.offset })).collect();
+        self.record(self.entry(AuditAction::EventsReplayed).target(transaction_id).after(&summary)).await;
+        Ok(replayed)
+    }
+
+    /// The most recent `limit` messages consumers rejected, oldest first.
+    pub async fn dead_letters(&self, limit: usize) -> Result<Vec<DeadLetter>, AdminError> {
+        let events = self.events.read(&[&self.topics.dead_letter]).await.map_err(AdminError::Events)?;
+        let letters: Vec<DeadLetter> = events
+            .iter()
+            .filter_map(|event| match serde_json::from_str(&event.payload) {
+                Ok(letter) => Some(letter),
+                Err(e) => {
+                    eprintln!("Skipping unreadable dead letter at offset {}: {}", event.offset, e);
+                    None
+                }
+            })
+            .collect();
+
+        let skip = letters.len().saturating_sub(limit);
+        Ok(letters.into_iter().skip(skip).collect())
+    }
+
+    pub async fn create_merchant(&self, id: &str, name: &str) -> Result<Merchant, AdminError> {
+        let merchant = self.accounts.create_merchant(id, name).await?;
+        self.record(self.entry(AuditAction::MerchantCreated).target(&merchant.id).after(&merchant)).await;
+        Ok(merchant)
+    }
+
+    pub async fn create_user(&self, username: &str, password: &str, role: &str, merchant_id: Option<&str>) -> Result<User, AdminError> {
+        let user = self.accounts.create_user(username, password, role, merchant_id).await?;
+        // the hash stays out of the audit log
+        let after = json!({ "username": user.username, "role": user.role, "merchant_id": user.merchant_id });
+        self.record(self.entry(AuditAction::UserCreated).target(&user.username).after(&after)).await;
+        Ok(user)
+    }
+
+    /// The key itself is returned here and nowhere else.
+    pub async fn create_api_key(&self, merchant_id: &str) -> Result<(ApiKey, String), AdminError> {
+        let (key, secret) = self.accounts.create_api_key(merchant_id).await?;
+        let after = json!({ "id": key.id, "merchant_id": key.merchant_id, "prefix": key.prefix });
+        self.record(self.entry(AuditAction::ApiKeyCreated).target(key.id).after(&after)).await;
+        Ok((key, secret))
+    }
+
+    /// Signs new tokens with a fresh key from the api's next start, tokens already out stay valid.
+    /// Returns the new key's id.
+    pub async fn rotate_jwt_key(&self) -> Result<String, AdminError> {
+        if self.keys_path.trim().is_empty() {
+            return Err(AdminError::InvalidRequest("auth.keys_path isn't set".to_string()));
+        }
+
+        let mut ring = KeyRing::load(&self.keys_path).map_err(AdminError::Keys)?;
+        let before: Vec<&str> = ring.keys.iter().map(|key| key.kid.as_str()).collect();
+        let before = json!({ "kids": before });
+        let kid = ring.rotate(Utc::now()).kid.clone();
+        ring.save(&self.keys_path).map_err(AdminError::Keys)?;
+
+        // key ids only, the secrets never leave the ring
+        let after: Vec<&str> = ring.keys.iter().map(|key| key.kid.as_str()).collect();
+        self.record(self.entry(AuditAction::JwtKeyRotated).target(&kid).before(&before).after(&json!({ "kids": after }))).await;
+        Ok(kid)
+    }
+
+    /* the transaction's events and a projection of nothing but them */
+    async fn rebuild(&self, transaction_id: Uuid) -> Result<(TransactionProjection, Vec<RecordedEvent>), AdminError> {
+        let mut events: Vec<(RecordedEvent, ProjectionEvent)> = self
+            .events
+            .read(&ProjectionEvent::topics(&self.topics))
+            .await
+            .map_err(AdminError::Events)?
+            .into_iter()
+            .filter_map(|event| self.parse(&event).map(|parsed| (event, parsed)))
+            .filter(|(_, parsed)| parsed.transaction_id() == transaction_id)
+            .collect();
+        // topics are read one after the other, the broker's timestamps put them back in order
+        events.sort_by_key(|(event, _)| event.timestamp);
+
+        let projection = TransactionProjection::default();
+        for (_, parsed) in &events {
+            projection.apply(parsed).await;
+        }
+        Ok((projection, events.into_iter().map(|(event, _)| event).collect()))
+    }
+
+    fn parse(&self, event: &RecordedEvent) -> Option<ProjectionEvent> {
+        ProjectionEvent::parse(&self.topics, &event.topic, event.payload.as_bytes()).ok()
+    }
+
+    fn entry(&self, action: AuditAction) -> AuditRecord {
+        AuditRecord::new(self.actor.clone(), action, self.run_id.clone(), None)
+    }
+
+    async fn record(&self, record: AuditRecord) {
+        self.audit.record(record).await;
+    }
+}
@@ -0,0 +1,178 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use uuid::Uuid;
+
+    use crate::{
+        api::authentication::keys::KeyRing,
+        core::{
+            config::Config,
+            events::{PaymentStatusUpdatedEvent, TransactionCreatedEvent},
+            infrastructure::{
+                audit::{AuditFilter, AuditLog},
+                dead_letter::DeadLetter,
+                publisher::{EventPublisher, InMemoryPublisher, RecordedEvent},
+            },
+            models::{Actor, AuditAction, AuditEntry, TransactionStatus},
+            services::admin::{AdminError, AdminService},
+        },
+    };
+
+    struct Setup {
+        admin: AdminService,
+        publisher: Arc<InMemoryPublisher>,
+        audit: AuditLog,
+        config: Config,
+    }
+
+    fn setup(config: Config) -> Setup {
+        let publisher = Arc::new(InMemoryPublisher::new());
+        let audit = AuditLog::new();
+        let actor = Actor { subject: "support_1".to_string(), role: "admin".to_string() };
+        let admin = AdminService::new(&config, publisher.clone(), publisher.clone(), audit.clone(), actor);
+        Setup { admin, publisher, audit, config }
+    }
+
+    // oldest first
+    async fn logged(audit: &AuditLog) -> Vec<AuditEntry> {
+        let mut entries = audit.search(&AuditFilter::default(), usize::MAX).await;
+        entries.reverse();
+        entries
+    }
+
+    async fn create(setup: &Setup, status: Option<TransactionStatus>) -> Uuid {
+        let topics = &setup.config.kafka.topics;
+        let id = Uuid::new_v4();
+        let created = TransactionCreatedEvent::new(id, 2500, "USD".to_string(), "merch_1".to_string(), "cust_1".to_string());
+        setup.publisher.publish(&topics.transactions, &id.to_string(), serde_json::to_string(&created).unwrap()).await.unwrap();
+        if let Some(status) = status {
+            let updated = PaymentStatusUpdatedEvent::new(id, "merch_1".to_string(), status, "pi_1".to_string());
+            setup.publisher.publish(&topics.payment_status, &id.to_string(), serde_json::to_string(&updated).unwrap()).await.unwrap();
+        }
+        id
+    }
+
+    #[tokio::test]
+    async fn test_history_is_the_transaction_and_only_its_events() {
+        let setup = setup(Config::default());
+        let id = create(&setup, Some(TransactionStatus::Completed)).await;
+        create(&setup, None).await;
+
+        let history = setup.admin.history(id).await.unwrap();
+
+        assert_eq!(history.transaction.status, TransactionStatus::Completed);
+        assert_eq!(history.transaction.provider_payment_id.as_deref(), Some("pi_1"));
+        let topics: Vec<&str> = history.events.iter().map(|event| event.topic.as_str()).collect();
+        assert_eq!(topics, ["transactions", "payment-status"]);
+        assert!(matches!(setup.admin.history(Uuid::new_v4()).await, Err(AdminError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_only_pending_transactions_can_be_force_failed() {
+        let setup = setup(Config::default());
+        let pending = create(&setup, None).await;
+        let completed = create(&setup, Some(TransactionStatus::Completed)).await;
+
+        let event = setup.admin.force_fail(pending, "stuck since the outage").await.unwrap();
+
+        assert_eq!(event.status, TransactionStatus::Failed { reason: "stuck since the outage".to_string() });
+        assert_eq!(setup.admin.history(pending).await.unwrap().transaction.status.kind(), "failed");
+        assert!(matches!(setup.admin.force_fail(completed, "oops").await, Err(AdminError::InvalidRequest(_))));
+
+        let entries = logged(&setup.audit).await;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].action, AuditAction::TransactionForceFailed);
+        assert_eq!(entries[0].actor.subject, "support_1");
+    }
+
+    #[tokio::test]
+    async fn test_refunds_go_through_the_api_checks() {
+        let setup = setup(Config::default());
+        let pending = create(&setup, None).await;
+
+        let refused = setup.admin.refund(pending, None, None).await;
+
+        assert!(matches!(refused, Err(AdminError::Command(_))));
+        assert!(setup.publisher.published(&setup.config.kafka.topics.refunds).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_replay_republishes_everything_but_the_creation() {
+        let setup = setup(Config::default());
+        let id = create(&setup, Some(TransactionStatus::Completed)).await;
+
+        let replayed = setup.admin.replay(id).await.unwrap();
+
+        assert_eq!(replayed.len(), 1);
+        let topics = &setup.config.kafka.topics;
+        assert_eq!(setup.publisher.published(&topics.transactions).len(), 1);
+        let statuses = setup.publisher.published(&topics.payment_status);
+        assert_eq!(statuses.len(), 2);
+        assert_eq!(statuses[0], statuses[1]);
+        assert_eq!(logged(&setup.audit).await[0].action, AuditAction::EventsReplayed);
+    }
+
+    #[tokio::test]
+    async fn test_dead_letters_are_the_most_recent_ones() {
+        let setup = setup(Config::default());
+        let topic = &setup.config.kafka.topics.dead_letter;
+        for offset in 0..3 {
+            let letter = DeadLetter {
+                consumer: "fee-calculator".to_string(),
+                kind: "fee".to_string(),
+                error: "expected value".to_string(),
+                rejected_at: chrono::Utc::now(),
+                message: RecordedEvent {
+                    topic: "fees".to_string(),
+                    partition: 0,
+                    offset,
+                    key: None,
+                    timestamp: None,
+                    payload: "{".to_string(),
+                },
+            };
+            setup.publisher.publish(topic, "", serde_json::to_string(&letter).unwrap()).await.unwrap();
+        }
+        setup.publisher.publish(topic, "", "not a letter".to_string()).await.unwrap();
+
+        let letters = setup.admin.dead_letters(2).await.unwrap();
+
+        let offsets: Vec<i64> = letters.iter().map(|letter| letter.message.offset).collect();
+        assert_eq!(offsets, [1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_accounts_and_key_rotation_are_audited_without_secrets() {
+        let mut config = Config::default();
+        let keys_path = std::env::temp_dir().join(format!("payme-admin-keys-{}.json", Uuid::new_v4()));
+        config.auth.keys_path = keys_path.to_string_lossy().into_owned();
+        let setup = setup(config);
+
+        setup.admin.create_merchant("merch_1", "Coffee Shop").await.unwrap();
+        setup.admin.create_user("barista", "correct horse battery", "merchant", Some("merch_1")).await.unwrap();
+        let (_, api_key) = setup.admin.create_api_key("merch_1").await.unwrap();
+        let first = setup.admin.rotate_jwt_key().await.unwrap();
+        let second = setup.admin.rotate_jwt_key().await.unwrap();
+        assert_ne!(first, second);
+
+        let entries = logged(&setup.audit).await;
+        let actions: Vec<AuditAction> = entries.iter().map(|entry| entry.action).collect();
+        assert_eq!(
+            actions,
+            [
+                AuditAction::MerchantCreated,
+                AuditAction::UserCreated,
+                AuditAction::ApiKeyCreated,
+                AuditAction::JwtKeyRotated,
+                AuditAction::JwtKeyRotated
+            ]
+        );
+        let logged = serde_json::to_string(&entries).unwrap();
+        assert!(!logged.contains("correct horse battery") && !logged.contains("password_hash") && !logged.contains(&api_key));
+        let ring = KeyRing::load(&keys_path).unwrap();
+        assert_eq!(ring.current().unwrap().kid, second);
+        assert!(ring.keys.iter().all(|key| !logged.contains(&key.secret)));
+        std::fs::remove_file(keys_path).unwrap();
+    }
+}
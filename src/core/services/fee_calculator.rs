@@ -0,0 +1,181 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use chrono::Utc;
+use rdkafka::{
+    consumer::{Consumer, StreamConsumer},
+    ClientConfig, Message,
+};
+use tokio::sync::Mutex;
+use tracing::Instrument;
+use uuid::Uuid;
+
+use crate::core::{
+    config::{KafkaConfig, Topics},
+    events::{FeeAssessedEvent, PaymentStatusUpdatedEvent, TransactionCreatedEvent},
+    infrastructure::{dead_letter::dead_letter, kafka::KafkaProducer, metrics::metrics, pricing::PricingStore, shutdown::{commit_offsets, Shutdown}, telemetry::consumer_span},
+    models::TransactionStatus,
+};
+
+// a completion whose creation hasn't shown up by then is a duplicate of one already assessed
+const PARKED_FOR_MINS: i64 = 60;
+// publishing the fee is tried a few times before the completion is dead lettered
+const PUBLISH_ATTEMPTS: u32 = 3;
+const PUBLISH_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Default)]
+struct FeeState {
+    // what the status events don't carry: who to charge and for how much, until the payment is
+    // assessed or failed
+    transactions: HashMap<Uuid, TransactionCreatedEvent>,
+    // completions that overtook their creation event
+    parked: HashMap<Uuid, PaymentStatusUpdatedEvent>,
+}
+
+/* prices every completed payment against the merchant's plan and publishes the fee */
+pub struct FeeCalculator {
+    consumer: Arc<StreamConsumer>,
+    producer: KafkaProducer,
+    topics: Topics,
+    pricing: PricingStore,
+    state: Mutex<FeeState>,
+}
+
+impl FeeCalculator {
+    pub fn new(kafka: &KafkaConfig, pricing: PricingStore) -> Self {
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("group.id", &kafka.groups.fee_calculator)
+            .set("bootstrap.servers", &kafka.brokers)
+            .set("enable.auto.commit", "true")
+            .create()
+            .expect("Failed to create consumer");
+
+        let producer = KafkaProducer::new(&kafka.brokers, &kafka.topics.fees);
+
+        Self {
+            consumer: Arc::new(consumer),
+            producer,
+            topics: kafka.topics.clone(),
+            pricing,
+            state: Mutex::new(FeeState::default()),
+        }
+    }
+
+    pub async fn start(&self, shutdown: Shutdown) -> Result<(), Box<dyn std::error::Error>> {
+        println!("Starting fee calculator...");
+
+        self.consumer
+            .subscribe(&[&self.topics.transactions, &self.topics.payment_status])
+            .expect("Failed to subscribe to fee calculator topics");
+
+        loop {
+            let received = tokio::select! {
+                received = self.consumer.recv() => received,
+                _ = shutdown.wait() => break,
+            };
+            match received {
+                Ok(msg) => {
+                    metrics().record_consumed("fee_calculator", &self.consumer, &msg);
+                    let span = consumer_span("fee_calculator", &msg);
+                    async {
+                        if let Some(payload) = msg.payload() {
+                            match msg.topic() {
+                                topic if topic == self.topics.transactions => match serde_json::from_slice::<TransactionCreatedEvent>(payload) {
+                                    Ok(event) => self.track(&msg, event).await,
+                                    Err(e) => dead_letter("fee_calculator", &msg, "transaction", e).await,
+                                },
+                                _ => match serde_json::from_slice::<PaymentStatusUpdatedEvent>(payload) {
+                                    Ok(event) => self.on_status(&msg, event).await,
+                                    Err(e) => dead_letter("fee_calculator", &msg, "status", e).await,
+                                },
+                            }
+                        }
+                    }
+                    .instrument(span)
+                    .await;
+                }
+                Err(e) => eprintln!("Failed to receive message: {}", e),
+            }
+        }
+
+        commit_offsets("fee_calculator", self.consumer.as_ref());
+        if let Err(e) = self.producer.flush().await {
+            eprintln!("Events may have been lost on shutdown: {}", e);
+        }
+        Ok(())
+    }
+
+    async fn track<M: Message>(&self, msg: &M, event: TransactionCreatedEvent) {
+        let parked = {
+            let mut state = self.state.lock().await;
+            let parked = state.parked.remove(&event.transaction_id);
+            state.transactions.insert(event.transaction_id, event);
+            parked
+        };
+
+        if let Some(status_event) = parked {
+            self.on_status(msg, status_event).await;
+        }
+    }
+
+    async fn on_status<M: Message>(&self, msg: &M, event: PaymentStatusUpdatedEvent) {
+        let transaction = {
+            let mut state = self.state.lock().await;
+            match event.status {
+                TransactionStatus::Completed => {}
+                // never assessed, nothing more to keep
+                TransactionStatus::Failed { .. } => {
+                    state.transactions.remove(&event.transaction_id);
+                    state.parked.remove(&event.transaction_id);
+                    return;
+                }
+                _ => return,
+            }
+
+            // taken out, so a completion consumed again finds nothing to assess
+            let Some(transaction) = state.transactions.remove(&event.transaction_id) else {
+                let cutoff = Utc::now() - chrono::Duration::minutes(PARKED_FOR_MINS);
+                state.parked.retain(|_, parked| parked.timestamp > cutoff);
+                state.parked.insert(event.transaction_id, event);
+                return;
+            };
+            transaction
+        };
+
+        // the application fees are our cut of split payments, and stripe pays the sellers directly
+        if !transaction.splits.is_empty() {
+            return;
+        }
+
+        let fee = self
+            .pricing
+            .assess(&transaction.merchant_id, transaction.amount, &transaction.currency, event.timestamp)
+            .await;
+
+        let fee_event = FeeAssessedEvent::new(
+            transaction.transaction_id,
+            transaction.merchant_id.clone(),
+            transaction.amount,
+            transaction.currency.clone(),
+            fee,
+        );
+
+        // offsets are committed automatically, the completion won't come back by itself
+        let mut attempt = 1;
+        loop {
+            match self.producer.publish_event(&fee_event).await {
+                Ok(()) => return,
+                Err(e) if attempt < PUBLISH_ATTEMPTS => {
+                    eprintln!("Failed to publish fee for {} (attempt {}): {}", transaction.transaction_id, attempt, e);
+                    tokio::time::sleep(PUBLISH_RETRY_DELAY * attempt).await;
+                    attempt += 1;
+                }
+                Err(e) => {
+                    // a replay of the completion assesses it again
+                    self.state.lock().await.transactions.insert(transaction.transaction_id, transaction);
+                    dead_letter("fee_calculator", msg, "fee", e).await;
+                    return;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use std::sync::Arc;
+
+use rdkafka::{
+    consumer::{Consumer, StreamConsumer},
+    ClientConfig, Message,
+};
+use tracing::Instrument;
+
+use crate::core::{
+    config::{KafkaConfig, Topics},
+    events::{FeeAssessedEvent, PaymentStatusUpdatedEvent, PayoutStatusUpdatedEvent, RefundCreatedEvent, TransactionCreatedEvent},
+    infrastructure::{
+        dead_letter::dead_letter,
+        ledger::{Ledger, LedgerError},
+        metrics::metrics,
+        shutdown::{commit_offsets, Shutdown},
+        telemetry::consumer_span,
+    },
+};
+
+pub struct LedgerConsumer {
+    consumer: Arc<StreamConsumer>,
+    topics: Topics,
+    ledger: Ledger,
+}
+
+impl LedgerConsumer {
+    pub fn new(kafka: &KafkaConfig, ledger: Ledger) -> Self {
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("group.id", &kafka.groups.ledger)
+            .set("bootstrap.servers", &kafka.brokers)
+            .set("enable.auto.commit", "true")
+            .create()
+            .expect("Failed to create consumer");
+
+        Self {
+            consumer: Arc::new(consumer),
+            topics: kafka.topics.clone(),
+            ledger,
+        }
+    }
+
+    pub async fn start(&self, shutdown: Shutdown) -> Result<(), Box<dyn std::error::Error>> {
+        println!("Starting ledger consumer...");
+
+        self.consumer
+            .subscribe(&[
+                &self.topics.transactions,
+                &self.topics.payment_status,
+                &self.topics.refunds,
+                &self.topics.fees,
+                &self.topics.payout_status,
+            ])
+            .expect("Failed to subscribe to ledger topics");
+
+        loop {
+            let received = tokio::select! {
+                received = self.consumer.recv() => received,
+                _ = shutdown.wait() => break,
+            };
+            match received {
+                Ok(msg) => {
+                    metrics().record_consumed("ledger", &self.consumer, &msg);
+                    let span = consumer_span("ledger", &msg);
+                    async {
+                        if let Some(payload) = msg.payload() {
+                            if let Err(e) = self.apply(&msg, payload).await {
+                                eprintln!("Failed to post to the ledger: {}", e);
+                            }
+                        }
+                    }
+                    .instrument(span)
+                    .await;
+                }
+                Err(e) => eprintln!("Failed to receive message: {}", e),
+            }
+        }
+
+        commit_offsets("ledger", self.consumer.as_ref());
+        Ok(())
+    }
+
+    async fn apply<M: Message>(&self, msg: &M, payload: &[u8]) -> Result<(), LedgerError> {
+        match msg.topic() {
+            t if t == self.topics.transactions => match serde_json::from_slice::<TransactionCreatedEvent>(payload) {
+                Ok(event) => self.ledger.record_created(&event).await?,
+                Err(e) => dead_letter("ledger", msg, "transaction", e).await,
+            },
+            t if t == self.topics.refunds => match serde_json::from_slice::<RefundCreatedEvent>(payload) {
+                Ok(event) => self.ledger.record_refund(&event).await?,
+                Err(e) => dead_letter("ledger", msg, "refund", e).await,
+            },
+            t if t == self.topics.fees => match serde_json::from_slice::<FeeAssessedEvent>(payload) {
+                Ok(event) => self.ledger.record_fee(&event).await?,
+                Err(e) => dead_letter("ledger", msg, "fee", e).await,
+            },
+            t if t == self.topics.payout_status => match serde_json::from_slice::<PayoutStatusUpdatedEvent>(payload) {
+                Ok(event) => self.ledger.record_payout(&event).await?,
+                Err(e) => dead_letter("ledger", msg, "payout", e).await,
+            },
+            _ => match serde_json::from_slice::<PaymentStatusUpdatedEvent>(payload) {
+                Ok(event) => self.ledger.record_status(&event).await?,
+                Err(e) => dead_letter("ledger", msg, "status", e).await,
+            },
+        }
+
+        Ok(())
+    }
+}
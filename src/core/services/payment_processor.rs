@@ -1,30 +1,74 @@
-use std::time::Duration;
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::Duration};
 
+use chrono::Utc;
 use rdkafka::{consumer::{Consumer, StreamConsumer}, producer::{FutureProducer, FutureRecord}, ClientConfig, Message};
+use tracing::Instrument;
+use uuid::Uuid;
 
-use crate::core::{events::{PaymentStatusUpdatedEvent, TransactionCreatedEvent}, infrastructure::stripe::StripeService, models::TransactionStatus};
+use crate::core::{config::{Config, Topics}, events::{PaymentStatusUpdatedEvent, SplitTransfersCreatedEvent, TransactionCreatedEvent}, infrastructure::{dead_letter::dead_letter, kafka::{flush_producer, KafkaEventLog, FLUSH_TIMEOUT}, metrics::metrics, projection::{ProjectionEvent, TransactionProjection}, publisher::EventLog, shutdown::{commit_offsets, Shutdown}, stripe::{status_from_intent, StripeService}, telemetry::{consumer_span, trace_headers}}, models::{SplitLeg, Transaction, TransactionStatus}};
+
+// paying the sellers is tried a few times before the completion is dead lettered
+const TRANSFER_ATTEMPTS: u32 = 3;
+const TRANSFER_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/* what paying a split payment's sellers takes, from its creation or rebuilt from its events */
+struct SplitPayment {
+    transaction_id: Uuid,
+    merchant_id: String,
+    currency: String,
+    legs: Vec<SplitLeg>
+}
+
+impl From<&TransactionCreatedEvent> for SplitPayment {
+    fn from(event: &TransactionCreatedEvent) -> Self {
+        Self {
+            transaction_id: event.transaction_id,
+            merchant_id: event.merchant_id.clone(),
+            currency: event.currency.clone(),
+            legs: event.splits.clone()
+        }
+    }
+}
+
+impl From<&Transaction> for SplitPayment {
+    fn from(transaction: &Transaction) -> Self {
+        Self {
+            transaction_id: transaction.id,
+            merchant_id: transaction.merchant_id.clone(),
+            currency: format!("{:?}", transaction.currency),
+            legs: transaction.splits.clone()
+        }
+    }
+}
 
 pub struct PaymentProcessor {
     stripe_service: StripeService,
-    consumer: StreamConsumer,
-    producer: FutureProducer
+    consumer: Arc<StreamConsumer>,
+    producer: FutureProducer,
+    topics: Topics,
+    // older payments are the sweeper's, it may have failed them already
+    pending_sla: chrono::Duration,
+    // split payments waiting for their charge to succeed, rebuilt from the events on start
+    split_payments: Mutex<HashMap<Uuid, SplitPayment>>,
+    events: Arc<dyn EventLog>
 }
 
 impl PaymentProcessor {
-    pub fn new(kf_broker: &str, stripe_secret_key: &str) -> Self {
-        
-        let stripe_service = StripeService::new(stripe_secret_key);
+    pub fn new(config: &Config) -> Self {
+
+        let stripe_service = StripeService::new(config.stripe.secret_key.expose(), &config.server.public_url);
+        let kafka = &config.kafka;
 
         // create the consumer group
         let consumer = ClientConfig::new()
-                .set("group.id", "stripe-payment-processor")
-                .set("bootstrap.servers", kf_broker)
+                .set("group.id", &kafka.groups.payment_processor)
+                .set("bootstrap.servers", &kafka.brokers)
                 .set("enable.auto.commit", "true")
                 .create()
                 .expect("Consumer creation failed");
 
         let producer = ClientConfig::new()
-                    .set("bootstrap.servers", kf_broker)
+                    .set("bootstrap.servers", &kafka.brokers)
                     .set("message.timeout.ms", "5000")
                     .create()
                     .expect("Failed to created the producer");
@@ -32,47 +76,53 @@ impl PaymentProcessor {
 
         Self {
             stripe_service,
-            consumer,
-            producer
+            consumer: Arc::new(consumer),
+            producer,
+            topics: kafka.topics.clone(),
+            pending_sla: config.sweeper.pending_sla(),
+            split_payments: Mutex::new(HashMap::new()),
+            events: Arc::new(KafkaEventLog::new(&kafka.brokers))
         }
     }
 
-    pub async fn start(&self) -> Result<(), Box<dyn std::error::Error>> {
+    pub async fn start(&self, shutdown: Shutdown) -> Result<(), Box<dyn std::error::Error>> {
 
         println!("Stripe service starting....");
 
-        self.consumer.subscribe(&["transactions"])
+        if let Err(e) = self.recover_splits().await {
+            eprintln!("Failed to recover unpaid split payments: {}", e);
+        }
+
+        // only payments the risk engine let through, completions of split payments are followed to pay the sellers
+        self.consumer.subscribe(&[&self.topics.transactions_screened, &self.topics.payment_status])
         .expect("Failed to subscribe to the topic");
 
-        
+
         loop {
-            match self.consumer.recv().await {
+            let received = tokio::select! {
+                received = self.consumer.recv() => received,
+                _ = shutdown.wait() => break,
+            };
+            match received {
                 Ok(msg) => {
-                    if let Some(paylod) = msg.payload() {
-                        match serde_json::from_slice::<TransactionCreatedEvent>(paylod) {
-                            Ok(event) => {
-                                println!("Processig the transaction id: {}", event.transaction_id);
-
-                                match self.stripe_service.process_payment(event.clone()).await {
-                                    Ok(payment_intent) => {
-                                        let status_event = PaymentStatusUpdatedEvent::new(event.transaction_id, TransactionStatus::Completed, payment_intent.id.to_string());
-
-                                        self.publish_status_update(status_event).await;
-                                        println!("Paymnet processed successfully..");
-                                    },
-                                    Err(e) => {
-                                        let status_event = PaymentStatusUpdatedEvent::new(event.transaction_id, TransactionStatus::Failed {
-                                            reason: e.to_string()
-                                        }, String::new());
-
-                                        self.publish_status_update(status_event).await;
-                                        println!("Failed to process the payment..");
-                                    }
+                    metrics().record_consumed("payment_processor", &self.consumer, &msg);
+                    let span = consumer_span("payment_processor", &msg);
+                    async {
+                        if let Some(paylod) = msg.payload() {
+                            match msg.topic() {
+                                topic if topic == self.topics.transactions_screened => match serde_json::from_slice::<TransactionCreatedEvent>(paylod) {
+                                    Ok(event) => self.process_transaction(event).await,
+                                    Err(e) => dead_letter("payment_processor", &msg, "transaction", e).await
+                                },
+                                _ => match serde_json::from_slice::<PaymentStatusUpdatedEvent>(paylod) {
+                                    Ok(event) => self.transfer_splits(&msg, event).await,
+                                    Err(e) => dead_letter("payment_processor", &msg, "status", e).await
                                 }
-                            },
-                            Err(e) => eprintln!("Failed to deserialise event {}", e)
+                            }
                         }
                     }
+                    .instrument(span)
+                    .await;
                 },
                 Err(e) => {
                     eprintln!("Failed to recv message: {}", e)
@@ -80,6 +130,132 @@ impl PaymentProcessor {
             }
         }
 
+        commit_offsets("payment_processor", self.consumer.as_ref());
+        if let Err(e) = flush_producer(&self.producer, FLUSH_TIMEOUT).await {
+            eprintln!("Events may have been lost on shutdown: {}", e);
+        }
+        Ok(())
+    }
+
+    async fn process_transaction(&self, event: TransactionCreatedEvent) {
+        println!("Processig the transaction id: {}", event.transaction_id);
+
+        // after a crash or a long backlog, charging it now could take money for a payment already failed
+        if Utc::now() - event.pending_since() > self.pending_sla {
+            println!("Skipping transaction {}, pending past the SLA, the sweeper expires it", event.transaction_id);
+            return;
+        }
+
+        if !event.splits.is_empty() {
+            self.split_payments.lock().unwrap().insert(event.transaction_id, SplitPayment::from(&event));
+        }
+
+        match self.stripe_service.process_payment(event.clone()).await {
+            Ok(payment_intent) => {
+                // one the customer never authenticates is canceled and failed by the sweeper
+                let status = status_from_intent(&payment_intent);
+
+                let status_event = PaymentStatusUpdatedEvent::new(event.transaction_id, event.merchant_id.clone(), status, payment_intent.id.to_string())
+                    .with_client_secret(payment_intent.client_secret.clone());
+
+                self.publish_status_update(status_event).await;
+                println!("Paymnet processed successfully..");
+            },
+            Err(e) => {
+                let status_event = PaymentStatusUpdatedEvent::new(event.transaction_id, event.merchant_id.clone(), TransactionStatus::Failed {
+                    reason: e.to_string()
+                }, String::new());
+
+                self.publish_status_update(status_event).await;
+                println!("Failed to process the payment..");
+            }
+        }
+    }
+
+    /* completion can come from us, the 3DS return or a stripe webhook, so it is picked up from the topic */
+    async fn transfer_splits<M: Message>(&self, msg: &M, event: PaymentStatusUpdatedEvent) {
+        let payment = match event.status {
+            TransactionStatus::Completed => self.split_payments.lock().unwrap().remove(&event.transaction_id),
+            TransactionStatus::Failed { .. } => {
+                self.split_payments.lock().unwrap().remove(&event.transaction_id);
+                return;
+            }
+            _ => return
+        };
+
+        let Some(payment) = payment else {
+            return;
+        };
+
+        if let Err(e) = self.pay_sellers(&payment, &event.stripe_payment_id).await {
+            // a replay of the completion, or the next start, tries again
+            self.split_payments.lock().unwrap().insert(payment.transaction_id, payment);
+            dead_letter("payment_processor", msg, "transfer", e).await;
+        }
+    }
+
+    /// Rebuilds the split payments whose sellers haven't all been paid from the transaction, status
+    /// and transfer topics, read from the start. Completed ones are paid now, the ones still in
+    /// flight wait for their completion like new ones do.
+    pub async fn recover_splits(&self) -> Result<(), String> {
+        let mut events = self.events
+            .read(&[&self.topics.transactions, &self.topics.payment_status, &self.topics.transfers])
+            .await?;
+        // topics are read one after the other, the broker's timestamps put them back in order
+        events.sort_by_key(|event| event.timestamp);
+
+        let projection = TransactionProjection::new();
+        for event in &events {
+            if let Ok(parsed) = ProjectionEvent::parse(&self.topics, &event.topic, event.payload.as_bytes()) {
+                projection.apply(&parsed).await;
+            }
+        }
+
+        for transaction in projection.unpaid_splits().await {
+            let payment = SplitPayment::from(&transaction);
+            match (&transaction.status, &transaction.provider_payment_id) {
+                (TransactionStatus::Completed, Some(provider_payment_id)) => {
+                    if let Err(e) = self.pay_sellers(&payment, provider_payment_id).await {
+                        eprintln!("Failed to pay the sellers of transaction id {}, retried on the next start: {}", transaction.id, e);
+                    }
+                }
+                (TransactionStatus::Failed { .. } | TransactionStatus::Refunded, _) => {}
+                _ => {
+                    self.split_payments.lock().unwrap().insert(transaction.id, payment);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /* transfers are idempotent per leg, so a retry only makes the ones still missing */
+    async fn pay_sellers(&self, payment: &SplitPayment, stripe_payment_id: &str) -> Result<(), String> {
+        let mut attempt = 1;
+        let legs = loop {
+            match self.stripe_service.create_split_transfers(stripe_payment_id, payment.transaction_id, &payment.currency, &payment.legs).await {
+                Ok(legs) => break legs,
+                Err(e) if attempt < TRANSFER_ATTEMPTS => {
+                    eprintln!("Failed to transfer splits for transaction id {} (attempt {}): {}", payment.transaction_id, attempt, e);
+                    tokio::time::sleep(TRANSFER_RETRY_DELAY * attempt).await;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e.to_string())
+            }
+        };
+
+        let transfers_event = SplitTransfersCreatedEvent::new(payment.transaction_id, payment.merchant_id.clone(), legs);
+        let payload = serde_json::to_string(&transfers_event).expect("Failed to serialise the evnet");
+
+        self.producer.send(FutureRecord::to(&self.topics.transfers)
+                .payload(&payload)
+                .key(&payment.transaction_id.to_string())
+                .headers(trace_headers()),
+                Duration::from_secs(5))
+            .await
+            .map_err(|(e, _)| e.to_string())?;
+
+        println!("Split transfers created for transaction id: {}", payment.transaction_id);
+        Ok(())
     }
 
     async fn publish_status_update(&self, event: PaymentStatusUpdatedEvent) {
@@ -87,12 +263,13 @@ impl PaymentProcessor {
         let payload = serde_json::to_string(&event).expect("Failed to serialise the evnet");
 
 
-        if let Err(e) = self.producer.send(FutureRecord::to("payment-status")
+        if let Err(e) = self.producer.send(FutureRecord::to(&self.topics.payment_status)
                             .payload(&payload)
-                            .key(&event.transaction_id.to_string()),
+                            .key(&event.transaction_id.to_string())
+                            .headers(trace_headers()),
                             Duration::from_secs(5)).await {
 
                                 eprintln!("Failed to publish event to kafka broker: {}", e.0);
                             }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,87 @@
+use std::{path::PathBuf, time::Duration};
+
+use chrono::{DateTime, Utc};
+
+use crate::core::{
+    config::ScheduledReport,
+    infrastructure::{
+        reporting::{ReportRequest, Reports},
+        schedule::CronSchedule,
+        shutdown::Shutdown,
+    },
+};
+
+const SCHEDULE_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/* writes the configured reports to the output directory whenever their schedule fires */
+pub struct ReportScheduler {
+    reports: Reports,
+    schedules: Vec<(ScheduledReport, CronSchedule)>,
+}
+
+impl ReportScheduler {
+    /// Schedules that don't parse are left out, config validation reports them before anything starts.
+    pub fn new(reports: Reports, schedules: &[ScheduledReport]) -> Self {
+        let schedules = schedules
+            .iter()
+            .filter_map(|scheduled| match scheduled.cron.parse::<CronSchedule>() {
+                Ok(cron) => Some((scheduled.clone(), cron)),
+                Err(e) => {
+                    eprintln!("Skipping scheduled report {}: {}", scheduled.name, e);
+                    None
+                }
+            })
+            .collect();
+
+        Self { reports, schedules }
+    }
+
+    pub async fn run(&self, shutdown: Shutdown) {
+        let mut interval = tokio::time::interval(SCHEDULE_POLL_INTERVAL);
+        // a run missed while the process was down is not made up for
+        let mut next_runs: Vec<Option<DateTime<Utc>>> = self.schedules.iter().map(|(_, cron)| cron.next_after(Utc::now())).collect();
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {},
+                _ = shutdown.wait() => return,
+            }
+            let now = Utc::now();
+
+            for ((scheduled, cron), next_run) in self.schedules.iter().zip(next_runs.iter_mut()) {
+                let Some(due) = next_run.filter(|due| *due <= now) else { continue };
+                self.run_scheduled(scheduled, due).await;
+                *next_run = cron.next_after(now);
+            }
+        }
+    }
+
+    /// Generates the report for the period before `due`, e.g. last month's on the 1st.
+    pub async fn run_scheduled(&self, scheduled: &ScheduledReport, due: DateTime<Utc>) -> Option<PathBuf> {
+        let (from, to) = scheduled.period.range_before(due);
+        let request = ReportRequest {
+            kind: scheduled.kind,
+            format: scheduled.format,
+            merchant_id: scheduled.merchant_id.clone(),
+            from,
+            to,
+        };
+
+        let path = self
+            .reports
+            .output_dir()
+            .join("scheduled")
+            .join(format!("{}-{}.{}", scheduled.name, from.format("%Y-%m-%d"), request.format.extension()));
+
+        match self.reports.generate(&request, &path).await {
+            Ok(rows) => {
+                println!("Scheduled report {} written to {} ({} rows)", scheduled.name, path.display(), rows);
+                Some(path)
+            }
+            Err(e) => {
+                eprintln!("Scheduled report {} failed: {}", scheduled.name, e);
+                None
+            }
+        }
+    }
+}
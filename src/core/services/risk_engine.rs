@@ -0,0 +1,117 @@
+use std::sync::Arc;
+
+use chrono::Utc;
+use rdkafka::{
+    consumer::{Consumer, StreamConsumer},
+    ClientConfig, Message,
+};
+use tracing::Instrument;
+
+use crate::core::{
+    config::KafkaConfig,
+    events::{PaymentStatusUpdatedEvent, TransactionCreatedEvent},
+    infrastructure::{dead_letter::dead_letter, kafka::KafkaProducer, metrics::metrics, risk::RiskStore, shutdown::{commit_offsets, Shutdown}, telemetry::consumer_span},
+    models::{RiskAction, TransactionStatus},
+};
+
+/* scores every new payment before it reaches the provider */
+pub struct RiskEngine {
+    consumer: Arc<StreamConsumer>,
+    transactions_topic: String,
+    screened: KafkaProducer,
+    status: KafkaProducer,
+    store: RiskStore,
+}
+
+impl RiskEngine {
+    pub fn new(kafka: &KafkaConfig, store: RiskStore) -> Self {
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("group.id", &kafka.groups.risk_engine)
+            .set("bootstrap.servers", &kafka.brokers)
+            .set("enable.auto.commit", "true")
+            .create()
+            .expect("Failed to create consumer");
+
+        Self {
+            consumer: Arc::new(consumer),
+            transactions_topic: kafka.topics.transactions.clone(),
+            // only payments let through here reach the processor
+            screened: KafkaProducer::new(&kafka.brokers, &kafka.topics.transactions_screened),
+            status: KafkaProducer::new(&kafka.brokers, &kafka.topics.payment_status),
+            store,
+        }
+    }
+
+    pub async fn start(&self, shutdown: Shutdown) -> Result<(), Box<dyn std::error::Error>> {
+        println!("Starting risk engine...");
+
+        self.consumer
+            .subscribe(&[&self.transactions_topic])
+            .expect("Failed to subscribe to transactions topic");
+
+        loop {
+            let received = tokio::select! {
+                received = self.consumer.recv() => received,
+                _ = shutdown.wait() => break,
+            };
+            match received {
+                Ok(msg) => {
+                    metrics().record_consumed("risk_engine", &self.consumer, &msg);
+                    let span = consumer_span("risk_engine", &msg);
+                    async {
+                        if let Some(payload) = msg.payload() {
+                            match serde_json::from_slice::<TransactionCreatedEvent>(payload) {
+                                Ok(event) => self.screen(event).await,
+                                Err(e) => dead_letter("risk_engine", &msg, "transaction", e).await,
+                            }
+                        }
+                    }
+                    .instrument(span)
+                    .await;
+                }
+                Err(e) => eprintln!("Failed to receive message: {}", e),
+            }
+        }
+
+        commit_offsets("risk_engine", self.consumer.as_ref());
+        if let Err(e) = self.screened.flush().await {
+            eprintln!("Events may have been lost on shutdown: {}", e);
+        }
+        if let Err(e) = self.status.flush().await {
+            eprintln!("Events may have been lost on shutdown: {}", e);
+        }
+        Ok(())
+    }
+
+    async fn screen(&self, event: TransactionCreatedEvent) {
+        let now = Utc::now();
+        let Some(decision) = self.store.screen(&event, now).await else {
+            return;
+        };
+
+        let result = match decision.action {
+            RiskAction::Allow => self.screened.publish_event(&event.clone().screened(now)).await,
+            RiskAction::Review => {
+                println!("Transaction {} held for review: {:?}", event.transaction_id, decision.reasons);
+                self.publish_status(&event, TransactionStatus::UnderReview).await
+            }
+            RiskAction::Block => {
+                println!("Transaction {} blocked: {:?}", event.transaction_id, decision.reasons);
+                // the matched rules stay in the audit trail, merchants only learn it was blocked
+                self.publish_status(&event, TransactionStatus::Failed {
+                    reason: "blocked by risk checks".to_string(),
+                })
+                .await
+            }
+        };
+
+        if let Err(e) = result {
+            eprintln!("Failed to publish risk decision for {}: {}", event.transaction_id, e);
+        }
+    }
+
+    async fn publish_status(&self, event: &TransactionCreatedEvent, status: TransactionStatus) -> Result<(), String> {
+        let status_event = PaymentStatusUpdatedEvent::new(event.transaction_id, event.merchant_id.clone(), status, String::new());
+        self.status.publish_event(&status_event).await
+    }
+}
@@ -0,0 +1,112 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::Utc;
+use rdkafka::{
+    consumer::{Consumer, StreamConsumer},
+    ClientConfig, Message,
+};
+use tracing::Instrument;
+
+use crate::core::{
+    config::{KafkaConfig, Topics},
+    events::{FeeAssessedEvent, PayoutCreatedEvent, RefundCreatedEvent},
+    infrastructure::{dead_letter::dead_letter, kafka::KafkaProducer, metrics::metrics, settlement::SettlementStore, shutdown::{commit_offsets, Shutdown}, telemetry::consumer_span},
+};
+
+const PAYOUT_POLL_INTERVAL: Duration = Duration::from_secs(300);
+
+/* rolls assessed payments and refunds into daily batches and pays them out once due */
+pub struct SettlementService {
+    consumer: Arc<StreamConsumer>,
+    producer: KafkaProducer,
+    topics: Topics,
+    store: SettlementStore,
+}
+
+impl SettlementService {
+    pub fn new(kafka: &KafkaConfig, group_id: &str, store: SettlementStore) -> Self {
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("group.id", group_id)
+            .set("bootstrap.servers", &kafka.brokers)
+            .set("enable.auto.commit", "true")
+            .create()
+            .expect("Failed to create consumer");
+
+        let producer = KafkaProducer::new(&kafka.brokers, &kafka.topics.payouts);
+
+        Self {
+            consumer: Arc::new(consumer),
+            producer,
+            topics: kafka.topics.clone(),
+            store,
+        }
+    }
+
+    // payments are settled from the fee topic, a completed payment only shows up there once priced
+    pub async fn start(&self, shutdown: Shutdown) -> Result<(), Box<dyn std::error::Error>> {
+        println!("Starting settlement consumer...");
+
+        self.consumer
+            .subscribe(&[&self.topics.fees, &self.topics.refunds])
+            .expect("Failed to subscribe to settlement topics");
+
+        loop {
+            let received = tokio::select! {
+                received = self.consumer.recv() => received,
+                _ = shutdown.wait() => break,
+            };
+            match received {
+                Ok(msg) => {
+                    metrics().record_consumed("settlement", &self.consumer, &msg);
+                    let span = consumer_span("settlement", &msg);
+                    async {
+                        if let Some(payload) = msg.payload() {
+                            match msg.topic() {
+                                topic if topic == self.topics.fees => match serde_json::from_slice::<FeeAssessedEvent>(payload) {
+                                    Ok(event) => self.store.add_payment(&event).await,
+                                    Err(e) => dead_letter("settlement", &msg, "fee", e).await,
+                                },
+                                _ => match serde_json::from_slice::<RefundCreatedEvent>(payload) {
+                                    Ok(event) => self.store.add_refund(&event).await,
+                                    Err(e) => dead_letter("settlement", &msg, "refund", e).await,
+                                },
+                            }
+                        }
+                    }
+                    .instrument(span)
+                    .await;
+                }
+                Err(e) => eprintln!("Failed to receive message: {}", e),
+            }
+        }
+
+        commit_offsets("settlement", self.consumer.as_ref());
+        if let Err(e) = self.producer.flush().await {
+            eprintln!("Events may have been lost on shutdown: {}", e);
+        }
+        Ok(())
+    }
+
+    pub async fn run_payouts(&self, shutdown: Shutdown) {
+        let mut interval = tokio::time::interval(PAYOUT_POLL_INTERVAL);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {},
+                _ = shutdown.wait() => return,
+            }
+
+            for (payout, batch) in self.store.release_due(Utc::now()).await {
+                println!("Paying out {} {} to {} for {}", payout.amount, payout.currency, payout.merchant_id, batch.settlement_date);
+
+                // nobody pays a payout that wasn't published, the batch waits for the next tick
+                if let Err(e) = self.producer.publish_event(&PayoutCreatedEvent::new(&payout, &batch)).await {
+                    eprintln!("Failed to publish payout {}, reopening batch {}: {}", payout.id, batch.id, e);
+                    if let Err(e) = self.store.reopen(payout.id).await {
+                        eprintln!("Failed to reopen batch {}: {}", batch.id, e);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,51 +1,96 @@
+use std::sync::Arc;
+
 use rdkafka::{
     consumer::{StreamConsumer, Consumer},
     ClientConfig,
     Message
 };
-use crate::core::events::PaymentStatusUpdatedEvent;
+use crate::core::{
+    config::{KafkaConfig, Topics},
+    infrastructure::{dead_letter::dead_letter, metrics::metrics, projection::{ProjectionEvent, TransactionProjection}, shutdown::{commit_offsets, Shutdown}, status_hub::StatusHub, telemetry::consumer_span},
+    models::TransactionStatus,
+};
+use tracing::Instrument;
+use uuid::Uuid;
 
 pub struct StatusConsumer {
-    consumer: StreamConsumer,
+    consumer: Arc<StreamConsumer>,
+    topics: Topics,
+    projection: TransactionProjection,
+    // clients streaming a transaction's status are fed from here
+    hub: StatusHub,
 }
 
 impl StatusConsumer {
-    pub fn new(kf_broker: &str) -> Self {
+    pub fn new(kafka: &KafkaConfig, group_id: &str, projection: TransactionProjection, hub: StatusHub) -> Self {
         // Initialize Kafka consumer
         let consumer: StreamConsumer = ClientConfig::new()
-            .set("group.id", "payment-status-consumer")
-            .set("bootstrap.servers", kf_broker)
+            .set("group.id", group_id)
+            .set("bootstrap.servers", &kafka.brokers)
             .set("enable.auto.commit", "true")
             .create()
             .expect("Failed to create consumer");
 
-        Self { consumer }
+        Self { consumer: Arc::new(consumer), topics: kafka.topics.clone(), projection, hub }
     }
 
-    pub async fn start(&self) -> Result<(), Box<dyn std::error::Error>> {
+    pub async fn start(&self, shutdown: Shutdown) -> Result<(), Box<dyn std::error::Error>> {
         println!("Starting payment status consumer service...");
 
-        self.consumer.subscribe(&["payment-status"])
+        // creations are needed too, otherwise pending transactions never show up in the projection
+        self.consumer.subscribe(&ProjectionEvent::topics(&self.topics))
             .expect("Failed to subscribe to payment-status topic");
 
         loop {
-            match self.consumer.recv().await {
+            let received = tokio::select! {
+                received = self.consumer.recv() => received,
+                _ = shutdown.wait() => break,
+            };
+            match received {
                 Ok(msg) => {
-                    if let Some(payload) = msg.payload() {
-                        match serde_json::from_slice::<PaymentStatusUpdatedEvent>(payload) {
-                            Ok(event) => {
-                                // 1. Update the query database with new status
-                                // 2. Cache the status for quick retrieval
-                                println!("Status updated for transaction: {}", event.transaction_id);
-                                println!("New status: {:?}", event.status);
-                                println!("Stripe payment ID: {}", event.stripe_payment_id);
+                    metrics().record_consumed("status_consumer", &self.consumer, &msg);
+                    let span = consumer_span("status_consumer", &msg);
+                    async {
+                        if let Some(payload) = msg.payload() {
+                            match ProjectionEvent::parse(&self.topics, msg.topic(), payload) {
+                                Ok(event) => {
+                                    self.projection.apply(&event).await;
+                                    match &event {
+                                        ProjectionEvent::Created(created) => {
+                                            self.count(&TransactionStatus::Pending, created.transaction_id, &created.merchant_id).await;
+                                        }
+                                        ProjectionEvent::Status(update) => {
+                                            // after the projection, so a stream opened now finds the status in either
+                                            self.hub.publish(update);
+                                            self.count(&update.status, update.transaction_id, &update.merchant_id).await;
+                                            println!("Status updated for transaction: {}", update.transaction_id);
+                                            println!("New status: {:?}", update.status);
+                                            println!("Stripe payment ID: {}", update.stripe_payment_id);
+                                        }
+                                        _ => {}
+                                    }
+                                }
+                                Err((kind, e)) => dead_letter("status_consumer", &msg, kind, e).await,
                             }
-                            Err(e) => eprintln!("Failed to deserialize status event: {}", e)
                         }
                     }
+                    .instrument(span)
+                    .await;
                 }
                 Err(e) => eprintln!("Failed to receive message: {}", e)
             }
         }
+
+        commit_offsets("status_consumer", self.consumer.as_ref());
+        Ok(())
     }
-} 
\ No newline at end of file
+
+    // the currency only comes with the creation event, the projection has it unless a status overtook it
+    async fn count(&self, status: &TransactionStatus, transaction_id: Uuid, merchant_id: &str) {
+        let currency = match self.projection.get(transaction_id).await {
+            Some(transaction) if transaction.amount > 0 => format!("{:?}", transaction.currency),
+            _ => "unknown".to_string(),
+        };
+        metrics().count_transaction(status, &currency, merchant_id);
+    }
+}
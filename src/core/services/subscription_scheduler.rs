@@ -0,0 +1,277 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use rdkafka::{
+    consumer::{Consumer, StreamConsumer},
+    ClientConfig, Message,
+};
+use tracing::Instrument;
+use uuid::Uuid;
+
+use crate::core::{
+    config::KafkaConfig,
+    events::{PaymentStatusUpdatedEvent, TransactionCreatedEvent},
+    infrastructure::{
+        billing::{next_boundary, prorate, sub_interval, BillingStore, DunningPolicy},
+        customers::CustomerStore,
+        dead_letter::dead_letter,
+        kafka::KafkaProducer,
+        metrics::metrics,
+        shutdown::{commit_offsets, Shutdown},
+        telemetry::consumer_span,
+    },
+    models::{Invoice, InvoiceStatus, Subscription, SubscriptionStatus, TransactionStatus},
+};
+
+const BILLING_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+pub struct SubscriptionScheduler {
+    store: BillingStore,
+    customers: CustomerStore,
+    dunning: DunningPolicy,
+    producer: KafkaProducer,
+    consumer: Arc<StreamConsumer>,
+    status_topic: String,
+}
+
+impl SubscriptionScheduler {
+    pub fn new(
+        kafka: &KafkaConfig,
+        store: BillingStore,
+        customers: CustomerStore,
+        dunning: DunningPolicy,
+    ) -> Self {
+        // invoices are charged through the same pipeline as any other transaction
+        let producer = KafkaProducer::new(&kafka.brokers, &kafka.topics.transactions);
+
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("group.id", &kafka.groups.subscription_dunning)
+            .set("bootstrap.servers", &kafka.brokers)
+            .set("enable.auto.commit", "true")
+            .create()
+            .expect("Failed to create consumer");
+
+        Self {
+            store,
+            customers,
+            dunning,
+            producer,
+            consumer: Arc::new(consumer),
+            status_topic: kafka.topics.payment_status.clone(),
+        }
+    }
+
+    /* renews due subscriptions and retries failed invoices */
+    pub async fn run_billing(&self, shutdown: Shutdown) {
+        let mut interval = tokio::time::interval(BILLING_POLL_INTERVAL);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {},
+                _ = shutdown.wait() => return,
+            }
+            let now = Utc::now();
+
+            for subscription in self.store.subscriptions_due(now).await {
+                self.renew(subscription, now).await;
+            }
+
+            for invoice in self.store.invoices_due_for_retry(now).await {
+                println!("Retrying invoice {} (attempt {})", invoice.id, invoice.attempt_count + 1);
+                self.charge_invoice(invoice).await;
+            }
+        }
+    }
+
+    /* follows the outcome of invoice charges on the status topic */
+    pub async fn start(&self, shutdown: Shutdown) -> Result<(), Box<dyn std::error::Error>> {
+        println!("Starting subscription dunning consumer...");
+
+        self.consumer
+            .subscribe(&[&self.status_topic])
+            .expect("Failed to subscribe to payment-status topic");
+
+        loop {
+            let received = tokio::select! {
+                received = self.consumer.recv() => received,
+                _ = shutdown.wait() => break,
+            };
+            match received {
+                Ok(msg) => {
+                    metrics().record_consumed("subscription_dunning", &self.consumer, &msg);
+                    let span = consumer_span("subscription_dunning", &msg);
+                    async {
+                        if let Some(payload) = msg.payload() {
+                            match serde_json::from_slice::<PaymentStatusUpdatedEvent>(payload) {
+                                Ok(event) => self.apply_payment_outcome(&event).await,
+                                Err(e) => dead_letter("subscription_dunning", &msg, "status", e).await,
+                            }
+                        }
+                    }
+                    .instrument(span)
+                    .await;
+                }
+                Err(e) => eprintln!("Failed to receive message: {}", e),
+            }
+        }
+
+        commit_offsets("subscription_dunning", self.consumer.as_ref());
+        if let Err(e) = self.producer.flush().await {
+            eprintln!("Events may have been lost on shutdown: {}", e);
+        }
+        Ok(())
+    }
+
+    async fn renew(&self, mut subscription: Subscription, now: DateTime<Utc>) {
+        let Some(plan) = self.store.plan(subscription.plan_id).await else {
+            eprintln!("Subscription {} references unknown plan {}", subscription.id, subscription.plan_id);
+            return;
+        };
+
+        if subscription.cancel_at_period_end {
+            subscription.status = SubscriptionStatus::Canceled;
+            subscription.canceled_at = Some(subscription.current_period_end);
+            self.store.save_subscription(subscription).await;
+            return;
+        }
+
+        let period_start = subscription.current_period_end;
+        let period_end = next_boundary(subscription.billing_anchor, plan.interval, plan.interval_count, period_start);
+
+        // a period shorter than the interval (e.g. up to a future anchor) only pays its share
+        let full_period = period_end - sub_interval(period_end, plan.interval, plan.interval_count);
+        let amount = prorate(plan.amount, period_start, period_end, full_period) + subscription.pending_proration;
+
+        subscription.current_period_start = period_start;
+        subscription.current_period_end = period_end;
+        subscription.pending_proration = amount.min(0);
+        if subscription.status == SubscriptionStatus::Trialing {
+            subscription.status = SubscriptionStatus::Active;
+        }
+
+        let invoice = Invoice {
+            id: Uuid::new_v4(),
+            subscription_id: subscription.id,
+            merchant_id: subscription.merchant_id.clone(),
+            customer_id: subscription.customer_id.clone(),
+            amount: amount.max(0) as u64,
+            currency: plan.currency.clone(),
+            period_start,
+            period_end,
+            status: if amount > 0 { InvoiceStatus::Open } else { InvoiceStatus::Void },
+            transaction_ids: Vec::new(),
+            attempt_count: 0,
+            next_retry_at: None,
+            created_at: now,
+        };
+
+        self.store.save_subscription(subscription).await;
+
+        if invoice.status == InvoiceStatus::Open {
+            self.charge_invoice(invoice).await;
+        } else {
+            self.store.save_invoice(invoice).await;
+        }
+    }
+
+    async fn charge_invoice(&self, mut invoice: Invoice) {
+        let transaction_id = Uuid::new_v4();
+        invoice.attempt_count += 1;
+        invoice.next_retry_at = None;
+        invoice.transaction_ids.push(transaction_id);
+
+        let metadata = HashMap::from([
+            ("subscription_id".to_string(), invoice.subscription_id.to_string()),
+            ("invoice_id".to_string(), invoice.id.to_string()),
+        ]);
+
+        let payment_method = self.customers.get(&invoice.merchant_id, &invoice.customer_id).await.and_then(|customer| {
+            customer
+                .default_payment_method_id
+                .map(|pm| (customer.provider_customer_id, pm))
+        });
+
+        // off-session charges need a saved method, without one the attempt fails straight away
+        let Some((provider_customer_id, payment_method_id)) = payment_method else {
+            eprintln!("Customer {} has no default payment method for invoice {}", invoice.customer_id, invoice.id);
+            self.store.save_invoice(invoice.clone()).await;
+            self.handle_failed_attempt(invoice, Utc::now()).await;
+            return;
+        };
+
+        let event = TransactionCreatedEvent::new(
+            transaction_id,
+            invoice.amount,
+            invoice.currency.clone(),
+            invoice.merchant_id.clone(),
+            invoice.customer_id.clone(),
+        )
+        .with_metadata(metadata)
+        .with_payment_method(provider_customer_id, payment_method_id);
+
+        // linked before publishing so the outcome can't arrive for an unknown transaction
+        self.store.save_invoice(invoice).await;
+
+        if let Err(e) = self.producer.publish_event(&event).await {
+            eprintln!("Failed to publish invoice charge {}: {}", transaction_id, e);
+        }
+    }
+
+    async fn apply_payment_outcome(&self, event: &PaymentStatusUpdatedEvent) {
+        let Some(mut invoice) = self.store.invoice_for_transaction(event.transaction_id).await else {
+            return;
+        };
+
+        // only the latest attempt decides the invoice, late updates for older ones are ignored
+        if invoice.status != InvoiceStatus::Open || invoice.transaction_ids.last() != Some(&event.transaction_id) {
+            return;
+        }
+
+        match &event.status {
+            TransactionStatus::Completed => {
+                invoice.status = InvoiceStatus::Paid;
+                invoice.next_retry_at = None;
+                let subscription_id = invoice.subscription_id;
+                self.store.save_invoice(invoice).await;
+
+                if let Some(mut subscription) = self.store.subscription(subscription_id).await {
+                    if matches!(subscription.status, SubscriptionStatus::PastDue | SubscriptionStatus::Unpaid) {
+                        subscription.status = SubscriptionStatus::Active;
+                        self.store.save_subscription(subscription).await;
+                    }
+                }
+            }
+            TransactionStatus::Failed { reason } => {
+                println!("Invoice {} charge failed: {}", invoice.id, reason);
+                self.handle_failed_attempt(invoice, event.timestamp).await;
+            }
+            _ => {}
+        }
+    }
+
+    async fn handle_failed_attempt(&self, mut invoice: Invoice, failed_at: DateTime<Utc>) {
+        let next_retry = self.dunning.next_retry(invoice.attempt_count, failed_at);
+
+        let subscription_status = match next_retry {
+            Some(_) => SubscriptionStatus::PastDue,
+            None => {
+                invoice.status = InvoiceStatus::Uncollectible;
+                self.dunning.on_exhausted.clone()
+            }
+        };
+        invoice.next_retry_at = next_retry;
+
+        let subscription_id = invoice.subscription_id;
+        self.store.save_invoice(invoice).await;
+
+        if let Some(mut subscription) = self.store.subscription(subscription_id).await {
+            if subscription.status != SubscriptionStatus::Canceled {
+                if subscription_status == SubscriptionStatus::Canceled {
+                    subscription.canceled_at = Some(Utc::now());
+                }
+                subscription.status = subscription_status;
+                self.store.save_subscription(subscription).await;
+            }
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::core::{
+    config::{SweeperConfig, Topics},
+    events::PaymentStatusUpdatedEvent,
+    infrastructure::{
+        metrics::metrics,
+        projection::{PageCursor, TransactionFilter, TransactionProjection, TransactionSort},
+        publisher::EventPublisher,
+        shutdown::Shutdown,
+        stripe::PaymentProvider,
+    },
+    models::{Transaction, TransactionStatus},
+};
+
+// how many stuck transactions are read from the projection at a time
+const SWEEP_PAGE_SIZE: usize = 100;
+
+/// What one sweep did, by the action counted in `sweeper_actions_total`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SweepSummary {
+    pub corrected: usize,
+    pub expired: usize,
+    pub still_pending: usize,
+    pub errors: usize,
+}
+
+/* settles payments left pending past the SLA, by a processor that crashed or a provider that hung,
+   and fails the ones whose customer never authenticated or that nobody reviewed in time */
+pub struct TransactionSweeper {
+    pending_sla: chrono::Duration,
+    review_sla: chrono::Duration,
+    requires_action_timeout: chrono::Duration,
+    interval: Duration,
+    status_topic: String,
+    projection: TransactionProjection,
+    provider: Arc<dyn PaymentProvider>,
+    publisher: Arc<dyn EventPublisher>,
+    // published for but not yet back through the projection, so they aren't published for twice
+    settled: Mutex<HashSet<Uuid>>,
+}
+
+impl TransactionSweeper {
+    pub fn new(
+        config: &SweeperConfig,
+        topics: &Topics,
+        projection: TransactionProjection,
+        provider: Arc<dyn PaymentProvider>,
+        publisher: Arc<dyn EventPublisher>,
+    ) -> Self {
+        Self {
+            pending_sla: config.pending_sla(),
+            review_sla: config.review_sla(),
+            requires_action_timeout: config.requires_action_timeout(),
+            interval: config.interval(),
+            status_topic: topics.payment_status.clone(),
+            projection,
+            provider,
+            publisher,
+            settled: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub async fn run(&self, shutdown: Shutdown) {
+        let mut interval = tokio::time::interval(self.interval);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {},
+                _ = shutdown.wait() => return,
+            }
+
+            let summary = self.sweep(Utc::now()).await;
+            if summary != SweepSummary::default() {
+                println!("Swept stuck transactions: {:?}", summary);
+            }
+        }
+    }
+
+    /// Looks every transaction pending since before `now` minus the SLA up at the provider, counting
+    /// from its last status change so a payment approved after review gets the whole SLA. One the
+    /// provider got further with gets the status it has there, one it has no record of is failed.
+    /// One still pending there too was abandoned, it's canceled at the provider and then failed, and
+    /// left for the next sweep when the cancel doesn't go through. Payments held for review since
+    /// before `now` minus the review SLA never reached the provider and are failed. Payments waiting
+    /// on the customer to authenticate for longer than the timeout are settled like pending ones,
+    /// one still waiting at the provider is canceled and failed.
+    pub async fn sweep(&self, now: DateTime<Utc>) -> SweepSummary {
+        let mut stuck = self.idle("pending", now - self.pending_sla).await;
+        stuck.extend(self.stuck("under_review", now - self.review_sla).await);
+        stuck.extend(self.idle("requires_action", now - self.requires_action_timeout).await);
+        let mut summary = SweepSummary::default();
+
+        // what the projection no longer has stuck doesn't need remembering
+        let stuck_ids: HashSet<Uuid> = stuck.iter().map(|transaction| transaction.id).collect();
+        self.settled.lock().unwrap().retain(|id| stuck_ids.contains(id));
+
+        for transaction in stuck {
+            if self.settled.lock().unwrap().contains(&transaction.id) {
+                continue;
+            }
+
+            let action = if transaction.status == TransactionStatus::UnderReview {
+                self.publish(&transaction, expired("nobody reviewed the payment in time"), String::new(), "expired").await
+            } else {
+                self.settle(&transaction).await
+            };
+            metrics().count_sweep(action);
+            match action {
+                "corrected" => summary.corrected += 1,
+                "expired" => summary.expired += 1,
+                "still_pending" => summary.still_pending += 1,
+                _ => summary.errors += 1,
+            }
+        }
+
+        summary
+    }
+
+    /* like `stuck`, but in the status since before `changed_before`, which may be long after creation */
+    async fn idle(&self, kind: &str, changed_before: DateTime<Utc>) -> Vec<Transaction> {
+        let mut idle = self.stuck(kind, changed_before).await;
+        idle.retain(|transaction| transaction.update_at <= changed_before);
+        idle
+    }
+
+    /* `kind` as in `TransactionStatus::kind` */
+    async fn stuck(&self, kind: &str, created_before: DateTime<Utc>) -> Vec<Transaction> {
+        let filter = TransactionFilter {
+            status: Some(kind.to_string()),
+            created_to: Some(created_before),
+            ..Default::default()
+        };
+
+        let mut stuck = Vec::new();
+        let mut cursor: Option<PageCursor> = None;
+        loop {
+            let page = self.projection.search(&filter, TransactionSort::CreatedAtAsc, cursor.as_ref(), SWEEP_PAGE_SIZE).await;
+            stuck.extend(page.transactions);
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => return stuck,
+            }
+        }
+    }
+
+    /* the action taken, as counted */
+    async fn settle(&self, transaction: &Transaction) -> &'static str {
+        let provider_payment_id = transaction.provider_payment_id.clone().unwrap_or_default();
+
+        let status = if provider_payment_id.is_empty() {
+            None
+        } else {
+            match self.provider.payment_status(&provider_payment_id).await {
+                Ok(status) => status,
+                Err(e) => {
+                    eprintln!("Failed to look up payment {} of transaction {}: {}", provider_payment_id, transaction.id, e);
+                    return "error";
+                }
+            }
+        };
+
+        let awaiting_customer = matches!(transaction.status, TransactionStatus::RequiresAction { .. });
+        let (status, action) = match status {
+            None => (expired("the payment provider has no record of the payment"), "expired"),
+            Some(TransactionStatus::Pending) => {
+                return self.cancel(transaction, provider_payment_id, "the payment was abandoned and canceled").await
+            }
+            Some(TransactionStatus::RequiresAction { .. }) if awaiting_customer => {
+                return self.cancel(transaction, provider_payment_id, "customer authentication timed out").await
+            }
+            Some(status) => (status, "corrected"),
+        };
+
+        self.publish(transaction, status, provider_payment_id, action).await
+    }
+
+    async fn cancel(&self, transaction: &Transaction, provider_payment_id: String, why: &str) -> &'static str {
+        if let Err(e) = self.provider.cancel_payment(&provider_payment_id).await {
+            // it may be going through right now, the next sweep sees where it ended up
+            eprintln!("Failed to cancel payment {} of transaction {}: {}", provider_payment_id, transaction.id, e);
+            return "still_pending";
+        }
+
+        self.publish(transaction, expired(why), provider_payment_id, "expired").await
+    }
+
+    async fn publish(&self, transaction: &Transaction, status: TransactionStatus, provider_payment_id: String, action: &'static str) -> &'static str {
+        let event = PaymentStatusUpdatedEvent::new(transaction.id, transaction.merchant_id.clone(), status, provider_payment_id);
+        if let Err(e) = self.publisher.publish_event(&self.status_topic, &transaction.id.to_string(), &event).await {
+            eprintln!("Failed to publish the swept status of transaction {}: {}", transaction.id, e);
+            return "error";
+        }
+
+        self.settled.lock().unwrap().insert(transaction.id);
+        action
+    }
+}
+
+fn expired(why: &str) -> TransactionStatus {
+    TransactionStatus::Failed { reason: format!("expired: {}", why) }
+}
@@ -0,0 +1,222 @@
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use chrono::{Duration, Utc};
+    use uuid::Uuid;
+
+    use crate::core::{
+        config::{SweeperConfig, Topics},
+        events::{PaymentStatusUpdatedEvent, TransactionCreatedEvent},
+        infrastructure::{metrics::metrics, projection::TransactionProjection, publisher::InMemoryPublisher, stripe::PaymentProvider},
+        models::TransactionStatus,
+        services::transaction_sweeper::{SweepSummary, TransactionSweeper},
+    };
+
+    /* stands in for stripe, payments it doesn't know are missing there */
+    #[derive(Default)]
+    struct MockProvider {
+        statuses: Mutex<HashMap<String, TransactionStatus>>,
+        unreachable: bool,
+        refuse_cancel: bool,
+        canceled: Mutex<Vec<String>>,
+    }
+
+    #[axum::async_trait]
+    impl PaymentProvider for MockProvider {
+        async fn payment_status(&self, provider_payment_id: &str) -> Result<Option<TransactionStatus>, String> {
+            if self.unreachable {
+                return Err("timeout communicating with stripe".to_string());
+            }
+            Ok(self.statuses.lock().unwrap().get(provider_payment_id).cloned())
+        }
+
+        async fn cancel_payment(&self, provider_payment_id: &str) -> Result<(), String> {
+            if self.refuse_cancel {
+                return Err("payment intent is processing".to_string());
+            }
+            self.canceled.lock().unwrap().push(provider_payment_id.to_string());
+            self.statuses.lock().unwrap().insert(provider_payment_id.to_string(), TransactionStatus::Failed { reason: "canceled".to_string() });
+            Ok(())
+        }
+    }
+
+    struct Setup {
+        sweeper: TransactionSweeper,
+        projection: TransactionProjection,
+        provider: Arc<MockProvider>,
+        publisher: Arc<InMemoryPublisher>,
+    }
+
+    fn setup(provider: MockProvider) -> Setup {
+        let projection = TransactionProjection::new();
+        let provider = Arc::new(provider);
+        let publisher = Arc::new(InMemoryPublisher::new());
+        let config = SweeperConfig { pending_sla_secs: 1800, review_sla_secs: 86400, requires_action_timeout_secs: 1800, interval_secs: 60 };
+        let sweeper = TransactionSweeper::new(&config, &Topics::default(), projection.clone(), provider.clone(), publisher.clone());
+        Setup { sweeper, projection, provider, publisher }
+    }
+
+    async fn pending(setup: &Setup, age_mins: i64, provider_payment_id: Option<&str>) -> Uuid {
+        let id = Uuid::new_v4();
+        let mut created = TransactionCreatedEvent::new(id, 2500, "USD".to_string(), "merch_1".to_string(), "cust_1".to_string());
+        created.timestamp = Utc::now() - Duration::minutes(age_mins);
+        setup.projection.apply_created(&created).await;
+
+        if let Some(provider_payment_id) = provider_payment_id {
+            let mut updated = PaymentStatusUpdatedEvent::new(id, "merch_1".to_string(), TransactionStatus::Pending, provider_payment_id.to_string());
+            updated.timestamp = created.timestamp;
+            setup.projection.apply_status(&updated).await;
+        }
+        id
+    }
+
+    fn published(setup: &Setup) -> Vec<PaymentStatusUpdatedEvent> {
+        setup
+            .publisher
+            .published(&Topics::default().payment_status)
+            .into_iter()
+            .map(|event| serde_json::from_value(event).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_stuck_payments_get_the_status_the_provider_has() {
+        let setup = setup(MockProvider::default());
+        setup.provider.statuses.lock().unwrap().insert("pi_done".to_string(), TransactionStatus::Completed);
+        let stuck = pending(&setup, 45, Some("pi_done")).await;
+        pending(&setup, 5, Some("pi_done")).await;
+
+        let summary = setup.sweeper.sweep(Utc::now()).await;
+
+        assert_eq!(summary, SweepSummary { corrected: 1, ..Default::default() });
+        let events = published(&setup);
+        assert_eq!(events.len(), 1);
+        assert_eq!((events[0].transaction_id, &events[0].status), (stuck, &TransactionStatus::Completed));
+        assert_eq!(events[0].stripe_payment_id, "pi_done");
+    }
+
+    #[tokio::test]
+    async fn test_payments_the_provider_never_got_are_expired() {
+        let setup = setup(MockProvider::default());
+        let never_sent = pending(&setup, 45, None).await;
+        let unknown = pending(&setup, 45, Some("pi_missing")).await;
+
+        let summary = setup.sweeper.sweep(Utc::now()).await;
+
+        assert_eq!(summary, SweepSummary { expired: 2, ..Default::default() });
+        let events = published(&setup);
+        let failed: Vec<Uuid> = events.iter().filter(|event| event.status.kind() == "failed").map(|event| event.transaction_id).collect();
+        assert_eq!(failed.len(), 2);
+        assert!(failed.contains(&never_sent) && failed.contains(&unknown));
+        assert!(setup.provider.canceled.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_abandoned_payments_are_canceled_before_they_are_failed() {
+        let setup = setup(MockProvider::default());
+        setup.provider.statuses.lock().unwrap().insert("pi_abandoned".to_string(), TransactionStatus::Pending);
+        pending(&setup, 45, Some("pi_abandoned")).await;
+
+        let summary = setup.sweeper.sweep(Utc::now()).await;
+
+        assert_eq!(summary, SweepSummary { expired: 1, ..Default::default() });
+        assert_eq!(*setup.provider.canceled.lock().unwrap(), ["pi_abandoned"]);
+        assert_eq!(published(&setup)[0].status.kind(), "failed");
+    }
+
+    #[tokio::test]
+    async fn test_nothing_is_published_when_the_provider_cant_say() {
+        let setup = setup(MockProvider { unreachable: true, ..Default::default() });
+        pending(&setup, 45, Some("pi_1")).await;
+        let errors_before = metrics().sweeper_actions.with_label_values(&["error"]).get();
+
+        let summary = setup.sweeper.sweep(Utc::now()).await;
+
+        assert_eq!(summary, SweepSummary { errors: 1, ..Default::default() });
+        assert!(published(&setup).is_empty());
+        assert!(metrics().sweeper_actions.with_label_values(&["error"]).get() > errors_before);
+
+        let setup = self::setup(MockProvider { refuse_cancel: true, ..Default::default() });
+        setup.provider.statuses.lock().unwrap().insert("pi_processing".to_string(), TransactionStatus::Pending);
+        pending(&setup, 45, Some("pi_processing")).await;
+
+        assert_eq!(setup.sweeper.sweep(Utc::now()).await, SweepSummary { still_pending: 1, ..Default::default() });
+        assert!(published(&setup).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_a_settled_payment_is_published_once_while_the_projection_catches_up() {
+        let setup = setup(MockProvider::default());
+        let id = pending(&setup, 45, None).await;
+
+        setup.sweeper.sweep(Utc::now()).await;
+        let again = setup.sweeper.sweep(Utc::now()).await;
+
+        assert_eq!(again, SweepSummary::default());
+        assert_eq!(published(&setup).len(), 1);
+
+        // once it's through, the projection has it failed and it's no longer stuck
+        setup.projection.apply_status(&published(&setup)[0]).await;
+        assert_eq!(setup.projection.get(id).await.unwrap().status.kind(), "failed");
+        assert_eq!(setup.sweeper.sweep(Utc::now()).await, SweepSummary::default());
+    }
+
+    #[tokio::test]
+    async fn test_payments_nobody_reviewed_in_time_are_failed() {
+        let setup = setup(MockProvider::default());
+        let stale = pending(&setup, 25 * 60, None).await;
+        let recent = pending(&setup, 60, None).await;
+        for id in [stale, recent] {
+            let mut held = PaymentStatusUpdatedEvent::new(id, "merch_1".to_string(), TransactionStatus::UnderReview, String::new());
+            held.timestamp = setup.projection.get(id).await.unwrap().created_at;
+            setup.projection.apply_status(&held).await;
+        }
+
+        let summary = setup.sweeper.sweep(Utc::now()).await;
+
+        assert_eq!(summary, SweepSummary { expired: 1, ..Default::default() });
+        let events = published(&setup);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].transaction_id, stale);
+        assert!(matches!(&events[0].status, TransactionStatus::Failed { reason } if reason.contains("reviewed")));
+    }
+
+    #[tokio::test]
+    async fn test_payments_the_customer_never_authenticated_are_canceled_and_failed() {
+        let setup = setup(MockProvider::default());
+        let awaiting = TransactionStatus::RequiresAction { next_action_url: "https://hooks.stripe.com/3ds".to_string() };
+        let timed_out = pending(&setup, 45, Some("pi_timed_out")).await;
+        let recent = pending(&setup, 45, Some("pi_recent")).await;
+        for (id, provider_payment_id, since_mins) in [(timed_out, "pi_timed_out", 40), (recent, "pi_recent", 5)] {
+            setup.provider.statuses.lock().unwrap().insert(provider_payment_id.to_string(), awaiting.clone());
+            let mut asked = PaymentStatusUpdatedEvent::new(id, "merch_1".to_string(), awaiting.clone(), provider_payment_id.to_string());
+            asked.timestamp = Utc::now() - Duration::minutes(since_mins);
+            setup.projection.apply_status(&asked).await;
+        }
+
+        let summary = setup.sweeper.sweep(Utc::now()).await;
+
+        assert_eq!(summary, SweepSummary { expired: 1, ..Default::default() });
+        assert_eq!(*setup.provider.canceled.lock().unwrap(), ["pi_timed_out"]);
+        let events = published(&setup);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].transaction_id, timed_out);
+        assert!(matches!(&events[0].status, TransactionStatus::Failed { reason } if reason.contains("authentication")));
+    }
+
+    #[tokio::test]
+    async fn test_payments_approved_after_review_get_the_whole_sla() {
+        let setup = setup(MockProvider::default());
+        let approved = pending(&setup, 45, None).await;
+        let mut released = PaymentStatusUpdatedEvent::new(approved, "merch_1".to_string(), TransactionStatus::Pending, String::new());
+        released.timestamp = Utc::now() - Duration::minutes(5);
+        setup.projection.apply_status(&released).await;
+
+        assert_eq!(setup.sweeper.sweep(Utc::now()).await, SweepSummary::default());
+        assert_eq!(setup.sweeper.sweep(Utc::now() + Duration::minutes(30)).await, SweepSummary { expired: 1, ..Default::default() });
+    }
+}
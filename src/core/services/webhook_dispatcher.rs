@@ -0,0 +1,111 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::Utc;
+use rdkafka::{
+    consumer::{Consumer, StreamConsumer},
+    ClientConfig, Message,
+};
+use tracing::Instrument;
+
+use crate::core::{config::KafkaConfig, events::PaymentStatusUpdatedEvent, infrastructure::{dead_letter::dead_letter, metrics::metrics, shutdown::{commit_offsets, Shutdown}, telemetry::consumer_span, webhook::{WebhookError, WebhookSender}}};
+
+const RETRY_POLL_INTERVAL: Duration = Duration::from_secs(15);
+
+pub struct WebhookDispatcher {
+    sender: WebhookSender,
+    consumer: Arc<StreamConsumer>,
+    status_topic: String,
+}
+
+impl WebhookDispatcher {
+    pub fn new(kafka: &KafkaConfig, sender: WebhookSender) -> Self {
+        // own consumer group so we see every status update the status consumer sees
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("group.id", &kafka.groups.webhook_dispatcher)
+            .set("bootstrap.servers", &kafka.brokers)
+            .set("enable.auto.commit", "true")
+            // offsets are stored once the delivery is saved, a crash before that consumes the event again
+            .set("enable.auto.offset.store", "false")
+            .create()
+            .expect("Failed to create consumer");
+
+        Self {
+            sender,
+            consumer: Arc::new(consumer),
+            status_topic: kafka.topics.payment_status.clone(),
+        }
+    }
+
+    pub async fn start(&self, shutdown: Shutdown) -> Result<(), Box<dyn std::error::Error>> {
+        println!("Starting merchant webhook dispatcher...");
+
+        self.consumer
+            .subscribe(&[&self.status_topic])
+            .expect("Failed to subscribe to payment-status topic");
+
+        loop {
+            let received = tokio::select! {
+                received = self.consumer.recv() => received,
+                _ = shutdown.wait() => break,
+            };
+            match received {
+                Ok(msg) => {
+                    metrics().record_consumed("webhook_dispatcher", &self.consumer, &msg);
+                    let span = consumer_span("webhook_dispatcher", &msg);
+                    async {
+                        if let Some(payload) = msg.payload() {
+                            match serde_json::from_slice::<PaymentStatusUpdatedEvent>(payload) {
+                                Ok(event) => self.dispatch(&msg, &event).await,
+                                Err(e) => dead_letter("webhook_dispatcher", &msg, "status", e).await,
+                            }
+                        }
+                    }
+                    .instrument(span)
+                    .await;
+                    // the delivery is saved or the event dead lettered, either way it's handled
+                    if let Err(e) = self.consumer.store_offset_from_message(&msg) {
+                        eprintln!("Failed to store offset {}/{}@{}: {}", msg.topic(), msg.partition(), msg.offset(), e);
+                    }
+                }
+                Err(e) => eprintln!("Failed to receive message: {}", e),
+            }
+        }
+
+        commit_offsets("webhook_dispatcher", self.consumer.as_ref());
+        Ok(())
+    }
+
+    async fn dispatch<M: Message>(&self, msg: &M, event: &PaymentStatusUpdatedEvent) {
+        match self.sender.enqueue(event).await {
+            // the retry loop makes the first attempt too, a slow endpoint doesn't hold up the consumer
+            Ok(Some(_)) => {}
+            Ok(None) => println!(
+                "No webhook endpoint for merchant {} or event {} already queued, skipping transaction {}",
+                event.merchant_id, event.event_id, event.transaction_id
+            ),
+            // not saved, parked so it can be replayed once the store is writable again
+            Err(e) => dead_letter("webhook_dispatcher", msg, "delivery", e).await,
+        }
+    }
+
+    /* attempts new deliveries as they're queued, and failed ones whose backoff has elapsed */
+    pub async fn run_retries(sender: WebhookSender, shutdown: Shutdown) {
+        let mut interval = tokio::time::interval(RETRY_POLL_INTERVAL);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {},
+                _ = sender.wait_for_queued() => {},
+                _ = shutdown.wait() => return,
+            }
+
+            for delivery_id in sender.store().due_deliveries(Utc::now()).await {
+                match sender.attempt(delivery_id, false).await {
+                    // a manual redelivery got to it first
+                    Ok(_) | Err(WebhookError::NotPending(_)) => {}
+                    Err(e) => eprintln!("Failed to retry webhook {}: {}", delivery_id, e),
+                }
+            }
+        }
+    }
+}
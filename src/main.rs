@@ -2,39 +2,177 @@
 mod core;
 mod api;
 
-use axum::{
-    middleware,
-    routing::get,
-    Router,
-};
+use axum::middleware;
 use tower_http::trace::TraceLayer;
 
 use crate::api::{
-    middleware::AuthMiddleware,
+    middleware::{authenticate, AuthMiddleware},
+    authentication::AuthenticationService,
     routes::create_router,
 };
+use crate::core::{
+    api::{create_router_with, state::AppState},
+    config::{Component, Config},
+    infrastructure::{
+        accounts::AccountStore,
+        dead_letter::install_dead_letter_queue,
+        health::{health_routes, Health, KafkaCheck, ProviderCheck},
+        kafka::KafkaPublisher,
+        publisher::EventPublisher,
+        shutdown::Shutdown,
+        telemetry::init_telemetry,
+    },
+    services::{fee_calculator::FeeCalculator, ledger_consumer::LedgerConsumer, risk_engine::RiskEngine, settlement_service::SettlementService, status_consumer::StatusConsumer, subscription_scheduler::SubscriptionScheduler},
+};
+
+// how long buffered events get to reach the broker once we are asked to stop
+const PUBLISHER_FLUSH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
 
 #[tokio::main]
 async fn main() {
-    // Initialize tracing
-    tracing_subscriber::fmt::init();
+    // Refuse to start half configured, every problem is listed at once
+    let config = match Config::load(Component::ApiServer) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Initialize tracing, spans follow a payment through kafka into the other services
+    let telemetry = init_telemetry("payme-api", &config.telemetry);
+    let kafka = &config.kafka;
+
+    // SIGTERM stops the server taking requests and the consumers taking messages, then everything drains
+    let shutdown = Shutdown::new();
+    shutdown.trigger_on_signal();
+    let mut workers = Vec::new();
+
+    // One producer for every handler, the stores are shared with the consumers below
+    let publisher = std::sync::Arc::new(KafkaPublisher::new(&kafka.brokers));
+    let state = AppState::new(&config, publisher.clone());
+    // what the consumers below can't read is parked on the dead letter topic instead of dropped
+    install_dead_letter_queue(publisher.clone(), &kafka.topics.dead_letter);
+
+    // Keep the query side projection up to date in-process
+    let status_consumer = StatusConsumer::new(kafka, &kafka.groups.query_projection, state.projection.clone(), state.status_hub.clone());
+    let stopping = shutdown.clone();
+    workers.push(tokio::spawn(async move {
+        if let Err(e) = status_consumer.start(stopping).await {
+            eprintln!("Status consumer stopped: {}", e);
+        }
+    }));
+
+    // Open status streams end on shutdown, they'd keep the server from draining otherwise
+    let status_hub = state.status_hub.clone();
+    let stopping = shutdown.clone();
+    tokio::spawn(async move {
+        stopping.wait().await;
+        status_hub.close();
+    });
+
+    // Subscriptions bill through the customers' saved payment methods, so they share the stores
+    let scheduler = std::sync::Arc::new(SubscriptionScheduler::new(kafka, state.billing.clone(), state.customers.clone(), config.billing.dunning_policy()));
+    let billing_scheduler = scheduler.clone();
+    let stopping = shutdown.clone();
+    workers.push(tokio::spawn(async move { billing_scheduler.run_billing(stopping).await }));
+    let stopping = shutdown.clone();
+    workers.push(tokio::spawn(async move {
+        if let Err(e) = scheduler.start(stopping).await {
+            eprintln!("Dunning consumer stopped: {}", e);
+        }
+    }));
+
+    // Post every payment event to the ledger
+    let ledger_consumer = LedgerConsumer::new(kafka, state.ledger.clone());
+    let stopping = shutdown.clone();
+    workers.push(tokio::spawn(async move {
+        if let Err(e) = ledger_consumer.start(stopping).await {
+            eprintln!("Ledger consumer stopped: {}", e);
+        }
+    }));
 
-    // Create the router with authentication
-    let app = create_router()
+    // Price completed payments against the merchants' plans
+    let fee_calculator = FeeCalculator::new(kafka, state.pricing.clone());
+    let stopping = shutdown.clone();
+    workers.push(tokio::spawn(async move {
+        if let Err(e) = fee_calculator.start(stopping).await {
+            eprintln!("Fee calculator stopped: {}", e);
+        }
+    }));
+
+    // Batch what we owe merchants and pay it out T+2
+    let settlement_service = std::sync::Arc::new(SettlementService::new(kafka, &kafka.groups.settlement, state.settlements.clone()));
+    let payout_service = settlement_service.clone();
+    let stopping = shutdown.clone();
+    workers.push(tokio::spawn(async move { payout_service.run_payouts(stopping).await }));
+    let stopping = shutdown.clone();
+    workers.push(tokio::spawn(async move {
+        if let Err(e) = settlement_service.start(stopping).await {
+            eprintln!("Settlement consumer stopped: {}", e);
+        }
+    }));
+
+    // Screen new payments before the processor sees them, held ones wait for the review api
+    let risk_engine = RiskEngine::new(kafka, state.risk.clone());
+    let stopping = shutdown.clone();
+    workers.push(tokio::spawn(async move {
+        if let Err(e) = risk_engine.start(stopping).await {
+            eprintln!("Risk engine stopped: {}", e);
+        }
+    }));
+
+    // The sweeper and the scheduled reports run once for the deployment, in payme-scheduler
+
+    // Create the router with authentication, signing with the key ring once payme-admin rotated a key into it
+    let auth_service = match AuthenticationService::from_config(&config.auth) {
+        Ok(auth_service) => auth_service,
+        Err(e) => {
+            eprintln!("Failed to load the jwt key ring: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let accounts = AccountStore::from_path(&config.auth.accounts_path);
+    // merchants' integrations call with the api keys payme-admin issues, people with a login token
+    let authentication = AuthMiddleware::with_service(auth_service.clone()).with_api_keys(accounts.clone());
+    let app = create_router(auth_service, accounts, state.audit.clone(), config.auth.example_login, state.rate_limiter.clone())
+        .merge(create_router_with(state).await)
         .layer(TraceLayer::new_for_http())
-        .layer(middleware::from_fn_with_state(
-            AuthMiddleware::new(),
-            |state, req, next| async move {
-                let mut auth_middleware = state.clone();
-                auth_middleware.authorize(req).await?;
-                next.run(req).await
-            },
-        ));
+        .layer(middleware::from_fn_with_state(authentication, authenticate));
+
+    // Probes stay outside the auth layer, orchestrators don't carry tokens
+    let health = Health::new(shutdown.clone())
+        .with_check(KafkaCheck::new(&kafka.brokers))
+        .with_check(ProviderCheck::new(&config.stripe.secret_key));
+    let app = app.merge(health_routes(health));
 
     // Start the server
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
+    let listener = tokio::net::TcpListener::bind(&config.server.bind_address)
         .await
         .unwrap();
     println!("listening on {}", listener.local_addr().unwrap());
-    axum::serve(listener, app).await.unwrap();
+    let stopping = shutdown.clone();
+    // the peer address ends up in audit entries of requests that weren't forwarded
+    let server = axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>())
+        .with_graceful_shutdown(async move { stopping.wait().await });
+
+    // Requests already accepted are answered and consumers finish their message, within the grace period
+    shutdown
+        .drain(
+            async {
+                if let Err(e) = server.await {
+                    eprintln!("Server error: {}", e);
+                }
+                for worker in workers {
+                    let _ = worker.await;
+                }
+            },
+            config.server.shutdown_grace_period(),
+        )
+        .await;
+
+    if let Err(e) = publisher.flush(PUBLISHER_FLUSH_TIMEOUT).await {
+        eprintln!("Events may have been lost on shutdown: {}", e);
+    }
+    telemetry.shutdown();
 }